@@ -1,4 +1,5 @@
 #import bevy_sprite::mesh2d_vertex_output::VertexOutput
+#import "shaders/overlay.wgsl"::overlay_color
 
 struct GridMaterial {
     viewport_bottom_left: vec2<f32>,
@@ -7,15 +8,25 @@ struct GridMaterial {
     line_width: f32,
     color_bg: vec4<f32>,
     color_line: vec4<f32>,
-    grid_dimensions: vec2<f32>,
+    grid_dimensions: vec2<f32>, // Viewport buffer size in cells (the sheet itself is unbounded)
     show_grid: f32,
+    frozen_panes: vec2<f32>, // Frozen (columns, rows)
+    time: f32, // Seconds, for the marching ants
+    stripes: f32, // Zebra striping on every other row
+    heatmap: f32, // Heatmap lens on
+    heat_range: vec2<f32>, // (min, max) of the heatmap scale
+    major_every: f32, // Cells between major gridlines
+    major_line_width: f32, // Screen pixels (line_width is too)
+    color_major_line: vec4<f32>,
+    pixel_size: f32, // World units per screen pixel
+    resized_cols: f32, // Entries in col_offsets
 }
 
 @group(2) @binding(0)
 var<uniform> material: GridMaterial;
 
 @group(2) @binding(1)
-var<storage, read> cell_data: array<u32>; // Viewport-relative buffer
+var<storage, read> cell_data: array<u32>; // Viewport-relative, CELL_STRIDE words per slot
 
 @group(2) @binding(2)
 var rich_cell_textures: texture_2d_array<f32>;
@@ -26,59 +37,268 @@ var rich_cell_sampler: sampler;
 @group(2) @binding(4)
 var<storage, read> rich_cell_indices: array<i32>; // Viewport-relative buffer
 
+@group(2) @binding(5)
+var glyph_atlas: texture_2d<f32>;
+
+@group(2) @binding(6)
+var glyph_sampler: sampler;
+
+@group(2) @binding(7)
+var<storage, read> cell_text: array<u32>; // Viewport-relative, TEXT_STRIDE words per slot
+
+@group(2) @binding(8)
+var<storage, read> cell_borders: array<u32>; // Viewport-relative, BORDER_STRIDE words per slot
+
+@group(2) @binding(9)
+var<storage, read> col_offsets: array<f32>; // (column, left, width) per resized column, in order
+
+@group(2) @binding(10)
+var<storage, read> overlay_data: array<u32>; // Viewport-relative, one host word per slot (see overlay.wgsl)
+
+// Flags, background, text color and the value's f64 bits (see gpu_cell.rs)
+const CELL_STRIDE: u32 = 5u;
+
+// An f64 from its bits (low word first), narrowed to f32
+// Subnormals count as zero; out-of-range values become infinite
+fn unpack_f64(low: u32, high: u32) -> f32 {
+    let sign = select(1.0, -1.0, (high >> 31u) != 0u);
+    let exponent = i32((high >> 20u) & 0x7ffu);
+    if (exponent == 0) {
+        return 0.0;
+    }
+    // 52 mantissa bits: 20 in the high word, 32 in the low
+    let mantissa = 1.0 + f32(high & 0xfffffu) / 1048576.0 + f32(low) / 4503599627370496.0;
+    return sign * ldexp(mantissa, exponent - 1023);
+}
+
+// A packed sRGB RGBA color (red in the low byte) in linear space
+fn unpack_color(packed: u32) -> vec4<f32> {
+    let srgb = unpack4x8unorm(packed);
+    return vec4<f32>(pow(srgb.rgb, vec3<f32>(2.2)), srgb.a);
+}
+
+// Cool-to-warm scale for the heatmap lens, `t` in 0..1 (linear colors)
+fn heat_color(t: f32) -> vec4<f32> {
+    let cool = vec3<f32>(0.05, 0.15, 0.65);
+    let mid = vec3<f32>(0.9, 0.9, 0.9);
+    let warm = vec3<f32>(0.7, 0.03, 0.03);
+    let x = clamp(t, 0.0, 1.0);
+    if (x < 0.5) {
+        return vec4<f32>(mix(cool, mid, x * 2.0), 1.0);
+    }
+    return vec4<f32>(mix(mid, warm, x * 2.0 - 1.0), 1.0);
+}
+
+// Keep in sync with glyph_atlas.rs
+const GLYPH_SIZE: vec2<f32> = vec2<f32>(8.0, 16.0);
+const ATLAS_COLUMNS: u32 = 16u;
+const TEXT_STRIDE: u32 = 4u;
+const TEXT_PADDING: f32 = 4.0;
+
+// Coverage of the glyph at `px` (pixels from the text's top-left), 0 outside it
+fn glyph_coverage(code: u32, px: vec2<f32>) -> f32 {
+    if (px.x < 0.0 || px.x >= GLYPH_SIZE.x || px.y < 0.0 || px.y >= GLYPH_SIZE.y) {
+        return 0.0;
+    }
+    let origin = vec2<f32>(f32(code % ATLAS_COLUMNS), f32(code / ATLAS_COLUMNS)) * GLYPH_SIZE;
+    let atlas_size = vec2<f32>(textureDimensions(glyph_atlas));
+    return textureSampleLevel(glyph_atlas, glyph_sampler, (origin + px) / atlas_size, 0.0).r;
+}
+
+// Coverage of a cell's plain text at `px` (pixels from the cell's top-left)
+// Header word: glyph count (bits 0-3), alignment (bits 4-5), bold (bit 6),
+// italic (bit 7); then glyph indices, four per word
+fn text_coverage(base: u32, px: vec2<f32>, cell_size: vec2<f32>) -> f32 {
+    let header = cell_text[base];
+    let len = header & 15u;
+    if (len == 0u) {
+        return 0.0;
+    }
+
+    let text_width = f32(len) * GLYPH_SIZE.x;
+    let align = (header >> 4u) & 3u;
+    var left = TEXT_PADDING;
+    if (align == 1u) {
+        left = (cell_size.x - text_width) * 0.5;
+    } else if (align == 2u) {
+        left = cell_size.x - TEXT_PADDING - text_width;
+    }
+    var local = px - vec2<f32>(left, (cell_size.y - GLYPH_SIZE.y) * 0.5);
+    if ((header & 128u) != 0u) {
+        // Italic: lean the glyphs right
+        local.x -= (GLYPH_SIZE.y - local.y) * 0.2;
+    }
+    if (local.x < 0.0 || local.x >= text_width || local.y < 0.0 || local.y >= GLYPH_SIZE.y) {
+        return 0.0;
+    }
+
+    let i = u32(local.x / GLYPH_SIZE.x);
+    let code = (cell_text[base + 1u + i / 4u] >> ((i % 4u) * 8u)) & 255u;
+    let in_glyph = vec2<f32>(local.x - f32(i) * GLYPH_SIZE.x, local.y);
+    var coverage = glyph_coverage(code, in_glyph);
+    if ((header & 64u) != 0u) {
+        // Bold: smear each glyph a pixel to the right
+        coverage = max(coverage, glyph_coverage(code, in_glyph - vec2<f32>(1.0, 0.0)));
+    }
+    return coverage;
+}
+
+// Resized columns: see layout::ColumnOffsets, which these mirror
+const OFFSET_STRIDE: u32 = 3u;
+
+// Visual column containing world `x`, with its left edge and width
+// Binary search over the resized columns, default widths in between
+fn column_at(x: f32) -> vec3<f32> {
+    let default_width = material.cell_size.x;
+    var lo = 0u;
+    var hi = u32(material.resized_cols);
+    while (lo < hi) {
+        let mid = (lo + hi) / 2u;
+        if (col_offsets[mid * OFFSET_STRIDE + 1u] <= x) {
+            lo = mid + 1u;
+        } else {
+            hi = mid;
+        }
+    }
+    if (lo == 0u) {
+        let col = floor(x / default_width);
+        return vec3<f32>(col, col * default_width, default_width);
+    }
+
+    let base = (lo - 1u) * OFFSET_STRIDE;
+    let resized = vec3<f32>(col_offsets[base], col_offsets[base + 1u], col_offsets[base + 2u]);
+    let end = resized.y + resized.z;
+    if (x < end) {
+        return resized;
+    }
+    let past = floor((x - end) / default_width);
+    return vec3<f32>(resized.x + 1.0 + past, end + past * default_width, default_width);
+}
+
+// Left edge of a visual column
+fn column_left(col: f32) -> f32 {
+    let default_width = material.cell_size.x;
+    var lo = 0u;
+    var hi = u32(material.resized_cols);
+    while (lo < hi) {
+        let mid = (lo + hi) / 2u;
+        if (col_offsets[mid * OFFSET_STRIDE] < col) {
+            lo = mid + 1u;
+        } else {
+            hi = mid;
+        }
+    }
+    if (lo == 0u) {
+        return col * default_width;
+    }
+    let base = (lo - 1u) * OFFSET_STRIDE;
+    return col_offsets[base + 1u] + col_offsets[base + 2u] + (col - col_offsets[base] - 1.0) * default_width;
+}
+
+// Cells in error: diagonal hatching, a badge in the bottom-right corner and
+// the error code (the cell's text) in red
+const ERROR_HATCH: f32 = 8.0; // Pixels per stripe pair
+const ERROR_BADGE: f32 = 9.0;
+
+// Cell size on screen, in pixels, over which minor gridlines fade out
+const MINOR_FADE_START: f32 = 4.0;
+const MINOR_FADE_END: f32 = 12.0;
+
+// Selection outline and copy border, drawn inside the cell's outline edges
+const BORDER_WIDTH: f32 = 2.0;
+const ACTIVE_BORDER_WIDTH: f32 = 3.0;
+const FILL_HANDLE_SIZE: f32 = 6.0;
+const ANTS_DASH: f32 = 4.0; // Pixels per dash and per gap
+const ANTS_SPEED: f32 = 16.0; // Pixels per second
+
+// Whether `px` (pixels from the cell's top-left) is on one of the 4 edge bits
+// (top, right, bottom, left), and if so how far along the sheet it is, for
+// lining dashes up across cells
+fn on_edges(edges: u32, px: vec2<f32>, sheet_px: vec2<f32>, size: vec2<f32>) -> vec2<f32> {
+    if (((edges & 1u) != 0u && px.y < BORDER_WIDTH) || ((edges & 4u) != 0u && px.y > size.y - BORDER_WIDTH)) {
+        return vec2<f32>(1.0, sheet_px.x);
+    }
+    if (((edges & 8u) != 0u && px.x < BORDER_WIDTH) || ((edges & 2u) != 0u && px.x > size.x - BORDER_WIDTH)) {
+        return vec2<f32>(1.0, sheet_px.y);
+    }
+    return vec2<f32>(0.0, 0.0);
+}
+
+// Cell borders: one word per side (top, right, bottom, left; see gpu_cell.rs)
+const BORDER_STRIDE: u32 = 4u;
+const THIN_BORDER: f32 = 1.0;
+const THICK_BORDER: f32 = 3.0;
+const BORDER_DASH: f32 = 3.0;
+
+// Color of the cell's own border at `px` (pixels from the cell's top-left),
+// zero alpha off it
+// Each word: RGB in the low bytes, line in the top byte (1 thin, 2 thick, 3 dashed)
+fn cell_border(base: u32, px: vec2<f32>, sheet_px: vec2<f32>, size: vec2<f32>) -> vec4<f32> {
+    var from_side = array<f32, 4>(px.y, size.x - px.x, size.y - px.y, px.x);
+    var along = array<f32, 4>(sheet_px.x, sheet_px.y, sheet_px.x, sheet_px.y);
+    for (var side = 0u; side < 4u; side++) {
+        let word = cell_borders[base + side];
+        let line = word >> 24u;
+        if (line == 0u) {
+            continue;
+        }
+        // At least a screen pixel, so thin borders survive zooming out
+        let width = max(select(THIN_BORDER, THICK_BORDER, line == 2u), material.pixel_size);
+        if (from_side[side] >= width) {
+            continue;
+        }
+        if (line == 3u && fract(along[side] / (BORDER_DASH * 2.0)) >= 0.5) {
+            continue;
+        }
+        return vec4<f32>(unpack_color((word & 0xffffffu) | 0xff000000u).rgb, 1.0);
+    }
+    return vec4<f32>(0.0);
+}
+
 @fragment
 fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
     // Flip V coordinate: UV (0,0) is top-left, but we want bottom-left for world pos
     let uv_flipped = vec2<f32>(mesh.uv.x, 1.0 - mesh.uv.y);
-    let world_pos = material.viewport_bottom_left + uv_flipped * material.viewport_size;
+    let screen_world = material.viewport_bottom_left + uv_flipped * material.viewport_size;
+    let viewport_top = material.viewport_bottom_left.y + material.viewport_size.y;
+    // World units per screen pixel, taken before any branching so the
+    // derivatives are well defined
+    let pixel_footprint = fwidth(screen_world);
+
+    // Frozen panes: the first N columns/rows stay pinned to the left/top edge,
+    // so those screen regions map back to the sheet origin instead of scrolling
+    let frozen_size = vec2<f32>(column_left(material.frozen_panes.x), material.frozen_panes.y * material.cell_size.y);
+    let from_left = screen_world.x - material.viewport_bottom_left.x;
+    let from_top = viewport_top - screen_world.y;
+    let in_frozen_cols = from_left < frozen_size.x;
+    let in_frozen_rows = from_top < frozen_size.y;
+
+    var world_pos = screen_world;
+    if (in_frozen_cols) {
+        world_pos.x = from_left;
+    }
+    if (in_frozen_rows) {
+        world_pos.y = -from_top;
+    }
+
+    // Pane dividers
+    if ((frozen_size.x > 0.0 && abs(from_left - frozen_size.x) < material.line_width * 2.0) ||
+        (frozen_size.y > 0.0 && abs(from_top - frozen_size.y) < material.line_width * 2.0)) {
+        return vec4<f32>(0.3, 0.3, 0.3, 1.0);
+    }
 
     // Grid Logic
-    let col = i32(floor(world_pos.x / material.cell_size.x));
+    // Columns can be resized, so the cell's own size stands in for cell_size
+    // within it; grid_pos counts columns and rows
+    let column = column_at(world_pos.x);
+    let cell_dims = vec2<f32>(column.z, material.cell_size.y);
+    let col = i32(column.x);
     let row = i32(floor(-world_pos.y / material.cell_size.y));
-    let grid_pos = vec2<f32>(world_pos.x, -world_pos.y) / material.cell_size;
+    let grid_pos = vec2<f32>(column.x + (world_pos.x - column.y) / column.z, -world_pos.y / material.cell_size.y);
     let cell_uv = fract(grid_pos);
-    let dist_to_line = min(cell_uv, 1.0 - cell_uv);
-    
-    let closest_line_idx = vec2<i32>(round(grid_pos));
-    
-    // Determine line thickness multiplier
-    var width_mult = vec2<f32>(1.0, 1.0);
-    
-    // Vertical lines (X-index)
-    if (closest_line_idx.x == 0) {
-        width_mult.x = 2.0; // Axis slightly thicker
-    } else if (closest_line_idx.x % 5 == 0) {
-        width_mult.x = 2.0; // Major line
-    }
-    
-    // Horizontal lines (Y-index)
-    if (closest_line_idx.y == 0) {
-        width_mult.y = 2.0; // Axis slightly thicker
-    } else if (closest_line_idx.y % 5 == 0) {
-        width_mult.y = 2.0; // Major line
-    }
-
-    let line_width_norm = (material.line_width * width_mult) / material.cell_size;
-    
-    let on_vert = dist_to_line.x < line_width_norm.x;
-    let on_horiz = dist_to_line.y < line_width_norm.y;
-
-    if (material.show_grid > 0.5 && (on_vert || on_horiz)) {
-        // Axis Colors
-        // Horizontal Axis (y=0) -> Red
-        if (on_horiz && closest_line_idx.y == 0) {
-            return vec4<f32>(0.8, 0.2, 0.2, 1.0);
-        }
-        // Vertical Axis (x=0) -> Green
-        if (on_vert && closest_line_idx.x == 0) {
-            return vec4<f32>(0.2, 0.8, 0.2, 1.0);
-        }
-        
-        return material.color_line;
-    }
 
     // Calculate viewport-relative coordinates
-    let min_col = i32(floor(material.viewport_bottom_left.x / material.cell_size.x));
+    let min_col = i32(column_at(material.viewport_bottom_left.x).x);
 
     let viewport_top_right = material.viewport_bottom_left + material.viewport_size;
     let min_row = i32(floor(-viewport_top_right.y / material.cell_size.y));
@@ -87,25 +307,189 @@ fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
     let rel_row = row - min_row;
     let width = i32(material.grid_dimensions.x);
     let height = i32(material.grid_dimensions.y);
+    let in_cols = rel_col >= 0 && rel_col < width;
+    let in_rows = rel_row >= 0 && rel_row < height;
+
+    // Buffer layout: scrolling pane, then left pane (frozen columns),
+    // top pane (frozen rows) and the frozen corner (see SheetLayout::viewport_slots)
+    let frozen_cols = i32(material.frozen_panes.x);
+    let frozen_rows = i32(material.frozen_panes.y);
+    let left_start = width * height;
+    let top_start = left_start + height * frozen_cols;
+    let corner_start = top_start + frozen_rows * width;
+
+    var slot = -1;
+    if (in_frozen_cols && in_frozen_rows) {
+        if (col < frozen_cols && row < frozen_rows) {
+            slot = corner_start + row * frozen_cols + col;
+        }
+    } else if (in_frozen_cols) {
+        if (in_rows && col < frozen_cols) {
+            slot = left_start + rel_row * frozen_cols + col;
+        }
+    } else if (in_frozen_rows) {
+        if (in_cols && row < frozen_rows) {
+            slot = top_start + row * width + rel_col;
+        }
+    } else if (in_cols && in_rows) {
+        slot = rel_row * width + rel_col;
+    }
+
+    // Outlines and cell borders go over the grid lines
+    if (slot >= 0 && u32(slot) * CELL_STRIDE + CELL_STRIDE <= arrayLength(&cell_data)) {
+        let outline_flags = cell_data[u32(slot) * CELL_STRIDE];
+        let px = cell_uv * cell_dims;
+        let sheet_px = vec2<f32>(world_pos.x, -world_pos.y);
+
+        // Copy source: dashes marching along the edges
+        let ants = on_edges((outline_flags >> 12u) & 15u, px, sheet_px, cell_dims);
+        if (ants.x > 0.0) {
+            if (fract((ants.y - material.time * ANTS_SPEED) / (ANTS_DASH * 2.0)) < 0.5) {
+                return vec4<f32>(0.05, 0.05, 0.05, 1.0);
+            }
+            return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+        }
+        // Fill handle: a square in the selection's bottom-right corner (bit 19)
+        if ((outline_flags & 524288u) != 0u && all(cell_dims - px < vec2<f32>(FILL_HANDLE_SIZE))) {
+            return vec4<f32>(0.1, 0.35, 0.85, 1.0);
+        }
+        // Active cell: a heavier border all the way round (bit 3)
+        if ((outline_flags & 8u) != 0u) {
+            let from_edge = min(px, cell_dims - px);
+            if (min(from_edge.x, from_edge.y) < ACTIVE_BORDER_WIDTH) {
+                return vec4<f32>(0.02, 0.15, 0.5, 1.0);
+            }
+        }
+        // Selection: a solid outline around each selected range (bits 8-11)
+        if (on_edges((outline_flags >> 8u) & 15u, px, sheet_px, cell_dims).x > 0.0) {
+            return vec4<f32>(0.1, 0.35, 0.85, 1.0);
+        }
+
+        let border_base = u32(slot) * BORDER_STRIDE;
+        if (border_base + BORDER_STRIDE <= arrayLength(&cell_borders)) {
+            let border = cell_border(border_base, px, sheet_px, cell_dims);
+            if (border.a > 0.0) {
+                return border;
+            }
+        }
+    }
+
+    let dist_to_line = min(cell_uv, 1.0 - cell_uv);
+    let closest_line_idx = vec2<i32>(round(grid_pos));
+
+    // Major lines every `major_every` cells (the axes included), minor lines
+    // in between; widths are in screen pixels, measured against the pixel
+    // footprint so lines stay the same width, with soft edges, at any zoom
+    let major_every = max(i32(material.major_every), 1);
+    let is_major = vec2<bool>(closest_line_idx.x % major_every == 0, closest_line_idx.y % major_every == 0);
+    let half_width = select(vec2<f32>(material.line_width), vec2<f32>(material.major_line_width), is_major);
+    let dist_px = dist_to_line * cell_dims / max(pixel_footprint, vec2<f32>(1e-6));
+    let coverage = clamp(half_width + 0.5 - dist_px, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // Partly covered pixels are blended over the cell at the end
+    var line_color = material.color_line;
+    var line_alpha = 0.0;
+    if (material.show_grid > 0.5 && max(coverage.x, coverage.y) > 0.0) {
+        // Minor lines fade out as cells shrink on screen when zoomed far out
+        let cell_px = min(material.cell_size.x, material.cell_size.y) / material.pixel_size;
+        let fade = smoothstep(MINOR_FADE_START, MINOR_FADE_END, cell_px);
+        let alpha = coverage * select(vec2<f32>(fade), vec2<f32>(1.0), is_major);
+
+        // The stronger of the horizontal and vertical line wins
+        let horiz = alpha.y >= alpha.x;
+        line_alpha = select(alpha.x, alpha.y, horiz);
+        if (select(closest_line_idx.x, closest_line_idx.y, horiz) == 0) {
+            // Axes: red horizontal (y=0), green vertical (x=0)
+            line_color = select(vec4<f32>(0.2, 0.8, 0.2, 1.0), vec4<f32>(0.8, 0.2, 0.2, 1.0), horiz);
+        } else if (select(is_major.x, is_major.y, horiz)) {
+            line_color = material.color_major_line;
+        }
+        if (line_alpha >= 1.0) {
+            return line_color;
+        }
+    }
 
     var final_color = material.color_bg;
+    var text_color = vec4<f32>(0.0, 0.0, 0.0, 1.0);
 
     // Check bounds of relative coordinates
-    if (rel_col >= 0 && rel_col < width && rel_row >= 0 && rel_row < height) {
-        let index = u32(rel_row) * u32(width) + u32(rel_col);
+    if (slot >= 0) {
+        let index = u32(slot);
         
-        if (index < arrayLength(&cell_data)) {
-            let cell_flags = cell_data[index];
+        let cell_base = index * CELL_STRIDE;
+        if (cell_base + CELL_STRIDE <= arrayLength(&cell_data)) {
+            let cell_flags = cell_data[cell_base];
             let is_selected = (cell_flags & 1u) != 0u;  // Bit 0
             let is_error = (cell_flags & 4u) != 0u;     // Bit 2
 
+            // Zero alpha: no color of its own
+            // Table header and banding (bits 16-17), then sheet-wide stripes;
+            // a cell's own background wins over both
+            var sheet_bg = material.color_bg;
+            if ((cell_flags & 65536u) != 0u) {
+                sheet_bg = vec4<f32>(0.55, 0.65, 0.8, 1.0);
+            } else if ((cell_flags & 131072u) != 0u) {
+                sheet_bg = vec4<f32>(0.8, 0.86, 0.94, 1.0);
+            } else if (material.stripes > 0.5 && (row % 2) != 0) {
+                sheet_bg = vec4<f32>(0.9, 0.9, 0.9, 1.0);
+            }
+            let background = unpack_color(cell_data[cell_base + 1u]);
+            var cell_bg = mix(sheet_bg, vec4<f32>(background.rgb, 1.0), background.a);
+            // Heatmap lens: numbers (bit 18) take their color from the scale
+            if (material.heatmap > 0.5 && (cell_flags & 262144u) != 0u) {
+                let value = unpack_f64(cell_data[cell_base + 3u], cell_data[cell_base + 4u]);
+                let range = material.heat_range;
+                cell_bg = heat_color((value - range.x) / (range.y - range.x));
+            }
+            final_color = cell_bg;
+            let own_text_color = unpack_color(cell_data[cell_base + 2u]);
+            text_color = mix(text_color, vec4<f32>(own_text_color.rgb, 1.0), own_text_color.a);
+
             if (is_error) {
-                final_color = vec4<f32>(1.0, 0.3, 0.3, 1.0);
+                let px = cell_uv * cell_dims;
+                if (cell_dims.x - px.x + cell_dims.y - px.y < ERROR_BADGE) {
+                    return vec4<f32>(0.8, 0.1, 0.1, 1.0);
+                }
+                // Stripes follow the sheet so they line up across neighbouring cells
+                let hatched = fract((world_pos.x + world_pos.y) / ERROR_HATCH) < 0.5;
+                final_color = select(vec4<f32>(1.0, 0.93, 0.93, 1.0), vec4<f32>(1.0, 0.8, 0.8, 1.0), hatched);
+                if (is_selected) {
+                    final_color = mix(final_color, vec4<f32>(0.2, 0.4, 0.8, 1.0), 0.3);
+                }
+                text_color = vec4<f32>(0.72, 0.11, 0.11, 1.0);
             } else if (is_selected) {
-                final_color = mix(material.color_bg, vec4<f32>(0.2, 0.4, 0.8, 1.0), 0.5);
+                final_color = mix(cell_bg, vec4<f32>(0.2, 0.4, 0.8, 1.0), 0.5);
+            }
+
+            // Hidden rows/columns marker on the edge they collapsed into
+            let marker_size = vec2<f32>(3.0, 3.0) / cell_dims;
+            let hidden_cols_before = (cell_flags & 16u) != 0u; // Bit 4
+            let hidden_rows_before = (cell_flags & 32u) != 0u; // Bit 5
+            if ((hidden_cols_before && cell_uv.x < marker_size.x) || (hidden_rows_before && cell_uv.y < marker_size.y)) {
+                return vec4<f32>(0.9, 0.6, 0.1, 1.0);
+            }
+
+            // Dropdown arrow on filter headers and list-validated cells (right edge)
+            if ((cell_flags & 64u) != 0u) { // Bit 6
+                let px = cell_uv * cell_dims;
+                let tx = abs(px.x - (cell_dims.x - 9.0));
+                let ty = px.y - (cell_dims.y * 0.5 - 3.0);
+                if (ty >= 0.0 && ty <= 6.0 && tx <= 6.0 - ty) {
+                    if ((cell_flags & 128u) != 0u) { // Bit 7: column has an active filter
+                        return vec4<f32>(0.1, 0.4, 0.9, 1.0);
+                    }
+                    return vec4<f32>(0.4, 0.4, 0.4, 1.0);
+                }
             }
         }
 
+        // Plain text, drawn from the glyph atlas
+        let text_base = index * TEXT_STRIDE;
+        if (text_base + TEXT_STRIDE <= arrayLength(&cell_text)) {
+            let coverage = text_coverage(text_base, cell_uv * cell_dims, cell_dims);
+            final_color = mix(final_color, text_color, coverage);
+        }
+
         // Rich Content (SVG) Layer
         if (index < arrayLength(&rich_cell_indices)) {
             let texture_layer = rich_cell_indices[index];
@@ -124,6 +508,20 @@ fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
             }
         }
     }
-    
-    return final_color;
+
+    // Host overlay (see overlay.wgsl)
+    var overlay_flags = 0u;
+    var overlay_word = 0u;
+    if (slot >= 0) {
+        let index = u32(slot);
+        if ((index + 1u) * CELL_STRIDE <= arrayLength(&cell_data)) {
+            overlay_flags = cell_data[index * CELL_STRIDE];
+        }
+        if (index < arrayLength(&overlay_data)) {
+            overlay_word = overlay_data[index];
+        }
+    }
+    final_color = overlay_color(final_color, vec2<i32>(col, row), overlay_flags, overlay_word, cell_uv);
+
+    return mix(final_color, line_color, line_alpha);
 }
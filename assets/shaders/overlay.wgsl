@@ -0,0 +1,13 @@
+// Host hook, called by grid.wgsl for every pixel of a cell after the cell is
+// drawn (gridlines, outlines and the selection still go on top)
+// Embedding apps ship their own copy of this file to overlay data on the grid,
+// e.g. fog of war or an ownership tint in a game
+//   color: the cell's pixel so far
+//   cell:  visual (column, row)
+//   flags: the cell's flags word (see gpu_cell.rs)
+//   data:  the cell's word from `OverlayData`, 0 when it has none
+//   uv:    position within the cell, (0, 0) top-left
+
+fn overlay_color(color: vec4<f32>, cell: vec2<i32>, flags: u32, data: u32, uv: vec2<f32>) -> vec4<f32> {
+    return color;
+}
@@ -0,0 +1,74 @@
+use crate::cell::CellDisplay;
+use crate::formula::coord_to_name;
+use crate::grid_state::GridState;
+
+/// What assistive tech announces for a cell: its name, shown value, and its
+/// formula if it has one ("C1, 42, formula =A0*2")
+pub fn describe_cell(grid: &GridState, col: i32, row: i32) -> String {
+    let name = coord_to_name(col, row);
+    let Some(cell) = grid.get_cell(col, row).filter(|cell| !cell.raw.is_empty()) else {
+        return format!("{}, blank", name);
+    };
+    let value = match cell.display(&grid.theme.resolve(cell.style)) {
+        CellDisplay::Text(text) => text,
+        CellDisplay::Checkbox(true) => "checked".to_string(),
+        CellDisplay::Checkbox(false) => "not checked".to_string(),
+    };
+    let mut description = format!("{}, {}", name, value);
+    if cell.is_formula {
+        description.push_str(&format!(", formula {}", cell.raw.trim()));
+    }
+    if grid.is_locked(col, row) {
+        description.push_str(", locked");
+    }
+    description
+}
+
+/// Have screen readers speak `text`, through an ARIA live region beside the
+/// canvas (native builds go through AccessKit instead, see
+/// `ScreenReaderStatus`)
+#[cfg(target_arch = "wasm32")]
+pub fn announce(text: &str) {
+    web::announce(text);
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(inline_js = r#"
+        export function announce(text) {
+            let region = document.getElementById("gregsheet-live");
+            if (!region) {
+                region = document.createElement("div");
+                region.id = "gregsheet-live";
+                region.setAttribute("role", "status");
+                region.setAttribute("aria-live", "polite");
+                // Offscreen rather than hidden, so it's still read out
+                region.style.cssText = "position:absolute;left:-10000px;width:1px;height:1px;overflow:hidden";
+                document.body.appendChild(region);
+            }
+            region.textContent = text;
+        }
+    "#)]
+    extern "C" {
+        pub fn announce(text: &str);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::Value;
+
+    #[test]
+    fn test_describe_cell() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["7", "= A0 * 6"]]);
+        grid.get_cell_mut(1, 0).unwrap().value = Value::Int(42);
+
+        assert_eq!(describe_cell(&grid, 0, 0), "A0, 7");
+        assert_eq!(describe_cell(&grid, 1, 0), "B0, 42, formula = A0 * 6");
+        assert_eq!(describe_cell(&grid, 2, 5), "C5, blank");
+    }
+}
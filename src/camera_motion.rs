@@ -0,0 +1,160 @@
+use bevy::math::{Rect, Vec2};
+
+/// Where the camera looks and how far it's zoomed out (world units per pixel)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub center: Vec2,
+    pub scale: f32,
+}
+
+impl CameraPose {
+    /// The pose showing all of `area` in a viewport of `viewport` pixels, with
+    /// `margin` (a fraction of the area) to spare
+    pub fn fitting(area: Rect, viewport: Vec2, margin: f32) -> Self {
+        let size = area.size() * (1.0 + 2.0 * margin);
+        Self { center: area.center(), scale: (size / viewport).max_element() }
+    }
+
+    /// Scale multiplied by `factor`, keeping the world point `anchor` in place
+    /// on screen
+    pub fn zoomed_about(self, anchor: Vec2, factor: f32) -> Self {
+        Self { center: anchor + (self.center - anchor) * factor, scale: self.scale * factor }
+    }
+}
+
+/// Limits on where the camera can go, so the view can't get lost in empty cells
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraBounds {
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Room past the used range the view's center may wander into, in world units
+    pub margin: Vec2,
+}
+
+impl Default for CameraBounds {
+    fn default() -> Self {
+        Self { min_scale: 0.1, max_scale: 20.0, margin: Vec2::new(2000.0, 1500.0) }
+    }
+}
+
+impl CameraBounds {
+    /// `pose` pulled back within the limits: zoom clamped, and the center kept
+    /// within the margin of the used range (`used`, None for an empty sheet)
+    /// The origin always counts as used, so the starting view stays in bounds
+    pub fn clamp(&self, pose: CameraPose, used: Option<Rect>) -> CameraPose {
+        let area = used.map_or(Rect::default(), |used| used.union_point(Vec2::ZERO));
+        CameraPose {
+            center: pose.center.clamp(area.min - self.margin, area.max + self.margin),
+            scale: pose.scale.clamp(self.min_scale, self.max_scale),
+        }
+    }
+}
+
+/// How far to move `view` so it contains `target`, scrolling as little as
+/// possible (a target bigger than the view lines up with its top left)
+pub fn scroll_into_view(view: Rect, target: Rect) -> Vec2 {
+    let x = if target.min.x < view.min.x || target.width() > view.width() {
+        target.min.x - view.min.x
+    } else {
+        (target.max.x - view.max.x).max(0.0)
+    };
+    // The top is the larger y
+    let y = if target.max.y > view.max.y || target.height() > view.height() {
+        target.max.y - view.max.y
+    } else {
+        (target.min.y - view.min.y).min(0.0)
+    };
+    Vec2::new(x, y)
+}
+
+/// An eased move between two camera poses
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraTween {
+    from: CameraPose,
+    to: CameraPose,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl CameraTween {
+    pub fn new(from: CameraPose, to: CameraPose, duration: f32) -> Self {
+        Self { from, to, elapsed: 0.0, duration }
+    }
+
+    /// Where the move ends
+    pub fn target(&self) -> CameraPose {
+        self.to
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Move `dt` seconds along and return the pose to show now
+    /// Zoom is interpolated geometrically, so zooming in and out feel the same
+    pub fn advance(&mut self, dt: f32) -> CameraPose {
+        self.elapsed += dt;
+        if self.is_done() {
+            return self.to;
+        }
+        let t = ease_out_cubic(self.elapsed / self.duration);
+        CameraPose {
+            center: self.from.center.lerp(self.to.center, t),
+            scale: self.from.scale * (self.to.scale / self.from.scale).powf(t),
+        }
+    }
+}
+
+/// Fast at first, settling gently onto the target
+fn ease_out_cubic(t: f32) -> f32 {
+    1.0 - (1.0 - t.clamp(0.0, 1.0)).powi(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tween_eases_to_target() {
+        let from = CameraPose { center: Vec2::ZERO, scale: 1.0 };
+        let to = CameraPose { center: Vec2::new(100.0, 0.0), scale: 4.0 };
+        let mut tween = CameraTween::new(from, to, 1.0);
+
+        let halfway = tween.advance(0.5);
+        assert!(!tween.is_done());
+        // Eased out: already past the midpoint in both position and zoom
+        assert!(halfway.center.x > 50.0 && halfway.center.x < 100.0);
+        assert!(halfway.scale > 2.0 && halfway.scale < 4.0);
+        assert_eq!(tween.advance(0.6), to);
+        assert!(tween.is_done());
+
+        // A zero-length move lands at once
+        assert_eq!(CameraTween::new(from, to, 0.0).advance(0.0), to);
+
+        let fit = CameraPose::fitting(Rect::new(0.0, -300.0, 800.0, 0.0), Vec2::new(400.0, 300.0), 0.0);
+        assert_eq!(fit, CameraPose { center: Vec2::new(400.0, -150.0), scale: 2.0 });
+        assert_eq!(fit.zoomed_about(Vec2::new(0.0, -150.0), 0.5), CameraPose { center: Vec2::new(200.0, -150.0), scale: 1.0 });
+    }
+
+    #[test]
+    fn test_bounds_clamp_zoom_and_position() {
+        let bounds = CameraBounds { min_scale: 0.5, max_scale: 4.0, margin: Vec2::splat(100.0) };
+        let used = Rect::new(0.0, -300.0, 800.0, 0.0);
+        let lost = CameraPose { center: Vec2::new(5000.0, 50.0), scale: 10.0 };
+        assert_eq!(bounds.clamp(lost, Some(used)), CameraPose { center: Vec2::new(900.0, 50.0), scale: 4.0 });
+
+        // An empty sheet keeps the view near the origin
+        let pose = CameraPose { center: Vec2::new(-500.0, -20.0), scale: 0.1 };
+        assert_eq!(bounds.clamp(pose, None), CameraPose { center: Vec2::new(-100.0, -20.0), scale: 0.5 });
+    }
+
+    #[test]
+    fn test_scroll_into_view() {
+        let view = Rect::new(0.0, -300.0, 400.0, 0.0);
+        assert_eq!(scroll_into_view(view, Rect::new(80.0, -60.0, 160.0, -30.0)), Vec2::ZERO);
+        // Past the right and bottom edges, just far enough to show it
+        assert_eq!(scroll_into_view(view, Rect::new(400.0, -330.0, 480.0, -300.0)), Vec2::new(80.0, -30.0));
+        // Above and to the left
+        assert_eq!(scroll_into_view(view, Rect::new(-80.0, 30.0, 0.0, 60.0)), Vec2::new(-80.0, 60.0));
+    }
+}
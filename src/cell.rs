@@ -1,18 +1,277 @@
 use evalexpr::Value;
+use serde::{Deserialize, Serialize};
+
+/// Horizontal placement of the cell's text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HorizontalAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+/// How a numeric value is displayed (the stored value is unaffected)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberFormat {
+    /// Integers as-is, floats to two decimals
+    #[default]
+    General,
+    /// Fixed number of decimal places
+    Fixed(u8),
+    /// `$1,234.50`
+    Currency,
+    /// `0.125` -> `12.5%`
+    Percent,
+    /// `12345` -> `1.23E4`
+    Scientific,
+    /// Seconds as a duration: `4980` -> `1h 23m`
+    Duration,
+}
+
+/// How a value is drawn in its cell
+#[derive(Clone, Debug, PartialEq)]
+pub enum CellDisplay {
+    Text(String),
+    /// Booleans are drawn as a checkbox (ticked when true)
+    Checkbox(bool),
+}
+
+impl NumberFormat {
+    /// Formats offered by the toolbar, in cycling order
+    pub const CYCLE: [NumberFormat; 6] = [
+        NumberFormat::General,
+        NumberFormat::Fixed(0),
+        NumberFormat::Currency,
+        NumberFormat::Percent,
+        NumberFormat::Scientific,
+        NumberFormat::Duration,
+    ];
+
+    /// Next format in `CYCLE`, wrapping around
+    pub fn next(self) -> Self {
+        let index = Self::CYCLE.iter().position(|f| *f == self).map_or(0, |i| i + 1);
+        Self::CYCLE[index % Self::CYCLE.len()]
+    }
+
+    /// Display string for a value; non-numeric values ignore the format
+    pub fn format_value(self, value: &Value) -> String {
+        let n = match value {
+            Value::Int(i) if self == NumberFormat::General => return i.to_string(),
+            Value::Int(i) => *i as f64,
+            Value::Float(f) => *f,
+            Value::String(s) => return s.clone(),
+            Value::Boolean(b) => return b.to_string(),
+            Value::Empty => return String::new(),
+            Value::Tuple(_) => return "Tuple".to_string(),
+        };
+
+        match self {
+            NumberFormat::General => format!("{:.2}", n),
+            NumberFormat::Fixed(decimals) => format!("{:.*}", decimals as usize, n),
+            NumberFormat::Currency => {
+                let sign = if n < 0.0 { "-" } else { "" };
+                let fixed = format!("{:.2}", n.abs());
+                let (whole, cents) = fixed.split_once('.').unwrap_or((&fixed, "00"));
+                format!("{}${}.{}", sign, group_thousands(whole), cents)
+            }
+            NumberFormat::Percent => format!("{:.1}%", n * 100.0),
+            NumberFormat::Scientific => format!("{:.2E}", n),
+            NumberFormat::Duration => format_duration(n),
+        }
+    }
+
+    /// What a renderer should draw for a value
+    pub fn display(self, value: &Value) -> CellDisplay {
+        match value {
+            Value::Boolean(b) => CellDisplay::Checkbox(*b),
+            _ => CellDisplay::Text(self.format_value(value)),
+        }
+    }
+}
+
+/// Seconds as the two largest units: "1h 23m", "2d 5h", "45s"
+fn format_duration(seconds: f64) -> String {
+    const UNITS: [(u64, &str); 4] = [(86_400, "d"), (3_600, "h"), (60, "m"), (1, "s")];
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let total = seconds.abs().round() as u64;
+
+    let first = UNITS.iter().position(|(size, _)| total >= *size).unwrap_or(UNITS.len() - 1);
+    let (size, unit) = UNITS[first];
+    let mut out = format!("{}{}{}", sign, total / size, unit);
+    if let Some((next_size, next_unit)) = UNITS.get(first + 1) {
+        let rest = total % size / next_size;
+        if rest > 0 {
+            out.push_str(&format!(" {}{}", rest, next_unit));
+        }
+    }
+    out
+}
+
+/// Insert `,` between groups of three digits: "1234567" -> "1,234,567"
+fn group_thousands(digits: &str) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// How one side of a cell's border is drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BorderLine {
+    #[default]
+    None,
+    Thin,
+    Thick,
+    Dashed,
+}
+
+impl BorderLine {
+    /// Next line in the toolbar's cycle, wrapping back to none
+    pub fn next(self) -> Self {
+        match self {
+            BorderLine::None => BorderLine::Thin,
+            BorderLine::Thin => BorderLine::Thick,
+            BorderLine::Thick => BorderLine::Dashed,
+            BorderLine::Dashed => BorderLine::None,
+        }
+    }
+}
+
+/// One side of a cell's border
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BorderSide {
+    pub line: BorderLine,
+    /// RGB line color, None for black
+    pub color: Option<[u8; 3]>,
+}
+
+/// Border drawn along each side of a cell, inside its bounds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Borders {
+    pub top: BorderSide,
+    pub right: BorderSide,
+    pub bottom: BorderSide,
+    pub left: BorderSide,
+}
+
+/// Visual formatting of a cell, independent of its contents
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CellStyle {
+    pub bold: bool,
+    pub italic: bool,
+    /// RGB text color, None for the default
+    pub text_color: Option<[u8; 3]>,
+    /// RGB background color, None for the default
+    pub background: Option<[u8; 3]>,
+    pub align: HorizontalAlign,
+    pub number_format: NumberFormat,
+    /// Per-side borders, kept when following a named style
+    #[serde(deserialize_with = "crate::persist::since::v5")]
+    pub borders: Borders,
+    /// Edits are refused while the sheet is protected
+    pub locked: bool,
+    /// Named style (theme ID) this cell follows; while set, the formatting
+    /// fields above (borders aside) are ignored in favour of the theme's (see `Theme::resolve`)
+    pub named: Option<u32>,
+}
+
+/// Spreadsheet-style error a cell shows in place of its value
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    DivZero,
+    Ref,
+    Name,
+    Value,
+    Num,
+    NotAvailable,
+    #[default]
+    Other,
+}
+
+impl ErrorCode {
+    const ALL: [ErrorCode; 7] = [
+        ErrorCode::DivZero,
+        ErrorCode::Ref,
+        ErrorCode::Name,
+        ErrorCode::Value,
+        ErrorCode::Num,
+        ErrorCode::NotAvailable,
+        ErrorCode::Other,
+    ];
+
+    pub fn text(self) -> &'static str {
+        match self {
+            ErrorCode::DivZero => "#DIV/0!",
+            ErrorCode::Ref => "#REF!",
+            ErrorCode::Name => "#NAME?",
+            ErrorCode::Value => "#VALUE!",
+            ErrorCode::Num => "#NUM!",
+            ErrorCode::NotAvailable => "#N/A",
+            ErrorCode::Other => "#ERROR!",
+        }
+    }
+
+    /// The code spelled `text` (as Excel writes them), if any
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|code| code.text().eq_ignore_ascii_case(text.trim()))
+    }
+
+    /// Which error a formula failed with, from its source and the evaluator's message
+    pub fn classify(raw: &str, message: &str) -> Self {
+        let message = message.to_lowercase();
+        if raw.contains(ErrorCode::Ref.text()) {
+            ErrorCode::Ref
+        } else if message.contains("dividing") || message.contains("division") {
+            ErrorCode::DivZero
+        } else if message.contains("waiting for") {
+            // A page-computed function with no answer yet (see `HostFunctions`)
+            ErrorCode::NotAvailable
+        } else if message.contains("not bound") || message.contains("unknown") {
+            ErrorCode::Name
+        } else if message.contains("expected") {
+            ErrorCode::Value
+        } else {
+            ErrorCode::Other
+        }
+    }
+}
+
+/// The persistent part of a cell (what undo, copy and move carry around);
+/// everything else on `Cell` is recomputed from it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CellContent {
+    pub raw: String,
+    pub style: CellStyle,
+}
 
 /// Represents a single spreadsheet cell on the CPU side
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Cell {
     /// The raw source text: "= A0 + B0" or "100"
     pub raw: String,
-    /// The computed result
+    /// The computed result (saved too: formulas like counters carry state between ticks)
+    #[serde(with = "crate::persist::value_serde")]
     pub value: Value,
     /// True if the content starts with '='
     pub is_formula: bool,
     /// True if evalexpr returned an error
     pub error: bool,
+    /// What went wrong, while `error` is set
+    #[serde(deserialize_with = "crate::persist::since::v6")]
+    pub error_code: ErrorCode,
     /// Hash of the SVG content for caching
+    #[serde(skip)]
     pub content_hash: Option<u64>,
+    /// Formatting (bold, colors, alignment)
+    pub style: CellStyle,
 }
 
 impl Default for Cell {
@@ -22,7 +281,9 @@ impl Default for Cell {
             value: Value::Int(0),
             is_formula: false,
             error: false,
+            error_code: ErrorCode::Other,
             content_hash: None,
+            style: CellStyle::default(),
         }
     }
 }
@@ -36,7 +297,27 @@ impl Cell {
             value: Value::Int(0),
             is_formula,
             error: false,
+            error_code: ErrorCode::Other,
             content_hash: None,
+            style: CellStyle::default(),
+        }
+    }
+
+    /// Snapshot of the persistent contents
+    pub fn content(&self) -> CellContent {
+        CellContent {
+            raw: self.raw.clone(),
+            style: self.style,
+        }
+    }
+
+    /// What renderers draw: the error code while in error, else the formatted value
+    /// `style` is the cell's resolved style (see `Theme::resolve`)
+    pub fn display(&self, style: &CellStyle) -> CellDisplay {
+        if self.error {
+            CellDisplay::Text(self.error_code.text().to_string())
+        } else {
+            style.number_format.display(&self.value)
         }
     }
 
@@ -47,3 +328,62 @@ impl Cell {
         self.error = false;
     }
 }
+
+/// Parse a literal (non-formula) value
+/// Tries to parse as number first (Int or Float), then `true`/`false`, else String
+pub fn parse_literal(raw: &str) -> Value {
+    if let Ok(i) = raw.trim().parse::<i64>() {
+        Value::Int(i)
+    } else if let Ok(f) = raw.trim().parse::<f64>() {
+        Value::Float(f)
+    } else if let Ok(b) = raw.trim().parse::<bool>() {
+        Value::Boolean(b)
+    } else {
+        Value::String(raw.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_formats() {
+        assert_eq!(NumberFormat::General.format_value(&Value::Int(7)), "7");
+        assert_eq!(NumberFormat::General.format_value(&Value::Float(1.0 / 3.0)), "0.33");
+        assert_eq!(NumberFormat::Fixed(0).format_value(&Value::Float(2.6)), "3");
+        assert_eq!(NumberFormat::Currency.format_value(&Value::Float(1234567.5)), "$1,234,567.50");
+        assert_eq!(NumberFormat::Currency.format_value(&Value::Int(-42)), "-$42.00");
+        assert_eq!(NumberFormat::Percent.format_value(&Value::Float(0.125)), "12.5%");
+        assert_eq!(NumberFormat::Scientific.format_value(&Value::Int(12345)), "1.23E4");
+        // Text is shown as-is whatever the format
+        assert_eq!(NumberFormat::Percent.format_value(&Value::String("x".into())), "x");
+    }
+
+    #[test]
+    fn test_rich_display() {
+        assert_eq!(NumberFormat::Duration.format_value(&Value::Int(4980)), "1h 23m");
+        assert_eq!(NumberFormat::Duration.format_value(&Value::Int(3600)), "1h");
+        assert_eq!(NumberFormat::Duration.format_value(&Value::Float(45.4)), "45s");
+        assert_eq!(NumberFormat::Duration.format_value(&Value::Int(-190_000)), "-2d 4h");
+        assert_eq!(NumberFormat::Duration.format_value(&Value::Int(0)), "0s");
+
+        assert_eq!(NumberFormat::General.display(&Value::Boolean(true)), CellDisplay::Checkbox(true));
+        assert_eq!(NumberFormat::General.display(&Value::Int(3)), CellDisplay::Text("3".into()));
+        assert_eq!(parse_literal("false"), Value::Boolean(false));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(ErrorCode::parse("#div/0!"), Some(ErrorCode::DivZero));
+        assert_eq!(ErrorCode::parse("#NULL!"), None);
+        assert_eq!(ErrorCode::classify("= 1 / A0", "Error dividing 1 / 0"), ErrorCode::DivZero);
+        assert_eq!(ErrorCode::classify("= #REF! + 1", "anything"), ErrorCode::Ref);
+        assert_eq!(ErrorCode::classify("= [Qty]0", "unknown column [Qty]"), ErrorCode::Name);
+
+        let mut cell = Cell::new("= 1 / 0".to_string());
+        cell.error = true;
+        cell.error_code = ErrorCode::DivZero;
+        assert_eq!(cell.display(&CellStyle::default()), CellDisplay::Text("#DIV/0!".into()));
+    }
+}
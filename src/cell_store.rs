@@ -0,0 +1,373 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::ops::Index;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::cell::Cell;
+use crate::grid_state::CellRange;
+
+/// Width and height of a storage chunk, in cells
+pub const CHUNK_SIZE: i32 = 32;
+
+/// The cells of one CHUNK_SIZE x CHUNK_SIZE block
+/// Only occupied slots are stored, sorted by slot (row-major), so a chunk
+/// with a handful of cells costs a handful of entries
+#[derive(Clone, Debug, Default)]
+struct Chunk {
+    cells: Vec<(u16, Cell)>,
+}
+
+impl Chunk {
+    /// Index of `slot` in `cells`, or where it would go
+    fn find(&self, slot: usize) -> Result<usize, usize> {
+        self.cells.binary_search_by_key(&(slot as u16), |(s, _)| *s)
+    }
+
+    fn get(&self, slot: usize) -> Option<&Cell> {
+        self.find(slot).ok().map(|i| &self.cells[i].1)
+    }
+
+    /// Cells of this chunk, with their coordinates
+    fn iter(&self, key: (i32, i32)) -> impl Iterator<Item = ((i32, i32), &Cell)> {
+        self.cells.iter().map(move |(slot, cell)| (coord(key, *slot as usize), cell))
+    }
+
+    /// This chunk's cells in one row, left to right
+    fn row(&self, key: (i32, i32), row: i32) -> impl Iterator<Item = ((i32, i32), &Cell)> {
+        let first = (row.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE) as u16;
+        let start = self.cells.partition_point(|(slot, _)| *slot < first);
+        let end = self.cells.partition_point(|(slot, _)| *slot < first + CHUNK_SIZE as u16);
+        self.cells[start..end].iter().map(move |(slot, cell)| (coord(key, *slot as usize), cell))
+    }
+}
+
+/// Chunk key and slot index of a cell coordinate (works for negative coordinates)
+fn locate(col: i32, row: i32) -> ((i32, i32), usize) {
+    let chunk = (col.div_euclid(CHUNK_SIZE), row.div_euclid(CHUNK_SIZE));
+    let slot = row.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + col.rem_euclid(CHUNK_SIZE);
+    (chunk, slot as usize)
+}
+
+/// Source of `CellStore::revision` stamps, unique across every store
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Cell coordinate of a slot in a chunk
+fn coord(chunk: (i32, i32), slot: usize) -> (i32, i32) {
+    let slot = slot as i32;
+    (chunk.0 * CHUNK_SIZE + slot % CHUNK_SIZE, chunk.1 * CHUNK_SIZE + slot / CHUNK_SIZE)
+}
+
+/// Sparse cell storage in 32x32 chunks
+/// Keeps the `HashMap<(i32, i32), Cell>` API the rest of the code uses, but
+/// neighbouring cells share a chunk so rectangular scans (viewport buffers,
+/// SVG rendering) cost one hash lookup per chunk instead of one per cell
+/// Clones share their chunks until either side writes to one (copy on write),
+/// so snapshots for the eval worker and tick history only copy the chunks
+/// that change
+#[derive(Clone, Debug, Default)]
+pub struct CellStore {
+    chunks: HashMap<(i32, i32), Arc<Chunk>>,
+    len: usize,
+    /// Stamp of the last change to the cells besides their evaluated state
+    revision: u64,
+}
+
+impl CellStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &(i32, i32)) -> Option<&Cell> {
+        let (chunk, slot) = locate(key.0, key.1);
+        self.chunks.get(&chunk)?.get(slot)
+    }
+
+    pub fn get_mut(&mut self, key: &(i32, i32)) -> Option<&mut Cell> {
+        let (chunk, slot) = locate(key.0, key.1);
+        let chunk = self.chunks.get_mut(&chunk)?;
+        let index = chunk.find(slot).ok()?;
+        self.revision = next_revision();
+        Some(&mut Arc::make_mut(chunk).cells[index].1)
+    }
+
+    /// `get_mut` for writing a cell's evaluated state (value and error) only,
+    /// which leaves the revision alone; raw text and style go through `get_mut`
+    pub fn get_evaluated_mut(&mut self, key: &(i32, i32)) -> Option<&mut Cell> {
+        let (chunk, slot) = locate(key.0, key.1);
+        let chunk = self.chunks.get_mut(&chunk)?;
+        let index = chunk.find(slot).ok()?;
+        Some(&mut Arc::make_mut(chunk).cells[index].1)
+    }
+
+    /// Changes whenever a cell is added, removed or borrowed through `get_mut`,
+    /// but not when ticks write values; equal revisions mean the same raw text,
+    /// so work derived from the formulas can be kept between ticks
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Insert a cell, returning the one it replaced
+    pub fn insert(&mut self, key: (i32, i32), cell: Cell) -> Option<Cell> {
+        let (chunk, slot) = locate(key.0, key.1);
+        self.revision = next_revision();
+        let chunk = Arc::make_mut(self.chunks.entry(chunk).or_default());
+        match chunk.find(slot) {
+            Ok(index) => Some(std::mem::replace(&mut chunk.cells[index].1, cell)),
+            Err(index) => {
+                chunk.cells.insert(index, (slot as u16, cell));
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Remove a cell; chunks are freed once they're empty
+    pub fn remove(&mut self, key: &(i32, i32)) -> Option<Cell> {
+        let (chunk_key, slot) = locate(key.0, key.1);
+        let chunk = self.chunks.get_mut(&chunk_key)?;
+        let index = chunk.find(slot).ok()?;
+        let chunk = Arc::make_mut(chunk);
+        let (_, old) = chunk.cells.remove(index);
+        self.len -= 1;
+        self.revision = next_revision();
+        if chunk.cells.is_empty() {
+            self.chunks.remove(&chunk_key);
+        }
+        Some(old)
+    }
+
+    /// Mutable access to a cell, creating an empty one if needed
+    pub fn get_or_insert_default(&mut self, key: (i32, i32)) -> &mut Cell {
+        self.revision = next_revision();
+        let (chunk, slot) = locate(key.0, key.1);
+        let chunk = Arc::make_mut(self.chunks.entry(chunk).or_default());
+        let index = match chunk.find(slot) {
+            Ok(index) => index,
+            Err(index) => {
+                chunk.cells.insert(index, (slot as u16, Cell::default()));
+                self.len += 1;
+                index
+            }
+        };
+        &mut chunk.cells[index].1
+    }
+
+    /// All cells, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), &Cell)> {
+        self.chunks.iter().flat_map(|(&key, chunk)| chunk.iter(key))
+    }
+
+    /// All occupied coordinates, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Cells in the chunks this store no longer shares with `base`: for a
+    /// clone of `base`, every cell either side could have written since
+    pub fn diverged_from(&self, base: &CellStore) -> Vec<((i32, i32), &Cell)> {
+        self.chunks
+            .iter()
+            .filter(|(key, chunk)| !base.chunks.get(key).is_some_and(|other| Arc::ptr_eq(chunk, other)))
+            .flat_map(|(&key, chunk)| chunk.iter(key))
+            .collect()
+    }
+
+    /// Lookup helper for scans that visit neighbouring cells in sequence
+    pub fn reader(&self) -> ChunkReader<'_> {
+        ChunkReader { store: self, cached: None }
+    }
+
+    /// Cells inside a range, row-major
+    /// Only chunks holding cells are visited, so huge ranges over a sparse
+    /// sheet cost what's in them rather than their area
+    pub fn region(&self, range: CellRange) -> impl Iterator<Item = ((i32, i32), &Cell)> {
+        let (low, _) = locate(range.min_col, range.min_row);
+        let (high, _) = locate(range.max_col, range.max_row);
+        let span = (high.0 - low.0 + 1) as i64 * (high.1 - low.1 + 1) as i64;
+        // Probe the range's chunks, or scan the stored ones if there are fewer
+        let mut chunks: Vec<((i32, i32), &Chunk)> = if span <= self.chunks.len() as i64 {
+            (low.1..=high.1)
+                .flat_map(|chunk_row| (low.0..=high.0).map(move |chunk_col| (chunk_col, chunk_row)))
+                .filter_map(|key| Some((key, self.chunks.get(&key)?.as_ref())))
+                .collect()
+        } else {
+            self.chunks
+                .iter()
+                .filter(|(key, _)| (low.0..=high.0).contains(&key.0) && (low.1..=high.1).contains(&key.1))
+                .map(|(&key, chunk)| (key, chunk.as_ref()))
+                .collect()
+        };
+        chunks.sort_by_key(|((chunk_col, chunk_row), _)| (*chunk_row, *chunk_col));
+
+        // A band of chunks at a time, each row across the whole band
+        let mut cells = Vec::new();
+        for band in chunks.chunk_by(|a, b| a.0 .1 == b.0 .1) {
+            let chunk_row = band[0].0 .1;
+            let top = (chunk_row * CHUNK_SIZE).max(range.min_row);
+            let bottom = (chunk_row * CHUNK_SIZE + CHUNK_SIZE - 1).min(range.max_row);
+            for row in top..=bottom {
+                for &(key, chunk) in band {
+                    cells.extend(chunk.row(key, row).filter(|((col, _), _)| (range.min_col..=range.max_col).contains(col)));
+                }
+            }
+        }
+        cells.into_iter()
+    }
+}
+
+/// Cached chunk lookup: consecutive reads within one chunk skip the hash lookup
+pub struct ChunkReader<'a> {
+    store: &'a CellStore,
+    cached: Option<((i32, i32), Option<&'a Chunk>)>,
+}
+
+impl<'a> ChunkReader<'a> {
+    pub fn get(&mut self, col: i32, row: i32) -> Option<&'a Cell> {
+        let (key, slot) = locate(col, row);
+        let chunk = match self.cached {
+            Some((cached_key, chunk)) if cached_key == key => chunk,
+            _ => {
+                let chunk = self.store.chunks.get(&key).map(Arc::as_ref);
+                self.cached = Some((key, chunk));
+                chunk
+            }
+        };
+        chunk?.get(slot)
+    }
+}
+
+impl Index<&(i32, i32)> for CellStore {
+    type Output = Cell;
+
+    fn index(&self, key: &(i32, i32)) -> &Cell {
+        self.get(key).expect("no cell at coordinate")
+    }
+}
+
+impl<'a> IntoIterator for &'a CellStore {
+    type Item = ((i32, i32), &'a Cell);
+    type IntoIter = Box<dyn Iterator<Item = ((i32, i32), &'a Cell)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl IntoIterator for CellStore {
+    type Item = ((i32, i32), Cell);
+    type IntoIter = Box<dyn Iterator<Item = ((i32, i32), Cell)>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.chunks.into_iter().flat_map(|(key, chunk)| {
+            Arc::unwrap_or_clone(chunk)
+                .cells
+                .into_iter()
+                .map(move |(slot, cell)| (coord(key, slot as usize), cell))
+        }))
+    }
+}
+
+impl FromIterator<((i32, i32), Cell)> for CellStore {
+    fn from_iter<I: IntoIterator<Item = ((i32, i32), Cell)>>(iter: I) -> Self {
+        let mut store = CellStore::new();
+        for (key, cell) in iter {
+            store.insert(key, cell);
+        }
+        store
+    }
+}
+
+/// Saved as a flat row-major list of `[[col, row], cell]` so files diff cleanly
+impl Serialize for CellStore {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<((i32, i32), &Cell)> = self.iter().collect();
+        entries.sort_by_key(|((col, row), _)| (*row, *col));
+        entries.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CellStore {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<((i32, i32), Cell)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_boundaries_and_negative_coords() {
+        let mut store = CellStore::new();
+        for key in [(0, 0), (31, 31), (32, 0), (-1, -1), (-33, 40)] {
+            store.get_or_insert_default(key).raw = format!("{:?}", key);
+        }
+        assert_eq!(store.len(), 5);
+        assert_eq!(store[&(-1, -1)].raw, "(-1, -1)");
+        assert_eq!(store.get(&(-33, 40)).unwrap().raw, "(-33, 40)");
+        assert!(store.get(&(1, 0)).is_none());
+
+        let mut keys: Vec<(i32, i32)> = store.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec![(-33, 40), (-1, -1), (0, 0), (31, 31), (32, 0)]);
+
+        let region: Vec<(i32, i32)> = store.region(CellRange::new((-1, -1), (40, 0))).map(|(k, _)| k).collect();
+        assert_eq!(region, vec![(-1, -1), (0, 0), (32, 0)]);
+        // Huge ranges only cost the chunks that hold cells
+        let everything: Vec<(i32, i32)> =
+            store.region(CellRange::new((-1 << 24, -1 << 24), (1 << 24, 1 << 24))).map(|(k, _)| k).collect();
+        assert_eq!(everything, vec![(-1, -1), (0, 0), (32, 0), (31, 31), (-33, 40)]);
+
+        assert!(store.remove(&(32, 0)).is_some());
+        assert!(store.remove(&(32, 0)).is_none());
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.chunks.len(), 3, "empty chunks are freed");
+    }
+
+    #[test]
+    fn test_clones_share_chunks_until_written() {
+        let mut store = CellStore::new();
+        for key in [(0, 0), (40, 0), (0, 40)] {
+            store.get_or_insert_default(key).raw = "1".to_string();
+        }
+        let mut snapshot = store.clone();
+        assert!(snapshot.diverged_from(&store).is_empty());
+
+        // Reads and misses don't unshare anything
+        assert!(snapshot.get_mut(&(1, 0)).is_none());
+        assert!(snapshot.diverged_from(&store).is_empty());
+
+        snapshot.get_mut(&(40, 0)).unwrap().raw = "2".to_string();
+        let diverged: Vec<(i32, i32)> = snapshot.diverged_from(&store).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(diverged, vec![(40, 0)]);
+        assert_eq!(store[&(40, 0)].raw, "1");
+    }
+
+    #[test]
+    fn test_revision_ignores_value_writes() {
+        let mut store = CellStore::new();
+        store.get_or_insert_default((0, 0)).raw = "= 1".to_string();
+        let revision = store.revision();
+        let snapshot = store.clone();
+        assert_eq!(snapshot.revision(), revision);
+
+        store.get_evaluated_mut(&(0, 0)).unwrap().value = evalexpr::Value::Int(1);
+        assert_eq!(store.revision(), revision);
+        store.get_mut(&(0, 0)).unwrap().raw = "= 2".to_string();
+        assert_ne!(store.revision(), revision);
+        assert_ne!(store.revision(), snapshot.revision());
+    }
+}
@@ -0,0 +1,176 @@
+use evalexpr::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::grid_state::{CellRange, GridState};
+
+/// Size of a new chart, in cells (columns, rows)
+const DEFAULT_SIZE: (i32, i32) = (5, 10);
+/// Space around the plot area, in pixels
+const PADDING: f32 = 24.0;
+/// Series colors, in column order
+const PALETTE: [&str; 5] = ["#1565c0", "#e65100", "#2e7d32", "#6a1b9a", "#c62828"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChartKind {
+    Line,
+    Bar,
+    Scatter,
+}
+
+/// A chart floating over the grid, plotting a range of cells
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chart {
+    pub kind: ChartKind,
+    /// One series per column, one point per row
+    /// Scatter charts take x from the first column when there's more than one
+    pub source: CellRange,
+    /// Cell under the chart's top-left corner
+    pub anchor: (i32, i32),
+    /// Size in cells (columns, rows)
+    pub size: (i32, i32),
+}
+
+impl Chart {
+    /// A chart of `source`, placed just right of it
+    pub fn new(kind: ChartKind, source: CellRange) -> Self {
+        Self { kind, source, anchor: (source.max_col + 2, source.min_row), size: DEFAULT_SIZE }
+    }
+
+    /// The source's numbers, column by column (None where a cell isn't a number)
+    pub fn series(&self, grid: &GridState) -> Vec<Vec<Option<f64>>> {
+        (self.source.min_col..=self.source.max_col)
+            .map(|col| {
+                (self.source.min_row..=self.source.max_row)
+                    .map(|row| match grid.get_cell(col, row).map(|cell| &cell.value) {
+                        Some(Value::Int(i)) => Some(*i as f64),
+                        Some(Value::Float(f)) if f.is_finite() => Some(*f),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Draw the chart as SVG, `width`x`height` pixels
+    pub fn to_svg(&self, grid: &GridState, width: f32, height: f32) -> String {
+        let mut series = self.series(grid);
+        let xs: Vec<Option<f64>> = if self.kind == ChartKind::Scatter && series.len() > 1 {
+            series.remove(0)
+        } else {
+            (0..self.source.height()).map(|i| Some(i as f64)).collect()
+        };
+
+        let ys = series.iter().flatten().flatten().copied();
+        // Bars grow from zero, so zero is always on the axis
+        let (y_min, y_max) = if self.kind == ChartKind::Bar { span(ys.chain([0.0])) } else { span(ys) };
+        let (x_min, x_max) = span(xs.iter().flatten().copied());
+        let plot_w = width - 2.0 * PADDING;
+        let plot_h = height - 2.0 * PADDING;
+        let px = |x: f64| PADDING + ((x - x_min) / (x_max - x_min)) as f32 * plot_w;
+        let py = |y: f64| height - PADDING - ((y - y_min) / (y_max - y_min)) as f32 * plot_h;
+
+        let mut body = String::new();
+        for (i, values) in series.iter().enumerate() {
+            let color = PALETTE[i % PALETTE.len()];
+            match self.kind {
+                ChartKind::Line => {
+                    // A blank cell breaks the line
+                    let mut path = String::new();
+                    let mut pen_down = false;
+                    for (x, y) in xs.iter().zip(values) {
+                        match (x, y) {
+                            (Some(x), Some(y)) => {
+                                path.push_str(&format!("{}{:.1},{:.1} ", if pen_down { "L" } else { "M" }, px(*x), py(*y)));
+                                pen_down = true;
+                            }
+                            _ => pen_down = false,
+                        }
+                    }
+                    body.push_str(&format!(r#"<path d="{}" fill="none" stroke="{}" stroke-width="2"/>"#, path.trim_end(), color));
+                }
+                ChartKind::Bar => {
+                    let slot = plot_w / values.len().max(1) as f32;
+                    let bar = slot * 0.8 / series.len() as f32;
+                    for (row, y) in values.iter().enumerate() {
+                        let Some(y) = y else { continue };
+                        let x = PADDING + row as f32 * slot + slot * 0.1 + i as f32 * bar;
+                        let (top, bottom) = (py(y.max(0.0)), py(y.min(0.0)));
+                        body.push_str(&format!(
+                            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#,
+                            x,
+                            top,
+                            bar,
+                            bottom - top,
+                            color
+                        ));
+                    }
+                }
+                ChartKind::Scatter => {
+                    for (x, y) in xs.iter().zip(values) {
+                        if let (Some(x), Some(y)) = (x, y) {
+                            body.push_str(&format!(r#"<circle cx="{:.1}" cy="{:.1}" r="3" fill="{}"/>"#, px(*x), py(*y), color));
+                        }
+                    }
+                }
+            }
+        }
+
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}">"#,
+                r##"<rect width="{w}" height="{h}" fill="white" stroke="#90a4ae"/>"##,
+                r##"<path d="M{l},{t} V{b} H{r}" fill="none" stroke="#607d8b"/>"##,
+                r##"<g font-family="sans-serif" font-size="10" fill="#455a64">"##,
+                r#"<text x="4" y="{t}">{y_max}</text><text x="4" y="{b}">{y_min}</text></g>"#,
+                "{body}</svg>"
+            ),
+            w = width,
+            h = height,
+            l = PADDING,
+            t = PADDING,
+            b = height - PADDING,
+            r = width - PADDING,
+            y_max = y_max,
+            y_min = y_min,
+            body = body,
+        )
+    }
+}
+
+/// (min, max) of some values, widened when empty or flat so it can be divided by
+fn span(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if !min.is_finite() {
+        (0.0, 1.0)
+    } else if max > min {
+        (min, max)
+    } else {
+        (min - 0.5, max + 0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chart_plots_its_source() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1", "10"], ["2", "x"], ["3", "30"]]);
+        let source = CellRange::new((0, 0), (1, 2));
+        let line = Chart::new(ChartKind::Line, source);
+        assert_eq!(line.anchor, (3, 0));
+        assert_eq!(line.series(&grid), vec![vec![Some(1.0), Some(2.0), Some(3.0)], vec![Some(10.0), None, Some(30.0)]]);
+
+        // The text cell breaks the second line in two
+        let svg = line.to_svg(&grid, 400.0, 300.0);
+        assert_eq!(svg.matches("<path d=\"M").count(), 3);
+        assert_eq!(svg.matches('M').count(), 1 + 1 + 2);
+
+        let bars = Chart { kind: ChartKind::Bar, ..line }.to_svg(&grid, 400.0, 300.0);
+        assert_eq!(bars.matches("<rect").count(), 1 + 5);
+        // Scatter: x from the first column, so one series of two points
+        let scatter = Chart { kind: ChartKind::Scatter, ..line }.to_svg(&grid, 400.0, 300.0);
+        assert_eq!(scatter.matches("<circle").count(), 2);
+    }
+}
@@ -0,0 +1,513 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::cell::{Cell, CellContent, CellStyle};
+use crate::formula::{rewrite_references, translate_formula, CellRef};
+use crate::export::shown_lines;
+use crate::filter::display_text;
+use crate::grid_state::{CellRange, GridState};
+use crate::undo::EditGroup;
+
+/// One copied cell, positioned relative to the copied region's top-left
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClipboardCell {
+    pub dx: i32,
+    pub dy: i32,
+    /// Raw text and style, or None for an empty cell (pasting clears the target)
+    pub content: Option<CellContent>,
+}
+
+/// Structured clipboard contents: raw text plus where it was copied from,
+/// so relative references can be shifted by the paste offset
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClipboardContents {
+    /// Top-left (min col, min row) of the copied region
+    pub origin: (i32, i32),
+    pub width: i32,
+    pub height: i32,
+    pub cells: Vec<ClipboardCell>,
+}
+
+/// Internal clipboard shared by copy/paste
+#[derive(Resource, Default)]
+pub struct Clipboard {
+    pub contents: Option<ClipboardContents>,
+    /// True when the contents were cut: pasting moves them instead of copying
+    pub is_cut: bool,
+    /// TSV last put on the system clipboard by a copy; pasting that same text
+    /// uses `contents` instead, which keeps styles and shifts references
+    pub exported: Option<String>,
+}
+
+/// Copy the selected cells
+/// Returns None when nothing is selected
+pub fn copy_selection(grid: &GridState) -> Option<ClipboardContents> {
+    let min_col = grid.selected.iter().map(|c| c.0).min()?;
+    let min_row = grid.selected.iter().map(|c| c.1).min()?;
+    let max_col = grid.selected.iter().map(|c| c.0).max()?;
+    let max_row = grid.selected.iter().map(|c| c.1).max()?;
+
+    let mut cells: Vec<ClipboardCell> = grid
+        .selected
+        .iter()
+        .map(|(col, row)| ClipboardCell {
+            dx: col - min_col,
+            dy: row - min_row,
+            content: grid.get_cell(col, row).map(Cell::content),
+        })
+        .collect();
+    cells.sort_by_key(|c| (c.dy, c.dx));
+
+    Some(ClipboardContents {
+        origin: (min_col, min_row),
+        width: max_col - min_col + 1,
+        height: max_row - min_row + 1,
+        cells,
+    })
+}
+
+/// Build the edit group pasting `contents` with its top-left at `target`
+/// Formulas have their relative references shifted by the paste offset
+pub fn paste(contents: &ClipboardContents, grid: &GridState, target: (i32, i32)) -> EditGroup {
+    let dx = target.0 - contents.origin.0;
+    let dy = target.1 - contents.origin.1;
+
+    let mut group = EditGroup::new("Paste");
+    for cell in &contents.cells {
+        let col = target.0 + cell.dx;
+        let row = target.1 + cell.dy;
+        let content = cell.content.clone().map(|mut content| {
+            if content.raw.trim_start().starts_with('=') {
+                content.raw = translate_formula(&content.raw, dx, dy);
+            }
+            content
+        });
+        group.set_content(grid, col, row, content);
+    }
+    group
+}
+
+/// Quote a TSV field if it holds a tab, newline or quote (Excel's convention)
+fn tsv_field(raw: &str) -> String {
+    if raw.contains(['\t', '\n', '\r', '"']) {
+        format!("\"{}\"", raw.replace('"', "\"\""))
+    } else {
+        raw.to_string()
+    }
+}
+
+/// Copied cells' raw text, row by row
+fn raw_rows(contents: &ClipboardContents) -> Vec<Vec<String>> {
+    let mut rows = vec![vec![String::new(); contents.width as usize]; contents.height as usize];
+    for cell in &contents.cells {
+        if let Some(content) = &cell.content {
+            rows[cell.dy as usize][cell.dx as usize] = content.raw.clone();
+        }
+    }
+    rows
+}
+
+/// Copied cells as tab-separated text for other applications
+pub fn to_tsv(contents: &ClipboardContents) -> String {
+    raw_rows(contents)
+        .iter()
+        .map(|row| row.iter().map(|field| tsv_field(field)).collect::<Vec<_>>().join("\t"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Starts the comment carrying our own cells in clipboard HTML
+const RICH_MARKER: &str = "<!--gregsheet-cells ";
+
+/// Copied cells as HTML for the system clipboard: a table other applications
+/// can paste, with the cells themselves (styles, and the origin for shifting
+/// references) in a comment so pasting into a sheet loses nothing
+pub fn to_clipboard_html(contents: &ClipboardContents) -> String {
+    // '>' only occurs inside JSON strings, so escaping it keeps "-->" out
+    let json = serde_json::to_string(contents).unwrap_or_default().replace('>', "\\u003e");
+    format!("{}{}-->{}", RICH_MARKER, json, html_table(&raw_rows(contents), false))
+}
+
+/// The cells a sheet put in clipboard HTML, None for anyone else's HTML
+pub fn from_clipboard_html(html: &str) -> Option<ClipboardContents> {
+    let start = html.find(RICH_MARKER)? + RICH_MARKER.len();
+    let end = start + html[start..].find("-->")?;
+    serde_json::from_str(&html[start..end]).ok()
+}
+
+/// Text the range's cells show, row by row (hidden rows and columns skipped)
+pub fn display_rows(grid: &GridState, range: CellRange) -> Vec<Vec<String>> {
+    let (cols, rows) = shown_lines(grid, range);
+    rows.iter()
+        .map(|&row| cols.iter().map(|&col| display_text(grid, col, row)).collect())
+        .collect()
+}
+
+/// Rows as a Markdown table; the first row is the header (Markdown needs one)
+pub fn to_markdown(rows: &[Vec<String>]) -> String {
+    let line = |row: &[String]| {
+        let fields: Vec<String> = row.iter().map(|f| f.replace('|', "\\|").replace('\n', "<br>")).collect();
+        format!("| {} |", fields.join(" | "))
+    };
+    let Some((header, body)) = rows.split_first() else { return String::new() };
+    let mut lines = vec![line(header), line(&vec!["---".to_string(); header.len()])];
+    lines.extend(body.iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+/// Rows as an HTML table, the first row as headings (matching `to_markdown`)
+pub fn to_html(rows: &[Vec<String>]) -> String {
+    html_table(rows, true)
+}
+
+fn html_table(rows: &[Vec<String>], headings: bool) -> String {
+    let escape = |field: &str| {
+        field
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\n', "<br>")
+    };
+    let mut html = String::from("<table>\n");
+    for (i, row) in rows.iter().enumerate() {
+        let tag = if headings && i == 0 { "th" } else { "td" };
+        let cells: String = row.iter().map(|f| format!("<{tag}>{}</{tag}>", escape(f))).collect();
+        html.push_str(&format!("<tr>{}</tr>\n", cells));
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Split tab-separated text (as copied from Excel or Sheets) into rows of fields
+pub fn parse_tsv(text: &str) -> Vec<Vec<String>> {
+    parse_delimited(text, '\t')
+}
+
+/// Split delimited text (TSV, CSV) into rows of fields
+/// Quoted fields may contain delimiters, newlines and doubled quotes; a
+/// trailing newline doesn't start an extra row
+pub fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            _ if quoted => field.push(c),
+            _ if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Build the edit group pasting tab-separated text with its top-left at `target`
+pub fn paste_text(text: &str, grid: &GridState, target: (i32, i32)) -> EditGroup {
+    paste_rows(parse_tsv(text), grid, target)
+}
+
+/// Build the edit group writing rows of fields with their top-left at `target`
+/// Each field becomes a cell's raw text (keeping the target's style); empty
+/// fields clear their cell
+pub fn paste_rows(rows: Vec<Vec<String>>, grid: &GridState, target: (i32, i32)) -> EditGroup {
+    let mut group = EditGroup::new("Paste");
+    for (dy, fields) in rows.into_iter().enumerate() {
+        for (dx, raw) in fields.into_iter().enumerate() {
+            let (col, row) = (target.0 + dx as i32, target.1 + dy as i32);
+            let styled = grid.get_cell(col, row).is_some_and(|c| c.style != CellStyle::default());
+            if raw.is_empty() && !styled {
+                group.clear(grid, col, row);
+            } else {
+                group.set_raw(grid, col, row, raw);
+            }
+        }
+    }
+    group
+}
+
+/// Build the edit group moving `sources` by (dx, dy)
+/// Formulas anywhere in the grid that referenced a moved cell are rewritten to
+/// follow it, so they keep pointing at the same data. Moved formulas keep their
+/// other references unchanged. Sources left uncovered by the move are cleared
+pub fn move_cells(grid: &GridState, sources: &[(i32, i32)], dx: i32, dy: i32) -> EditGroup {
+    let moved: HashSet<(i32, i32)> = sources.iter().copied().collect();
+
+    let follow = |raw: &str| {
+        rewrite_references(raw, |r| {
+            if !moved.contains(&(r.col, r.row)) {
+                return None;
+            }
+            Some(CellRef { col: r.col + dx, row: r.row + dy, ..r }.to_text())
+        })
+    };
+
+    // Final contents per touched coordinate (None = cleared)
+    let mut result: HashMap<(i32, i32), Option<CellContent>> = HashMap::new();
+
+    // Formulas outside the moved region that point into it
+    for (key, cell) in &grid.cells {
+        if cell.is_formula && !moved.contains(&key) {
+            let rewritten = follow(&cell.raw);
+            if rewritten != cell.raw {
+                result.insert(key, Some(CellContent { raw: rewritten, style: cell.style }));
+            }
+        }
+    }
+
+    for key in &moved {
+        result.insert(*key, None);
+    }
+    for &(col, row) in &moved {
+        let content = grid.get_cell(col, row).map(|c| CellContent {
+            raw: if c.is_formula { follow(&c.raw) } else { c.raw.clone() },
+            style: c.style,
+        });
+        result.insert((col + dx, row + dy), content);
+    }
+
+    let mut keys: Vec<(i32, i32)> = result.keys().copied().collect();
+    keys.sort_by_key(|(col, row)| (*row, *col));
+
+    let mut group = EditGroup::new("Move");
+    for (col, row) in keys {
+        group.set_content(grid, col, row, result.remove(&(col, row)).flatten());
+    }
+    group
+}
+
+/// The operating system's clipboard, for exchanging text with other applications
+pub mod system {
+    /// Put text on the system clipboard (failures are ignored: the internal
+    /// clipboard still works)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_text(text: &str) {
+        if let Ok(mut clipboard) = arboard::Clipboard::new() {
+            let _ = clipboard.set_text(text);
+        }
+    }
+
+    /// Put copied cells on the system clipboard: HTML (see
+    /// `to_clipboard_html`), with `text` for applications that only take text
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_cells(text: &str, html: &str) {
+        if let Ok(mut clipboard) = arboard::Clipboard::new() {
+            let _ = clipboard.set_html(html, Some(text));
+        }
+    }
+
+    /// Text currently on the system clipboard
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_text() -> Option<String> {
+        arboard::Clipboard::new().ok()?.get_text().ok()
+    }
+
+    /// HTML currently on the system clipboard
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_html() -> Option<String> {
+        arboard::Clipboard::new().ok()?.get().html().ok()
+    }
+
+    /// The browser's async Clipboard API (write needs the page focused, read
+    /// asks the user's permission the first time)
+    #[cfg(target_arch = "wasm32")]
+    mod web {
+        use std::sync::Mutex;
+        use wasm_bindgen::prelude::*;
+
+        /// Text and HTML a read brought back, until the next frame takes it
+        pub static PASTED: Mutex<Option<(String, Option<String>)>> = Mutex::new(None);
+
+        #[wasm_bindgen(inline_js = r#"
+            export function write_clipboard(text, html) {
+                const item = { "text/plain": new Blob([text], { type: "text/plain" }) };
+                if (html !== undefined) item["text/html"] = new Blob([html], { type: "text/html" });
+                navigator.clipboard.write([new ClipboardItem(item)])
+                    .catch(() => navigator.clipboard.writeText(text))
+                    .catch(() => {});
+            }
+
+            export async function read_clipboard() {
+                let items;
+                try {
+                    items = await navigator.clipboard.read();
+                } catch {
+                    return [await navigator.clipboard.readText(), undefined];
+                }
+                let text = "", html;
+                for (const item of items) {
+                    if (item.types.includes("text/plain")) text = await (await item.getType("text/plain")).text();
+                    if (item.types.includes("text/html")) html = await (await item.getType("text/html")).text();
+                }
+                return [text, html];
+            }
+        "#)]
+        extern "C" {
+            pub fn write_clipboard(text: &str, html: Option<String>);
+            #[wasm_bindgen(catch)]
+            async fn read_clipboard() -> Result<JsValue, JsValue>;
+        }
+
+        pub fn request_paste() {
+            wasm_bindgen_futures::spawn_local(async {
+                let Ok(read) = read_clipboard().await else { return };
+                let read = js_sys::Array::from(&read);
+                let Some(text) = read.get(0).as_string() else { return };
+                *PASTED.lock().unwrap() = Some((text, read.get(1).as_string()));
+            });
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn set_text(text: &str) {
+        web::write_clipboard(text, None);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn set_cells(text: &str, html: &str) {
+        web::write_clipboard(text, Some(html.to_string()));
+    }
+
+    /// Start reading the clipboard; the contents arrive through `take_pasted`
+    #[cfg(target_arch = "wasm32")]
+    pub fn request_paste() {
+        web::request_paste();
+    }
+
+    /// Text and HTML a `request_paste` read, once it has finished
+    #[cfg(target_arch = "wasm32")]
+    pub fn take_pasted() -> Option<(String, Option<String>)> {
+        web::PASTED.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::undo::UndoStack;
+
+    #[test]
+    fn test_copy_paste_shifts_relative_references() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("1".to_string());
+        grid.get_cell_mut_or_create(1, 0).set_raw("= A0 + $A$0".to_string());
+        grid.selected.insert((0, 0));
+        grid.selected.insert((1, 0));
+
+        let contents = copy_selection(&grid).unwrap();
+        assert_eq!((contents.width, contents.height), (2, 1));
+
+        let mut stack = UndoStack::default();
+        let group = paste(&contents, &grid, (2, 3));
+        stack.commit(&mut grid, group);
+
+        assert_eq!(grid.get_cell(2, 3).unwrap().raw, "1");
+        assert_eq!(grid.get_cell(3, 3).unwrap().raw, "= C3 + $A$0");
+
+        // The whole paste undoes as one step
+        stack.undo(&mut grid);
+        assert!(grid.get_cell(2, 3).is_none());
+        assert!(grid.get_cell(3, 3).is_none());
+    }
+
+    #[test]
+    fn test_move_updates_referencing_formulas() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("5".to_string());
+        grid.get_cell_mut_or_create(0, 1).set_raw("= A0 * 2".to_string());
+        grid.get_cell_mut_or_create(3, 3).set_raw("= A0 + A1".to_string());
+
+        let mut stack = UndoStack::default();
+        let group = move_cells(&grid, &[(0, 0), (0, 1)], 1, 0);
+        stack.commit(&mut grid, group);
+
+        assert!(grid.get_cell(0, 0).is_none());
+        assert_eq!(grid.get_cell(1, 0).unwrap().raw, "5");
+        // Moved formula follows the cell it referenced, which moved with it
+        assert_eq!(grid.get_cell(1, 1).unwrap().raw, "= B0 * 2");
+        // Formulas elsewhere follow the moved data
+        assert_eq!(grid.get_cell(3, 3).unwrap().raw, "= B0 + B1");
+
+        stack.undo(&mut grid);
+        assert_eq!(grid.get_cell(0, 1).unwrap().raw, "= A0 * 2");
+        assert!(grid.get_cell(1, 0).is_none());
+    }
+
+    #[test]
+    fn test_tsv_roundtrip_and_paste() {
+        let text = "Name\tQty\r\n\"Widget, \"\"large\"\"\"\t3\n\"two\nlines\"\t\n";
+        assert_eq!(
+            parse_tsv(text),
+            vec![
+                vec!["Name".to_string(), "Qty".to_string()],
+                vec!["Widget, \"large\"".to_string(), "3".to_string()],
+                vec!["two\nlines".to_string(), String::new()],
+            ]
+        );
+
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(1, 6).set_raw("old".to_string());
+        let mut stack = UndoStack::default();
+        let group = paste_text(text, &grid, (0, 4));
+        stack.commit(&mut grid, group);
+
+        assert_eq!(grid.get_cell(1, 5).unwrap().value, evalexpr::Value::Int(3));
+        assert_eq!(grid.get_cell(0, 6).unwrap().raw, "two\nlines");
+        assert!(grid.get_cell(1, 6).is_none(), "empty fields clear their cell");
+
+        // Copying the pasted block gives the same TSV back (minus the trailing newline)
+        grid.selected = (4..7).flat_map(|row| [(0, row), (1, row)]).collect();
+        let tsv = to_tsv(&copy_selection(&grid).unwrap());
+        assert_eq!(parse_tsv(&tsv), parse_tsv(text));
+    }
+
+    #[test]
+    fn test_markdown_and_html_tables() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["Item", "Price"], ["a|b", "= 1.5 * 2"], ["<c>", "2"]]);
+        grid.run_ticks(1);
+        grid.get_cell_mut_or_create(1, 1).style.number_format = crate::cell::NumberFormat::Fixed(2);
+        grid.layout.rows.hide(2);
+
+        let rows = display_rows(&grid, CellRange::new((0, 0), (1, 2)));
+        assert_eq!(rows, vec![vec!["Item", "Price"], vec!["a|b", "3.00"]]);
+        assert_eq!(to_markdown(&rows), "| Item | Price |\n| --- | --- |\n| a\\|b | 3.00 |");
+
+        grid.layout.rows.unhide(2);
+        let html = to_html(&display_rows(&grid, CellRange::new((0, 2), (0, 2))));
+        assert_eq!(html, "<table>\n<tr><th>&lt;c&gt;</th></tr>\n</table>");
+        assert_eq!(to_markdown(&[]), "");
+    }
+
+    #[test]
+    fn test_clipboard_html_carries_cells() {
+        let mut grid = GridState::new();
+        grid.set_range((2, 3), [["<b>", "= C3 --> 1"]]);
+        grid.get_cell_mut_or_create(2, 3).style.bold = true;
+        grid.selected.extend([(2, 3), (3, 3)]);
+        let contents = copy_selection(&grid).unwrap();
+
+        let html = to_clipboard_html(&contents);
+        assert!(html.ends_with("<table>\n<tr><td>&lt;b&gt;</td><td>= C3 --&gt; 1</td></tr>\n</table>"));
+        assert_eq!(from_clipboard_html(&html), Some(contents));
+        assert_eq!(from_clipboard_html("<table><tr><td>1</td></tr></table>"), None);
+    }
+}
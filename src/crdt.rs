@@ -0,0 +1,418 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::cell::{CellContent, CellStyle};
+use crate::formula::{rewrite_references, CellRef};
+use crate::grid_ops::Axis;
+use crate::journal::JournalEntry;
+
+/// Marks a reference's slot in a `SharedContent` formula (private use chars,
+/// so they can't clash with anything typed)
+const REF_START: char = '\u{E000}';
+const REF_END: char = '\u{E001}';
+
+/// Most lines one insert may add (as many rows as Excel has); every line of
+/// a block is materialised, so bigger or empty inserts aren't taken in
+pub const MAX_INSERT_LINES: u32 = 1 << 20;
+
+/// When a write happened, for last-writer-wins: milliseconds since the Unix
+/// epoch (never running backwards on a replica), ties broken by client id
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Stamp {
+    pub time: u64,
+    pub client: u64,
+}
+
+/// A row or column that keeps its identity as lines are inserted and deleted
+/// around it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LineId {
+    /// A line of the sheet as it was when syncing started, by index
+    Base(i32),
+    /// The `n`th line of the block inserted at a stamp
+    Inserted(Stamp, u32),
+}
+
+/// The order of one axis's lines, agreed on by every replica whatever order
+/// the edits reach it in: a block of inserted lines sits right before the
+/// line it was inserted at (blocks there ordered by stamp), and deleted lines
+/// stay on as tombstones, so later edits can still be placed against them
+#[derive(Default)]
+pub struct LineSeq {
+    /// The blocks inserted right before each line: (stamp, count), by stamp
+    blocks: HashMap<LineId, Vec<(Stamp, u32)>>,
+    /// The base line each block was inserted among
+    roots: HashMap<Stamp, i32>,
+    deleted: HashSet<LineId>,
+    /// The visible lines at each base line that has any inserted around it
+    /// or is deleted; every other base line is just itself
+    expanded: BTreeMap<i32, Vec<LineId>>,
+}
+
+impl LineSeq {
+    fn root(&self, id: LineId) -> Option<i32> {
+        match id {
+            LineId::Base(index) => Some(index),
+            LineId::Inserted(stamp, _) => self.roots.get(&stamp).copied(),
+        }
+    }
+
+    fn expand(&self, id: LineId, out: &mut Vec<LineId>) {
+        for &(stamp, count) in self.blocks.get(&id).into_iter().flatten() {
+            for n in 0..count {
+                self.expand(LineId::Inserted(stamp, n), out);
+            }
+        }
+        if !self.deleted.contains(&id) {
+            out.push(id);
+        }
+    }
+
+    fn refresh(&mut self, root: i32) {
+        let mut lines = Vec::new();
+        self.expand(LineId::Base(root), &mut lines);
+        if lines == [LineId::Base(root)] {
+            self.expanded.remove(&root);
+        } else {
+            self.expanded.insert(root, lines);
+        }
+    }
+
+    /// The line showing at `index`
+    pub fn id_at(&self, index: i32) -> LineId {
+        // Shown index minus base index, for the plain base lines so far
+        let mut offset = 0;
+        for (&root, lines) in &self.expanded {
+            if index < root + offset {
+                break;
+            }
+            if let Some(&id) = lines.get((index - root - offset) as usize) {
+                return id;
+            }
+            offset += lines.len() as i32 - 1;
+        }
+        LineId::Base(index - offset)
+    }
+
+    /// Where `id` shows, None once it's deleted (or not yet known here)
+    pub fn index_of(&self, id: LineId) -> Option<i32> {
+        let root = self.root(id)?;
+        if self.deleted.contains(&id) {
+            return None;
+        }
+        let offset: i32 = self.expanded.range(..root).map(|(_, lines)| lines.len() as i32 - 1).sum();
+        match self.expanded.get(&root) {
+            Some(lines) => lines.iter().position(|line| *line == id).map(|i| root + offset + i as i32),
+            None => Some(root + offset),
+        }
+    }
+
+    /// Place a block of `count` lines right before `before`; where its first
+    /// line shows, None if it's already here (or `before` isn't known yet)
+    pub fn insert(&mut self, before: LineId, stamp: Stamp, count: u32) -> Option<i32> {
+        let root = self.root(before)?;
+        if self.roots.contains_key(&stamp) {
+            return None;
+        }
+        let blocks = self.blocks.entry(before).or_default();
+        let at = blocks.partition_point(|(other, _)| *other < stamp);
+        blocks.insert(at, (stamp, count));
+        self.roots.insert(stamp, root);
+        self.refresh(root);
+        self.index_of(LineId::Inserted(stamp, 0))
+    }
+
+    /// Delete `lines`; where those that were still showing showed, highest
+    /// first, so they can be removed from the sheet in that order
+    pub fn delete(&mut self, lines: &[LineId]) -> Vec<i32> {
+        let mut shown: Vec<i32> = lines.iter().filter_map(|id| self.index_of(*id)).collect();
+        shown.sort_unstable_by(|a, b| b.cmp(a));
+        shown.dedup();
+        let mut roots = HashSet::new();
+        for id in lines {
+            if let Some(root) = self.root(*id) {
+                self.deleted.insert(*id);
+                roots.insert(root);
+            }
+        }
+        for root in roots {
+            self.refresh(root);
+        }
+        shown
+    }
+}
+
+/// A cell by its column's and row's identities
+pub type CellId = (LineId, LineId);
+
+/// A formula reference by line identity, so it follows its target
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharedRef {
+    pub col: LineId,
+    pub row: LineId,
+    pub col_absolute: bool,
+    pub row_absolute: bool,
+}
+
+/// Cell contents as replicas exchange them: formulas have their references
+/// lifted out into `refs`, leaving numbered slots in `raw`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedContent {
+    pub raw: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub refs: Vec<SharedRef>,
+    #[serde(default)]
+    pub style: CellStyle,
+}
+
+/// One cell's contents as written by some replica (None: cleared)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CellOp {
+    pub col: LineId,
+    pub row: LineId,
+    pub content: Option<SharedContent>,
+    pub stamp: Stamp,
+}
+
+/// Rows or columns inserted or deleted by some replica
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LineOp {
+    Insert { axis: Axis, before: LineId, count: u32, stamp: Stamp },
+    Delete { axis: Axis, lines: Vec<LineId> },
+}
+
+impl LineOp {
+    /// False for inserts of no lines or over `MAX_INSERT_LINES`, which no
+    /// replica makes, so they're dropped instead of applied or passed on
+    pub fn is_valid(&self) -> bool {
+        match self {
+            LineOp::Insert { count, .. } => (1..=MAX_INSERT_LINES).contains(count),
+            LineOp::Delete { .. } => true,
+        }
+    }
+}
+
+/// One replica of a shared workbook's cells: each cell is a last-writer-wins
+/// register keyed by its row's and column's identities, so edits land on the
+/// same data everywhere however they interleave with inserts and deletes
+/// Structural edits reach the sheet as `grid_ops::shift_lines` at the
+/// position this replica sees them at, keeping `GridState` in step
+pub struct Replica {
+    pub client: u64,
+    clock: u64,
+    pub rows: LineSeq,
+    pub cols: LineSeq,
+    stamps: HashMap<CellId, Stamp>,
+}
+
+impl Replica {
+    pub fn new(client: u64) -> Self {
+        Self { client, clock: 0, rows: LineSeq::default(), cols: LineSeq::default(), stamps: HashMap::new() }
+    }
+
+    fn stamp(&mut self, now: u64) -> Stamp {
+        self.clock = now.max(self.clock + 1);
+        Stamp { time: self.clock, client: self.client }
+    }
+
+    fn axis(&mut self, axis: Axis) -> &mut LineSeq {
+        match axis {
+            Axis::Row => &mut self.rows,
+            Axis::Column => &mut self.cols,
+        }
+    }
+
+    /// The cell at (col, row) here
+    pub fn cell_id(&self, col: i32, row: i32) -> CellId {
+        (self.cols.id_at(col), self.rows.id_at(row))
+    }
+
+    /// Where a cell shows here, None once its row or column is deleted
+    pub fn cell_at(&self, (col, row): CellId) -> Option<(i32, i32)> {
+        Some((self.cols.index_of(col)?, self.rows.index_of(row)?))
+    }
+
+    fn share(&self, content: &CellContent) -> SharedContent {
+        let mut refs = Vec::new();
+        let raw = if content.raw.trim_start().starts_with('=') {
+            rewrite_references(&content.raw, |r| {
+                refs.push(SharedRef {
+                    col: self.cols.id_at(r.col),
+                    row: self.rows.id_at(r.row),
+                    col_absolute: r.col_absolute,
+                    row_absolute: r.row_absolute,
+                });
+                Some(format!("{}{}{}", REF_START, refs.len() - 1, REF_END))
+            })
+        } else {
+            content.raw.clone()
+        };
+        SharedContent { raw, refs, style: content.style }
+    }
+
+    /// Contents as they read here: references to deleted lines become `#REF!`
+    fn unshare(&self, shared: &SharedContent) -> CellContent {
+        let mut raw = String::with_capacity(shared.raw.len());
+        let mut rest = shared.raw.as_str();
+        while let Some(start) = rest.find(REF_START) {
+            raw.push_str(&rest[..start]);
+            let slot = &rest[start + REF_START.len_utf8()..];
+            let end = slot.find(REF_END).unwrap_or(slot.len());
+            let target = slot[..end].parse::<usize>().ok().and_then(|i| shared.refs.get(i)).and_then(|r| {
+                let (col, row) = (self.cols.index_of(r.col)?, self.rows.index_of(r.row)?);
+                Some(CellRef { col, row, col_absolute: r.col_absolute, row_absolute: r.row_absolute })
+            });
+            raw.push_str(&target.map_or_else(|| "#REF!".to_string(), |r| r.to_text()));
+            rest = slot.get(end + REF_END.len_utf8()..).unwrap_or("");
+        }
+        raw.push_str(rest);
+        CellContent { raw, style: shared.style }
+    }
+
+    /// Stamp contents written here at `now` (milliseconds), to send
+    pub fn local_cells(&mut self, entries: &[JournalEntry], now: u64) -> Vec<CellOp> {
+        entries
+            .iter()
+            .map(|entry| {
+                let stamp = self.stamp(now);
+                let key = self.cell_id(entry.col, entry.row);
+                self.stamps.insert(key, stamp);
+                let content = entry.content.as_ref().map(|content| self.share(content));
+                CellOp { col: key.0, row: key.1, content, stamp }
+            })
+            .collect()
+    }
+
+    /// Every filled cell, stamped as last written (or as old as can be, if
+    /// never written since syncing started), to bring another replica in step
+    pub fn snapshot(&self, entries: &[JournalEntry]) -> Vec<CellOp> {
+        entries
+            .iter()
+            .map(|entry| {
+                let (col, row) = self.cell_id(entry.col, entry.row);
+                let stamp = self.stamps.get(&(col, row)).copied().unwrap_or_default();
+                CellOp { col, row, content: entry.content.as_ref().map(|content| self.share(content)), stamp }
+            })
+            .collect()
+    }
+
+    /// Record lines inserted (`count > 0`) or deleted here, as `shift_lines`
+    /// takes them, to send
+    pub fn local_lines(&mut self, axis: Axis, at: i32, count: i32, now: u64) -> LineOp {
+        let stamp = self.stamp(now);
+        let lines = self.axis(axis);
+        if count >= 0 {
+            let before = lines.id_at(at);
+            lines.insert(before, stamp, count as u32);
+            LineOp::Insert { axis, before, count: count as u32, stamp }
+        } else {
+            let ids: Vec<LineId> = (at..at - count).map(|index| lines.id_at(index)).collect();
+            lines.delete(&ids);
+            LineOp::Delete { axis, lines: ids }
+        }
+    }
+
+    /// The received writes newer than any seen for their cells, where they
+    /// show here (writes to deleted lines are kept track of, but not shown)
+    pub fn remote_cells(&mut self, ops: Vec<CellOp>) -> Vec<JournalEntry> {
+        let mut entries = Vec::new();
+        for op in ops {
+            self.clock = self.clock.max(op.stamp.time);
+            let key = (op.col, op.row);
+            if self.stamps.get(&key).is_some_and(|seen| *seen >= op.stamp) {
+                continue;
+            }
+            self.stamps.insert(key, op.stamp);
+            if let Some((col, row)) = self.cell_at(key) {
+                let content = op.content.as_ref().map(|content| self.unshare(content));
+                entries.push(JournalEntry { col, row, content });
+            }
+        }
+        entries
+    }
+
+    /// Take in lines another replica inserted or deleted: the `shift_lines`
+    /// calls (axis, at, count) that make the sheet here match, in order
+    /// Invalid ops (see `LineOp::is_valid`) change nothing
+    pub fn remote_lines(&mut self, op: LineOp) -> Vec<(Axis, i32, i32)> {
+        if !op.is_valid() {
+            return Vec::new();
+        }
+        match op {
+            LineOp::Insert { axis, before, count, stamp } => {
+                self.clock = self.clock.max(stamp.time);
+                let at = self.axis(axis).insert(before, stamp, count);
+                at.map(|at| (axis, at, count as i32)).into_iter().collect()
+            }
+            LineOp::Delete { axis, lines } => {
+                self.axis(axis).delete(&lines).into_iter().map(|at| (axis, at, -1)).collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(col: i32, row: i32, raw: &str) -> JournalEntry {
+        JournalEntry { col, row, content: Some(CellContent { raw: raw.into(), ..Default::default() }) }
+    }
+
+    #[test]
+    fn test_concurrent_inserts_agree() {
+        let (a, b) = (Stamp { time: 5, client: 1 }, Stamp { time: 5, client: 2 });
+        let (mut one, mut two) = (LineSeq::default(), LineSeq::default());
+        one.insert(LineId::Base(2), a, 2);
+        one.insert(LineId::Base(2), b, 1);
+        two.insert(LineId::Base(2), b, 1);
+        two.insert(LineId::Base(2), a, 2);
+        two.delete(&[LineId::Base(0)]);
+        one.delete(&[LineId::Base(0)]);
+
+        let order: Vec<LineId> = (0..6).map(|i| one.id_at(i)).collect();
+        assert_eq!(order, (0..6).map(|i| two.id_at(i)).collect::<Vec<_>>());
+        assert_eq!(order[1..4], [LineId::Inserted(a, 0), LineId::Inserted(a, 1), LineId::Inserted(b, 0)]);
+        assert_eq!((order[4], order[5]), (LineId::Base(2), LineId::Base(3)));
+        assert_eq!(one.index_of(LineId::Base(3)), Some(5));
+        assert_eq!(one.index_of(LineId::Base(0)), None);
+    }
+
+    #[test]
+    fn test_edits_follow_concurrent_inserts() {
+        let (mut here, mut there) = (Replica::new(1), Replica::new(2));
+
+        // Here a row goes in above row 1 while there a formula is typed on row 3
+        let insert = here.local_lines(Axis::Row, 1, 1, 100);
+        let formula = there.local_cells(&[entry(1, 3, "= A5 + $A$0")], 100);
+
+        // It lands one row down here, still pointing at the same data
+        assert_eq!(here.remote_cells(formula.clone()), vec![entry(1, 4, "= A6 + $A$0")]);
+        // and there the insert arrives as a shift, which moves the formula too
+        assert_eq!(there.remote_lines(insert), vec![(Axis::Row, 1, 1)]);
+
+        // Deleting the row it points at leaves `#REF!` in the reference's place
+        let delete = here.local_lines(Axis::Row, 6, -1, 200);
+        assert_eq!(there.remote_lines(delete), vec![(Axis::Row, 6, -1)]);
+        assert_eq!(there.unshare(formula[0].content.as_ref().unwrap()).raw, "= #REF! + $A$0");
+
+        // Older or repeated writes lose
+        let rewrite = here.local_cells(&[entry(2, 0, "= B4")], 300);
+        assert_eq!(there.remote_cells(rewrite.clone()), vec![entry(2, 0, "= B4")]);
+        assert!(there.remote_cells(rewrite).is_empty());
+    }
+
+    #[test]
+    fn test_oversized_inserts_are_ignored() {
+        let mut here = Replica::new(1);
+        let stamp = Stamp { time: 100, client: 2 };
+        for count in [0, MAX_INSERT_LINES + 1, u32::MAX] {
+            let op = LineOp::Insert { axis: Axis::Row, before: LineId::Base(1), count, stamp };
+            assert!(!op.is_valid());
+            assert!(here.remote_lines(op).is_empty());
+        }
+        assert_eq!(here.rows.id_at(1), LineId::Base(1));
+    }
+}
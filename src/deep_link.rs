@@ -0,0 +1,154 @@
+use bevy::prelude::Vec2;
+
+use crate::formula::{coord_to_name, name_to_coord};
+use crate::grid_state::CellRange;
+
+/// Seconds the view has to sit still before the page's URL follows it
+pub const IDLE_DELAY: f32 = 0.5;
+
+/// Query parameters a link sets (any others, like `sync`, are left alone)
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+const PARAMS: [&str; 6] = ["x", "y", "zoom", "doc", "cell", "sel"];
+
+/// Where someone was looking, for a shared link to open the sheet there
+#[derive(Clone, Debug, PartialEq)]
+pub struct ViewLink {
+    /// The camera's center in world units, and its scale (1 is 100%)
+    pub center: Vec2,
+    pub scale: f32,
+    /// The open document (see `DocumentStore`)
+    pub document: Option<String>,
+    pub active: Option<(i32, i32)>,
+    pub ranges: Vec<CellRange>,
+}
+
+fn range_text(range: &CellRange) -> String {
+    let (from, to) = (coord_to_name(range.min_col, range.min_row), coord_to_name(range.max_col, range.max_row));
+    if from == to {
+        from
+    } else {
+        format!("{}:{}", from, to)
+    }
+}
+
+fn parse_range(text: &str) -> Option<CellRange> {
+    let (from, to) = text.split_once(':').unwrap_or((text, text));
+    Some(CellRange::new(name_to_coord(from)?, name_to_coord(to)?))
+}
+
+impl ViewLink {
+    /// As query parameters: `x`, `y`, `zoom`, then `doc`, `cell` and `sel`
+    /// (comma-separated ranges like `A0:B3`) when there's anything to say
+    pub fn to_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("x", format!("{:.0}", self.center.x)),
+            ("y", format!("{:.0}", self.center.y)),
+            ("zoom", format!("{:.3}", self.scale)),
+        ];
+        if let Some(document) = &self.document {
+            params.push(("doc", document.clone()));
+        }
+        if let Some((col, row)) = self.active {
+            params.push(("cell", coord_to_name(col, row)));
+        }
+        if !self.ranges.is_empty() {
+            params.push(("sel", self.ranges.iter().map(range_text).collect::<Vec<_>>().join(",")));
+        }
+        params
+    }
+
+    /// Read a link back from its parameters, None without a position
+    /// Parts that don't parse are left out
+    pub fn from_params(get: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let number = |name: &str| get(name)?.parse::<f32>().ok().filter(|n| n.is_finite());
+        Some(Self {
+            center: Vec2::new(number("x")?, number("y")?),
+            scale: number("zoom").filter(|scale| *scale > 0.0).unwrap_or(1.0),
+            document: get("doc").filter(|name| !name.is_empty()),
+            active: get("cell").and_then(|name| name_to_coord(&name)),
+            ranges: get("sel").map_or_else(Vec::new, |sel| sel.split(',').filter_map(parse_range).collect()),
+        })
+    }
+}
+
+/// The link the page was opened with (nothing natively)
+pub fn read() -> Option<ViewLink> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web::read()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        None
+    }
+}
+
+/// Point the page's URL at `link`, without adding a history entry (nothing
+/// natively)
+#[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))]
+pub fn write(link: &ViewLink) {
+    #[cfg(target_arch = "wasm32")]
+    web::write(link);
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use super::*;
+    use wasm_bindgen::JsValue;
+
+    fn params() -> Option<web_sys::UrlSearchParams> {
+        let search = web_sys::window()?.location().search().ok()?;
+        web_sys::UrlSearchParams::new_with_str(&search).ok()
+    }
+
+    pub fn read() -> Option<ViewLink> {
+        let params = params()?;
+        ViewLink::from_params(|name| params.get(name))
+    }
+
+    pub fn write(link: &ViewLink) {
+        let (Some(window), Some(params)) = (web_sys::window(), params()) else { return };
+        for name in PARAMS {
+            params.delete(name);
+        }
+        for (name, value) in link.to_params() {
+            params.set(name, &value);
+        }
+        let url = format!("?{}{}", String::from(params.to_string()), window.location().hash().unwrap_or_default());
+        if let Ok(history) = window.history() {
+            let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&url));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_round_trips() {
+        let link = ViewLink {
+            center: Vec2::new(640.0, -300.0),
+            scale: 0.5,
+            document: Some("Budget 2026".to_string()),
+            active: Some((1, 3)),
+            ranges: vec![CellRange::new((0, 0), (2, 4)), CellRange::cell(5, 1)],
+        };
+        let params = link.to_params();
+        let sel = params.iter().find(|(name, _)| *name == "sel").map(|(_, value)| value.as_str());
+        assert_eq!(sel, Some("A0:C4,F1"));
+        let get = |name: &str| params.iter().find(|(n, _)| *n == name).map(|(_, value)| value.clone());
+        assert_eq!(ViewLink::from_params(get), Some(link));
+
+        // Without a position there's no link; bad parts are dropped
+        assert_eq!(ViewLink::from_params(|_| None), None);
+        let odd = |name: &str| match name {
+            "x" | "y" => Some("10".to_string()),
+            "zoom" => Some("-2".to_string()),
+            "sel" => Some("A0:B1,nope".to_string()),
+            _ => None,
+        };
+        let read = ViewLink::from_params(odd).unwrap();
+        assert_eq!((read.scale, read.ranges), (1.0, vec![CellRange::new((0, 0), (1, 1))]));
+    }
+}
@@ -1,4 +1,5 @@
-use crate::grid_state::GridState;
+use crate::grid_state::{CellRange, GridState};
+use crate::validation::{InvalidAction, Validation, ValidationRule};
 
 #[cfg(test)]
 mod tests;
@@ -31,4 +32,18 @@ pub fn setup_demo_data(grid: &mut GridState) {
     for i in 0..10 {
         grid.get_cell_mut_or_create(i % 5, 5 + (i / 5)).set_raw(i.to_string());
     }
+
+    // Validated inputs: F0 is picked from a list, F1 is flagged outside 0-100
+    grid.get_cell_mut_or_create(5, 0).set_raw("slow".to_string());
+    grid.validations.push(Validation {
+        range: CellRange::cell(5, 0),
+        rule: ValidationRule::List(vec!["slow".to_string(), "fast".to_string()]),
+        on_invalid: InvalidAction::Reject,
+    });
+    grid.get_cell_mut_or_create(5, 1).set_raw("50".to_string());
+    grid.validations.push(Validation {
+        range: CellRange::cell(5, 1),
+        rule: ValidationRule::Range { min: Some(0.0), max: Some(100.0) },
+        on_invalid: InvalidAction::Flag,
+    });
 }
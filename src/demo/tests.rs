@@ -168,3 +168,31 @@ fn test_all_digits_display() {
         assert!(!cell.is_formula);
     }
 }
+
+#[test]
+fn test_headless_run_ticks() {
+    let mut grid = GridState::new();
+    setup_demo_data(&mut grid);
+
+    grid.run_ticks(3);
+
+    // Counter increments once per tick
+    assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(3));
+    // Accumulator sees its literals after the first tick
+    assert_eq!(grid.get_cell(2, 2).unwrap().value, Value::Int(30));
+    // Blinker follows the previous tick's counter value (2)
+    assert_eq!(grid.get_cell(1, 0).unwrap().value, Value::Int(0));
+    assert_eq!(grid.get_cell(1, 1).unwrap().value, Value::Int(1));
+}
+
+#[test]
+fn test_tick_reports_changes() {
+    let mut grid = GridState::new();
+    setup_demo_data(&mut grid);
+    grid.run_ticks(2);
+
+    // The counter keeps changing, settled literals report nothing
+    let changes = grid.tick();
+    assert!(changes.iter().any(|c| (c.col, c.row) == (0, 0) && c.new == Value::Int(3)));
+    assert!(!changes.iter().any(|c| (c.col, c.row) == (2, 0)));
+}
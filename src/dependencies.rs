@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use crate::formula::references;
+use crate::grid_state::GridState;
+
+/// Which cells each formula reads, and which formulas read each cell
+/// Structured references are resolved through the header labels; a formula
+/// with an unknown label still links through its plain references
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    precedents: HashMap<(i32, i32), Vec<(i32, i32)>>,
+    dependents: HashMap<(i32, i32), Vec<(i32, i32)>>,
+}
+
+impl DependencyGraph {
+    pub fn build(grid: &GridState) -> Self {
+        let mut graph = Self::default();
+        for ((col, row), cell) in &grid.cells {
+            if !cell.is_formula {
+                continue;
+            }
+            let expr = cell.raw.trim_start().trim_start_matches('=');
+            let expr = grid.headers.resolve(expr).unwrap_or_else(|_| expr.to_string());
+            let mut refs: Vec<(i32, i32)> = references(&expr).into_iter().map(|r| (r.col, r.row)).collect();
+            refs.sort_by_key(|(col, row)| (*row, *col));
+            refs.dedup();
+            for &cell_ref in &refs {
+                graph.dependents.entry(cell_ref).or_default().push((col, row));
+            }
+            graph.precedents.insert((col, row), refs);
+        }
+        // Cells iterate in no particular order
+        for dependents in graph.dependents.values_mut() {
+            dependents.sort_by_key(|(col, row)| (*row, *col));
+        }
+        graph
+    }
+
+    /// Cells a formula reads, row-major
+    pub fn precedents(&self, col: i32, row: i32) -> &[(i32, i32)] {
+        self.precedents.get(&(col, row)).map_or(&[], Vec::as_slice)
+    }
+
+    /// Formulas reading a cell, row-major
+    pub fn dependents(&self, col: i32, row: i32) -> &[(i32, i32)] {
+        self.dependents.get(&(col, row)).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedents_and_dependents() {
+        let mut grid = GridState::new();
+        grid.headers.set_col_label(0, "Price");
+        grid.set_range((0, 0), [["1", "= A0 + A0 * 2", "= B0 + A0", "= [Price]0", "= \"A0\""]]);
+        let graph = DependencyGraph::build(&grid);
+
+        assert_eq!(graph.precedents(2, 0), [(0, 0), (1, 0)]);
+        assert_eq!(graph.precedents(1, 0), [(0, 0)]);
+        assert_eq!(graph.dependents(0, 0), [(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(graph.dependents(2, 0), []);
+        // String literals aren't references
+        assert_eq!(graph.precedents(4, 0), []);
+        assert_eq!(graph.precedents(0, 0), []);
+    }
+}
@@ -0,0 +1,66 @@
+use serde::Serialize;
+
+/// Numbers shown in the stats overlay (toggled with F3), and sent to the
+/// embedding page as metrics (see `HostRequest::WatchMetrics`)
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetStats {
+    /// None until Bevy's frame time diagnostics have a reading
+    pub fps: Option<f64>,
+    pub frame_ms: Option<f64>,
+    /// None until the first tick
+    pub tick_ms: Option<f64>,
+    pub cells: usize,
+    /// Formula cells, each evaluated once per tick
+    pub formulas: usize,
+    pub changed_last_tick: usize,
+    pub svg_cached: usize,
+    /// Rendered pixels held by the SVG cache
+    pub svg_cache_bytes: usize,
+    /// Rich cells waiting on the SVG render thread
+    pub svg_pending: usize,
+    /// Viewport buffers and textures kept for the GPU
+    pub gpu_bytes: usize,
+}
+
+impl SheetStats {
+    pub fn to_text(&self) -> String {
+        let reading = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.1}", v));
+        [
+            format!("FPS {} ({} ms)", reading(self.fps), reading(self.frame_ms)),
+            format!("Cells {} / formulas {}", self.cells, self.formulas),
+            format!("Tick {} ms, changed {}", reading(self.tick_ms), self.changed_last_tick),
+            format!("SVG cached {} ({}) / queued {}", self.svg_cached, format_bytes(self.svg_cache_bytes), self.svg_pending),
+            format!("GPU buffers {}", format_bytes(self.gpu_bytes)),
+        ]
+        .join("\n")
+    }
+}
+
+/// A byte count in B, KiB or MiB
+pub fn format_bytes(bytes: usize) -> String {
+    const KIB: f64 = 1024.0;
+    match bytes as f64 {
+        b if b < KIB => format!("{} B", bytes),
+        b if b < KIB * KIB => format!("{:.1} KiB", b / KIB),
+        b => format!("{:.1} MiB", b / (KIB * KIB)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_text() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+
+        let stats = SheetStats { fps: Some(59.94), tick_ms: Some(1.5), cells: 12, formulas: 3, gpu_bytes: 2048, ..Default::default() };
+        let text = stats.to_text();
+        assert!(text.starts_with("FPS 59.9 (- ms)\nCells 12 / formulas 3\nTick 1.5 ms, changed 0"));
+        assert!(text.ends_with("GPU buffers 2.0 KiB"));
+    }
+}
@@ -0,0 +1,304 @@
+use bevy::prelude::*;
+use std::sync::{Arc, Mutex};
+
+/// Outcome of a document store request
+#[derive(Clone, Debug, PartialEq)]
+pub enum DocumentEvent {
+    /// Names of every stored document, sorted
+    Listed(Vec<String>),
+    Opened { name: String, json: String },
+    Saved(String),
+    Deleted(String),
+    Failed(String),
+}
+
+/// Named workbooks kept side by side: IndexedDB in browsers, a directory of
+/// JSON files natively
+/// Browser storage is asynchronous, so requests answer through `poll` on a
+/// later frame (natively they're ready by the next `poll`)
+#[derive(Resource, Default)]
+pub struct DocumentStore {
+    events: Arc<Mutex<Vec<DocumentEvent>>>,
+    /// Stored document names, as of the last listing
+    pub names: Vec<String>,
+    /// Document the open workbook was last opened from or saved to
+    pub current: Option<String>,
+}
+
+/// Trimmed document name, refusing ones that can't be stored
+fn valid_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("invalid document name {:?}", name));
+    }
+    Ok(name)
+}
+
+impl DocumentStore {
+    /// Request the list of stored documents
+    pub fn list(&self) {
+        self.request(backend::list());
+    }
+
+    pub fn open(&self, name: &str) {
+        match valid_name(name) {
+            Ok(name) => self.request(backend::open(name.to_string())),
+            Err(e) => self.push(DocumentEvent::Failed(e)),
+        }
+    }
+
+    /// Store a workbook (from `persist::save_json`) under a name, replacing any
+    /// document already called that
+    pub fn save(&self, name: &str, json: String) {
+        match valid_name(name) {
+            Ok(name) => self.request(backend::save(name.to_string(), json)),
+            Err(e) => self.push(DocumentEvent::Failed(e)),
+        }
+    }
+
+    pub fn delete(&self, name: &str) {
+        match valid_name(name) {
+            Ok(name) => self.request(backend::delete(name.to_string())),
+            Err(e) => self.push(DocumentEvent::Failed(e)),
+        }
+    }
+
+    /// Take the answers that arrived since the last poll
+    pub fn poll(&self) -> Vec<DocumentEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    /// First "Sheet N" name not already taken
+    pub fn unused_name(&self) -> String {
+        (1..)
+            .map(|n| format!("Sheet {}", n))
+            .find(|name| !self.names.contains(name))
+            .unwrap()
+    }
+
+    fn push(&self, event: DocumentEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn request(&self, result: DocumentEvent) {
+        self.push(result);
+    }
+
+    /// Run the storage request in the background, queueing its answer
+    #[cfg(target_arch = "wasm32")]
+    fn request(&self, request: impl std::future::Future<Output = DocumentEvent> + 'static) {
+        let events = self.events.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let event = request.await;
+            events.lock().unwrap().push(event);
+        });
+    }
+}
+
+/// Name to save under: browsers ask (suggesting `suggested`); natively the
+/// suggestion is used as-is. None if the user cancelled
+pub fn ask_name(suggested: &str) -> Option<String> {
+    #[cfg(target_arch = "wasm32")]
+    let name = web_sys::window()?
+        .prompt_with_message_and_default("Save workbook as", suggested)
+        .ok()
+        .flatten();
+    #[cfg(not(target_arch = "wasm32"))]
+    let name = Some(suggested.to_string());
+    name
+}
+
+/// Documents as `<name>.json` files in `DOCUMENTS_DIR`
+#[cfg(not(target_arch = "wasm32"))]
+mod backend {
+    use super::DocumentEvent;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const DOCUMENTS_DIR: &str = "documents";
+
+    fn path(name: &str) -> PathBuf {
+        PathBuf::from(DOCUMENTS_DIR).join(format!("{}.json", name))
+    }
+
+    fn failed(e: std::io::Error) -> DocumentEvent {
+        DocumentEvent::Failed(e.to_string())
+    }
+
+    pub fn list() -> DocumentEvent {
+        // No directory yet just means no documents
+        let Ok(entries) = fs::read_dir(DOCUMENTS_DIR) else {
+            return DocumentEvent::Listed(Vec::new());
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|file| file.strip_suffix(".json").map(str::to_string))
+            .collect();
+        names.sort();
+        DocumentEvent::Listed(names)
+    }
+
+    pub fn open(name: String) -> DocumentEvent {
+        match fs::read_to_string(path(&name)) {
+            Ok(json) => DocumentEvent::Opened { name, json },
+            Err(e) => failed(e),
+        }
+    }
+
+    pub fn save(name: String, json: String) -> DocumentEvent {
+        match fs::create_dir_all(DOCUMENTS_DIR).and_then(|_| fs::write(path(&name), json)) {
+            Ok(()) => DocumentEvent::Saved(name),
+            Err(e) => failed(e),
+        }
+    }
+
+    pub fn delete(name: String) -> DocumentEvent {
+        match fs::remove_file(path(&name)) {
+            Ok(()) => DocumentEvent::Deleted(name),
+            Err(e) => failed(e),
+        }
+    }
+}
+
+/// The app's IndexedDB database: stored documents, plus the edit journal
+/// (see `journal`)
+#[cfg(target_arch = "wasm32")]
+pub mod idb {
+    use wasm_bindgen::{prelude::*, JsCast};
+    use web_sys::{IdbDatabase, IdbObjectStore, IdbObjectStoreParameters, IdbRequest, IdbTransactionMode};
+
+    const DB_NAME: &str = "gregsheet";
+    /// Bumped whenever a store is added, so existing databases get upgraded
+    const DB_VERSION: u32 = 2;
+    /// Documents keyed by name
+    pub const DOCUMENTS: &str = "documents";
+    /// Journal batches under auto-increment keys, in write order
+    pub const JOURNAL: &str = "journal";
+
+    pub fn js_error(e: JsValue) -> String {
+        e.as_string().unwrap_or_else(|| format!("{:?}", e))
+    }
+
+    /// Wait for an IndexedDB request to finish, returning its result
+    pub async fn finish(request: &IdbRequest) -> Result<JsValue, String> {
+        let promise = js_sys::Promise::new(&mut |resolve, reject| {
+            let done = Closure::once_into_js(move || {
+                let _ = resolve.call0(&JsValue::NULL);
+            });
+            let failed = Closure::once_into_js(move || {
+                let _ = reject.call0(&JsValue::NULL);
+            });
+            request.set_onsuccess(Some(done.unchecked_ref()));
+            request.set_onerror(Some(failed.unchecked_ref()));
+        });
+        wasm_bindgen_futures::JsFuture::from(promise)
+            .await
+            .map_err(|_| "storage request failed".to_string())?;
+        request.result().map_err(js_error)
+    }
+
+    pub async fn object_store(name: &str, mode: IdbTransactionMode) -> Result<IdbObjectStore, String> {
+        let factory = web_sys::window()
+            .and_then(|w| w.indexed_db().ok().flatten())
+            .ok_or("IndexedDB is unavailable")?;
+        let request = factory.open_with_u32(DB_NAME, DB_VERSION).map_err(js_error)?;
+        // First run or older version: create the missing stores (creating one
+        // that exists fails harmlessly)
+        let upgrading = request.clone();
+        let upgrade = Closure::once_into_js(move || {
+            if let Ok(db) = upgrading.result().and_then(|db| db.dyn_into::<IdbDatabase>()) {
+                let _ = db.create_object_store(DOCUMENTS);
+                let params = IdbObjectStoreParameters::new();
+                params.set_auto_increment(true);
+                let _ = db.create_object_store_with_optional_parameters(JOURNAL, &params);
+            }
+        });
+        request.set_onupgradeneeded(Some(upgrade.unchecked_ref()));
+
+        let db: IdbDatabase = finish(&request).await?.dyn_into().map_err(js_error)?;
+        db.transaction_with_str_and_mode(name, mode)
+            .and_then(|tx| tx.object_store(name))
+            .map_err(js_error)
+    }
+}
+
+/// Documents as entries of an IndexedDB object store, keyed by name
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::idb::{finish, js_error, DOCUMENTS};
+    use super::DocumentEvent;
+    use wasm_bindgen::prelude::*;
+    use web_sys::{IdbObjectStore, IdbTransactionMode};
+
+    async fn object_store(mode: IdbTransactionMode) -> Result<IdbObjectStore, String> {
+        super::idb::object_store(DOCUMENTS, mode).await
+    }
+
+    fn answer(result: Result<DocumentEvent, String>) -> DocumentEvent {
+        result.unwrap_or_else(DocumentEvent::Failed)
+    }
+
+    pub async fn list() -> DocumentEvent {
+        answer(async {
+            let store = object_store(IdbTransactionMode::Readonly).await?;
+            let keys = finish(&store.get_all_keys().map_err(js_error)?).await?;
+            let mut names: Vec<String> = js_sys::Array::from(&keys).iter().filter_map(|k| k.as_string()).collect();
+            names.sort();
+            Ok(DocumentEvent::Listed(names))
+        }
+        .await)
+    }
+
+    pub async fn open(name: String) -> DocumentEvent {
+        answer(async {
+            let store = object_store(IdbTransactionMode::Readonly).await?;
+            let json = finish(&store.get(&JsValue::from_str(&name)).map_err(js_error)?).await?;
+            let json = json.as_string().ok_or_else(|| format!("no document named {:?}", name))?;
+            Ok(DocumentEvent::Opened { name, json })
+        }
+        .await)
+    }
+
+    pub async fn save(name: String, json: String) -> DocumentEvent {
+        answer(async {
+            let store = object_store(IdbTransactionMode::Readwrite).await?;
+            let request = store
+                .put_with_key(&JsValue::from_str(&json), &JsValue::from_str(&name))
+                .map_err(js_error)?;
+            finish(&request).await?;
+            Ok(DocumentEvent::Saved(name))
+        }
+        .await)
+    }
+
+    pub async fn delete(name: String) -> DocumentEvent {
+        answer(async {
+            let store = object_store(IdbTransactionMode::Readwrite).await?;
+            finish(&store.delete(&JsValue::from_str(&name)).map_err(js_error)?).await?;
+            Ok(DocumentEvent::Deleted(name))
+        }
+        .await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(valid_name("  Budget "), Ok("Budget"));
+        assert!(valid_name("").is_err());
+        assert!(valid_name("../escape").is_err());
+        assert!(valid_name(".hidden").is_err());
+
+        let store = DocumentStore { names: vec!["Sheet 1".into(), "Sheet 3".into()], ..Default::default() };
+        assert_eq!(store.unused_name(), "Sheet 2");
+
+        // Bad names fail without touching storage
+        store.delete("a/b");
+        assert!(matches!(store.poll().as_slice(), [DocumentEvent::Failed(_)]));
+        assert!(store.poll().is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+use std::ops::Range;
+
+/// The formula bar's text, with a caret and an optional selection
+/// Positions are byte offsets, always on char boundaries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EditBuffer {
+    text: String,
+    caret: usize,
+    /// The selection's other end; the selection runs from here to the caret
+    anchor: Option<usize>,
+}
+
+impl EditBuffer {
+    /// Replace the text, with the caret at the end
+    pub fn set(&mut self, text: String) {
+        self.caret = text.len();
+        self.anchor = None;
+        self.text = text;
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// The selected bytes, None when nothing is selected
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        (anchor != self.caret).then(|| anchor.min(self.caret)..anchor.max(self.caret))
+    }
+
+    /// Type at the caret, replacing the selection
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        self.text.insert_str(self.caret, text);
+        self.caret += text.len();
+    }
+
+    /// Backspace: the selection, else the character before the caret
+    pub fn delete_back(&mut self) {
+        if !self.delete_selection() {
+            if let Some(previous) = self.previous_boundary() {
+                self.text.replace_range(previous..self.caret, "");
+                self.caret = previous;
+            }
+        }
+    }
+
+    /// Delete: the selection, else the character after the caret
+    pub fn delete_forward(&mut self) {
+        if !self.delete_selection() {
+            if let Some(next) = self.next_boundary() {
+                self.text.replace_range(self.caret..next, "");
+            }
+        }
+    }
+
+    /// One character left; without `select`, a selection collapses to its start
+    pub fn left(&mut self, select: bool) {
+        match self.selection() {
+            Some(range) if !select => self.move_to(range.start, false),
+            _ => self.move_to(self.previous_boundary().unwrap_or(0), select),
+        }
+    }
+
+    /// One character right; without `select`, a selection collapses to its end
+    pub fn right(&mut self, select: bool) {
+        match self.selection() {
+            Some(range) if !select => self.move_to(range.end, false),
+            _ => self.move_to(self.next_boundary().unwrap_or(self.caret), select),
+        }
+    }
+
+    /// Put the caret at byte `position` (clamped to the text, and back onto a
+    /// char boundary), extending the selection when `select`
+    pub fn place_caret(&mut self, position: usize, select: bool) {
+        let mut position = position.min(self.text.len());
+        while !self.text.is_char_boundary(position) {
+            position -= 1;
+        }
+        self.move_to(position, select);
+    }
+
+    pub fn home(&mut self, select: bool) {
+        self.move_to(0, select);
+    }
+
+    pub fn end(&mut self, select: bool) {
+        self.move_to(self.text.len(), select);
+    }
+
+    /// Move the caret, extending the selection when `select`
+    fn move_to(&mut self, position: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.caret);
+        } else {
+            self.anchor = None;
+        }
+        self.caret = position;
+    }
+
+    fn previous_boundary(&self) -> Option<usize> {
+        self.text[..self.caret].char_indices().next_back().map(|(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.text[self.caret..].chars().next().map(|c| self.caret + c.len_utf8())
+    }
+
+    /// Remove the selected text, if any; whether there was some
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.anchor = None;
+        let Some(range) = selection else { return false };
+        self.caret = range.start;
+        self.text.replace_range(range, "");
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caret_editing() {
+        let mut buffer = EditBuffer::default();
+        buffer.set("=A1+B1".to_string());
+        assert_eq!(buffer.caret(), 6);
+
+        buffer.home(false);
+        buffer.right(false);
+        buffer.insert("SUM(");
+        assert_eq!(buffer.text(), "=SUM(A1+B1");
+        buffer.end(false);
+        buffer.insert(")");
+        buffer.left(false);
+        buffer.delete_back();
+        assert_eq!(buffer.text(), "=SUM(A1+B)");
+
+        // Select "B" backwards and replace it
+        buffer.left(true);
+        assert_eq!(buffer.selection(), Some(8..9));
+        buffer.insert("C2");
+        assert_eq!(buffer.text(), "=SUM(A1+C2)");
+        assert_eq!(buffer.selection(), None);
+
+        // Multi-byte characters move and delete whole
+        buffer.set("né".to_string());
+        buffer.left(false);
+        assert_eq!(buffer.caret(), 1);
+        buffer.delete_forward();
+        assert_eq!(buffer.text(), "n");
+
+        // Collapsing a selection moves to its edge rather than past it
+        buffer.set("abc".to_string());
+        buffer.home(true);
+        buffer.right(false);
+        assert_eq!((buffer.caret(), buffer.selection()), (3, None));
+
+        // Clicked positions land on char boundaries within the text
+        buffer.set("né!".to_string());
+        buffer.place_caret(2, false);
+        assert_eq!(buffer.caret(), 1);
+        buffer.place_caret(99, true);
+        assert_eq!(buffer.selection(), Some(1..4));
+    }
+}
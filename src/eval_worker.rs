@@ -0,0 +1,195 @@
+use bevy::prelude::*;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use evalexpr::Value;
+use std::collections::{HashMap, HashSet};
+use std::thread;
+
+use crate::cell_store::CellStore;
+use crate::evaluator::evaluate_tick_with;
+use crate::events::CellChanged;
+use crate::grid_state::GridState;
+use crate::headers::HeaderLabels;
+use crate::host_functions::HostFunctions;
+
+/// Evaluates ticks on a background thread (native only) so huge sheets never
+/// hitch the render loop. Works like `SvgRenderer`: a snapshot of the cells goes
+/// out over one channel and the evaluated cells come back on the other
+/// Snapshots share the grid's chunks (see `CellStore`), so sending one is cheap
+/// and merging only visits the chunks the tick wrote to
+#[derive(Resource)]
+pub struct EvalWorker {
+    request_tx: Sender<EvalRequest>,
+    result_rx: Receiver<EvalResult>,
+
+    /// True while a tick is being evaluated off-thread
+    pub in_flight: bool,
+}
+
+pub struct EvalRequest {
+    pub cells: CellStore,
+    /// Labels used to resolve structured references
+    pub headers: HeaderLabels,
+    pub host_functions: HostFunctions,
+    /// Formula values already computed elsewhere (the GPU, see `gpu_eval`)
+    pub computed: HashMap<(i32, i32), Value>,
+}
+
+impl EvalRequest {
+    /// Snapshot of the grid, with nothing computed yet
+    pub fn snapshot(grid: &GridState) -> Self {
+        Self {
+            cells: grid.cells.clone(),
+            headers: grid.headers.clone(),
+            host_functions: grid.host_functions.clone(),
+            computed: HashMap::new(),
+        }
+    }
+
+    /// Evaluate the snapshot's tick, on whichever thread; `merge_result`
+    /// brings it back into the grid
+    pub fn evaluate(self) -> EvalResult {
+        let mut grid = GridState::new();
+        grid.cells = self.cells;
+        grid.headers = self.headers;
+        grid.host_functions = self.host_functions;
+        let changes = evaluate_tick_with(&mut grid, &self.computed);
+        EvalResult { cells: grid.cells, changes }
+    }
+}
+
+pub struct EvalResult {
+    /// Cells after evaluation, including the raw text they were evaluated from
+    pub cells: CellStore,
+    pub changes: Vec<CellChanged>,
+}
+
+impl EvalWorker {
+    pub fn new() -> Self {
+        let (req_tx, req_rx) = bounded::<EvalRequest>(1);
+        let (res_tx, res_rx) = bounded::<EvalResult>(1);
+
+        thread::spawn(move || {
+            eval_loop(req_rx, res_tx);
+        });
+
+        Self {
+            request_tx: req_tx,
+            result_rx: res_rx,
+            in_flight: false,
+        }
+    }
+
+    /// Send a snapshot of the grid for evaluation
+    /// Returns false if a tick is already in flight
+    pub fn submit(&mut self, grid: &GridState) -> bool {
+        self.send(EvalRequest::snapshot(grid))
+    }
+
+    /// Send a prepared snapshot, e.g. one the GPU computed part of
+    /// Returns false if a tick is already in flight
+    pub fn send(&mut self, request: EvalRequest) -> bool {
+        if self.in_flight {
+            return false;
+        }
+        self.in_flight = self.request_tx.send(request).is_ok();
+        self.in_flight
+    }
+
+    /// Take the finished tick, if the worker is done
+    pub fn poll(&mut self) -> Option<EvalResult> {
+        let result = self.result_rx.try_recv().ok()?;
+        self.in_flight = false;
+        Some(result)
+    }
+}
+
+fn eval_loop(rx: Receiver<EvalRequest>, tx: Sender<EvalResult>) {
+    while let Ok(req) = rx.recv() {
+        if tx.send(req.evaluate()).is_err() {
+            break;
+        }
+    }
+}
+
+/// Merge an off-thread result back into the grid
+/// Cells edited or deleted while the tick was in flight keep their new state
+/// Returns the changes that were actually applied
+pub fn merge_result(grid: &mut GridState, result: EvalResult) -> Vec<CellChanged> {
+    let mut stale = HashSet::new();
+
+    for (key, evaluated) in result.cells.diverged_from(&grid.cells) {
+        match grid.cells.get(&key) {
+            Some(cell) if cell.raw == evaluated.raw => {
+                if cell.value != evaluated.value || cell.error != evaluated.error || cell.error_code != evaluated.error_code {
+                    let cell = grid.cells.get_evaluated_mut(&key).expect("cell checked above");
+                    cell.value = evaluated.value.clone();
+                    cell.error = evaluated.error;
+                    cell.error_code = evaluated.error_code;
+                }
+            }
+            _ => {
+                stale.insert(key);
+            }
+        }
+    }
+
+    result
+        .changes
+        .into_iter()
+        .filter(|change| !stale.contains(&(change.col, change.row)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ChangeSource;
+    use std::time::Duration;
+
+    #[test]
+    fn test_worker_matches_sync_evaluation() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("= A0 + 1".to_string());
+        grid.get_cell_mut_or_create(1, 0).set_raw("= A0 * 10".to_string());
+
+        let mut worker = EvalWorker::new();
+        for _ in 0..3 {
+            assert!(worker.submit(&grid));
+            assert!(!worker.submit(&grid), "only one tick may be in flight");
+            let result = loop {
+                if let Some(result) = worker.poll() {
+                    break result;
+                }
+                thread::sleep(Duration::from_millis(1));
+            };
+            merge_result(&mut grid, result);
+        }
+
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(3));
+        assert_eq!(grid.get_cell(1, 0).unwrap().value, Value::Int(20));
+    }
+
+    #[test]
+    fn test_merge_skips_cells_edited_in_flight() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("= 5".to_string());
+
+        let mut evaluated = grid.cells.clone();
+        evaluated.get_mut(&(0, 0)).unwrap().value = Value::Int(5);
+        let changes = vec![CellChanged {
+            col: 0,
+            row: 0,
+            old: Value::Int(0),
+            new: Value::Int(5),
+            source: ChangeSource::Tick,
+        }];
+
+        // User committed a new value before the result came back
+        grid.get_cell_mut_or_create(0, 0).set_raw("7".to_string());
+        let applied = merge_result(&mut grid, EvalResult { cells: evaluated, changes });
+
+        assert!(applied.is_empty());
+        assert_eq!(grid.get_cell(0, 0).unwrap().raw, "7");
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(0));
+    }
+}
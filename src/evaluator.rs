@@ -1,15 +1,34 @@
+use bevy::platform::time::Instant;
+use std::collections::HashMap;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::cell::{parse_literal, ErrorCode};
+use crate::eval_worker::{merge_result, EvalWorker};
+use crate::events::{CellChanged, ChangeSource};
 use crate::formula::{build_context, evaluate_formula};
+use crate::gpu_eval::GpuEvaluator;
 use crate::grid_state::GridState;
+use crate::headers::HeaderLabels;
+use crate::history::TickHistory;
 
 /// Controls tick-based evaluation
-#[derive(Resource)]
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TickControl {
     /// When true, formulas auto-evaluate every 0.1s
     pub auto_tick_enabled: bool,
     /// When true, trigger one immediate evaluation and reset to false
+    #[serde(skip)]
     pub manual_tick_requested: bool,
+    /// Number of ticks evaluated so far
+    pub tick_count: u64,
+    /// Cells whose value the last tick changed
+    #[serde(skip)]
+    pub last_tick_changes: usize,
+    /// How long the last tick took to evaluate, in milliseconds
+    #[serde(skip)]
+    pub last_tick_ms: Option<f64>,
 }
 
 impl Default for TickControl {
@@ -17,6 +36,9 @@ impl Default for TickControl {
         Self {
             auto_tick_enabled: false, // Off by default
             manual_tick_requested: false,
+            tick_count: 0,
+            last_tick_changes: 0,
+            last_tick_ms: None,
         }
     }
 }
@@ -39,12 +61,68 @@ impl Default for EvaluationTimer {
 /// Runs every frame, but only evaluates when:
 /// - Manual tick is requested, OR
 /// - Auto-tick is enabled AND timer fires
+///
+/// When a `GpuEvaluator` is present, ticks with enough GPU-eligible formulas
+/// are dispatched there; once the results are read back on a later frame,
+/// the rest of the tick is evaluated like any other. When an `EvalWorker` is
+/// present (native builds) that happens off-thread and is merged back on a
+/// later frame, or it runs inline
+/// Paused while the history scrubber is active; ticks are only recorded
+/// where there's a `TickHistory` (not on the headless server)
 pub fn tick_evaluation_system(
     time: Res<Time>,
     mut timer: ResMut<EvaluationTimer>,
     mut tick_control: ResMut<TickControl>,
     mut grid_state: ResMut<GridState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    mut history: Option<ResMut<TickHistory>>,
+    mut worker: Option<ResMut<EvalWorker>>,
+    mut gpu: Option<ResMut<GpuEvaluator>>,
+    mut submitted: Local<Option<Instant>>,
 ) {
+    // Merge a finished off-thread tick (dropped if the user started scrubbing meanwhile)
+    if let Some(worker) = worker.as_deref_mut() {
+        if let Some(result) = worker.poll() {
+            if !scrubbing(&history) {
+                let changes = merge_result(&mut grid_state, result);
+                finish_tick(&mut tick_control, &grid_state, history.as_deref_mut(), submitted.take(), changes.len());
+                cell_changed.write_batch(changes);
+            }
+        }
+
+        // Wait for the in-flight tick before starting another
+        if worker.in_flight {
+            return;
+        }
+    }
+
+    // A GPU dispatch read back: the rest of its tick goes to the worker if
+    // there is one, else it's evaluated here (dropped while scrubbing, as above)
+    if let Some(gpu) = gpu.as_deref_mut() {
+        if let Some(request) = gpu.poll() {
+            if !scrubbing(&history) {
+                if let Some(worker) = worker.as_deref_mut() {
+                    worker.send(request);
+                    return;
+                }
+                let changes = merge_result(&mut grid_state, request.evaluate());
+                finish_tick(&mut tick_control, &grid_state, history.as_deref_mut(), submitted.take(), changes.len());
+                cell_changed.write_batch(changes);
+            }
+        }
+
+        if gpu.in_flight() {
+            return;
+        }
+    }
+
+    if scrubbing(&history) {
+        if tick_control.manual_tick_requested {
+            tick_control.manual_tick_requested = false;
+        }
+        return;
+    }
+
     // Check if we should evaluate this frame
     let should_evaluate = if tick_control.manual_tick_requested {
         tick_control.manual_tick_requested = false; // Reset flag
@@ -60,50 +138,209 @@ pub fn tick_evaluation_system(
         return;
     }
 
+    let started = Instant::now();
+    if gpu.as_deref_mut().is_some_and(|gpu| gpu.submit(&grid_state))
+        || worker.as_deref_mut().is_some_and(|worker| worker.submit(&grid_state))
+    {
+        *submitted = Some(started);
+        return;
+    }
+
+    let changes = evaluate_tick(&mut grid_state);
+    finish_tick(&mut tick_control, &grid_state, history.as_deref_mut(), Some(started), changes.len());
+    cell_changed.write_batch(changes);
+
+    // GridState is automatically marked as changed because we used ResMut
+}
+
+/// Count a tick whose `changes` cell values are in the grid, timed from
+/// `started`, and record it where there's history
+fn finish_tick(
+    tick_control: &mut TickControl,
+    grid_state: &GridState,
+    history: Option<&mut TickHistory>,
+    started: Option<Instant>,
+    changes: usize,
+) {
+    tick_control.last_tick_changes = changes;
+    tick_control.last_tick_ms = started.map(|at| at.elapsed().as_secs_f64() * 1000.0);
+    tick_control.tick_count += 1;
+    if let Some(history) = history {
+        history.record(tick_control.tick_count, grid_state);
+    }
+}
+
+fn scrubbing(history: &Option<ResMut<TickHistory>>) -> bool {
+    history.as_ref().is_some_and(|history| history.is_scrubbing())
+}
+
+/// Evaluate every cell once against a snapshot of the current values
+/// This is the single source of truth for tick semantics, shared by the
+/// Bevy system and headless callers (`GridState::run_ticks`)
+///
+/// Ordering guarantees:
+/// - Every formula reads the values from the *previous* tick (snapshot semantics),
+///   so results never depend on the order cells are visited
+/// - Cells are visited in row-major order (row, then col), so the returned
+///   `CellChanged` list is deterministic across runs
+pub fn evaluate_tick(grid_state: &mut GridState) -> Vec<CellChanged> {
+    evaluate_tick_with(grid_state, &HashMap::new())
+}
+
+/// `evaluate_tick`, taking the new values of the formula cells in `computed`
+/// as given (the GPU's results, see `gpu_eval`) instead of evaluating them
+pub fn evaluate_tick_with(
+    grid_state: &mut GridState,
+    computed: &HashMap<(i32, i32), evalexpr::Value>,
+) -> Vec<CellChanged> {
     // Phase 1: Build context from current grid values
-    let context = build_context(&grid_state);
+    let context = build_context(grid_state);
 
     // Phase 2: Evaluate all cells
     // Collect cells to avoid borrow checker issues
-    // We store (col, row) as key
-    let cells_to_evaluate: Vec<((i32, i32), String, bool)> = grid_state
+    // We store (col, row) as key, sorted row-major so HashMap order never leaks out
+    let mut cells_to_evaluate: Vec<((i32, i32), String, bool)> = grid_state
         .cells
         .iter()
-        .map(|(key, cell)| (*key, cell.raw.clone(), cell.is_formula))
+        .map(|(key, cell)| (key, cell.raw.clone(), cell.is_formula))
         .collect();
+    cells_to_evaluate.sort_by_key(|((col, row), _, _)| (*row, *col));
+
+    let mut changes = Vec::new();
 
     for (key, raw, is_formula) in cells_to_evaluate {
-        // We can use get_mut because we hold the key and grid_state is ResMut
-        // But we need to use 'if let Some' just in case, though keys came from it.
-        if let Some(cell) = grid_state.cells.get_mut(&key) {
-            if is_formula {
-                // Strip leading '=' and whitespace
-                let expr = raw.trim_start().trim_start_matches('=').trim();
-
-                match evaluate_formula(expr, &context) {
-                    Ok(new_value) => {
-                        cell.value = new_value;
-                        cell.error = false;
-                    }
-                    Err(_) => {
-                        cell.error = true;
-                        cell.value = evalexpr::Value::Int(0);
-                    }
-                }
-            } else {
-                // Parse literal value
-                // Try to parse as number first (Int or Float), else String
-                if let Ok(i) = raw.trim().parse::<i64>() {
-                    cell.value = evalexpr::Value::Int(i);
-                } else if let Ok(f) = raw.trim().parse::<f64>() {
-                    cell.value = evalexpr::Value::Float(f);
-                } else {
-                    cell.value = evalexpr::Value::String(raw.clone());
-                }
-                cell.error = false;
+        let Some(cell) = grid_state.cells.get(&key) else { continue };
+        let (value, error, error_code) = if let Some(value) = computed.get(&key).filter(|_| is_formula) {
+            (value.clone(), false, cell.error_code)
+        } else if is_formula {
+            match evaluate_source(&grid_state.headers, &raw, &context) {
+                Ok(new_value) => (new_value, false, cell.error_code),
+                Err(e) => (evalexpr::Value::Int(0), true, ErrorCode::classify(&raw, &e)),
             }
+        } else {
+            (parse_literal(&raw), false, cell.error_code)
+        };
+
+        // Leave unchanged cells alone, so chunks shared with snapshots stay shared
+        if value == cell.value && error == cell.error && error_code == cell.error_code {
+            continue;
+        }
+        let old_value = cell.value.clone();
+        let Some(cell) = grid_state.cells.get_evaluated_mut(&key) else { continue };
+        cell.value = value;
+        cell.error = error;
+        cell.error_code = error_code;
+
+        if cell.value != old_value {
+            changes.push(CellChanged {
+                col: key.0,
+                row: key.1,
+                old: old_value,
+                new: cell.value.clone(),
+                source: ChangeSource::Tick,
+            });
         }
     }
 
-    // GridState is automatically marked as changed because we used ResMut
+    changes
+}
+
+/// Evaluate a formula cell's source text (`= ...`), resolving structured references
+fn evaluate_source(
+    headers: &HeaderLabels,
+    raw: &str,
+    context: &evalexpr::HashMapContext,
+) -> Result<evalexpr::Value, String> {
+    // Strip leading '=' and whitespace
+    let expr = raw.trim_start().trim_start_matches('=').trim();
+    let expr = headers.resolve(expr)?;
+    evaluate_formula(&expr, context).map_err(|e| e.to_string())
+}
+
+/// Why an errored formula cell failed
+/// Ticks only keep the error flag, so this re-evaluates the formula against
+/// the current values; None if the cell isn't an errored formula
+pub fn formula_error(grid_state: &GridState, col: i32, row: i32) -> Option<String> {
+    let cell = grid_state.get_cell(col, row).filter(|c| c.is_formula && c.error)?;
+    evaluate_source(&grid_state.headers, &cell.raw, &build_context(grid_state)).err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::Value;
+
+    #[test]
+    fn test_snapshot_semantics() {
+        // Mutually referencing cells must both read the previous tick's values
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("= B0 + 1".to_string());
+        grid.get_cell_mut_or_create(1, 0).set_raw("= A0 + 1".to_string());
+
+        grid.run_ticks(1);
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(1));
+        assert_eq!(grid.get_cell(1, 0).unwrap().value, Value::Int(1));
+
+        grid.run_ticks(1);
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(2));
+        assert_eq!(grid.get_cell(1, 0).unwrap().value, Value::Int(2));
+    }
+
+    #[test]
+    fn test_changes_are_row_major() {
+        let mut grid = GridState::new();
+        for (col, row) in [(3, 1), (0, 2), (2, 0), (1, 1), (0, 0)] {
+            grid.get_cell_mut_or_create(col, row).set_raw("7".to_string());
+        }
+
+        let order: Vec<(i32, i32)> = grid.tick().iter().map(|c| (c.col, c.row)).collect();
+        assert_eq!(order, vec![(0, 0), (2, 0), (1, 1), (3, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn test_negative_coordinates_evaluate() {
+        let mut grid = GridState::new();
+        grid.set_range((-2, -1), [["4", "= _B_1 * 2"]]);
+        grid.set_range((0, 0), [["= _A_1 + _B_1"]]);
+
+        grid.run_ticks(2);
+
+        assert_eq!(grid.get_cell(-1, -1).unwrap().value, Value::Int(8));
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(12));
+    }
+
+    #[test]
+    fn test_computed_values_stand_in() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["2", "= A0 * 3", "= A0 + 1"]]);
+        grid.run_ticks(1);
+
+        // B0 comes from elsewhere (the GPU); C0 is still evaluated here
+        let computed = HashMap::from([((1, 0), Value::Int(60)), ((0, 0), Value::Int(99))]);
+        evaluate_tick_with(&mut grid, &computed);
+        assert_eq!(grid.get_cell(1, 0).unwrap().value, Value::Int(60));
+        assert_eq!(grid.get_cell(2, 0).unwrap().value, Value::Int(3));
+        // Literals never take a computed value
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(2));
+    }
+
+    #[test]
+    fn test_structured_references() {
+        let mut grid = GridState::new();
+        grid.headers.set_col_label(1, "Price");
+        grid.set_range((1, 0), [["4"], ["= [Price]0 * 2"], ["= [Qty]0"]]);
+
+        grid.run_ticks(1);
+        assert_eq!(grid.get_cell(1, 1).unwrap().value, Value::Int(8));
+        // Unknown labels are formula errors
+        assert!(grid.get_cell(1, 2).unwrap().error);
+        assert_eq!(formula_error(&grid, 1, 2).as_deref(), Some("unknown column [Qty]"));
+        assert_eq!(grid.get_cell(1, 2).unwrap().error_code, ErrorCode::Name);
+        assert_eq!(formula_error(&grid, 1, 1), None);
+
+        // Renaming the header breaks references to the old name
+        grid.headers.set_col_label(1, "Cost");
+        grid.run_ticks(1);
+        assert!(grid.get_cell(1, 1).unwrap().error);
+    }
 }
@@ -0,0 +1,24 @@
+use bevy::prelude::*;
+use evalexpr::Value;
+
+/// What caused a cell's value to change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeSource {
+    /// Recomputed during a tick evaluation
+    Tick,
+    /// Committed from the cell editor
+    Edit,
+    /// Set by a data feed (see `feeds`)
+    Feed,
+}
+
+/// Emitted whenever a cell's computed value changes
+/// Host systems (audio, game logic, exporters) can read these instead of polling `GridState`
+#[derive(Message, Clone, Debug)]
+pub struct CellChanged {
+    pub col: i32,
+    pub row: i32,
+    pub old: Value,
+    pub new: Value,
+    pub source: ChangeSource,
+}
@@ -0,0 +1,119 @@
+use crate::cell::Cell;
+use crate::grid_state::{CellRange, GridState};
+
+/// Cell size in exported images, the same as on screen
+const CELL_WIDTH: u32 = 80;
+const CELL_HEIGHT: u32 = 30;
+
+const GRID_LINE: &str = "#d0d0d0";
+
+/// Where Export image writes the PNG (native builds)
+#[cfg(not(target_arch = "wasm32"))]
+pub const IMAGE_PATH: &str = "gregsheet.png";
+
+/// Logical columns and rows of a range that are showing (hidden lines skipped)
+pub fn shown_lines(grid: &GridState, range: CellRange) -> (Vec<i32>, Vec<i32>) {
+    let cols = (range.min_col..=range.max_col).filter(|c| !grid.layout.cols.is_hidden(*c)).collect();
+    let rows = (range.min_row..=range.max_row).filter(|r| !grid.layout.rows.is_hidden(*r)).collect();
+    (cols, rows)
+}
+
+/// One SVG of the given columns and rows side by side: backgrounds, grid lines
+/// and each cell's own SVG (from `cell_svg`, drawn at 80x30)
+pub fn sheet_svg(grid: &GridState, cols: &[i32], rows: &[i32], mut cell_svg: impl FnMut(&Cell, i32, i32) -> String) -> String {
+    let (width, height) = (cols.len() as u32 * CELL_WIDTH, rows.len() as u32 * CELL_HEIGHT);
+    let mut elements = format!(r##"<rect width="{}" height="{}" fill="white"/>"##, width, height);
+    for (y, &row) in rows.iter().enumerate() {
+        for (x, &col) in cols.iter().enumerate() {
+            let (x, y) = (x as u32 * CELL_WIDTH, y as u32 * CELL_HEIGHT);
+            let cell = grid.get_cell(col, row);
+            let style = cell.map(|c| grid.theme.resolve(c.style)).unwrap_or_default();
+            let fill = style
+                .background
+                .map(|[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
+                .unwrap_or_else(|| "none".to_string());
+            elements.push_str(&format!(
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="{}" stroke-width="1"/>"##,
+                x, y, CELL_WIDTH, CELL_HEIGHT, fill, GRID_LINE
+            ));
+            if let Some(cell) = cell {
+                elements.push_str(&format!(r##"<g transform="translate({} {})">{}</g>"##, x, y, cell_svg(cell, col, row)));
+            }
+        }
+    }
+    format!(r##"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">{}</svg>"##, width.max(1), height.max(1), elements)
+}
+
+/// Rasterize an SVG (e.g. from `sheet_svg`) to PNG bytes at its own size
+pub fn render_png(svg: &str) -> Result<Vec<u8>, String> {
+    let mut fontdb = usvg::fontdb::Database::new();
+    fontdb.load_system_fonts();
+    let options = usvg::Options { fontdb: std::sync::Arc::new(fontdb), ..Default::default() };
+
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("image too large")?;
+    resvg::render(&tree, tiny_skia::Transform::identity(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+/// Store an exported image: `IMAGE_PATH` natively, a slot the page downloads
+/// from on wasm
+pub fn write_png(bytes: Vec<u8>) -> Result<(), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let result = std::fs::write(IMAGE_PATH, bytes).map_err(|e| e.to_string());
+    #[cfg(target_arch = "wasm32")]
+    let result = {
+        web::store(bytes);
+        Ok(())
+    };
+    result
+}
+
+/// Browsers have no filesystem: the page fetches the last export through this
+/// export and offers it as a download
+#[cfg(target_arch = "wasm32")]
+pub mod web {
+    use std::sync::Mutex;
+    use wasm_bindgen::prelude::*;
+
+    static EXPORTED: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+    pub fn store(bytes: Vec<u8>) {
+        *EXPORTED.lock().unwrap() = Some(bytes);
+    }
+
+    /// PNG bytes of the last Export image
+    #[wasm_bindgen]
+    pub fn exported_image() -> Option<Vec<u8>> {
+        EXPORTED.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sheet_image() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1", "2", "3"], ["4", "5", "6"]]);
+        grid.get_cell_mut_or_create(0, 0).style.background = Some([0xff, 0, 0]);
+        grid.layout.cols.hide(1);
+
+        let (cols, rows) = shown_lines(&grid, CellRange::new((0, 0), (2, 1)));
+        assert_eq!((cols.as_slice(), rows.as_slice()), ([0, 2].as_slice(), [0, 1].as_slice()));
+
+        let mut drawn = Vec::new();
+        let svg = sheet_svg(&grid, &cols, &rows, |cell, _, _| {
+            drawn.push(cell.raw.clone());
+            String::new()
+        });
+        assert_eq!(drawn, ["1", "3", "4", "6"]);
+        assert!(svg.contains(r#"width="160" height="60""#));
+        assert!(svg.contains("#ff0000"));
+
+        let png = render_png(&svg).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+}
@@ -0,0 +1,347 @@
+use bevy::prelude::*;
+use evalexpr::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::cell::{parse_literal, ErrorCode};
+use crate::events::{CellChanged, ChangeSource};
+use crate::formula::coord_to_name;
+use crate::grid_state::GridState;
+
+/// Seconds between polls when a feed spec doesn't give one
+const DEFAULT_INTERVAL: f32 = 10.0;
+/// Seconds to wait before reconnecting a dropped stream
+const RECONNECT_DELAY: f32 = 5.0;
+
+/// Where a feed's data comes from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FeedSource {
+    /// GET the URL every `interval` seconds
+    Http { url: String, interval: f32 },
+    /// Every message received on a WebSocket
+    WebSocket { url: String },
+}
+
+/// A cell bound to an external source: each response (or message) is parsed
+/// as JSON, the value at `pointer` is taken, and the cell is set to it
+/// An empty pointer takes the whole body (which needn't be JSON then)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DataFeed {
+    pub col: i32,
+    pub row: i32,
+    pub source: FeedSource,
+    /// JSON pointer (RFC 6901), e.g. `/data/0/price`
+    pub pointer: String,
+}
+
+impl DataFeed {
+    /// Parse a feed spec typed into a cell: `URL [pointer] [seconds]`, e.g.
+    /// `https://example.com/api /price 5` or `wss://example.com/stream /last`
+    pub fn parse(col: i32, row: i32, spec: &str) -> Option<Self> {
+        let mut parts = spec.split_whitespace();
+        let url = parts.next()?.to_string();
+        let mut pointer = String::new();
+        let mut interval = DEFAULT_INTERVAL;
+        for part in parts {
+            if part.starts_with('/') && pointer.is_empty() {
+                pointer = part.to_string();
+            } else {
+                interval = part.trim_end_matches('s').parse::<f32>().ok().filter(|s| *s > 0.0)?;
+            }
+        }
+        let source = if url.starts_with("http://") || url.starts_with("https://") {
+            FeedSource::Http { url, interval }
+        } else if url.starts_with("ws://") || url.starts_with("wss://") {
+            FeedSource::WebSocket { url }
+        } else {
+            return None;
+        };
+        Some(Self { col, row, source, pointer })
+    }
+}
+
+/// The value at `pointer` in a response body
+pub fn extract(body: &str, pointer: &str) -> Result<Value, String> {
+    let json = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(json) => json,
+        Err(_) if pointer.is_empty() => return Ok(parse_literal(body.trim())),
+        Err(e) => return Err(format!("response isn't JSON: {}", e)),
+    };
+    let found = json.pointer(pointer).ok_or_else(|| format!("nothing at {}", pointer))?;
+    Ok(json_value(found))
+}
+
+/// A JSON value as a cell value (arrays become tuples, objects their JSON text)
+pub(crate) fn json_value(json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Empty,
+        serde_json::Value::Bool(b) => Value::Boolean(*b),
+        serde_json::Value::Number(n) => n.as_i64().map(Value::Int).unwrap_or_else(|| Value::Float(n.as_f64().unwrap_or(f64::NAN))),
+        serde_json::Value::String(s) => Value::String(s.clone()),
+        serde_json::Value::Array(items) => Value::Tuple(items.iter().map(json_value).collect()),
+        serde_json::Value::Object(_) => Value::String(json.to_string()),
+    }
+}
+
+/// Cell text that reads back as `value`
+pub fn literal_text(value: &Value) -> String {
+    match value {
+        Value::Empty => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// A value (or error) fetched for a feed's cell
+pub struct FeedUpdate {
+    pub col: i32,
+    pub row: i32,
+    pub value: Result<Value, String>,
+}
+
+/// Write a fetched value into its feed's cell: the value replaces the cell's
+/// text (so ticks keep it), an error marks the cell `#N/A`
+/// Updates for coordinates no feed is bound to any more (one moved by a line
+/// insert, or removed) are dropped
+pub fn apply_update(grid: &mut GridState, update: FeedUpdate) -> Option<CellChanged> {
+    if !grid.feeds.iter().any(|f| (f.col, f.row) == (update.col, update.row)) {
+        return None;
+    }
+    let cell = grid.get_cell_mut_or_create(update.col, update.row);
+    let old = cell.value.clone();
+    match update.value {
+        Ok(value) => {
+            cell.set_raw(literal_text(&value));
+            cell.value = value;
+            cell.error = false;
+        }
+        Err(e) => {
+            warn!("Feed at {}: {}", coord_to_name(update.col, update.row), e);
+            cell.error = true;
+            cell.error_code = ErrorCode::NotAvailable;
+        }
+    }
+    (cell.value != old).then(|| CellChanged {
+        col: update.col,
+        row: update.row,
+        old,
+        new: cell.value.clone(),
+        source: ChangeSource::Feed,
+    })
+}
+
+type Updates = Arc<Mutex<Vec<FeedUpdate>>>;
+
+struct RunningFeed {
+    feed: DataFeed,
+    stop: Arc<AtomicBool>,
+}
+
+/// Runs the sheet's feeds off the render loop (a thread each natively, async
+/// tasks in browsers), collecting their values for `poll`
+#[derive(Resource, Default)]
+pub struct FeedRunner {
+    running: HashMap<(i32, i32), RunningFeed>,
+    updates: Updates,
+}
+
+impl FeedRunner {
+    /// Start feeds newly bound in the sheet; stop ones unbound or changed
+    pub fn sync(&mut self, feeds: &[DataFeed]) {
+        self.running.retain(|key, running| {
+            let keep = feeds.iter().any(|f| (f.col, f.row) == *key && *f == running.feed);
+            if !keep {
+                running.stop.store(true, Ordering::Relaxed);
+            }
+            keep
+        });
+        for feed in feeds {
+            if !self.running.contains_key(&(feed.col, feed.row)) {
+                let stop = Arc::new(AtomicBool::new(false));
+                backend::start(feed.clone(), stop.clone(), self.updates.clone());
+                self.running.insert((feed.col, feed.row), RunningFeed { feed: feed.clone(), stop });
+            }
+        }
+    }
+
+    /// Take the values that arrived since the last poll
+    pub fn poll(&self) -> Vec<FeedUpdate> {
+        std::mem::take(&mut *self.updates.lock().unwrap())
+    }
+}
+
+fn push(updates: &Updates, feed: &DataFeed, value: Result<Value, String>) {
+    updates.lock().unwrap().push(FeedUpdate { col: feed.col, row: feed.row, value });
+}
+
+/// One blocking thread per feed
+#[cfg(not(target_arch = "wasm32"))]
+mod backend {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Sleep for `seconds`, waking early if the feed is stopped
+    /// Returns false once it has been
+    fn wait(seconds: f32, stop: &AtomicBool) -> bool {
+        let mut left = Duration::from_secs_f32(seconds);
+        while !left.is_zero() && !stop.load(Ordering::Relaxed) {
+            let step = left.min(Duration::from_millis(100));
+            thread::sleep(step);
+            left -= step;
+        }
+        !stop.load(Ordering::Relaxed)
+    }
+
+    pub fn start(feed: DataFeed, stop: Arc<AtomicBool>, updates: Updates) {
+        thread::spawn(move || match feed.source.clone() {
+            FeedSource::Http { url, interval } => loop {
+                let body = ureq::get(&url)
+                    .call()
+                    .map_err(|e| e.to_string())
+                    .and_then(|response| response.into_string().map_err(|e| e.to_string()));
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                push(&updates, &feed, body.and_then(|body| extract(&body, &feed.pointer)));
+                if !wait(interval, &stop) {
+                    break;
+                }
+            },
+            FeedSource::WebSocket { url } => {
+                while !stop.load(Ordering::Relaxed) {
+                    match tungstenite::connect(url.as_str()) {
+                        Ok((mut socket, _)) => {
+                            // Checked between messages: a quiet stream stops on its next message
+                            while !stop.load(Ordering::Relaxed) {
+                                match socket.read() {
+                                    Ok(message) if message.is_text() || message.is_binary() => {
+                                        let body = message.into_text().map_err(|e| e.to_string());
+                                        push(&updates, &feed, body.and_then(|body| extract(&body, &feed.pointer)));
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        push(&updates, &feed, Err(e.to_string()));
+                                        break;
+                                    }
+                                }
+                            }
+                            let _ = socket.close(None);
+                        }
+                        Err(e) => push(&updates, &feed, Err(e.to_string())),
+                    }
+                    wait(RECONNECT_DELAY, &stop);
+                }
+            }
+        });
+    }
+}
+
+/// `fetch` and `WebSocket` on the browser's event loop
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::*;
+    use wasm_bindgen::{prelude::*, JsCast};
+    use wasm_bindgen_futures::JsFuture;
+
+    async fn sleep(seconds: f32) {
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            if let Some(window) = web_sys::window() {
+                let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, (seconds * 1000.0) as i32);
+            }
+        });
+        let _ = JsFuture::from(promise).await;
+    }
+
+    async fn fetch_text(url: &str) -> Result<String, String> {
+        let window = web_sys::window().ok_or("no window")?;
+        let response = JsFuture::from(window.fetch_with_str(url)).await.map_err(|e| format!("{:?}", e))?;
+        let response: web_sys::Response = response.dyn_into().map_err(|e| format!("{:?}", e))?;
+        if !response.ok() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        let text = JsFuture::from(response.text().map_err(|e| format!("{:?}", e))?)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        text.as_string().ok_or_else(|| "response isn't text".to_string())
+    }
+
+    pub fn start(feed: DataFeed, stop: Arc<AtomicBool>, updates: Updates) {
+        match feed.source.clone() {
+            FeedSource::Http { url, interval } => wasm_bindgen_futures::spawn_local(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    let body = fetch_text(&url).await;
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    push(&updates, &feed, body.and_then(|body| extract(&body, &feed.pointer)));
+                    sleep(interval).await;
+                }
+            }),
+            FeedSource::WebSocket { url } => connect(url, feed, stop, updates),
+        }
+    }
+
+    fn connect(url: String, feed: DataFeed, stop: Arc<AtomicBool>, updates: Updates) {
+        let socket = match web_sys::WebSocket::new(&url) {
+            Ok(socket) => socket,
+            Err(e) => return push(&updates, &feed, Err(format!("{:?}", e))),
+        };
+        // Checked between messages: a quiet stream stops on its next message
+        let (on_feed, on_stop, on_socket) = (feed.clone(), stop.clone(), socket.clone());
+        let on_updates = updates.clone();
+        let onmessage = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |event: web_sys::MessageEvent| {
+            if on_stop.load(Ordering::Relaxed) {
+                let _ = on_socket.close();
+                return;
+            }
+            let body = event.data().as_string().ok_or_else(|| "binary messages aren't supported".to_string());
+            push(&on_updates, &on_feed, body.and_then(|body| extract(&body, &on_feed.pointer)));
+        });
+        socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+
+        let onclose = Closure::once_into_js(move || {
+            if !stop.load(Ordering::Relaxed) {
+                push(&updates, &feed, Err("stream closed".to_string()));
+                wasm_bindgen_futures::spawn_local(async move {
+                    sleep(RECONNECT_DELAY).await;
+                    if !stop.load(Ordering::Relaxed) {
+                        connect(url, feed, stop, updates);
+                    }
+                });
+            }
+        });
+        socket.set_onclose(Some(onclose.unchecked_ref()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_specs_and_extraction() {
+        let feed = DataFeed::parse(1, 2, "https://example.com/api /data/0/price 2.5s").unwrap();
+        assert_eq!(feed.source, FeedSource::Http { url: "https://example.com/api".into(), interval: 2.5 });
+        assert_eq!(feed.pointer, "/data/0/price");
+        let feed = DataFeed::parse(0, 0, "wss://example.com/stream").unwrap();
+        assert_eq!((feed.source, feed.pointer.as_str()), (FeedSource::WebSocket { url: "wss://example.com/stream".into() }, ""));
+        assert!(DataFeed::parse(0, 0, "hello world").is_none());
+        assert!(DataFeed::parse(0, 0, "https://example.com -3").is_none());
+
+        let body = r#"{"data": [{"price": 12.5, "name": "AAPL", "up": true, "volume": 300}]}"#;
+        assert_eq!(extract(body, "/data/0/price"), Ok(Value::Float(12.5)));
+        assert_eq!(extract(body, "/data/0/volume"), Ok(Value::Int(300)));
+        assert_eq!(extract(body, "/data/0/up"), Ok(Value::Boolean(true)));
+        assert!(extract(body, "/data/1").is_err());
+        assert!(extract("42 degrees", "/temp").is_err());
+        // Plain-text bodies work without a pointer
+        assert_eq!(extract("42\n", ""), Ok(Value::Int(42)));
+
+        assert_eq!(parse_literal(&literal_text(&Value::Float(12.5))), Value::Float(12.5));
+        assert_eq!(literal_text(&Value::String("AAPL".into())), "AAPL");
+    }
+}
@@ -0,0 +1,163 @@
+use evalexpr::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::grid_state::{CellRange, GridState};
+
+/// Comparison used by a numeric filter criterion
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareOp {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+}
+
+impl CompareOp {
+    fn test(self, a: f64, b: f64) -> bool {
+        match self {
+            CompareOp::Less => a < b,
+            CompareOp::LessEq => a <= b,
+            CompareOp::Greater => a > b,
+            CompareOp::GreaterEq => a >= b,
+            CompareOp::Equal => a == b,
+            CompareOp::NotEqual => a != b,
+        }
+    }
+}
+
+/// Condition a column's cells must meet for their row to stay visible
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FilterCriterion {
+    /// Displayed text must be one of these (empty cells show as "")
+    Values(BTreeSet<String>),
+    /// Numeric value compared against a constant; non-numeric cells fail
+    Compare(CompareOp, f64),
+}
+
+/// Text a cell shows, which is what value filters match against
+pub fn display_text(grid: &GridState, col: i32, row: i32) -> String {
+    grid.get_cell(col, row)
+        .map(|c| grid.theme.resolve(c.style).number_format.format_value(&c.value))
+        .unwrap_or_default()
+}
+
+/// Filter on a declared table region
+/// The first row of the range is the header; rows below it are hidden unless
+/// every column's criterion matches
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TableFilter {
+    pub range: CellRange,
+    /// Criterion per column (absent = no filtering on that column)
+    pub criteria: BTreeMap<i32, FilterCriterion>,
+}
+
+impl TableFilter {
+    pub fn new(range: CellRange) -> Self {
+        Self {
+            range,
+            criteria: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_header(&self, col: i32, row: i32) -> bool {
+        row == self.range.min_row && col >= self.range.min_col && col <= self.range.max_col
+    }
+
+    /// Data rows (everything below the header)
+    fn data_rows(&self) -> std::ops::RangeInclusive<i32> {
+        self.range.min_row + 1..=self.range.max_row
+    }
+
+    pub fn row_matches(&self, grid: &GridState, row: i32) -> bool {
+        self.criteria.iter().all(|(&col, criterion)| match criterion {
+            FilterCriterion::Values(allowed) => allowed.contains(&display_text(grid, col, row)),
+            FilterCriterion::Compare(op, rhs) => match grid.get_cell(col, row).map(|c| &c.value) {
+                Some(Value::Int(i)) => op.test(*i as f64, *rhs),
+                Some(Value::Float(f)) => op.test(*f, *rhs),
+                _ => false,
+            },
+        })
+    }
+
+    /// Data rows that fail the filter and should be hidden
+    pub fn filtered_rows(&self, grid: &GridState) -> BTreeSet<i32> {
+        self.data_rows().filter(|&row| !self.row_matches(grid, row)).collect()
+    }
+
+    /// Distinct displayed values in a column, for the header dropdown
+    pub fn column_values(&self, grid: &GridState, col: i32) -> BTreeSet<String> {
+        self.data_rows().map(|row| display_text(grid, col, row)).collect()
+    }
+
+    /// Whether rows showing `value` in `col` are currently let through
+    pub fn allows_value(&self, col: i32, value: &str) -> bool {
+        match self.criteria.get(&col) {
+            Some(FilterCriterion::Values(allowed)) => allowed.contains(value),
+            _ => true,
+        }
+    }
+
+    /// Show or hide rows showing `value` in `col`
+    /// Toggling on a column without a value filter starts from "everything allowed"
+    pub fn toggle_value(&mut self, grid: &GridState, col: i32, value: &str) {
+        let all = self.column_values(grid, col);
+        let mut allowed = match self.criteria.get(&col) {
+            Some(FilterCriterion::Values(allowed)) => allowed.clone(),
+            _ => all.clone(),
+        };
+
+        if !allowed.remove(value) {
+            allowed.insert(value.to_string());
+        }
+        if allowed == all {
+            self.criteria.remove(&col);
+        } else {
+            self.criteria.insert(col, FilterCriterion::Values(allowed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> GridState {
+        let mut grid = GridState::new();
+        for (row, (name, qty)) in [("Item", "Qty"), ("a", "5"), ("b", "12"), ("c", "20")].iter().enumerate() {
+            for (col, raw) in [name, qty].iter().enumerate() {
+                let cell = grid.get_cell_mut_or_create(col as i32, row as i32);
+                cell.set_raw(raw.to_string());
+                cell.value = crate::cell::parse_literal(raw);
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_value_filter_toggles() {
+        let grid = table();
+        let mut filter = TableFilter::new(CellRange::new((0, 0), (1, 3)));
+
+        filter.toggle_value(&grid, 0, "b");
+        assert_eq!(filter.filtered_rows(&grid), BTreeSet::from([2]));
+        assert!(!filter.allows_value(0, "b"));
+
+        // Allowing everything again drops the criterion
+        filter.toggle_value(&grid, 0, "b");
+        assert!(filter.criteria.is_empty());
+        assert!(filter.filtered_rows(&grid).is_empty());
+    }
+
+    #[test]
+    fn test_compare_filter() {
+        let grid = table();
+        let mut filter = TableFilter::new(CellRange::new((0, 0), (1, 3)));
+        filter.criteria.insert(1, FilterCriterion::Compare(CompareOp::Greater, 10.0));
+
+        // Header row is never filtered
+        assert_eq!(filter.filtered_rows(&grid), BTreeSet::from([1]));
+    }
+}
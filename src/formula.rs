@@ -3,9 +3,16 @@ use evalexpr::{HashMapContext, Value, ContextWithMutableVariables};
 use crate::grid_state::GridState;
 
 /// Convert (col, row) to Excel-style name: A0, B0, ... Z0, AA0, AB0, etc.
+/// Negative coordinates are written with a leading `_`: (-1, 0) -> "_A0", (0, -3) -> "A_3"
 pub fn coord_to_name(col: i32, row: i32) -> String {
+    col_name(col) + &row_name(row)
+}
+
+/// Column part of a cell name: 0 -> "A", 26 -> "AA", -1 -> "_A"
+pub fn col_name(col: i32) -> String {
     let mut name = String::new();
-    let mut c = col;
+    // Negative columns mirror the positive ones: -1 -> _A, -27 -> _AA
+    let mut c = if col < 0 { -(col as i64) - 1 } else { col as i64 };
 
     // Convert column number to letters (A-Z, AA-AZ, BA-BZ, etc.)
     loop {
@@ -17,8 +24,200 @@ pub fn coord_to_name(col: i32, row: i32) -> String {
         c -= 1; // Adjust for 0-indexing
     }
 
-    // Reverse to get correct order, then append row number
-    name.chars().rev().collect::<String>() + &row.to_string()
+    if col < 0 {
+        name.push('_');
+    }
+    // Reverse to get correct order
+    name.chars().rev().collect()
+}
+
+/// Row part of a cell name: 3 -> "3", -3 -> "_3"
+pub fn row_name(row: i32) -> String {
+    if row < 0 {
+        format!("_{}", -(row as i64))
+    } else {
+        row.to_string()
+    }
+}
+
+/// Parse an Excel-style name back to (col, row): "A0" -> (0, 0), "AA10" -> (26, 10),
+/// "_A_2" -> (-1, -2)
+/// Returns None if the name isn't letters followed by digits (each optionally `_`-prefixed)
+pub fn name_to_coord(name: &str) -> Option<(i32, i32)> {
+    let (col_negative, name) = match name.strip_prefix('_') {
+        Some(rest) => (true, rest),
+        None => (false, name),
+    };
+    let letters_len = name.chars().take_while(|c| c.is_ascii_uppercase()).count();
+    let (letters, digits) = name.split_at(letters_len);
+    let (row_negative, digits) = match digits.strip_prefix('_') {
+        Some(rest) => (true, rest),
+        None => (false, digits),
+    };
+    if letters.is_empty() || digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    // Bijective base-26: A=1 .. Z=26, then shift back to 0-indexing
+    let mut col: i64 = 0;
+    for c in letters.bytes() {
+        col = col * 26 + (c - b'A' + 1) as i64;
+        if col > i32::MAX as i64 {
+            return None;
+        }
+    }
+    let col = if col_negative { -col } else { col - 1 };
+
+    let row = digits.parse::<i64>().ok()?;
+    // "_0" isn't a valid row: zero is never negative
+    if row_negative && row == 0 {
+        return None;
+    }
+    let row = i32::try_from(if row_negative { -row } else { row }).ok()?;
+
+    Some((col as i32, row))
+}
+
+/// A cell reference inside a formula: `B3`, `$B3`, `B$3` or `$B$3`
+/// A `$` anchors the column/row so it isn't shifted when the formula is copied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellRef {
+    pub col: i32,
+    pub row: i32,
+    pub col_absolute: bool,
+    pub row_absolute: bool,
+}
+
+impl CellRef {
+    /// Format back to formula text, keeping the anchors
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}{}{}{}",
+            if self.col_absolute { "$" } else { "" },
+            col_name(self.col),
+            if self.row_absolute { "$" } else { "" },
+            row_name(self.row)
+        )
+    }
+
+    /// Shift the relative parts by (dx, dy), leaving anchored parts in place
+    /// The grid is unbounded, so this only fails if a coordinate overflows
+    pub fn offset(&self, dx: i32, dy: i32) -> Option<CellRef> {
+        let col = if self.col_absolute { self.col } else { self.col.checked_add(dx)? };
+        let row = if self.row_absolute { self.row } else { self.row.checked_add(dy)? };
+        Some(CellRef { col, row, ..*self })
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Try to parse a reference at the start of `chars`, returning it and its length
+fn parse_ref_at(chars: &[char]) -> Option<(CellRef, usize)> {
+    let mut i = 0;
+    let col_absolute = chars.first() == Some(&'$');
+    if col_absolute {
+        i += 1;
+    }
+    let letters_start = i;
+    if chars.get(i) == Some(&'_') {
+        i += 1;
+    }
+    while i < chars.len() && chars[i].is_ascii_uppercase() {
+        i += 1;
+    }
+    let letters: String = chars[letters_start..i].iter().collect();
+
+    let row_absolute = chars.get(i) == Some(&'$');
+    if row_absolute {
+        i += 1;
+    }
+    let digits_start = i;
+    if chars.get(i) == Some(&'_') {
+        i += 1;
+    }
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    let digits: String = chars[digits_start..i].iter().collect();
+
+    let (col, row) = name_to_coord(&(letters + &digits))?;
+    Some((CellRef { col, row, col_absolute, row_absolute }, i))
+}
+
+/// Rewrite every cell reference in a formula, leaving everything else
+/// (operators, function names, string literals) untouched
+/// The callback returns replacement text for a reference, or None to keep it
+pub fn rewrite_references(formula: &str, mut f: impl FnMut(CellRef) -> Option<String>) -> String {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut out = String::with_capacity(formula.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // Copy string literals verbatim
+        if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            out.extend(&chars[start..i]);
+            continue;
+        }
+
+        let starts_token = i == 0 || !is_ident_char(chars[i - 1]);
+        if starts_token && (c == '$' || c == '_' || c.is_ascii_uppercase()) {
+            if let Some((cell_ref, len)) = parse_ref_at(&chars[i..]) {
+                // `AB12x` or `F1(` are identifiers/functions, not references
+                let continues = chars
+                    .get(i + len)
+                    .is_some_and(|n| is_ident_char(*n) || *n == '(');
+                if !continues {
+                    match f(cell_ref) {
+                        Some(text) => out.push_str(&text),
+                        None => out.extend(&chars[i..i + len]),
+                    }
+                    i += len;
+                    continue;
+                }
+            }
+        }
+
+        out.push(c);
+        i += 1;
+    }
+
+    out
+}
+
+/// All cell references in a formula, in order of appearance
+pub fn references(formula: &str) -> Vec<CellRef> {
+    let mut refs = Vec::new();
+    rewrite_references(formula, |r| {
+        refs.push(r);
+        None
+    });
+    refs
+}
+
+/// Shift relative references by (dx, dy), as when copying a formula
+/// References that overflow the coordinate range become `#REF!`
+pub fn translate_formula(formula: &str, dx: i32, dy: i32) -> String {
+    rewrite_references(formula, |r| {
+        Some(r.offset(dx, dy).map(|r| r.to_text()).unwrap_or_else(|| "#REF!".to_string()))
+    })
+}
+
+/// Remove `$` anchors so the expression only contains plain variable names
+pub fn strip_anchors(formula: &str) -> String {
+    rewrite_references(formula, |r| Some(coord_to_name(r.col, r.row)))
 }
 
 /// Build evaluation context from current grid state
@@ -27,12 +226,13 @@ pub fn build_context(grid: &GridState) -> HashMapContext {
     let mut context = HashMapContext::new();
 
     for ((col, row), cell) in &grid.cells {
-        let var_name = coord_to_name(*col, *row);
+        let var_name = coord_to_name(col, row);
         let value = cell.value.clone();
 
         // Set the variable in the context
         let _ = context.set_value(var_name, value);
     }
+    grid.host_functions.add_to(&mut context);
 
     context
 }
@@ -43,6 +243,9 @@ pub fn evaluate_formula(
     expr: &str,
     context: &HashMapContext,
 ) -> Result<Value, evalexpr::EvalexprError> {
+    if expr.contains('$') {
+        return evalexpr::eval_with_context(&strip_anchors(expr), context);
+    }
     evalexpr::eval_with_context(expr, context)
 }
 
@@ -60,4 +263,44 @@ mod tests {
         assert_eq!(coord_to_name(0, 15), "A15");
         assert_eq!(coord_to_name(26, 10), "AA10");
     }
+
+    #[test]
+    fn test_name_to_coord() {
+        assert_eq!(name_to_coord("A0"), Some((0, 0)));
+        assert_eq!(name_to_coord("Z0"), Some((25, 0)));
+        assert_eq!(name_to_coord("AA10"), Some((26, 10)));
+        assert_eq!(name_to_coord("BZ250"), Some((77, 250)));
+        assert_eq!(name_to_coord("A"), None);
+        assert_eq!(name_to_coord("10"), None);
+        assert_eq!(name_to_coord("a0"), None);
+
+        assert_eq!(coord_to_name(-1, 0), "_A0");
+        assert_eq!(coord_to_name(-27, -5), "_AA_5");
+        assert_eq!(name_to_coord("A_0"), None);
+
+        for (col, row) in [(0, 0), (25, 3), (26, 0), (701, 9), (702, 42), (-1, -1), (-26, 7), (-27, -30)] {
+            assert_eq!(name_to_coord(&coord_to_name(col, row)), Some((col, row)));
+        }
+    }
+
+    #[test]
+    fn test_translate_formula() {
+        assert_eq!(translate_formula("= A0 + B1", 1, 2), "= B2 + C3");
+        assert_eq!(translate_formula("= $A0 + B$1 + $C$2", 1, 1), "= $A1 + C$1 + $C$2");
+        // Function names and string literals are left alone
+        assert_eq!(translate_formula("= min(A0, 3) + str::len(\"A0\")", 0, 1), "= min(A1, 3) + str::len(\"A0\")");
+        // The grid is unbounded: references can move into negative coordinates
+        assert_eq!(translate_formula("= A0 + 1", -1, -2), "= _A_2 + 1");
+        assert_eq!(translate_formula("= $A0 + A$0", -1, -1), "= $A_1 + _A$0");
+        assert_eq!(translate_formula("= A0", i32::MIN, 0), "= #REF!");
+    }
+
+    #[test]
+    fn test_references_and_anchors() {
+        let refs = references("= $B$2 * C10 - AB3");
+        assert_eq!(refs.len(), 3);
+        assert_eq!((refs[0].col, refs[0].row, refs[0].col_absolute), (1, 2, true));
+        assert_eq!((refs[2].col, refs[2].row), (27, 3));
+        assert_eq!(strip_anchors("= $B$2 + B$2"), "= B2 + B2");
+    }
 }
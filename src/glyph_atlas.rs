@@ -0,0 +1,125 @@
+use crate::cell::{CellStyle, HorizontalAlign};
+
+/// Size of one glyph in the atlas, in pixels (monospace, so also the advance)
+pub const GLYPH_WIDTH: u32 = 8;
+pub const GLYPH_HEIGHT: u32 = 16;
+/// Glyphs per atlas row
+const ATLAS_COLUMNS: u32 = 16;
+/// Printable ASCII, space to tilde
+const FIRST_GLYPH: char = ' ';
+const GLYPH_COUNT: u32 = 95;
+pub const ATLAS_WIDTH: u32 = ATLAS_COLUMNS * GLYPH_WIDTH;
+pub const ATLAS_HEIGHT: u32 = GLYPH_COUNT.div_ceil(ATLAS_COLUMNS) * GLYPH_HEIGHT;
+
+/// Characters that fit an 80-wide cell with 4px padding either side
+pub const MAX_CHARS: usize = 9;
+/// Padding either side of a cell's text, in pixels
+pub const TEXT_PADDING: u32 = 4;
+/// u32 words per viewport slot in the text buffer: a header, then the glyph
+/// indices packed four to a word
+pub const TEXT_STRIDE: usize = 1 + MAX_CHARS.div_ceil(4);
+
+/// Header bits: glyph count in bits 0-3, alignment (0 left, 1 center,
+/// 2 right) in bits 4-5
+const ALIGN_SHIFT: u32 = 4;
+const FLAG_BOLD: u32 = 1 << 6;
+const FLAG_ITALIC: u32 = 1 << 7;
+
+/// Atlas index of a character the atlas covers
+fn glyph_index(c: char) -> Option<u32> {
+    let index = (c as u32).checked_sub(FIRST_GLYPH as u32)?;
+    (index < GLYPH_COUNT).then_some(index)
+}
+
+/// Whether the grid shader can draw this text itself: short enough and
+/// entirely in the atlas
+/// Anything else (long text, other scripts) stays on the SVG path
+pub fn fits(text: &str) -> bool {
+    text.chars().count() <= MAX_CHARS && text.chars().all(|c| glyph_index(c).is_some())
+}
+
+/// Width in pixels a cell needs to show `text` in full, padding included
+pub fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * GLYPH_WIDTH + 2 * TEXT_PADDING
+}
+
+/// One slot of the text buffer for text that `fits` (all zeros: no text)
+pub fn encode(text: &str, style: &CellStyle) -> [u32; TEXT_STRIDE] {
+    let mut words = [0u32; TEXT_STRIDE];
+    let indices: Vec<u32> = text.chars().filter_map(glyph_index).take(MAX_CHARS).collect();
+    if indices.is_empty() {
+        return words;
+    }
+
+    let align = match style.align {
+        HorizontalAlign::Left => 0,
+        HorizontalAlign::Center => 1,
+        HorizontalAlign::Right => 2,
+    };
+    words[0] = indices.len() as u32 | align << ALIGN_SHIFT;
+    if style.bold {
+        words[0] |= FLAG_BOLD;
+    }
+    if style.italic {
+        words[0] |= FLAG_ITALIC;
+    }
+    for (i, index) in indices.into_iter().enumerate() {
+        words[1 + i / 4] |= index << (i % 4 * 8);
+    }
+    words
+}
+
+/// Rasterize the glyphs into a single-channel coverage atlas
+/// (`ATLAS_COLUMNS` glyphs per row, each in a `GLYPH_WIDTH`x`GLYPH_HEIGHT` box)
+pub fn render_atlas() -> Vec<u8> {
+    let mut glyphs = String::new();
+    for index in 0..GLYPH_COUNT {
+        let Some(c) = char::from_u32(FIRST_GLYPH as u32 + index) else { continue };
+        let x = (index % ATLAS_COLUMNS) * GLYPH_WIDTH;
+        // Baseline 12px down the box, leaving room for descenders
+        let y = (index / ATLAS_COLUMNS) * GLYPH_HEIGHT + 12;
+        let text = match c {
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '&' => "&amp;".to_string(),
+            _ => c.to_string(),
+        };
+        glyphs.push_str(&format!(r#"<text x="{}" y="{}" xml:space="preserve">{}</text>"#, x, y, text));
+    }
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}"><g font-family="monospace" font-size="13" fill="black">{}</g></svg>"#,
+        ATLAS_WIDTH, ATLAS_HEIGHT, glyphs
+    );
+
+    let mut fontdb = usvg::fontdb::Database::new();
+    fontdb.load_system_fonts();
+    let options = usvg::Options { fontdb: std::sync::Arc::new(fontdb), ..Default::default() };
+    let mut pixmap = tiny_skia::Pixmap::new(ATLAS_WIDTH, ATLAS_HEIGHT).expect("atlas size is non-zero");
+    if let Ok(tree) = usvg::Tree::from_str(&svg, &options) {
+        resvg::render(&tree, tiny_skia::Transform::identity(), &mut pixmap.as_mut());
+    }
+    // Black glyphs: coverage is the alpha channel
+    pixmap.pixels().iter().map(|p| p.alpha()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_slots() {
+        assert!(fits("-12.50"));
+        assert!(!fits("far too long for a cell"));
+        assert!(!fits("héllo"));
+
+        let style = CellStyle { bold: true, align: HorizontalAlign::Right, ..Default::default() };
+        let words = encode("AB 12", &style);
+        assert_eq!(words[0], 5 | 2 << ALIGN_SHIFT | FLAG_BOLD);
+        // 'A' is 33 past the space, 'B' 34, ' ' 0, '1' 17
+        assert_eq!(words[1], 33 | 34 << 8 | 17 << 24);
+        assert_eq!(words[2], 18);
+        assert_eq!(encode("", &style), [0; TEXT_STRIDE]);
+
+        assert_eq!(render_atlas().len(), (ATLAS_WIDTH * ATLAS_HEIGHT) as usize);
+    }
+}
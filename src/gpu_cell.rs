@@ -1,20 +1,58 @@
-use crate::cell::Cell;
+use evalexpr::Value;
+use std::ops::Range;
 
-/// Compact GPU representation of a cell (8 bytes total: 2 × u32)
+use crate::cell::{BorderLine, Borders, Cell, CellStyle};
+
+/// Compact GPU representation of a cell (20 bytes total: 5 × u32)
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct GpuCell {
-    /// Bitmask flags: Bit 0 = Selected, Bit 1 = Is Formula, Bit 2 = Error
+    /// Bitmask flags: Bit 0 = Selected, Bit 1 = Is Formula, Bit 2 = Error,
+    /// Bit 3 = Active Cell, Bit 4 = Hidden Columns Before, Bit 5 = Hidden Rows Before,
+    /// Bit 6 = Dropdown Arrow (filter header or list validation), Bit 7 = Filter Active,
+    /// Bits 8-11 = Selection Outline Edges, Bits 12-15 = Copy Source Edges (see `border_edges`),
+    /// Bit 16 = Table Header Row, Bit 17 = Shaded Table Band, Bit 18 = Numeric Value
     pub flags: u32,
+    /// sRGB background packed as RGBA, red in the low byte (WGSL's
+    /// `unpack4x8unorm` order); zero alpha means the sheet background
+    pub background: u32,
+    /// Text color, packed the same way; zero alpha means the default (black)
+    pub text_color: u32,
+    /// The cell's number as f64 bits, low word first, for the heatmap lens
+    /// (see `FLAG_NUMERIC`); WGSL has no f64, so the shader unpacks it
+    pub value: [u32; 2],
 }
 
 impl GpuCell {
     pub const FLAG_SELECTED: u32 = 1 << 0; // Bit 0
     pub const FLAG_FORMULA: u32 = 1 << 1;  // Bit 1
     pub const FLAG_ERROR: u32 = 1 << 2;    // Bit 2
+    pub const FLAG_ACTIVE: u32 = 1 << 3;   // Bit 3
+    pub const FLAG_HIDDEN_COLS_BEFORE: u32 = 1 << 4; // Bit 4
+    pub const FLAG_HIDDEN_ROWS_BEFORE: u32 = 1 << 5; // Bit 5
+    pub const FLAG_DROPDOWN: u32 = 1 << 6; // Bit 6
+    pub const FLAG_FILTER_ACTIVE: u32 = 1 << 7; // Bit 7
+    pub const FLAG_BAND_HEADER: u32 = 1 << 16; // Bit 16
+    pub const FLAG_BAND_STRIPE: u32 = 1 << 17; // Bit 17
+    pub const FLAG_NUMERIC: u32 = 1 << 18; // Bit 18
+    pub const FLAG_FILL_HANDLE: u32 = 1 << 19; // Bit 19
+    pub const SELECTION_EDGES_SHIFT: u32 = 8;
+    pub const COPY_EDGES_SHIFT: u32 = 12;
+    pub const EDGE_TOP: u32 = 1 << 0;
+    pub const EDGE_RIGHT: u32 = 1 << 1;
+    pub const EDGE_BOTTOM: u32 = 1 << 2;
+    pub const EDGE_LEFT: u32 = 1 << 3;
+    /// u32 words per cell in the shader buffer
+    pub const STRIDE: usize = 5;
+
+    /// An empty cell
+    pub fn empty(selected: bool) -> Self {
+        Self { flags: if selected { Self::FLAG_SELECTED } else { 0 }, ..Default::default() }
+    }
 
     /// Convert a CPU Cell to GPU representation
-    pub fn from_cell(cell: &Cell, selected: bool) -> Self {
+    /// `style` is the cell's resolved style (see `Theme::resolve`)
+    pub fn from_cell(cell: &Cell, style: &CellStyle, selected: bool) -> Self {
         let mut flags = 0u32;
 
         if selected {
@@ -26,14 +64,145 @@ impl GpuCell {
         if cell.error {
             flags |= Self::FLAG_ERROR;
         }
+        let value = match cell.value {
+            Value::Int(i) => Some(i as f64),
+            Value::Float(f) => Some(f),
+            _ => None,
+        };
+        if value.is_some() {
+            flags |= Self::FLAG_NUMERIC;
+        }
 
         Self {
             flags,
+            background: pack_color(style.background),
+            text_color: pack_color(style.text_color),
+            value: split_f64(value.unwrap_or_default()),
         }
     }
 
-    /// Convert GpuCell to one u32 value for the shader buffer
-    pub fn to_u32(self) -> u32 {
-        self.flags
+    /// The words this cell takes in the shader buffer
+    pub fn to_words(self) -> [u32; Self::STRIDE] {
+        [self.flags, self.background, self.text_color, self.value[0], self.value[1]]
+    }
+}
+
+/// Numbers of the numeric cells in a shader buffer (see `to_words`)
+pub fn numeric_values(words: &[u32]) -> impl Iterator<Item = f64> + '_ {
+    words
+        .chunks_exact(GpuCell::STRIDE)
+        .filter(|w| w[0] & GpuCell::FLAG_NUMERIC != 0)
+        .map(|w| f64::from_bits(u64::from(w[3]) | u64::from(w[4]) << 32))
+}
+
+/// An f64's bits as two words, low first
+fn split_f64(value: f64) -> [u32; 2] {
+    let bits = value.to_bits();
+    [bits as u32, (bits >> 32) as u32]
+}
+
+/// Edges of a cell in a region that lie on the region's outline: those whose
+/// neighbor (`inside(dx, dy)`, rows growing downward) is outside it
+/// Works for any set of cells, so several ranges each get their own outline
+pub fn border_edges(inside: impl Fn(i32, i32) -> bool) -> u32 {
+    [(0, -1, GpuCell::EDGE_TOP), (1, 0, GpuCell::EDGE_RIGHT), (0, 1, GpuCell::EDGE_BOTTOM), (-1, 0, GpuCell::EDGE_LEFT)]
+        .into_iter()
+        .filter(|&(dx, dy, _)| !inside(dx, dy))
+        .fold(0, |edges, (_, _, edge)| edges | edge)
+}
+
+/// u32 words per viewport slot in the border buffer: top, right, bottom, left
+pub const BORDER_STRIDE: usize = 4;
+
+/// A cell's border sides as shader words: RGB in the low bytes (red lowest),
+/// the line in the top byte (0 none, 1 thin, 2 thick, 3 dashed)
+pub fn border_words(borders: &Borders) -> [u32; BORDER_STRIDE] {
+    [borders.top, borders.right, borders.bottom, borders.left].map(|side| {
+        let line = match side.line {
+            BorderLine::None => return 0,
+            BorderLine::Thin => 1,
+            BorderLine::Thick => 2,
+            BorderLine::Dashed => 3,
+        };
+        let [r, g, b] = side.color.unwrap_or([0, 0, 0]);
+        u32::from_le_bytes([r, g, b, line])
+    })
+}
+
+/// Words of a shader buffer that need rewriting to go from `old` to `new`:
+/// None when they match, everything when the size changed
+pub fn dirty_span(old: &[u32], new: &[u32]) -> Option<Range<usize>> {
+    if old.len() != new.len() {
+        return Some(0..new.len());
+    }
+    let start = old.iter().zip(new).position(|(a, b)| a != b)?;
+    let end = old.len() - old.iter().rev().zip(new.iter().rev()).position(|(a, b)| a != b)?;
+    Some(start..end)
+}
+
+/// An opaque color as RGBA, red in the low byte; None packs to zero
+fn pack_color(color: Option<[u8; 3]>) -> u32 {
+    color.map_or(0, |[r, g, b]| u32::from_le_bytes([r, g, b, 0xff]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::BorderSide;
+
+    #[test]
+    fn test_colors_are_packed() {
+        let cell = Cell::default();
+        let style = CellStyle { background: Some([0x12, 0x34, 0x56]), ..Default::default() };
+        let gpu = GpuCell::from_cell(&cell, &style, true);
+        assert_eq!(gpu.to_words(), [GpuCell::FLAG_SELECTED, 0xff56_3412, 0, 0, 0]);
+
+        let style = CellStyle { text_color: Some([0xff, 0, 0]), ..Default::default() };
+        assert_eq!(GpuCell::from_cell(&cell, &style, false).text_color, 0xff00_00ff);
+        assert_eq!(GpuCell::empty(false).to_words(), [0; GpuCell::STRIDE]);
+
+        let number = Cell { value: Value::Float(2.5), ..Default::default() };
+        let words = GpuCell::from_cell(&number, &CellStyle::default(), false).to_words();
+        assert_eq!(numeric_values(&words).collect::<Vec<_>>(), [2.5]);
+    }
+
+    #[test]
+    fn test_values_keep_full_precision() {
+        // Neither fits an f32: 2^24 + 1, and a fraction on a large number
+        let values = [Value::Int(16_777_217), Value::Float(1_234_567.891), Value::Int(i64::from(i32::MAX) * 4)];
+        let words: Vec<u32> = values
+            .into_iter()
+            .flat_map(|value| GpuCell::from_cell(&Cell { value, ..Default::default() }, &CellStyle::default(), false).to_words())
+            .collect();
+        assert_eq!(numeric_values(&words).collect::<Vec<_>>(), [16_777_217.0, 1_234_567.891, 8_589_934_588.0]);
+    }
+
+    #[test]
+    fn test_border_edges() {
+        // Two separate ranges: A0:B0 and D0
+        let region = [(0, 0), (1, 0), (3, 0)];
+        let edges = |col: i32, row: i32| border_edges(|dx, dy| region.contains(&(col + dx, row + dy)));
+        assert_eq!(edges(0, 0), GpuCell::EDGE_TOP | GpuCell::EDGE_BOTTOM | GpuCell::EDGE_LEFT);
+        assert_eq!(edges(1, 0), GpuCell::EDGE_TOP | GpuCell::EDGE_BOTTOM | GpuCell::EDGE_RIGHT);
+        assert_eq!(edges(3, 0), 0b1111);
+    }
+
+    #[test]
+    fn test_border_words() {
+        let thick_red = BorderSide { line: BorderLine::Thick, color: Some([0xff, 0, 0]) };
+        let borders = Borders { top: thick_red, left: BorderSide { line: BorderLine::Dashed, color: None }, ..Default::default() };
+        assert_eq!(border_words(&borders), [0x0200_00ff, 0, 0, 0x0300_0000]);
+        // A color without a line draws nothing
+        let unlined = Borders { bottom: BorderSide { color: Some([1, 2, 3]), ..Default::default() }, ..Default::default() };
+        assert_eq!(border_words(&unlined), [0; BORDER_STRIDE]);
+    }
+
+    #[test]
+    fn test_dirty_span() {
+        let old = [1, 2, 3, 4, 5];
+        assert_eq!(dirty_span(&old, &old), None);
+        assert_eq!(dirty_span(&old, &[1, 9, 3, 9, 5]), Some(1..4));
+        assert_eq!(dirty_span(&old, &[1, 2, 3, 4, 6]), Some(4..5));
+        assert_eq!(dirty_span(&old, &[1, 2]), Some(0..2));
     }
 }
@@ -0,0 +1,876 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntries, BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor,
+    BufferInitDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline,
+    DownlevelFlags, MapMode, PipelineLayoutDescriptor, PollType, RawComputePipelineDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages,
+};
+use bevy::render::renderer::{RenderAdapter, RenderDevice, RenderQueue};
+use crossbeam_channel::{bounded, Receiver, TryRecvError};
+use evalexpr::Value;
+
+use crate::eval_worker::EvalRequest;
+use crate::formula::name_to_coord;
+use crate::grid_state::GridState;
+
+/// Arithmetic-only formula that can be evaluated by a WGSL compute kernel
+/// References are stored relative to the evaluated cell, so every cell sharing
+/// the same "shape" of formula (e.g. `= A0 + B0` filled down) compiles to one kernel
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KernelExpr {
+    Const(i32),
+    /// Cell reference relative to the evaluated cell
+    Ref { dx: i32, dy: i32 },
+    Neg(Box<KernelExpr>),
+    Binary(BinOp, Box<KernelExpr>, Box<KernelExpr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(i32),
+    Ref(i32, i32),
+    Op(char),
+    LParen,
+    RParen,
+}
+
+/// Tokenize an expression, bailing out on anything outside integer arithmetic
+fn tokenize(expr: &str) -> Option<Vec<Token>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            // Floats need f64 semantics, leave them to the CPU
+            if i < chars.len() && chars[i] == '.' {
+                return None;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Num(text.parse().ok()?));
+        } else if c.is_ascii_uppercase() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let (col, row) = name_to_coord(&text)?;
+            tokens.push(Token::Ref(col, row));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '%' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                _ => return None,
+            });
+            i += 1;
+        }
+    }
+
+    Some(tokens)
+}
+
+/// Recursive-descent parser producing a `KernelExpr` relative to (col, row)
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    col: i32,
+    row: i32,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Option<KernelExpr> {
+        let mut lhs = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            let op = if op == '+' { BinOp::Add } else { BinOp::Sub };
+            lhs = KernelExpr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Some(lhs)
+    }
+
+    // term := unary (('*' | '/' | '%') unary)*
+    fn term(&mut self) -> Option<KernelExpr> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/' | '%'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            let op = match op {
+                '*' => BinOp::Mul,
+                '/' => BinOp::Div,
+                _ => BinOp::Rem,
+            };
+            lhs = KernelExpr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Some(lhs)
+    }
+
+    // unary := '-' unary | primary
+    fn unary(&mut self) -> Option<KernelExpr> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.pos += 1;
+            return Some(KernelExpr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    // primary := number | reference | '(' expr ')'
+    fn primary(&mut self) -> Option<KernelExpr> {
+        match self.next()? {
+            Token::Num(n) => Some(KernelExpr::Const(n)),
+            Token::Ref(col, row) => Some(KernelExpr::Ref {
+                dx: col - self.col,
+                dy: row - self.row,
+            }),
+            Token::LParen => {
+                let inner = self.expr()?;
+                match self.next()? {
+                    Token::RParen => Some(inner),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Compile a formula expression (without the leading '=') for the cell at (col, row)
+/// Returns None if the formula uses anything beyond integer arithmetic on references
+pub fn compile_formula(expr: &str, col: i32, row: i32) -> Option<KernelExpr> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        col,
+        row,
+    };
+    let compiled = parser.expr()?;
+    if parser.pos != parser.tokens.len() {
+        return None;
+    }
+    Some(compiled)
+}
+
+impl KernelExpr {
+    /// Render as a WGSL expression, where `col`/`row` are the evaluated cell
+    pub fn to_wgsl(&self) -> String {
+        match self {
+            KernelExpr::Const(n) => format!("{}i", n),
+            KernelExpr::Ref { dx, dy } => format!("read_cell(col + {}, row + {})", dx, dy),
+            KernelExpr::Neg(inner) => format!("safe_sub(0i, {})", inner.to_wgsl()),
+            KernelExpr::Binary(op, lhs, rhs) => match op {
+                BinOp::Add => format!("safe_add({}, {})", lhs.to_wgsl(), rhs.to_wgsl()),
+                BinOp::Sub => format!("safe_sub({}, {})", lhs.to_wgsl(), rhs.to_wgsl()),
+                BinOp::Mul => format!("safe_mul({}, {})", lhs.to_wgsl(), rhs.to_wgsl()),
+                BinOp::Div => format!("safe_div({}, {})", lhs.to_wgsl(), rhs.to_wgsl()),
+                BinOp::Rem => format!("safe_rem({}, {})", lhs.to_wgsl(), rhs.to_wgsl()),
+            },
+        }
+    }
+}
+
+/// One formula cell dispatched to the GPU (16 bytes, matches `Job` in the kernel)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuJob {
+    pub col: i32,
+    pub row: i32,
+    /// Index into `GpuEvalPlan::kernels`
+    pub kernel: u32,
+    pub _pad: u32,
+}
+
+/// Split of one tick's formula cells between the GPU kernel and the CPU fallback
+#[derive(Clone, Debug, Default)]
+pub struct GpuEvalPlan {
+    /// Distinct compiled formulas, one WGSL function each
+    pub kernels: Vec<KernelExpr>,
+    pub jobs: Vec<GpuJob>,
+    /// Formula cells that must go through evalexpr on the CPU
+    pub cpu_fallback: Vec<(i32, i32)>,
+    /// Bottom corner of the dense value window uploaded as `prev_values`
+    pub origin: (i32, i32),
+    /// Size of the dense value window
+    pub size: (i32, i32),
+}
+
+/// Partition the grid's formula cells into GPU jobs and CPU fallbacks
+/// A formula is GPU-eligible when it compiles; it only depends on the raw
+/// text, so a plan holds until `CellStore::revision` changes. A job reading a
+/// cell that doesn't hold an integer fitting in i32 (the kernel's value type)
+/// fails on the GPU and is redone on the CPU
+/// Sheets whose cells spread over more than `MAX_WINDOW` cells go wholly to
+/// the CPU
+pub fn plan(grid: &GridState) -> GpuEvalPlan {
+    let mut plan = GpuEvalPlan::default();
+    let mut kernel_ids: HashMap<KernelExpr, u32> = HashMap::new();
+
+    let mut keys: Vec<(i32, i32)> = grid.cells.keys().collect();
+    keys.sort_by_key(|(col, row)| (*row, *col));
+
+    for (col, row) in keys {
+        let cell = &grid.cells[&(col, row)];
+        if !cell.is_formula {
+            continue;
+        }
+
+        let expr = cell.raw.trim_start().trim_start_matches('=').trim();
+        match compile_formula(expr, col, row) {
+            Some(kernel) => {
+                let next_id = kernel_ids.len() as u32;
+                let id = *kernel_ids.entry(kernel.clone()).or_insert_with(|| {
+                    plan.kernels.push(kernel);
+                    next_id
+                });
+                plan.jobs.push(GpuJob { col, row, kernel: id, _pad: 0 });
+            }
+            None => plan.cpu_fallback.push((col, row)),
+        }
+    }
+
+    // Dense window covering every cell, read by `read_cell` in the kernel
+    if let (Some(min_col), Some(max_col), Some(min_row), Some(max_row)) = (
+        grid.cells.keys().map(|k| k.0).min(),
+        grid.cells.keys().map(|k| k.0).max(),
+        grid.cells.keys().map(|k| k.1).min(),
+        grid.cells.keys().map(|k| k.1).max(),
+    ) {
+        // Spans across the whole sheet don't fit in an i32
+        let width = max_col as i64 - min_col as i64 + 1;
+        let height = max_row as i64 - min_row as i64 + 1;
+        if width.saturating_mul(height) > MAX_WINDOW {
+            plan.cpu_fallback.extend(plan.jobs.drain(..).map(|job| (job.col, job.row)));
+            plan.cpu_fallback.sort_by_key(|(col, row)| (*row, *col));
+            plan.kernels.clear();
+            return plan;
+        }
+        plan.origin = (min_col, min_row);
+        plan.size = (width as i32, height as i32);
+    }
+
+    plan
+}
+
+/// Pack current values into the dense `prev_values` window (row-major), as
+/// `[value, 1]` for integers that fit in i32 and `[0, 0]` for anything else
+/// (empty cells included), which jobs reading them fail on
+pub fn pack_values(grid: &GridState, plan: &GpuEvalPlan) -> Vec<[i32; 2]> {
+    let (width, height) = (plan.size.0 as i64, plan.size.1 as i64);
+    let mut values = vec![[0i32; 2]; (width * height) as usize];
+    for ((col, row), cell) in &grid.cells {
+        if let Value::Int(i) = cell.value {
+            let Ok(i) = i32::try_from(i) else { continue };
+            let (x, y) = (col as i64 - plan.origin.0 as i64, row as i64 - plan.origin.1 as i64);
+            if (0..width).contains(&x) && (0..height).contains(&y) {
+                values[(y * width + x) as usize] = [i, 1];
+            }
+        }
+    }
+    values
+}
+
+/// Generate the compute kernel for a plan
+/// Each job writes its result to `next_values[job]` and a non-zero
+/// `errors[job]` on out-of-window or non-integer reads, division by zero or a
+/// result outside i32 (those cells are redone on the CPU, which works in i64)
+/// `commit` then copies the results into `prev_values`, so the window stays
+/// on the GPU between ticks
+pub fn generate_wgsl(plan: &GpuEvalPlan) -> String {
+    let mut source = String::from(
+        r#"struct Params {
+    origin: vec2<i32>,
+    size: vec2<i32>,
+    job_count: u32,
+}
+
+struct Job {
+    col: i32,
+    row: i32,
+    kernel: u32,
+    _pad: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> jobs: array<Job>;
+@group(0) @binding(2) var<storage, read_write> prev_values: array<vec2<i32>>;
+@group(0) @binding(3) var<storage, read_write> next_values: array<i32>;
+@group(0) @binding(4) var<storage, read_write> errors: array<u32>;
+
+var<private> failed: bool;
+
+fn read_cell(col: i32, row: i32) -> i32 {
+    let rel = vec2<i32>(col, row) - params.origin;
+    if (any(rel < vec2<i32>(0)) || any(rel >= params.size)) {
+        failed = true;
+        return 0;
+    }
+    let cell = prev_values[u32(rel.y * params.size.x + rel.x)];
+    if (cell.y == 0) {
+        failed = true;
+    }
+    return cell.x;
+}
+
+fn safe_add(a: i32, b: i32) -> i32 {
+    let sum = a + b;
+    if (((a ^ sum) & (b ^ sum)) < 0) {
+        failed = true;
+    }
+    return sum;
+}
+
+fn safe_sub(a: i32, b: i32) -> i32 {
+    let difference = a - b;
+    if (((a ^ b) & (a ^ difference)) < 0) {
+        failed = true;
+    }
+    return difference;
+}
+
+fn safe_mul(a: i32, b: i32) -> i32 {
+    let product = a * b;
+    if (a != 0 && (product / a != b || (a == -1 && b == i32(-2147483648)))) {
+        failed = true;
+    }
+    return product;
+}
+
+fn safe_div(a: i32, b: i32) -> i32 {
+    if (b == 0 || (a == i32(-2147483648) && b == -1)) {
+        failed = true;
+        return 0;
+    }
+    return a / b;
+}
+
+fn safe_rem(a: i32, b: i32) -> i32 {
+    if (b == 0 || (a == i32(-2147483648) && b == -1)) {
+        failed = true;
+        return 0;
+    }
+    return a % b;
+}
+"#,
+    );
+
+    for (id, kernel) in plan.kernels.iter().enumerate() {
+        source.push_str(&format!(
+            "\nfn kernel_{}(col: i32, row: i32) -> i32 {{\n    return {};\n}}\n",
+            id,
+            kernel.to_wgsl()
+        ));
+    }
+
+    source.push_str(
+        r#"
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.job_count) {
+        return;
+    }
+    let job = jobs[id.x];
+    failed = false;
+    var value = 0i;
+    switch job.kernel {
+"#,
+    );
+    for id in 0..plan.kernels.len() {
+        source.push_str(&format!(
+            "        case {}u: {{ value = kernel_{}(job.col, job.row); }}\n",
+            id, id
+        ));
+    }
+    source.push_str(
+        r#"        default: { failed = true; }
+    }
+    next_values[id.x] = select(value, 0i, failed);
+    errors[id.x] = select(0u, 1u, failed);
+}
+
+@compute @workgroup_size(64)
+fn commit(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.job_count || errors[id.x] != 0u) {
+        return;
+    }
+    let job = jobs[id.x];
+    let rel = vec2<i32>(job.col, job.row) - params.origin;
+    prev_values[u32(rel.y * params.size.x + rel.x)] = vec2<i32>(next_values[id.x], 1);
+}
+"#,
+    );
+
+    source
+}
+
+/// Fewest GPU-eligible cells worth a dispatch; smaller ticks stay on the CPU
+const MIN_GPU_JOBS: usize = 1024;
+/// Largest value window (in cells) uploaded, so sparse sheets spread over huge
+/// coordinates stay on the CPU (see `plan`)
+const MAX_WINDOW: i64 = 1 << 24;
+
+/// Pipelines and buffers for one plan's shape, kept while it holds
+struct Resident {
+    /// `plan_key` of the plan they were built for
+    key: u64,
+    job_count: usize,
+    main: ComputePipeline,
+    commit: ComputePipeline,
+    bind_group: BindGroup,
+    values: Buffer,
+    next_values: Buffer,
+    errors: Buffer,
+    /// `next_values` then `errors`, mapped to read results back
+    readback: Buffer,
+    /// What `values` holds on the GPU, so a tick only uploads what the CPU changed
+    uploaded: Vec<[i32; 2]>,
+}
+
+/// The latest plan, kept while the grid's raw text doesn't change
+struct Planned {
+    /// `CellStore::revision` it was made from
+    revision: u64,
+    /// Its `plan_key`
+    key: u64,
+    plan: GpuEvalPlan,
+}
+
+/// A dispatch whose results are still being mapped for reading
+struct Pending {
+    /// The snapshot the dispatch read, handed back with the results
+    request: EvalRequest,
+    /// Whether mapping the readback buffer succeeded, once it's done
+    mapped: Receiver<bool>,
+}
+
+/// Evaluates the GPU-eligible formulas of a tick (see `plan`) with the
+/// renderer's device; inserted by `init` when the adapter runs compute shaders
+#[derive(Resource)]
+pub struct GpuEvaluator {
+    device: RenderDevice,
+    queue: RenderQueue,
+    planned: Option<Planned>,
+    resident: Option<Resident>,
+    pending: Option<Pending>,
+}
+
+/// Set up GPU evaluation if the renderer's adapter can run compute shaders
+/// (WebGL2 can't, and the headless server has no renderer)
+pub fn init(
+    mut commands: Commands,
+    device: Option<Res<RenderDevice>>,
+    queue: Option<Res<RenderQueue>>,
+    adapter: Option<Res<RenderAdapter>>,
+) {
+    let (Some(device), Some(queue), Some(adapter)) = (device, queue, adapter) else {
+        return;
+    };
+    if !adapter.get_downlevel_capabilities().flags.contains(DownlevelFlags::COMPUTE_SHADERS) {
+        info!("GPU evaluation unavailable: no compute shaders");
+        return;
+    }
+    commands.insert_resource(GpuEvaluator {
+        device: device.clone(),
+        queue: queue.clone(),
+        planned: None,
+        resident: None,
+        pending: None,
+    });
+}
+
+/// Identifies a plan's kernels, jobs and window; values aren't part of it
+fn plan_key(plan: &GpuEvalPlan) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    plan.kernels.hash(&mut hasher);
+    for job in &plan.jobs {
+        (job.col, job.row, job.kernel).hash(&mut hasher);
+    }
+    (plan.origin, plan.size).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer { ty: BufferBindingType::Storage { read_only }, has_dynamic_offset: false, min_binding_size: None },
+        count: None,
+    }
+}
+
+impl GpuEvaluator {
+    /// Compile the plan's kernel and upload its jobs and window
+    fn build(&self, plan: &GpuEvalPlan, key: u64, values: &[[i32; 2]]) -> Resident {
+        let device = &self.device;
+        let layout = device.create_bind_group_layout(
+            "gpu_eval_layout",
+            &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer { ty: BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, false),
+                storage_entry(3, false),
+                storage_entry(4, false),
+            ],
+        );
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("gpu_eval_pipeline_layout"),
+            bind_group_layouts: &[&*layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.wgpu_device().create_shader_module(ShaderModuleDescriptor {
+            label: Some("gpu_eval"),
+            source: ShaderSource::Wgsl(generate_wgsl(plan).into()),
+        });
+        let pipeline = |entry_point: &'static str| {
+            device.create_compute_pipeline(&RawComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        // Params: origin, size, job count, padded to 32 bytes
+        let params = [plan.origin.0, plan.origin.1, plan.size.0, plan.size.1, plan.jobs.len() as i32, 0, 0, 0];
+        let params = device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gpu_eval_params"),
+            contents: bytemuck::cast_slice(&params),
+            usage: BufferUsages::UNIFORM,
+        });
+        let jobs = device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gpu_eval_jobs"),
+            contents: bytemuck::cast_slice(&plan.jobs),
+            usage: BufferUsages::STORAGE,
+        });
+        let window = device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gpu_eval_values"),
+            contents: bytemuck::cast_slice(values),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let results_size = (plan.jobs.len() * 4) as u64;
+        let results = |label| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: results_size,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        let (next_values, errors) = (results("gpu_eval_next_values"), results("gpu_eval_errors"));
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("gpu_eval_readback"),
+            size: results_size * 2,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(
+            "gpu_eval_bind_group",
+            &layout,
+            &BindGroupEntries::sequential((
+                params.as_entire_binding(),
+                jobs.as_entire_binding(),
+                window.as_entire_binding(),
+                next_values.as_entire_binding(),
+                errors.as_entire_binding(),
+            )),
+        );
+
+        Resident {
+            key,
+            job_count: plan.jobs.len(),
+            main: pipeline("main"),
+            commit: pipeline("commit"),
+            bind_group,
+            values: window,
+            next_values,
+            errors,
+            readback,
+            uploaded: values.to_vec(),
+        }
+    }
+
+    /// Dispatch the GPU-eligible formulas against the grid's current values
+    /// Returns false when the tick isn't worth a dispatch or one is still in
+    /// flight, and the tick is evaluated on the CPU. The results are read back
+    /// asynchronously and picked up by `poll` on a later frame
+    pub fn submit(&mut self, grid: &GridState) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        let revision = grid.cells.revision();
+        if self.planned.as_ref().map(|p| p.revision) != Some(revision) {
+            // Sheets with too few formulas for a dispatch aren't worth planning
+            let formulas = grid.cells.iter().filter(|(_, cell)| cell.is_formula).count();
+            let plan = if formulas < MIN_GPU_JOBS { GpuEvalPlan::default() } else { plan(grid) };
+            self.planned = Some(Planned { revision, key: plan_key(&plan), plan });
+        }
+        let Some(planned) = &self.planned else {
+            return false;
+        };
+        let plan = &planned.plan;
+        if plan.jobs.len() < MIN_GPU_JOBS {
+            return false;
+        }
+        let values = pack_values(grid, plan);
+        if self.resident.as_ref().map(|r| r.key) != Some(planned.key) {
+            self.resident = Some(self.build(plan, planned.key, &values));
+        }
+        let Some(resident) = self.resident.as_mut() else {
+            return false;
+        };
+
+        // Upload the runs of the window that changed since the last tick (the
+        // CPU's cells and literals; the GPU's own results are already there)
+        let mut at = 0;
+        while at < values.len() {
+            if values[at] == resident.uploaded[at] {
+                at += 1;
+                continue;
+            }
+            let start = at;
+            while at < values.len() && values[at] != resident.uploaded[at] {
+                at += 1;
+            }
+            self.queue.write_buffer(&resident.values, (start * 8) as u64, bytemuck::cast_slice(&values[start..at]));
+            resident.uploaded[start..at].copy_from_slice(&values[start..at]);
+        }
+
+        let workgroups = resident.job_count.div_ceil(64) as u32;
+        let results_size = (resident.job_count * 4) as u64;
+        let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("gpu_eval") });
+        for pipeline in [&resident.main, &resident.commit] {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None, timestamp_writes: None });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &*resident.bind_group, &[]);
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&resident.next_values, 0, &resident.readback, 0, results_size);
+        encoder.copy_buffer_to_buffer(&resident.errors, 0, &resident.readback, results_size, results_size);
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, mapped) = bounded(1);
+        resident.readback.slice(..).map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result.is_ok());
+        });
+        self.pending = Some(Pending { request: EvalRequest::snapshot(grid), mapped });
+        true
+    }
+
+    /// True while a dispatch is waiting for its results
+    pub fn in_flight(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The dispatched tick, once its results are read back: the snapshot it
+    /// read, with each computed cell's new value in `computed`, ready to be
+    /// evaluated (the CPU fallbacks) and merged like an eval worker tick
+    /// Cells the kernel couldn't compute are left out, for the CPU; if the
+    /// readback failed, nothing is computed and the CPU does the whole tick
+    pub fn poll(&mut self) -> Option<EvalRequest> {
+        let pending = self.pending.as_ref()?;
+        let _ = self.device.poll(PollType::Poll);
+        let mapped = match pending.mapped.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Ok(mapped) => mapped,
+            Err(TryRecvError::Disconnected) => false,
+        };
+        let mut request = self.pending.take()?.request;
+        if !mapped {
+            warn!("GPU evaluation failed to read back, evaluating on the CPU");
+            self.resident = None;
+            return Some(request);
+        }
+        let (Some(resident), Some(planned)) = (self.resident.as_mut(), self.planned.as_ref()) else {
+            return Some(request);
+        };
+
+        let plan = &planned.plan;
+        let results_size = resident.job_count * 4;
+        let slice = resident.readback.slice(..);
+        let range = slice.get_mapped_range();
+        let next: &[i32] = bytemuck::cast_slice(&range[..results_size]);
+        let errors: &[u32] = bytemuck::cast_slice(&range[results_size..]);
+
+        let width = plan.size.0;
+        request.computed.reserve(plan.jobs.len());
+        for ((job, &value), &error) in plan.jobs.iter().zip(next).zip(errors) {
+            if error != 0 {
+                continue;
+            }
+            // `commit` put the value in the window already
+            let index = (job.row - plan.origin.1) * width + (job.col - plan.origin.0);
+            resident.uploaded[index as usize] = [value, 1];
+            request.computed.insert((job.col, job.row), Value::Int(value as i64));
+        }
+        drop(range);
+        resident.readback.unmap();
+        Some(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_relative_formula() {
+        let compiled = compile_formula("A0 + 1", 0, 0).unwrap();
+        assert_eq!(
+            compiled,
+            KernelExpr::Binary(
+                BinOp::Add,
+                Box::new(KernelExpr::Ref { dx: 0, dy: 0 }),
+                Box::new(KernelExpr::Const(1)),
+            )
+        );
+
+        // Precedence and parentheses
+        let compiled = compile_formula("(A0 + 1) % 2", 1, 1).unwrap();
+        assert_eq!(compiled.to_wgsl(), "safe_rem(safe_add(read_cell(col + -1, row + -1), 1i), 2i)");
+
+        // Anything outside integer arithmetic stays on the CPU
+        assert_eq!(compile_formula("A0 + 1.5", 0, 0), None);
+        assert_eq!(compile_formula("min(A0, 1)", 0, 0), None);
+        assert_eq!(compile_formula("A0 +", 0, 0), None);
+    }
+
+    #[test]
+    fn test_plan_shares_kernels_and_falls_back() {
+        let mut grid = GridState::new();
+        for row in 0..3 {
+            grid.get_cell_mut_or_create(0, row).set_raw("1".to_string());
+            grid.get_cell_mut_or_create(1, row).set_raw(format!("= A{} * 2", row));
+        }
+        grid.get_cell_mut_or_create(2, 0).set_raw("= \"text\"".to_string());
+        grid.run_ticks(1);
+
+        let plan = plan(&grid);
+        // Filled-down formulas share one kernel
+        assert_eq!(plan.kernels.len(), 1);
+        assert_eq!(plan.jobs.len(), 3);
+        assert_eq!(plan.cpu_fallback, vec![(2, 0)]);
+        assert_eq!(plan.origin, (0, 0));
+        assert_eq!(plan.size, (3, 3));
+
+        let wgsl = generate_wgsl(&plan);
+        assert!(wgsl.contains("fn kernel_0(col: i32, row: i32) -> i32"));
+        assert!(wgsl.contains("case 0u: { value = kernel_0(job.col, job.row); }"));
+
+        assert_eq!(pack_values(&grid, &plan)[0], [1, 1]);
+        // Strings can't be read on the GPU
+        assert_eq!(pack_values(&grid, &plan)[2], [0, 0]);
+    }
+
+    #[test]
+    fn test_plan_leaves_huge_windows_to_the_cpu() {
+        let mut grid = GridState::new();
+        grid.set_range((0, -2_000_000_000), [["1"]]);
+        grid.set_range((0, 2_000_000_000), [["= 1 + 1"]]);
+        grid.set_range((5, 0), [["= F1 * 2"], ["3"]]);
+
+        let plan = plan(&grid);
+        assert!(plan.jobs.is_empty() && plan.kernels.is_empty());
+        assert_eq!(plan.cpu_fallback, vec![(5, 0), (0, 2_000_000_000)]);
+        assert_eq!(plan.size, (0, 0));
+        assert!(pack_values(&grid, &plan).is_empty());
+    }
+
+    /// What the kernel computes for `expr` at (col, row), step for step with
+    /// `read_cell` and the `safe_*` helpers: None where it sets `failed`
+    fn run_kernel(expr: &KernelExpr, grid: &GridState, col: i32, row: i32) -> Option<i32> {
+        match expr {
+            KernelExpr::Const(n) => Some(*n),
+            KernelExpr::Ref { dx, dy } => match grid.get_cell(col + dx, row + dy)?.value {
+                Value::Int(i) => i32::try_from(i).ok(),
+                _ => None,
+            },
+            KernelExpr::Neg(inner) => 0i32.checked_sub(run_kernel(inner, grid, col, row)?),
+            KernelExpr::Binary(op, lhs, rhs) => {
+                let (a, b) = (run_kernel(lhs, grid, col, row)?, run_kernel(rhs, grid, col, row)?);
+                match op {
+                    BinOp::Add => a.checked_add(b),
+                    BinOp::Sub => a.checked_sub(b),
+                    BinOp::Mul => a.checked_mul(b),
+                    BinOp::Div => a.checked_div(b),
+                    BinOp::Rem => a.checked_rem(b),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernels_match_the_cpu() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["7", "-3", "0", "2147483647", "x"]]);
+        let formulas = [
+            "= A0 / B0",
+            "= A0 % B0",
+            "= B0 % 2",
+            "= -B0 * A0",
+            "= A0 - B0 * (A0 + 1)",
+            "= (A0 + B0) * (A0 - B0) / 4",
+            "= -D0 - 1",
+            "= A0 / C0",
+            "= D0 + 1",
+            "= D0 * 2",
+            "= E0 + 1",
+            "= F0 + 1",
+        ];
+        grid.set_range((0, 1), [formulas]);
+        grid.run_ticks(1);
+
+        let plan = plan(&grid);
+        assert_eq!(plan.jobs.len(), formulas.len());
+        let expected: Vec<((i32, i32), Option<i32>)> = plan
+            .jobs
+            .iter()
+            .map(|job| ((job.col, job.row), run_kernel(&plan.kernels[job.kernel as usize], &grid, job.col, job.row)))
+            .collect();
+        grid.tick();
+
+        // Everything the kernel computes agrees with the CPU; the rest (division
+        // by zero, overflow, non-integer reads) is left to it
+        for &((col, row), value) in &expected {
+            if let Some(value) = value {
+                assert_eq!(grid.get_cell(col, row).unwrap().value, Value::Int(value as i64), "{}", formulas[col as usize]);
+            }
+        }
+        assert_eq!(expected.iter().filter(|(_, value)| value.is_some()).count(), 7);
+    }
+}
@@ -0,0 +1,667 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::cell::{CellContent, CellDisplay};
+use crate::filter::TableFilter;
+use crate::glyph_atlas;
+use crate::formula::{rewrite_references, translate_formula, CellRef};
+use crate::grid_state::{CellRange, GridState, SheetPositions};
+use crate::undo::{EditGroup, PositionsEdit};
+
+/// Direction a fill propagates in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillDirection {
+    Down,
+    Right,
+    Up,
+    Left,
+}
+
+/// Arithmetic progression detected in the seed cells
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Series {
+    Int { last: i64, step: i64 },
+    Float { last: f64, step: f64 },
+}
+
+impl Series {
+    /// Value `n` steps after the last seed cell
+    fn nth(&self, n: i64) -> String {
+        match self {
+            Series::Int { last, step } => (last + step * n).to_string(),
+            Series::Float { last, step } => (last + step * n as f64).to_string(),
+        }
+    }
+}
+
+/// Detect an arithmetic series (`1, 2` -> step 1, `10, 20, 30` -> step 10)
+/// Needs at least two numeric literals with a constant difference
+fn detect_series(seed: &[String]) -> Option<Series> {
+    if seed.len() < 2 {
+        return None;
+    }
+
+    if let Some(ints) = seed.iter().map(|r| r.trim().parse::<i64>().ok()).collect::<Option<Vec<_>>>() {
+        let step = ints[1] - ints[0];
+        return ints
+            .windows(2)
+            .all(|w| w[1] - w[0] == step)
+            .then_some(Series::Int { last: ints[ints.len() - 1], step });
+    }
+
+    let floats = seed.iter().map(|r| r.trim().parse::<f64>().ok()).collect::<Option<Vec<_>>>()?;
+    let step = floats[1] - floats[0];
+    floats
+        .windows(2)
+        .all(|w| ((w[1] - w[0]) - step).abs() < 1e-9)
+        .then_some(Series::Float { last: floats[floats.len() - 1], step })
+}
+
+/// Split a range into lanes ordered along the fill direction
+/// (columns top-to-bottom for Down, rows left-to-right for Right, etc.)
+fn lanes(range: CellRange, direction: FillDirection) -> Vec<Vec<(i32, i32)>> {
+    match direction {
+        FillDirection::Down | FillDirection::Up => (range.min_col..=range.max_col)
+            .map(|col| {
+                let mut lane: Vec<(i32, i32)> = (range.min_row..=range.max_row).map(|row| (col, row)).collect();
+                if direction == FillDirection::Up {
+                    lane.reverse();
+                }
+                lane
+            })
+            .collect(),
+        FillDirection::Right | FillDirection::Left => (range.min_row..=range.max_row)
+            .map(|row| {
+                let mut lane: Vec<(i32, i32)> = (range.min_col..=range.max_col).map(|col| (col, row)).collect();
+                if direction == FillDirection::Left {
+                    lane.reverse();
+                }
+                lane
+            })
+            .collect(),
+    }
+}
+
+/// Fill a range in the given direction
+/// In each lane the leading non-empty cells are the seed: numeric seeds forming
+/// an arithmetic series are continued, anything else is repeated with formula
+/// references shifted relative to the seed cell they were copied from
+pub fn fill(grid: &GridState, range: CellRange, direction: FillDirection) -> EditGroup {
+    let mut group = EditGroup::new("Fill");
+
+    for lane in lanes(range, direction) {
+        let seed: Vec<(i32, i32)> = lane
+            .iter()
+            .copied()
+            .take_while(|&(col, row)| grid.get_cell(col, row).is_some_and(|c| !c.raw.is_empty()))
+            .collect();
+        if seed.is_empty() {
+            continue;
+        }
+
+        let seed_raw: Vec<String> = seed
+            .iter()
+            .map(|&(col, row)| grid.get_cell(col, row).map(|c| c.raw.clone()).unwrap_or_default())
+            .collect();
+        let series = detect_series(&seed_raw);
+
+        for (i, &(col, row)) in lane[seed.len()..].iter().enumerate() {
+            let raw = match series {
+                Some(series) => series.nth(i as i64 + 1),
+                None => {
+                    let source = i % seed.len();
+                    let (src_col, src_row) = seed[source];
+                    let raw = &seed_raw[source];
+                    if raw.trim_start().starts_with('=') {
+                        translate_formula(raw, col - src_col, row - src_row)
+                    } else {
+                        raw.clone()
+                    }
+                }
+            };
+            group.set_raw(grid, col, row, raw);
+        }
+    }
+
+    group
+}
+
+/// Ctrl+D / Ctrl+R: fill the selection down or right
+/// A single-row (or single-column) selection fills from the cell above (or left)
+pub fn fill_selection(grid: &GridState, direction: FillDirection) -> Option<EditGroup> {
+    let mut range = grid.selection_bounds()?;
+    match direction {
+        FillDirection::Down if range.height() == 1 => range.min_row -= 1,
+        FillDirection::Right if range.width() == 1 => range.min_col -= 1,
+        FillDirection::Up if range.height() == 1 => range.max_row += 1,
+        FillDirection::Left if range.width() == 1 => range.max_col += 1,
+        _ => {}
+    }
+    Some(fill(grid, range, direction))
+}
+
+/// Dragging the fill handle of `source` to `cell`: the range to fill and which
+/// way, stretched along whichever axis the cell lies further outside on
+/// None while the cell is still inside the source
+pub fn fill_handle_target(source: CellRange, cell: (i32, i32)) -> Option<(CellRange, FillDirection)> {
+    let outside = |v: i32, min: i32, max: i32| if v > max { v - max } else if v < min { v - min } else { 0 };
+    let dx = outside(cell.0, source.min_col, source.max_col);
+    let dy = outside(cell.1, source.min_row, source.max_row);
+    let mut range = source;
+    let direction = match (dx, dy) {
+        (0, 0) => return None,
+        _ if dy.abs() >= dx.abs() && dy > 0 => {
+            range.max_row = cell.1;
+            FillDirection::Down
+        }
+        _ if dy.abs() >= dx.abs() => {
+            range.min_row = cell.1;
+            FillDirection::Up
+        }
+        _ if dx > 0 => {
+            range.max_col = cell.0;
+            FillDirection::Right
+        }
+        _ => {
+            range.min_col = cell.0;
+            FillDirection::Left
+        }
+    };
+    Some((range, direction))
+}
+
+/// Delete key: clear the raw text of every selected cell, keeping its style
+pub fn clear_selection(grid: &GridState) -> EditGroup {
+    let mut group = EditGroup::new("Clear");
+    for (col, row) in grid.selected.iter() {
+        if grid.get_cell(col, row).is_some_and(|cell| !cell.raw.is_empty()) {
+            group.set_raw(grid, col, row, String::new());
+        }
+    }
+    group
+}
+
+/// Width in pixels that shows the widest value in `col` in full, over the
+/// rows that aren't hidden; None if none of them is filled
+pub fn fit_column_width(grid: &GridState, col: i32) -> Option<u32> {
+    grid.cells
+        .iter()
+        .filter(|((c, row), cell)| *c == col && !cell.raw.is_empty() && grid.layout.rows.to_visual(*row).is_some())
+        .map(|(_, cell)| match cell.display(&grid.theme.resolve(cell.style)) {
+            CellDisplay::Text(text) => glyph_atlas::text_width(&text),
+            CellDisplay::Checkbox(_) => glyph_atlas::text_width(" "),
+        })
+        .max()
+}
+
+/// Which kind of line a structural edit inserts or deletes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Axis {
+    Row,
+    Column,
+}
+
+/// Map an index through a structural edit: `count > 0` inserts that many lines
+/// before `at`, `count < 0` deletes `-count` lines starting at `at`
+/// Returns None for indices inside a deleted block
+fn remap_index(index: i32, at: i32, count: i32) -> Option<i32> {
+    if count >= 0 {
+        Some(if index >= at { index + count } else { index })
+    } else if index < at {
+        Some(index)
+    } else if index < at - count {
+        None
+    } else {
+        Some(index + count)
+    }
+}
+
+/// Map a cell coordinate through a structural edit (see `shift_lines`)
+pub fn remap_coord(axis: Axis, at: i32, count: i32, col: i32, row: i32) -> Option<(i32, i32)> {
+    match axis {
+        Axis::Row => remap_index(row, at, count).map(|row| (col, row)),
+        Axis::Column => remap_index(col, at, count).map(|col| (col, row)),
+    }
+}
+
+/// Map a range through a structural edit: lines inserted or deleted inside it
+/// grow or shrink it, lines before it move it along
+/// Returns None once every line it spanned is deleted
+pub fn remap_range(axis: Axis, at: i32, count: i32, range: CellRange) -> Option<CellRange> {
+    let (min, max) = match axis {
+        Axis::Row => (range.min_row, range.max_row),
+        Axis::Column => (range.min_col, range.max_col),
+    };
+    // A deleted end closes up onto the lines after (or before) the deleted block
+    let min = remap_index(min, at, count).unwrap_or(at);
+    let max = remap_index(max, at, count).unwrap_or(at - 1);
+    if min > max {
+        return None;
+    }
+    Some(match axis {
+        Axis::Row => CellRange { min_row: min, max_row: max, ..range },
+        Axis::Column => CellRange { min_col: min, max_col: max, ..range },
+    })
+}
+
+/// The table filter after a structural edit: its range follows its cells and
+/// each criterion stays on its column
+/// A deleted column takes its criterion with it; deleting the header row (or
+/// every line of the table) ends the filter
+fn remap_table_filter(axis: Axis, at: i32, count: i32, table: TableFilter) -> Option<TableFilter> {
+    let range = remap_range(axis, at, count, table.range)?;
+    let criteria = match axis {
+        Axis::Row => {
+            remap_index(table.range.min_row, at, count)?;
+            table.criteria
+        }
+        Axis::Column => table
+            .criteria
+            .into_iter()
+            .filter_map(|(col, criterion)| Some((remap_index(col, at, count)?, criterion)))
+            .collect(),
+    };
+    Some(TableFilter { range, criteria })
+}
+
+/// Carry everything positional other than cell contents through a structural
+/// edit (see `shift_lines`): the selection and the sheet's positional
+/// settings (see `remap_positions`)
+pub fn remap_sheet(grid: &mut GridState, axis: Axis, at: i32, count: i32) {
+    remap_selection(grid, axis, at, count);
+    let mut positions = grid.take_positions();
+    remap_positions(&mut positions, axis, at, count);
+    grid.set_positions(positions);
+}
+
+/// Move the selection and active cell through a structural edit
+pub fn remap_selection(grid: &mut GridState, axis: Axis, at: i32, count: i32) {
+    // Selection follows its cells; deleted cells drop out of it, and what's
+    // left of each range closes up into a smaller one
+    grid.selected = grid.selected.map_ranges(|range| {
+        let mut kept = range.iter().filter_map(|(col, row)| remap_coord(axis, at, count, col, row));
+        // Row-major, so the first and last survivors are opposite corners
+        let first = kept.next()?;
+        Some(CellRange::new(first, kept.last().unwrap_or(first)))
+    });
+    if let Some((col, row)) = grid.active {
+        grid.active = remap_coord(axis, at, count, col, row);
+    }
+}
+
+/// Carry the sheet's positional settings through a structural edit: hidden
+/// and grouped lines, column widths, headers, data feeds, validation rules,
+/// the table filter, the banded table region and charts
+pub fn remap_positions(positions: &mut SheetPositions, axis: Axis, at: i32, count: i32) {
+    let remap_row = |row| remap_coord(axis, at, count, 0, row).map(|c| c.1);
+    let remap_col = |col| remap_coord(axis, at, count, col, 0).map(|c| c.0);
+    match axis {
+        Axis::Row => {
+            positions.layout.rows.remap(remap_row);
+            positions.headers.remap_rows(remap_row);
+        }
+        Axis::Column => {
+            positions.layout.cols.remap(remap_col);
+            positions.layout.remap_col_widths(remap_col);
+            positions.headers.remap_cols(remap_col);
+        }
+    }
+    // Feeds move with their cells; ones on deleted lines are unbound
+    positions.feeds.retain_mut(|feed| match remap_coord(axis, at, count, feed.col, feed.row) {
+        Some((col, row)) => {
+            (feed.col, feed.row) = (col, row);
+            true
+        }
+        None => false,
+    });
+    // Rules cover what's left of their range
+    positions.validations.retain_mut(|validation| match remap_range(axis, at, count, validation.range) {
+        Some(range) => {
+            validation.range = range;
+            true
+        }
+        None => false,
+    });
+    if let Some(table) = positions.table_filter.take() {
+        positions.table_filter = remap_table_filter(axis, at, count, table);
+    }
+    positions.banded = positions.banded.and_then(|range| remap_range(axis, at, count, range));
+    // Charts plot what's left of their source and go once none of it is; one
+    // anchored on a deleted line moves to the line that took its place
+    positions.charts.retain_mut(|chart| {
+        let Some(source) = remap_range(axis, at, count, chart.source) else { return false };
+        chart.source = source;
+        chart.anchor = remap_coord(axis, at, count, chart.anchor.0, chart.anchor.1).unwrap_or(match axis {
+            Axis::Row => (chart.anchor.0, at),
+            Axis::Column => (at, chart.anchor.1),
+        });
+        true
+    });
+}
+
+/// `shift_lines` as a single undo step: the group also carries the sheet's
+/// positional settings before and after, so undo puts headers, widths, rules
+/// and the like back where they were along with the cells
+pub fn shift_sheet(grid: &GridState, axis: Axis, at: i32, count: i32) -> EditGroup {
+    let mut group = shift_lines(grid, axis, at, count);
+    let before = grid.positions();
+    let mut after = before.clone();
+    remap_positions(&mut after, axis, at, count);
+    group.positions = Some(Box::new(PositionsEdit { before, after }));
+    group
+}
+
+/// Build the edit group for inserting (`count > 0`) or deleting (`count < 0`)
+/// whole rows/columns at `at`
+/// Cells after the edit shift along, and every formula reference is rewritten
+/// to follow its target. References to deleted cells become `#REF!`
+/// Unlike copying, `$` anchors shift too: they still name the same data
+pub fn shift_lines(grid: &GridState, axis: Axis, at: i32, count: i32) -> EditGroup {
+    let rewrite = |raw: &str| {
+        rewrite_references(raw, |r| {
+            Some(match remap_coord(axis, at, count, r.col, r.row) {
+                Some((col, row)) => CellRef { col, row, ..r }.to_text(),
+                None => "#REF!".to_string(),
+            })
+        })
+    };
+
+    // Final contents per touched coordinate (None = cleared)
+    let mut result: HashMap<(i32, i32), Option<CellContent>> = HashMap::new();
+
+    // Stationary formulas are rewritten in place, everything else vacates its slot
+    for (key, cell) in &grid.cells {
+        if remap_coord(axis, at, count, key.0, key.1) == Some(key) {
+            if cell.is_formula {
+                let rewritten = rewrite(&cell.raw);
+                if rewritten != cell.raw {
+                    result.insert(key, Some(CellContent { raw: rewritten, style: cell.style }));
+                }
+            }
+        } else {
+            result.insert(key, None);
+        }
+    }
+
+    // Surviving cells land at their new coordinates
+    for (key, cell) in &grid.cells {
+        if let Some(new_key) = remap_coord(axis, at, count, key.0, key.1) {
+            if new_key != key {
+                let raw = if cell.is_formula { rewrite(&cell.raw) } else { cell.raw.clone() };
+                result.insert(new_key, Some(CellContent { raw, style: cell.style }));
+            }
+        }
+    }
+
+    let mut keys: Vec<(i32, i32)> = result.keys().copied().collect();
+    keys.sort_by_key(|(col, row)| (*row, *col));
+
+    let label = if count >= 0 { "Insert" } else { "Delete" };
+    let mut group = EditGroup::new(label);
+    for (col, row) in keys {
+        group.set_content(grid, col, row, result.remove(&(col, row)).flatten());
+    }
+    group
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::{Chart, ChartKind};
+    use crate::feeds::{apply_update, DataFeed, FeedUpdate};
+    use crate::filter::{CompareOp, FilterCriterion};
+    use crate::undo::UndoStack;
+    use crate::validation::{InvalidAction, Validation, ValidationRule};
+    use evalexpr::Value;
+
+    fn raw(grid: &GridState, col: i32, row: i32) -> String {
+        grid.get_cell(col, row).map(|c| c.raw.clone()).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_fill_continues_series() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("1".to_string());
+        grid.get_cell_mut_or_create(0, 1).set_raw("2".to_string());
+        grid.get_cell_mut_or_create(1, 0).set_raw("0.5".to_string());
+        grid.get_cell_mut_or_create(1, 1).set_raw("1".to_string());
+        grid.get_cell_mut_or_create(2, 0).set_raw("x".to_string());
+
+        let group = fill(&grid, CellRange::new((0, 0), (2, 4)), FillDirection::Down);
+        UndoStack::default().commit(&mut grid, group);
+
+        assert_eq!(raw(&grid, 0, 2), "3");
+        assert_eq!(raw(&grid, 0, 4), "5");
+        assert_eq!(raw(&grid, 1, 3), "2");
+        // Non-numeric seeds are repeated
+        assert_eq!(raw(&grid, 2, 4), "x");
+    }
+
+    #[test]
+    fn test_fill_shifts_formulas() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("= B0 * $C$0".to_string());
+        grid.selected.extend([(0, 0), (0, 1), (0, 2)]);
+
+        let group = fill_selection(&grid, FillDirection::Down).unwrap();
+        UndoStack::default().commit(&mut grid, group);
+        assert_eq!(raw(&grid, 0, 2), "= B2 * $C$0");
+
+        // Single-cell selection fills right from its left neighbour
+        grid.selected.clear();
+        grid.selected.insert((1, 0));
+        let group = fill_selection(&grid, FillDirection::Right).unwrap();
+        UndoStack::default().commit(&mut grid, group);
+        assert_eq!(raw(&grid, 1, 0), "= C0 * $C$0");
+    }
+
+    #[test]
+    fn test_fill_handle_target() {
+        let source = CellRange::new((1, 1), (2, 2));
+        assert_eq!(fill_handle_target(source, (2, 1)), None);
+        assert_eq!(fill_handle_target(source, (3, 6)), Some((CellRange::new((1, 1), (2, 6)), FillDirection::Down)));
+        assert_eq!(fill_handle_target(source, (6, 3)), Some((CellRange::new((1, 1), (6, 2)), FillDirection::Right)));
+        assert_eq!(fill_handle_target(source, (-2, 1)), Some((CellRange::new((-2, 1), (2, 2)), FillDirection::Left)));
+
+        // The fill continues the series down the new cells
+        let mut grid = GridState::new();
+        grid.set_range((1, 1), [["1"], ["2"]]);
+        let (range, direction) = fill_handle_target(CellRange::new((1, 1), (1, 2)), (1, 4)).unwrap();
+        UndoStack::default().commit(&mut grid, fill(&grid, range, direction));
+        assert_eq!(raw(&grid, 1, 4), "4");
+    }
+
+    #[test]
+    fn test_clear_selection_keeps_style() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1", "= A0 * 2", "x"]]);
+        grid.get_cell_mut_or_create(1, 0).style.bold = true;
+        grid.selected.extend([(0, 0), (1, 0), (5, 5)]);
+
+        let mut stack = UndoStack::default();
+        stack.commit(&mut grid, clear_selection(&grid));
+        assert_eq!((raw(&grid, 0, 0), raw(&grid, 1, 0), raw(&grid, 2, 0)), (String::new(), String::new(), "x".to_string()));
+        assert!(grid.get_cell(1, 0).is_some_and(|c| c.style.bold));
+
+        // One undo brings both back
+        stack.undo(&mut grid);
+        assert_eq!((raw(&grid, 0, 0), raw(&grid, 1, 0)), ("1".to_string(), "= A0 * 2".to_string()));
+    }
+
+    #[test]
+    fn test_fit_column_width_skips_hidden_rows() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["abc"], ["a much longer value"], ["abcdef"]]);
+        grid.layout.rows.hide(1);
+        assert_eq!(fit_column_width(&grid, 0), Some(glyph_atlas::text_width("abcdef")));
+        assert_eq!(fit_column_width(&grid, 1), None);
+    }
+
+    #[test]
+    fn test_insert_rows_rewrites_references() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("1".to_string());
+        grid.get_cell_mut_or_create(0, 1).set_raw("2".to_string());
+        grid.get_cell_mut_or_create(1, 0).set_raw("= A0 + $A$1".to_string());
+
+        let group = shift_lines(&grid, Axis::Row, 1, 2);
+        UndoStack::default().commit(&mut grid, group);
+
+        assert_eq!(raw(&grid, 0, 0), "1");
+        assert!(grid.get_cell(0, 1).is_none());
+        assert_eq!(raw(&grid, 0, 3), "2");
+        assert_eq!(raw(&grid, 1, 0), "= A0 + $A$3");
+    }
+
+    #[test]
+    fn test_delete_columns_produces_ref_errors() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("1".to_string());
+        grid.get_cell_mut_or_create(1, 0).set_raw("2".to_string());
+        grid.get_cell_mut_or_create(2, 0).set_raw("3".to_string());
+        grid.get_cell_mut_or_create(3, 0).set_raw("= A0 + B0 + C0".to_string());
+
+        let group = shift_lines(&grid, Axis::Column, 1, -1);
+        let mut stack = UndoStack::default();
+        stack.commit(&mut grid, group);
+
+        assert_eq!(raw(&grid, 1, 0), "3");
+        assert_eq!(raw(&grid, 2, 0), "= A0 + #REF! + B0");
+        assert!(grid.get_cell(3, 0).is_none());
+
+        stack.undo(&mut grid);
+        assert_eq!(raw(&grid, 3, 0), "= A0 + B0 + C0");
+        assert_eq!(raw(&grid, 1, 0), "2");
+    }
+
+    #[test]
+    fn test_undo_puts_the_sheet_back_with_the_cells() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1", "2", "3"]]);
+        grid.headers.set_col_label(2, "Total");
+        grid.layout.cols.hide(3);
+        grid.layout.col_widths.insert(2, 120);
+        grid.validations.push(Validation {
+            range: CellRange::new((1, 0), (1, 5)),
+            rule: ValidationRule::List(vec!["2".into()]),
+            on_invalid: InvalidAction::Reject,
+        });
+        let before = grid.positions();
+
+        let mut stack = UndoStack::default();
+        stack.commit(&mut grid, shift_sheet(&grid, Axis::Column, 1, -1));
+        assert_eq!(raw(&grid, 1, 0), "3");
+        assert_eq!(grid.headers.find_col("Total"), Some(1));
+        assert!(grid.layout.cols.is_hidden(2));
+        assert_eq!(grid.layout.col_widths.get(&1), Some(&120));
+        assert!(grid.validations.is_empty());
+        let after = grid.positions();
+
+        stack.undo(&mut grid);
+        assert_eq!(raw(&grid, 1, 0), "2");
+        assert_eq!(grid.positions(), before);
+        stack.redo(&mut grid);
+        assert_eq!(grid.positions(), after);
+    }
+
+    #[test]
+    fn test_feeds_follow_their_cells() {
+        let mut grid = GridState::new();
+        grid.feeds.push(DataFeed::parse(0, 2, "https://example.com/a /price").unwrap());
+        grid.feeds.push(DataFeed::parse(1, 5, "https://example.com/b").unwrap());
+
+        UndoStack::default().commit(&mut grid, shift_lines(&grid, Axis::Row, 1, 1));
+        remap_sheet(&mut grid, Axis::Row, 1, 1);
+        assert_eq!(grid.feeds.iter().map(|f| (f.col, f.row)).collect::<Vec<_>>(), [(0, 3), (1, 6)]);
+
+        // The next value lands on the moved cell; one still in flight for the
+        // old cell is dropped
+        let update = |col, row| FeedUpdate { col, row, value: Ok(Value::Int(7)) };
+        assert!(apply_update(&mut grid, update(0, 2)).is_none());
+        assert!(apply_update(&mut grid, update(0, 3)).is_some());
+        assert_eq!((raw(&grid, 0, 2), raw(&grid, 0, 3)), (String::new(), "7".to_string()));
+
+        // Deleting a feed's line unbinds it
+        remap_sheet(&mut grid, Axis::Row, 6, -1);
+        assert_eq!(grid.feeds.len(), 1);
+    }
+
+    #[test]
+    fn test_validations_follow_their_ranges() {
+        let rule = |range| Validation { range, rule: ValidationRule::List(vec!["a".into()]), on_invalid: InvalidAction::Reject };
+        let mut grid = GridState::new();
+        grid.validations = vec![rule(CellRange::new((1, 0), (1, 9))), rule(CellRange::new((3, 0), (4, 0)))];
+
+        // Inserting inside a range grows it, before it moves it
+        remap_sheet(&mut grid, Axis::Column, 1, 1);
+        remap_sheet(&mut grid, Axis::Row, 5, 2);
+        assert_eq!(grid.validations[0].range, CellRange::new((2, 0), (2, 11)));
+        assert_eq!(grid.validations[1].range, CellRange::new((4, 0), (5, 0)));
+
+        // Deleted lines are clipped off, and a rule with none left goes
+        remap_sheet(&mut grid, Axis::Row, 0, -3);
+        assert_eq!(grid.validations.len(), 1);
+        assert_eq!(grid.validations[0].range, CellRange::new((2, 0), (2, 8)));
+        remap_sheet(&mut grid, Axis::Column, 2, -1);
+        assert!(grid.validations.is_empty());
+    }
+
+    #[test]
+    fn test_table_filter_follows_its_columns() {
+        let mut grid = GridState::new();
+        let mut table = TableFilter::new(CellRange::new((0, 0), (2, 5)));
+        table.criteria.insert(1, FilterCriterion::Compare(CompareOp::Greater, 10.0));
+        table.criteria.insert(2, FilterCriterion::Compare(CompareOp::Less, 3.0));
+        grid.table_filter = Some(table);
+
+        remap_sheet(&mut grid, Axis::Column, 1, 1);
+        let table = grid.table_filter.as_ref().unwrap();
+        assert_eq!(table.range, CellRange::new((0, 0), (3, 5)));
+        assert_eq!(table.criteria.keys().copied().collect::<Vec<_>>(), [2, 3]);
+
+        // A deleted column's criterion goes with it
+        remap_sheet(&mut grid, Axis::Column, 2, -1);
+        let table = grid.table_filter.as_ref().unwrap();
+        assert_eq!(table.range, CellRange::new((0, 0), (2, 5)));
+        assert_eq!(table.criteria.get(&2), Some(&FilterCriterion::Compare(CompareOp::Less, 3.0)));
+        assert_eq!(table.criteria.len(), 1);
+
+        // Data rows can go, the header row can't
+        remap_sheet(&mut grid, Axis::Row, 0, 1);
+        remap_sheet(&mut grid, Axis::Row, 3, -2);
+        assert_eq!(grid.table_filter.as_ref().unwrap().range, CellRange::new((0, 1), (2, 4)));
+        remap_sheet(&mut grid, Axis::Row, 1, -1);
+        assert!(grid.table_filter.is_none());
+    }
+
+    #[test]
+    fn test_banded_region_follows_its_cells() {
+        let mut grid = GridState::new();
+        grid.banded = Some(CellRange::new((1, 1), (3, 6)));
+
+        remap_sheet(&mut grid, Axis::Row, 0, 2);
+        remap_sheet(&mut grid, Axis::Column, 2, -1);
+        assert_eq!(grid.banded, Some(CellRange::new((1, 3), (2, 8))));
+
+        remap_sheet(&mut grid, Axis::Column, 0, -3);
+        assert_eq!(grid.banded, None);
+    }
+
+    #[test]
+    fn test_charts_follow_their_source() {
+        let mut grid = GridState::new();
+        grid.charts.push(Chart::new(ChartKind::Line, CellRange::new((0, 0), (1, 9))));
+        grid.charts.push(Chart::new(ChartKind::Bar, CellRange::new((4, 2), (4, 3))));
+
+        remap_sheet(&mut grid, Axis::Row, 1, 3);
+        assert_eq!(grid.charts[0].source, CellRange::new((0, 0), (1, 12)));
+        assert_eq!(grid.charts[1].source, CellRange::new((4, 5), (4, 6)));
+        assert_eq!(grid.charts[1].anchor, (6, 5));
+
+        // The first chart's anchor column goes, the second's source does
+        remap_sheet(&mut grid, Axis::Column, 3, -2);
+        assert_eq!(grid.charts.len(), 1);
+        assert_eq!((grid.charts[0].source, grid.charts[0].anchor), (CellRange::new((0, 0), (1, 12)), (3, 0)));
+    }
+}
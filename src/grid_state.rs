@@ -1,25 +1,297 @@
 use bevy::prelude::*;
-use std::collections::{HashSet, HashMap};
+use evalexpr::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
-use crate::cell::Cell;
-use crate::gpu_cell::GpuCell;
+use crate::cell::{parse_literal, Cell};
+use crate::cell_store::CellStore;
+use crate::chart::Chart;
+use crate::evaluator::evaluate_tick;
+use crate::events::CellChanged;
+use crate::feeds::DataFeed;
+use crate::filter::TableFilter;
+use crate::gpu_cell::{border_edges, border_words, GpuCell, BORDER_STRIDE};
+use crate::grid_ops::Axis;
+use crate::headers::HeaderLabels;
+use crate::host_functions::HostFunctions;
+use crate::layout::SheetLayout;
+use crate::selection::Selection;
+use crate::theme::Theme;
+use crate::undo::{CellEdit, EditGroup};
+use crate::validation::{validation_at, Validation};
+
+/// Rectangular range of cells, inclusive on all sides
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CellRange {
+    pub min_col: i32,
+    pub min_row: i32,
+    pub max_col: i32,
+    pub max_row: i32,
+}
+
+impl CellRange {
+    /// Range spanning two corner cells, in any order
+    pub fn new(a: (i32, i32), b: (i32, i32)) -> Self {
+        Self {
+            min_col: a.0.min(b.0),
+            min_row: a.1.min(b.1),
+            max_col: a.0.max(b.0),
+            max_row: a.1.max(b.1),
+        }
+    }
+
+    /// Range covering a single cell
+    pub fn cell(col: i32, row: i32) -> Self {
+        Self::new((col, row), (col, row))
+    }
+
+    pub fn width(&self) -> i32 {
+        self.max_col - self.min_col + 1
+    }
+
+    pub fn height(&self) -> i32 {
+        self.max_row - self.min_row + 1
+    }
+
+    pub fn contains(&self, col: i32, row: i32) -> bool {
+        col >= self.min_col && col <= self.max_col && row >= self.min_row && row <= self.max_row
+    }
+
+    /// Whether the two ranges share a cell
+    pub fn intersects(&self, other: CellRange) -> bool {
+        self.min_col <= other.max_col
+            && other.min_col <= self.max_col
+            && self.min_row <= other.max_row
+            && other.min_row <= self.max_row
+    }
+
+    /// All coordinates in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32)> {
+        let range = *self;
+        (range.min_row..=range.max_row)
+            .flat_map(move |row| (range.min_col..=range.max_col).map(move |col| (col, row)))
+    }
+}
 
 /// CPU-side grid state - source of truth for all cell data
-#[derive(Resource)]
+/// Serializes as the sheet's contents and settings (see `persist` for the file envelope);
+/// the selection is session state and isn't saved
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GridState {
-    /// Sparse cells storage
-    pub cells: HashMap<(i32, i32), Cell>,
-    /// Set of selected cell coordinates (col, row)
-    pub selected: HashSet<(i32, i32)>,
+    /// Sparse cells storage, chunked for fast rectangular scans
+    pub cells: CellStore,
+    /// Selected cells (col, row), as one or more ranges
+    #[serde(skip)]
+    pub selected: Selection,
+    /// The active cell: where the editor and keyboard input go, and the
+    /// anchor of the selection (it needn't be selected itself)
+    #[serde(skip)]
+    pub active: Option<(i32, i32)>,
+    /// The selection's corner opposite the active cell, where Shift+click
+    /// and Shift+Arrow move it to
+    #[serde(skip)]
+    pub selection_end: Option<(i32, i32)>,
+    /// Hidden rows/columns
+    pub layout: SheetLayout,
+    /// Filtered table region, if one is declared
+    pub table_filter: Option<TableFilter>,
+    /// Region formatted as a table: a header row, then banded rows
+    #[serde(deserialize_with = "crate::persist::since::v3")]
+    pub banded: Option<CellRange>,
+    /// Data validation rules (later rules win where regions overlap)
+    pub validations: Vec<Validation>,
+    /// Sheet protection: locked cells can't be edited while set
+    pub protected: bool,
+    /// Custom column/row names for the header gutters and structured references
+    pub headers: HeaderLabels,
+    /// Named styles cells can follow
+    pub theme: Theme,
+    /// Cells bound to external data feeds
+    pub feeds: Vec<DataFeed>,
+    /// Charts floating over the sheet
+    #[serde(deserialize_with = "crate::persist::since::v4")]
+    pub charts: Vec<Chart>,
+    /// Formula functions the embedding page computes (web only)
+    #[serde(skip)]
+    pub host_functions: HostFunctions,
+    /// Open transaction, if any (see `begin_transaction`)
+    #[serde(skip)]
+    transaction: Option<Box<Transaction>>,
+}
+
+/// The sheet as it was when a transaction began, restored on rollback
+struct Transaction {
+    label: String,
+    /// Nested `begin_transaction` calls join the outer transaction
+    depth: usize,
+    cells: CellStore,
+    layout: SheetLayout,
+    table_filter: Option<TableFilter>,
+    banded: Option<CellRange>,
+    validations: Vec<Validation>,
+    protected: bool,
+    headers: HeaderLabels,
+    theme: Theme,
+    feeds: Vec<DataFeed>,
+    charts: Vec<Chart>,
+}
+
+/// The sheet's settings that sit on particular lines or cells, which
+/// structural edits carry along (see `grid_ops::remap_positions`)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SheetPositions {
+    pub layout: SheetLayout,
+    pub table_filter: Option<TableFilter>,
+    pub banded: Option<CellRange>,
+    pub validations: Vec<Validation>,
+    pub headers: HeaderLabels,
+    pub feeds: Vec<DataFeed>,
+    pub charts: Vec<Chart>,
+}
+
+impl Default for GridState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GridState {
     /// Create a new empty grid
     pub fn new() -> Self {
         Self {
-            cells: HashMap::new(),
-            selected: HashSet::new(),
+            cells: CellStore::new(),
+            selected: Selection::default(),
+            active: None,
+            selection_end: None,
+            layout: SheetLayout::default(),
+            table_filter: None,
+            banded: None,
+            validations: Vec::new(),
+            protected: false,
+            headers: HeaderLabels::default(),
+            theme: Theme::default(),
+            feeds: Vec::new(),
+            charts: Vec::new(),
+            host_functions: HostFunctions::default(),
+            transaction: None,
+        }
+    }
+
+    /// Start a transaction: direct edits from here on (cells, layout, names,
+    /// rules) can be abandoned as a whole with `rollback`, or finished with `commit`
+    /// Beginning inside an open transaction nests into it
+    pub fn begin_transaction(&mut self, label: &str) {
+        if let Some(transaction) = &mut self.transaction {
+            transaction.depth += 1;
+            return;
+        }
+        self.transaction = Some(Box::new(Transaction {
+            label: label.to_string(),
+            depth: 0,
+            cells: self.cells.clone(),
+            layout: self.layout.clone(),
+            table_filter: self.table_filter.clone(),
+            banded: self.banded,
+            validations: self.validations.clone(),
+            protected: self.protected,
+            headers: self.headers.clone(),
+            theme: self.theme.clone(),
+            feeds: self.feeds.clone(),
+            charts: self.charts.clone(),
+        }));
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Finish the open transaction, returning its cell changes as one edit group
+    /// (already applied; hand it to `UndoStack::record` to make it undoable)
+    /// Nested commits just close their level and return an empty group
+    /// A transaction that edited locked cells of a protected sheet is rolled back
+    pub fn commit(&mut self) -> Result<EditGroup, String> {
+        let Some(transaction) = &mut self.transaction else {
+            return Err("no transaction in progress".to_string());
+        };
+        if transaction.depth > 0 {
+            transaction.depth -= 1;
+            return Ok(EditGroup::new(&transaction.label));
+        }
+
+        let transaction = self.transaction.take().unwrap();
+        let mut keys: Vec<(i32, i32)> = transaction.cells.keys().chain(self.cells.keys()).collect();
+        keys.sort_by_key(|(col, row)| (*row, *col));
+        keys.dedup();
+
+        let mut group = EditGroup::new(&transaction.label);
+        for (col, row) in keys {
+            let before = transaction.cells.get(&(col, row)).map(Cell::content);
+            let after = self.get_cell(col, row).map(Cell::content);
+            if before != after {
+                group.edits.push(CellEdit { col, row, before, after });
+            }
         }
+
+        let touches_locked = |e: &CellEdit| e.before.as_ref().is_some_and(|c| c.style.locked);
+        if transaction.protected && group.edits.iter().any(touches_locked) {
+            self.transaction = Some(transaction);
+            self.rollback();
+            return Err("Cell is locked".to_string());
+        }
+        Ok(group)
+    }
+
+    /// Abandon the open transaction (all nesting levels), restoring the sheet
+    /// to how it was when it began
+    pub fn rollback(&mut self) {
+        let Some(transaction) = self.transaction.take() else { return };
+        let transaction = *transaction;
+        self.cells = transaction.cells;
+        self.layout = transaction.layout;
+        self.table_filter = transaction.table_filter;
+        self.banded = transaction.banded;
+        self.validations = transaction.validations;
+        self.protected = transaction.protected;
+        self.headers = transaction.headers;
+        self.theme = transaction.theme;
+        self.feeds = transaction.feeds;
+        self.charts = transaction.charts;
+    }
+
+    pub fn positions(&self) -> SheetPositions {
+        SheetPositions {
+            layout: self.layout.clone(),
+            table_filter: self.table_filter.clone(),
+            banded: self.banded,
+            validations: self.validations.clone(),
+            headers: self.headers.clone(),
+            feeds: self.feeds.clone(),
+            charts: self.charts.clone(),
+        }
+    }
+
+    /// Move the positional settings out, leaving defaults until `set_positions`
+    pub fn take_positions(&mut self) -> SheetPositions {
+        SheetPositions {
+            layout: std::mem::take(&mut self.layout),
+            table_filter: self.table_filter.take(),
+            banded: self.banded.take(),
+            validations: std::mem::take(&mut self.validations),
+            headers: std::mem::take(&mut self.headers),
+            feeds: std::mem::take(&mut self.feeds),
+            charts: std::mem::take(&mut self.charts),
+        }
+    }
+
+    pub fn set_positions(&mut self, positions: SheetPositions) {
+        self.layout = positions.layout;
+        self.table_filter = positions.table_filter;
+        self.banded = positions.banded;
+        self.validations = positions.validations;
+        self.headers = positions.headers;
+        self.feeds = positions.feeds;
+        self.charts = positions.charts;
     }
 
     /// Get an immutable reference to a cell
@@ -34,7 +306,7 @@ impl GridState {
 
     /// Get a mutable reference to a cell, creating it if it doesn't exist
     pub fn get_cell_mut_or_create(&mut self, col: i32, row: i32) -> &mut Cell {
-        self.cells.entry((col, row)).or_default()
+        self.cells.get_or_insert_default((col, row))
     }
     
     /// Insert or update a cell
@@ -42,33 +314,343 @@ impl GridState {
         self.cells.insert((col, row), cell);
     }
 
+    /// Existing cells inside a range, row-major
+    pub fn iter_region(&self, range: CellRange) -> impl Iterator<Item = ((i32, i32), &Cell)> {
+        self.cells.region(range)
+    }
+
+    /// Values of a range, one Vec per row (`Value::Empty` for empty cells)
+    pub fn get_range(&self, range: CellRange) -> Vec<Vec<Value>> {
+        (range.min_row..=range.max_row)
+            .map(|row| {
+                (range.min_col..=range.max_col)
+                    .map(|col| self.get_cell(col, row).map(|c| c.value.clone()).unwrap_or(Value::Empty))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Write rows of raw text with their top-left at `origin`
+    /// Like `set_cell` this writes directly (no undo); literals take effect
+    /// immediately, formulas on the next tick
+    pub fn set_range<R, S>(&mut self, origin: (i32, i32), rows: impl IntoIterator<Item = R>)
+    where
+        R: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        for (dy, row) in rows.into_iter().enumerate() {
+            for (dx, raw) in row.into_iter().enumerate() {
+                self.write_raw(origin.0 + dx as i32, origin.1 + dy as i32, raw.into());
+            }
+        }
+    }
+
+    /// Set every cell in a range to the same raw text
+    pub fn fill_region(&mut self, range: CellRange, raw: &str) {
+        for (col, row) in range.iter() {
+            self.write_raw(col, row, raw.to_string());
+        }
+    }
+
+    /// Remove every cell in a range
+    pub fn clear_region(&mut self, range: CellRange) {
+        for key in range.iter() {
+            self.cells.remove(&key);
+        }
+    }
+
+    /// Rewrite cells in a range: `f` gets each coordinate and cell (None if
+    /// empty) and returns new raw text, or None to leave the cell alone
+    pub fn map_region(&mut self, range: CellRange, mut f: impl FnMut((i32, i32), Option<&Cell>) -> Option<String>) {
+        for (col, row) in range.iter() {
+            if let Some(raw) = f((col, row), self.get_cell(col, row)) {
+                self.write_raw(col, row, raw);
+            }
+        }
+    }
+
+    /// Set a cell's raw text, evaluating literals straight away
+    fn write_raw(&mut self, col: i32, row: i32, raw: String) {
+        let cell = self.get_cell_mut_or_create(col, row);
+        cell.set_raw(raw);
+        if !cell.is_formula {
+            cell.value = parse_literal(&cell.raw);
+        }
+    }
+
+    /// True if the sheet is protected and this cell is locked
+    pub fn is_locked(&self, col: i32, row: i32) -> bool {
+        self.protected && self.get_cell(col, row).is_some_and(|c| c.style.locked)
+    }
+
+    /// Bounding box of the current selection
+    pub fn selection_bounds(&self) -> Option<CellRange> {
+        self.selected.bounds()
+    }
+
+    /// Where the selection's far corner is: `selection_end` while it's still
+    /// part of the selection, else the active cell
+    pub fn selection_corner(&self) -> Option<(i32, i32)> {
+        self.selection_end.filter(|end| self.selected.contains(end)).or(self.active)
+    }
+
+    /// Reshape the last selected range into the rectangle from the active cell
+    /// to `corner`, keeping the active cell where it is
+    pub fn extend_selection(&mut self, corner: (i32, i32)) {
+        let anchor = self.active.unwrap_or(corner);
+        self.selected.set_last(CellRange::new(anchor, corner));
+        self.selection_end = Some(corner);
+    }
+
+    /// Cell carrying the fill handle: the selection's bottom-right, while it's
+    /// a single range
+    pub fn fill_handle(&self) -> Option<(i32, i32)> {
+        match self.selected.ranges() {
+            [range] => Some((range.max_col, range.max_row)),
+            _ => None,
+        }
+    }
+
+    /// Select a whole column or row, over the used cells and the lines in
+    /// `view` (e.g. the first and last on screen), whichever reach further
+    /// either way (negative lines included)
+    pub fn select_line(&mut self, axis: Axis, index: i32, view: (i32, i32)) {
+        let (first, last) = self
+            .cells
+            .keys()
+            .map(|(col, row)| match axis {
+                Axis::Column => row,
+                Axis::Row => col,
+            })
+            .fold(view, |(first, last), line| (first.min(line), last.max(line)));
+        self.selected.set(match axis {
+            Axis::Column => CellRange::new((index, first), (index, last)),
+            Axis::Row => CellRange::new((first, index), (last, index)),
+        });
+    }
+
+    /// Run a single tick evaluation without any rendering
+    /// Returns the cells whose values changed
+    pub fn tick(&mut self) -> Vec<CellChanged> {
+        evaluate_tick(self)
+    }
+
+    /// Run `n` tick evaluations headlessly (tests, benchmarks, server-side jobs)
+    /// Uses exactly the same semantics as the app's tick system
+    pub fn run_ticks(&mut self, n: usize) {
+        for _ in 0..n {
+            evaluate_tick(self);
+        }
+    }
+
     /// Generate GPU buffer for a specific viewport region
-    pub fn to_gpu_cells_viewport(&self, min_col: i32, min_row: i32, width: i32, height: i32) -> Vec<u32> {
-        let count = (width * height) as usize;
-        let mut buffer = Vec::with_capacity(count); // 1 u32 per cell
-
-        for y in 0..height {
-            for x in 0..width {
-                let col = min_col + x;
-                let row = min_row + y;
-                
-                let is_selected = self.selected.contains(&(col, row));
-                
-                if let Some(cell) = self.cells.get(&(col, row)) {
-                    let gpu_cell = GpuCell::from_cell(cell, is_selected);
-                    let flags = gpu_cell.to_u32();
-                    buffer.push(flags);
-                } else {
-                    // Empty cell
-                    let mut flags = 0u32;
-                    if is_selected {
-                        flags |= GpuCell::FLAG_SELECTED;
-                    }
-                    buffer.push(flags);
+    /// The region is in visual coordinates: hidden rows/columns are skipped,
+    /// and frozen panes are appended after it (see `SheetLayout::viewport_slots`)
+    /// `copied`: cells on the clipboard, outlined with marching ants
+    pub fn to_gpu_cells_viewport(&self, min_col: i32, min_row: i32, width: i32, height: i32, copied: &HashSet<(i32, i32)>) -> Vec<u32> {
+        let slots = self.layout.viewport_slots(min_col, min_row, width, height);
+        let mut buffer = Vec::with_capacity(slots.len() * GpuCell::STRIDE);
+        let mut cells = self.cells.reader();
+        let fill_handle = self.fill_handle();
+        // Bands alternate by visual row, so they stay even when rows are hidden
+        let band_top = self
+            .banded
+            .and_then(|band| (band.min_row..=band.max_row).find_map(|row| self.layout.rows.to_visual(row)));
+
+        for (visual_col, visual_row) in slots {
+            let (col, row) = self.layout.to_logical(visual_col, visual_row);
+
+            let is_selected = self.selected.contains(&(col, row));
+
+            let mut gpu_cell = match cells.get(col, row) {
+                Some(cell) => GpuCell::from_cell(cell, &self.theme.resolve(cell.style), is_selected),
+                None => GpuCell::empty(is_selected),
+            };
+
+            if self.active == Some((col, row)) {
+                gpu_cell.flags |= GpuCell::FLAG_ACTIVE;
+            }
+            if fill_handle == Some((col, row)) {
+                gpu_cell.flags |= GpuCell::FLAG_FILL_HANDLE;
+            }
+            if let (Some(band), Some(top)) = (self.banded.filter(|b| b.contains(col, row)), band_top) {
+                if row == band.min_row {
+                    gpu_cell.flags |= GpuCell::FLAG_BAND_HEADER;
+                } else if (visual_row - top) % 2 == 0 {
+                    gpu_cell.flags |= GpuCell::FLAG_BAND_STRIPE;
                 }
             }
+
+            // Outline the selection and the copy source along their visual edges
+            let on_outline = |inside: &dyn Fn(&(i32, i32)) -> bool| {
+                border_edges(|dx, dy| inside(&self.layout.to_logical(visual_col + dx, visual_row + dy)))
+            };
+            if is_selected {
+                gpu_cell.flags |= on_outline(&|cell| self.selected.contains(cell)) << GpuCell::SELECTION_EDGES_SHIFT;
+            }
+            if copied.contains(&(col, row)) {
+                gpu_cell.flags |= on_outline(&|cell| copied.contains(cell)) << GpuCell::COPY_EDGES_SHIFT;
+            }
+
+            // Mark the edges where hidden lines were collapsed
+            if self.layout.cols.hidden_before(col) {
+                gpu_cell.flags |= GpuCell::FLAG_HIDDEN_COLS_BEFORE;
+            }
+            if self.layout.rows.hidden_before(row) {
+                gpu_cell.flags |= GpuCell::FLAG_HIDDEN_ROWS_BEFORE;
+            }
+            if let Some(filter) = self.table_filter.as_ref().filter(|f| f.is_header(col, row)) {
+                gpu_cell.flags |= GpuCell::FLAG_DROPDOWN;
+                if filter.criteria.contains_key(&col) {
+                    gpu_cell.flags |= GpuCell::FLAG_FILTER_ACTIVE;
+                }
+            }
+            if validation_at(self, col, row).is_some_and(|v| v.choices().is_some()) {
+                gpu_cell.flags |= GpuCell::FLAG_DROPDOWN;
+            }
+            buffer.extend_from_slice(&gpu_cell.to_words());
         }
 
         buffer
     }
+
+    /// Border words for the same slots as `to_gpu_cells_viewport` (see `border_words`)
+    pub fn to_gpu_borders_viewport(&self, min_col: i32, min_row: i32, width: i32, height: i32) -> Vec<u32> {
+        let mut cells = self.cells.reader();
+        self.layout
+            .viewport_slots(min_col, min_row, width, height)
+            .into_iter()
+            .flat_map(|(visual_col, visual_row)| {
+                let (col, row) = self.layout.to_logical(visual_col, visual_row);
+                cells.get(col, row).map_or([0; BORDER_STRIDE], |cell| border_words(&cell.style.borders))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_accessors() {
+        let mut grid = GridState::new();
+        grid.set_range((1, 1), [["1", "2"], ["3", "= B1 + C1"]]);
+        assert_eq!(
+            grid.get_range(CellRange::new((1, 1), (2, 2))),
+            vec![vec![Value::Int(1), Value::Int(2)], vec![Value::Int(3), Value::Empty]]
+        );
+
+        grid.map_region(CellRange::new((1, 1), (2, 1)), |_, cell| {
+            cell.map(|c| format!("{}0", c.raw))
+        });
+        assert_eq!(grid.get_cell(2, 1).unwrap().value, Value::Int(20));
+
+        let region = CellRange::new((0, 0), (3, 3));
+        let coords: Vec<(i32, i32)> = grid.iter_region(region).map(|(coord, _)| coord).collect();
+        assert_eq!(coords, vec![(1, 1), (2, 1), (1, 2), (2, 2)]);
+
+        grid.fill_region(CellRange::new((0, 0), (0, 2)), "x");
+        assert_eq!(grid.iter_region(region).count(), 7);
+
+        grid.clear_region(region);
+        assert!(grid.cells.is_empty());
+    }
+
+    #[test]
+    fn test_transaction_rollback_and_commit() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1", "2"]]);
+
+        // Rolled back transactions leave no trace, even across nesting
+        grid.begin_transaction("Script");
+        grid.set_range((0, 0), [["9"]]);
+        grid.begin_transaction("Step");
+        grid.layout.rows.hide(0);
+        grid.clear_region(CellRange::cell(1, 0));
+        grid.rollback();
+        assert!(!grid.in_transaction());
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(1));
+        assert_eq!(grid.get_cell(1, 0).unwrap().raw, "2");
+        assert!(!grid.layout.rows.is_hidden(0));
+
+        // Committing yields one undoable group covering every step
+        grid.begin_transaction("Script");
+        grid.set_range((0, 0), [["5"]]);
+        grid.begin_transaction("Step");
+        grid.clear_region(CellRange::cell(1, 0));
+        assert!(grid.commit().unwrap().is_empty());
+        let group = grid.commit().unwrap();
+        assert_eq!(group.label, "Script");
+        assert_eq!(group.edits.len(), 2);
+
+        let mut stack = crate::undo::UndoStack::default();
+        stack.record(group);
+        stack.undo(&mut grid);
+        assert_eq!(grid.get_cell(0, 0).unwrap().raw, "1");
+        assert_eq!(grid.get_cell(1, 0).unwrap().raw, "2");
+        assert!(grid.commit().is_err());
+    }
+
+    #[test]
+    fn test_transaction_refuses_locked_cells() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1"]]);
+        grid.get_cell_mut(0, 0).unwrap().style.locked = true;
+        grid.protected = true;
+
+        grid.begin_transaction("Script");
+        grid.set_range((0, 0), [["2", "3"]]);
+        assert!(grid.commit().is_err());
+        assert_eq!(grid.get_cell(0, 0).unwrap().raw, "1");
+        assert!(grid.get_cell(1, 0).is_none());
+    }
+
+    #[test]
+    fn test_select_line() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1"], ["2"], ["3"]]);
+        grid.select_line(Axis::Column, 4, (0, 1));
+        assert_eq!(grid.selection_bounds(), Some(CellRange::new((4, 0), (4, 2))));
+        grid.select_line(Axis::Row, 1, (0, 5));
+        assert_eq!(grid.selection_bounds(), Some(CellRange::new((0, 1), (5, 1))));
+
+        // Lines before 0 are part of it, whether used or on screen
+        grid.set_range((0, -3), [["x"]]);
+        grid.select_line(Axis::Column, 4, (1, 2));
+        assert_eq!(grid.selection_bounds(), Some(CellRange::new((4, -3), (4, 2))));
+        grid.select_line(Axis::Row, 1, (-6, -2));
+        assert_eq!(grid.selection_bounds(), Some(CellRange::new((-6, 1), (0, 1))));
+    }
+
+    #[test]
+    fn test_extend_selection_keeps_active() {
+        let mut grid = GridState::new();
+        grid.active = Some((2, 2));
+        grid.extend_selection((0, 3));
+        assert_eq!(grid.selection_bounds(), Some(CellRange::new((0, 2), (2, 3))));
+        assert_eq!((grid.active, grid.selection_corner()), (Some((2, 2)), Some((0, 3))));
+
+        // Shrinking back past the active cell flips the rectangle
+        grid.extend_selection((3, 1));
+        assert_eq!(grid.selection_bounds(), Some(CellRange::new((2, 1), (3, 2))));
+
+        // A corner no longer selected falls back to the active cell
+        grid.selected.clear();
+        assert_eq!(grid.selection_corner(), Some((2, 2)));
+    }
+
+    #[test]
+    fn test_banded_rows_skip_hidden() {
+        let mut grid = GridState::new();
+        grid.banded = Some(CellRange::new((0, 0), (0, 4)));
+        grid.layout.rows.hide(2);
+        let words = grid.to_gpu_cells_viewport(0, 0, 1, 5, &HashSet::new());
+        let flags: Vec<u32> = words
+            .chunks(GpuCell::STRIDE)
+            .map(|w| w[0] & (GpuCell::FLAG_BAND_HEADER | GpuCell::FLAG_BAND_STRIPE))
+            .collect();
+        // Visual rows show logical 0, 1, 3, 4, 5
+        assert_eq!(flags, [GpuCell::FLAG_BAND_HEADER, 0, GpuCell::FLAG_BAND_STRIPE, 0, 0]);
+    }
 }
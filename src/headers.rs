@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::formula::{col_name, coord_to_name, name_to_coord, row_name};
+
+/// Display names for columns and rows
+/// Shown in the header gutters in place of the default letters/numbers, and
+/// usable in formulas as structured references: `[Price]5`, `C[Total]` or
+/// `[Price][Total]`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderLabels {
+    cols: BTreeMap<i32, String>,
+    rows: BTreeMap<i32, String>,
+}
+
+/// Store a label, or remove it when it's blank
+/// Brackets are dropped since they delimit labels in formulas
+fn set_label(labels: &mut BTreeMap<i32, String>, index: i32, label: &str) {
+    let label: String = label.trim().chars().filter(|c| !matches!(c, '[' | ']')).collect();
+    if label.is_empty() {
+        labels.remove(&index);
+    } else {
+        labels.insert(index, label);
+    }
+}
+
+/// First (lowest) index carrying a label
+fn find_label(labels: &BTreeMap<i32, String>, label: &str) -> Option<i32> {
+    labels.iter().find(|(_, l)| *l == label).map(|(&i, _)| i)
+}
+
+impl HeaderLabels {
+    /// Gutter text for a column: its label, or the column letters
+    pub fn col_label(&self, col: i32) -> String {
+        self.cols.get(&col).cloned().unwrap_or_else(|| col_name(col))
+    }
+
+    /// Gutter text for a row: its label, or the row number
+    pub fn row_label(&self, row: i32) -> String {
+        self.rows.get(&row).cloned().unwrap_or_else(|| row_name(row))
+    }
+
+    pub fn set_col_label(&mut self, col: i32, label: &str) {
+        set_label(&mut self.cols, col, label);
+    }
+
+    pub fn set_row_label(&mut self, row: i32, label: &str) {
+        set_label(&mut self.rows, row, label);
+    }
+
+    pub fn find_col(&self, label: &str) -> Option<i32> {
+        find_label(&self.cols, label)
+    }
+
+    pub fn find_row(&self, label: &str) -> Option<i32> {
+        find_label(&self.rows, label)
+    }
+
+    /// Map every labelled column through `f`, dropping those it returns None for
+    /// (keeps labels attached to their data across inserts/deletes)
+    pub fn remap_cols(&mut self, f: impl Fn(i32) -> Option<i32>) {
+        self.cols = std::mem::take(&mut self.cols).into_iter().filter_map(|(i, l)| Some((f(i)?, l))).collect();
+    }
+
+    /// Row counterpart of `remap_cols`
+    pub fn remap_rows(&mut self, f: impl Fn(i32) -> Option<i32>) {
+        self.rows = std::mem::take(&mut self.rows).into_iter().filter_map(|(i, l)| Some((f(i)?, l))).collect();
+    }
+
+    /// Replace structured references with plain cell names so the formula can
+    /// be evaluated: `[Price]5` -> `C5` when column C is labelled "Price"
+    /// String literals are left alone; unknown labels are an error
+    pub fn resolve(&self, formula: &str) -> Result<String, String> {
+        if !formula.contains('[') {
+            return Ok(formula.to_string());
+        }
+
+        let chars: Vec<char> = formula.chars().collect();
+        let mut out = String::with_capacity(formula.len());
+        let mut i = 0;
+
+        // Bracketed label starting at `i`, returning it and the index after `]`
+        let bracketed = |i: usize| -> Option<(String, usize)> {
+            if chars.get(i) != Some(&'[') {
+                return None;
+            }
+            let len = chars[i + 1..].iter().position(|&c| c == ']')?;
+            Some((chars[i + 1..i + 1 + len].iter().collect(), i + len + 2))
+        };
+        // Plain part of a name (`_`-prefixed run of matching chars) starting at `i`
+        let plain = |i: usize, f: fn(&char) -> bool| -> (String, usize) {
+            let start = i;
+            let mut end = if chars.get(i) == Some(&'_') { i + 1 } else { i };
+            while end < chars.len() && f(&chars[end]) {
+                end += 1;
+            }
+            (chars[start..end].iter().collect(), end)
+        };
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            // Copy string literals verbatim
+            if c == '"' {
+                let start = i;
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(chars.len());
+                out.extend(&chars[start..i]);
+                continue;
+            }
+
+            let starts_token = i == 0 || !(chars[i - 1].is_ascii_alphanumeric() || chars[i - 1] == '_');
+
+            // `[Col]` followed by a row number or `[Row]`
+            if let Some((col_label, next)) = bracketed(i) {
+                let col = self.find_col(&col_label).ok_or_else(|| format!("unknown column [{}]", col_label))?;
+                let (row, next) = match bracketed(next) {
+                    Some((row_label, next)) => {
+                        (self.find_row(&row_label).ok_or_else(|| format!("unknown row [{}]", row_label))?, next)
+                    }
+                    None => {
+                        let (digits, next) = plain(next, char::is_ascii_digit);
+                        let row = name_to_coord(&format!("A{}", digits)).map(|(_, row)| row);
+                        (row.ok_or_else(|| format!("[{}] needs a row", col_label))?, next)
+                    }
+                };
+                out.push_str(&coord_to_name(col, row));
+                i = next;
+                continue;
+            }
+
+            // Column letters followed by `[Row]`
+            if starts_token && (c.is_ascii_uppercase() || c == '_') {
+                let (letters, next) = plain(i, char::is_ascii_uppercase);
+                if let Some((row_label, after)) = bracketed(next) {
+                    if let Some((col, _)) = name_to_coord(&format!("{}0", letters)) {
+                        let row = self.find_row(&row_label).ok_or_else(|| format!("unknown row [{}]", row_label))?;
+                        out.push_str(&coord_to_name(col, row));
+                        i = after;
+                        continue;
+                    }
+                }
+            }
+
+            out.push(c);
+            i += 1;
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_and_defaults() {
+        let mut labels = HeaderLabels::default();
+        labels.set_col_label(2, " Price ");
+        labels.set_row_label(-1, "Total");
+
+        assert_eq!(labels.col_label(2), "Price");
+        assert_eq!(labels.col_label(3), "D");
+        assert_eq!(labels.row_label(-1), "Total");
+        assert_eq!(labels.row_label(4), "4");
+
+        // Labels follow their columns across inserts; a blank label clears one
+        labels.remap_cols(|col| if col >= 1 { Some(col + 1) } else { Some(col) });
+        assert_eq!(labels.find_col("Price"), Some(3));
+        labels.set_col_label(3, "");
+        assert_eq!(labels.find_col("Price"), None);
+    }
+
+    #[test]
+    fn test_structured_references() {
+        let mut labels = HeaderLabels::default();
+        labels.set_col_label(2, "Price");
+        labels.set_row_label(7, "Total");
+
+        assert_eq!(labels.resolve("= [Price]5 * 2").unwrap(), "= C5 * 2");
+        assert_eq!(labels.resolve("= A[Total] + [Price][Total]").unwrap(), "= A7 + C7");
+        assert_eq!(labels.resolve("= [Price]_1").unwrap(), "= C_1");
+        // Plain formulas and string literals are untouched
+        assert_eq!(labels.resolve("= A0 + 1").unwrap(), "= A0 + 1");
+        assert_eq!(labels.resolve("= str::len(\"[Price]5\")").unwrap(), "= str::len(\"[Price]5\")");
+
+        assert!(labels.resolve("= [Cost]5").is_err());
+        assert!(labels.resolve("= [Price]").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+/// Value range the heatmap lens spreads its color scale over
+/// Either end left unset follows the visible cells, so the scale keeps up with
+/// a simulation as it evolves
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeatScale {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+impl HeatScale {
+    /// Fixed at both ends
+    pub fn fixed(min: f32, max: f32) -> Self {
+        Self { min: Some(min.min(max)), max: Some(min.max(max)) }
+    }
+
+    pub fn is_auto(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    /// The (min, max) to color by, given the numbers in view
+    /// An empty or single-valued range is widened so the shader never divides by zero
+    pub fn resolve(&self, values: impl IntoIterator<Item = f32>) -> (f32, f32) {
+        let (mut low, mut high) = (f32::INFINITY, f32::NEG_INFINITY);
+        for value in values.into_iter().filter(|v| v.is_finite()) {
+            low = low.min(value);
+            high = high.max(value);
+        }
+        let min = self.min.unwrap_or(if low.is_finite() { low } else { 0.0 });
+        let max = self.max.unwrap_or(if high.is_finite() { high } else { 1.0 });
+        if max > min { (min, max) } else { (min, min + 1.0) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_resolves() {
+        let auto = HeatScale::default();
+        assert_eq!(auto.resolve([3.0, -1.0, f32::NAN, 7.0]), (-1.0, 7.0));
+        assert_eq!(auto.resolve([]), (0.0, 1.0));
+        assert_eq!(auto.resolve([2.0, 2.0]), (2.0, 3.0));
+
+        assert_eq!(HeatScale::fixed(10.0, 0.0).resolve([50.0]), (0.0, 10.0));
+        let floor = HeatScale { min: Some(0.0), max: None };
+        assert_eq!(floor.resolve([4.0, 8.0]), (0.0, 8.0));
+    }
+}
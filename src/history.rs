@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::cell::Cell;
+use crate::cell_store::CellStore;
+use crate::grid_state::GridState;
+
+/// Number of ticks kept for time travel by default
+pub const DEFAULT_HISTORY_CAPACITY: usize = 256;
+
+/// Computed state of every cell right after one tick
+#[derive(Clone, Debug)]
+pub struct TickSnapshot {
+    /// Tick number this snapshot was taken after
+    pub tick: u64,
+    /// The cells as they were; chunks are shared with the grid and the other
+    /// snapshots until a later tick writes to them, so each tick costs what it
+    /// changed
+    pub cells: CellStore,
+}
+
+impl TickSnapshot {
+    /// Capture the computed values of every cell in the grid
+    pub fn capture(tick: u64, grid: &GridState) -> Self {
+        Self { tick, cells: grid.cells.clone() }
+    }
+
+    /// Write the recorded values back into the grid
+    /// Only chunks the grid no longer shares with the snapshot can differ;
+    /// cells created after the snapshot was taken are left untouched
+    pub fn apply(&self, grid: &mut GridState) {
+        for (key, recorded) in self.cells.diverged_from(&grid.cells) {
+            let same = |cell: &Cell| {
+                cell.value == recorded.value && cell.error == recorded.error && cell.error_code == recorded.error_code
+            };
+            if grid.cells.get(&key).is_none_or(same) {
+                continue;
+            }
+            if let Some(cell) = grid.cells.get_evaluated_mut(&key) {
+                cell.value = recorded.value.clone();
+                cell.error = recorded.error;
+                cell.error_code = recorded.error_code;
+            }
+        }
+    }
+}
+
+/// Ring buffer of recent tick snapshots plus the scrubbing cursor
+/// While scrubbing, ticks and edits are paused
+#[derive(Resource)]
+pub struct TickHistory {
+    pub capacity: usize,
+    snapshots: VecDeque<TickSnapshot>,
+    /// Index into `snapshots` while scrubbing, None when live
+    cursor: Option<usize>,
+}
+
+impl Default for TickHistory {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl TickHistory {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+            cursor: None,
+        }
+    }
+
+    /// Record the grid state after a tick, evicting the oldest snapshot when full
+    pub fn record(&mut self, tick: u64, grid: &GridState) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(TickSnapshot::capture(tick, grid));
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn is_scrubbing(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Index of the snapshot currently shown, if scrubbing
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    /// Tick number of the snapshot currently shown, if scrubbing
+    pub fn current_tick(&self) -> Option<u64> {
+        self.cursor.and_then(|i| self.snapshots.get(i)).map(|s| s.tick)
+    }
+
+    /// Show the snapshot at `index` (clamped to the recorded range)
+    pub fn scrub_to(&mut self, index: usize, grid: &mut GridState) {
+        if self.snapshots.is_empty() {
+            return;
+        }
+        let index = index.min(self.snapshots.len() - 1);
+        self.snapshots[index].apply(grid);
+        self.cursor = Some(index);
+    }
+
+    /// Move the cursor by `delta` ticks, entering scrub mode from the latest snapshot
+    pub fn step(&mut self, delta: i64, grid: &mut GridState) {
+        if self.snapshots.is_empty() {
+            return;
+        }
+        let current = self.cursor.unwrap_or(self.snapshots.len() - 1) as i64;
+        let target = (current + delta).max(0) as usize;
+        self.scrub_to(target, grid);
+    }
+
+    /// Leave scrub mode, continuing the simulation from the shown snapshot
+    /// Later snapshots are discarded so replay starts from here
+    /// Returns the tick number to resume counting from
+    pub fn resume(&mut self) -> Option<u64> {
+        let index = self.cursor.take()?;
+        self.snapshots.truncate(index + 1);
+        self.snapshots.back().map(|s| s.tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::Value;
+
+    fn counter_grid() -> GridState {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 0).set_raw("= A0 + 1".to_string());
+        grid
+    }
+
+    #[test]
+    fn test_scrub_and_resume() {
+        let mut grid = counter_grid();
+        let mut history = TickHistory::default();
+        for tick in 1..=5 {
+            grid.tick();
+            history.record(tick, &grid);
+        }
+
+        history.step(-2, &mut grid);
+        assert!(history.is_scrubbing());
+        assert_eq!(history.current_tick(), Some(3));
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(3));
+
+        // Resuming drops the future and continues from the shown state
+        assert_eq!(history.resume(), Some(3));
+        assert_eq!(history.len(), 3);
+        grid.tick();
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(4));
+    }
+
+    #[test]
+    fn test_snapshots_share_unchanged_chunks() {
+        let mut grid = counter_grid();
+        grid.set_range((100, 100), [["7"]]);
+        let mut history = TickHistory::default();
+        for tick in 1..=2 {
+            grid.tick();
+            history.record(tick, &grid);
+        }
+
+        // Only the counter's chunk was copied by the second tick
+        let diverged = history.snapshots[0].cells.diverged_from(&grid.cells);
+        assert_eq!(diverged.iter().map(|(key, _)| *key).collect::<Vec<_>>(), [(0, 0)]);
+        history.scrub_to(0, &mut grid);
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Int(1));
+        assert_eq!(grid.get_cell(100, 100).unwrap().value, Value::Int(7));
+    }
+
+    #[test]
+    fn test_capacity_evicts_oldest() {
+        let mut grid = counter_grid();
+        let mut history = TickHistory::with_capacity(2);
+        for tick in 1..=3 {
+            grid.tick();
+            history.record(tick, &grid);
+        }
+
+        assert_eq!(history.len(), 2);
+        history.scrub_to(0, &mut grid);
+        assert_eq!(history.current_tick(), Some(2));
+    }
+}
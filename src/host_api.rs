@@ -0,0 +1,1116 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bevy::prelude::{Rect, Resource, Timer, TimerMode};
+use evalexpr::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::cell::CellDisplay;
+use crate::diagnostics::SheetStats;
+use crate::evaluator::TickControl;
+use crate::events::{CellChanged, ChangeSource};
+use crate::feeds;
+use crate::formula::coord_to_name;
+use crate::grid_state::{CellRange, GridState};
+use crate::host_functions::HostCall;
+use crate::navigation::go_to_target;
+use crate::persist::{load_binary, save_binary};
+use crate::undo::EditGroup;
+
+/// Most cells one `GetRange` may return
+const MAX_RANGE_CELLS: i64 = 100_000;
+/// How often metrics come by default, in milliseconds
+const METRICS_INTERVAL_MS: u32 = 1000;
+/// Length of the value mirror's header, in numbers (see `mirror_frame`)
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub const MIRROR_HEADER: usize = 5;
+/// Most cells the value mirror holds; rows past it are left out
+pub const MIRROR_CELLS: usize = 16_384;
+
+/// Version of the message protocol (see `HostMessage`), bumped whenever a
+/// message or reply changes shape
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A call from the page embedding the web build (see `web`)
+/// Names and ranges are read like the name box reads them: `B3`, `A0:C5`,
+/// `[Price]2`
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum HostRequest {
+    GetCell { name: String },
+    SetCell { name: String, raw: String },
+    GetRange { range: String },
+    ClearRange { range: String },
+    /// Hear about value changes in `range` (or anywhere), after each tick or
+    /// edit (see `Subscriptions::notices`)
+    Subscribe {
+        #[serde(default)]
+        range: Option<String>,
+    },
+    Unsubscribe { subscription: u32 },
+    /// Leave the cells in `range` for the page to draw, as its own elements
+    /// (inputs, videos): while they're on screen, where they are and what they
+    /// hold comes to the page (see `OverlayCell`); `Unsubscribe` hands them back
+    ShowOverlays { range: String },
+    /// Hear the sheet's performance numbers (the F3 overlay's) every
+    /// `interval_ms`, a second by default; `Unsubscribe` stops them
+    WatchMetrics {
+        #[serde(default)]
+        interval_ms: Option<u32>,
+    },
+    /// Evaluate one tick, like the Tick button
+    Tick,
+    /// Turn ticking by itself on or off, optionally every `interval_ms`
+    SetAutoTick {
+        enabled: bool,
+        #[serde(default)]
+        interval_ms: Option<u32>,
+    },
+    GetTickCount,
+    /// The workbook in the binary format (see `persist::save_binary`), for
+    /// the page to store where it likes
+    ExportWorkbook,
+    /// Replace the open workbook with an exported one (`data`, base64 like
+    /// `ExportWorkbook`'s), starting a fresh session like loading a file
+    ImportWorkbook { data: String },
+    /// A PNG of `range` as Export image draws it, or of the whole cells on
+    /// screen (served by the app, which knows the view)
+    Screenshot {
+        #[serde(default)]
+        range: Option<String>,
+    },
+    /// Let formulas call `name`, computed by the page (see `HostFunctions`):
+    /// after each tick the calls come to the page, which answers each with
+    /// `AnswerCall` (a value, or an error message)
+    RegisterFunction { name: String },
+    UnregisterFunction { name: String },
+    AnswerCall {
+        call: u64,
+        #[serde(default)]
+        #[cfg_attr(target_arch = "wasm32", tsify(type = "unknown"))]
+        value: Option<serde_json::Value>,
+        #[serde(default)]
+        error: Option<String>,
+    },
+}
+
+/// A request as a JSON message, for `web::post_message`:
+/// `{"version": 1, "id": 7, "type": "setCell", "name": "B3", "raw": "=A0*2"}`
+/// Every message gets exactly one `HostReply`, carrying the same `id`
+/// (Messages only arrive on the web, so native builds leave these unused)
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct HostMessage {
+    pub version: u32,
+    #[serde(default)]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub request: HostRequest,
+}
+
+/// What goes back to the page for a `HostMessage` (or, for `notices`,
+/// `overlays`, `metrics` and `call`, to the listener given with `subscribe`,
+/// `showOverlays`, `watchMetrics` or `registerFunction`)
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HostReply {
+    Ok { version: u32, id: Option<u64>, result: HostResponse },
+    /// The message wasn't understood (bad JSON, wrong version, unknown type)
+    /// or couldn't be carried out
+    Error { version: u32, id: Option<u64>, message: String },
+    Notices { version: u32, subscription: u32, notices: Vec<CellNotice> },
+    /// Every overlaid cell on screen, whenever any moved or changed
+    Overlays { version: u32, subscription: u32, cells: Vec<OverlayCell> },
+    Metrics { version: u32, subscription: u32, metrics: SheetStats },
+    /// A formula called a function the page registered: answer with
+    /// `answerCall` and the same `call`
+    Call {
+        version: u32,
+        call: u64,
+        name: String,
+        #[cfg_attr(target_arch = "wasm32", tsify(type = "unknown[]"))]
+        args: Vec<serde_json::Value>,
+    },
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+impl HostReply {
+    pub fn new(id: Option<u64>, result: Result<HostResponse, String>) -> Self {
+        match result {
+            Ok(result) => Self::Ok { version: PROTOCOL_VERSION, id, result },
+            Err(message) => Self::Error { version: PROTOCOL_VERSION, id, message },
+        }
+    }
+}
+
+/// Read a message from the page; if it can't be, the error to reply with and
+/// the message's id if it had one
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+pub fn parse_message(text: &str) -> Result<HostMessage, (Option<u64>, String)> {
+    let value: serde_json::Value = serde_json::from_str(text).map_err(|e| (None, format!("Not JSON: {}", e)))?;
+    let id = value.get("id").and_then(serde_json::Value::as_u64);
+    match value.get("version").and_then(serde_json::Value::as_u64) {
+        Some(version) if version == PROTOCOL_VERSION as u64 => {}
+        Some(version) => {
+            return Err((id, format!("Protocol version {} isn't supported (this is {})", version, PROTOCOL_VERSION)));
+        }
+        None => return Err((id, "Missing protocol version".to_string())),
+    }
+    serde_json::from_value(value).map_err(|e| (id, format!("Bad message: {}", e)))
+}
+
+/// One cell as the page sees it
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CellInfo {
+    pub name: String,
+    pub raw: String,
+    /// The computed value: a number, boolean or string (null when empty)
+    #[cfg_attr(target_arch = "wasm32", tsify(type = "number | boolean | string | null"))]
+    pub value: serde_json::Value,
+    /// The value as the sheet shows it, number format applied
+    pub text: String,
+    pub formula: bool,
+    /// The error code while the cell is in error (`#DIV/0!`)
+    pub error: Option<String>,
+}
+
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum HostResponse {
+    Cell(CellInfo),
+    /// Rows of cells, top to bottom
+    Range(Vec<Vec<CellInfo>>),
+    /// The new subscription's id
+    Subscribed(u32),
+    TickCount(u64),
+    /// A binary workbook, base64 encoded
+    Workbook(String),
+    /// A PNG image, base64 encoded
+    Png(String),
+    Done,
+}
+
+impl HostResponse {
+    pub fn png(bytes: &[u8]) -> Self {
+        Self::Png(BASE64.encode(bytes))
+    }
+}
+
+/// One cell's value change, as sent to subscribers
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CellNotice {
+    pub cell: String,
+    #[cfg_attr(target_arch = "wasm32", tsify(type = "number | boolean | string | null"))]
+    pub old: serde_json::Value,
+    #[cfg_attr(target_arch = "wasm32", tsify(type = "number | boolean | string | null"))]
+    pub new: serde_json::Value,
+    /// The tick count when it changed
+    pub tick: u64,
+    #[cfg_attr(target_arch = "wasm32", tsify(type = "\"tick\" | \"edit\" | \"feed\""))]
+    pub source: &'static str,
+}
+
+/// An overlaid cell on screen, for the page to put its element over
+#[cfg_attr(target_arch = "wasm32", derive(tsify_next::Tsify))]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OverlayCell {
+    #[serde(flatten)]
+    pub cell: CellInfo,
+    /// CSS pixels from the canvas's top-left corner
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// What the page has subscribed to, by id
+#[derive(Resource, Default)]
+pub struct Subscriptions {
+    next_id: u32,
+    ranges: Vec<(u32, Option<CellRange>)>,
+    /// Cells the page draws itself (see `HostRequest::ShowOverlays`)
+    overlays: Vec<(u32, CellRange)>,
+    /// Metrics watchers, each timing its next report
+    metrics: Vec<(u32, Timer)>,
+}
+
+impl Subscriptions {
+    pub fn has_metrics(&self) -> bool {
+        !self.metrics.is_empty()
+    }
+
+    /// Advance the metrics watchers' timers: those due a report now
+    pub fn due_metrics(&mut self, delta: Duration) -> Vec<u32> {
+        self.metrics
+            .iter_mut()
+            .filter_map(|(id, timer)| timer.tick(delta).just_finished().then_some(*id))
+            .collect()
+    }
+
+    pub fn has_overlays(&self) -> bool {
+        !self.overlays.is_empty()
+    }
+
+    /// Whether the page draws this cell, so the sheet leaves it blank
+    pub fn is_overlaid(&self, col: i32, row: i32) -> bool {
+        self.overlays.iter().any(|(_, range)| range.contains(col, row))
+    }
+
+    /// Each overlay's share of the cells on screen, given with their screen
+    /// rects (overlays with none get an empty list, so the page can clear)
+    pub fn overlay_cells(&self, grid: &GridState, on_screen: &[((i32, i32), Rect)]) -> Vec<(u32, Vec<OverlayCell>)> {
+        self.overlays
+            .iter()
+            .map(|(id, range)| {
+                let cells = on_screen
+                    .iter()
+                    .filter(|((col, row), _)| range.contains(*col, *row))
+                    .map(|&((col, row), rect)| OverlayCell {
+                        cell: cell_info(grid, col, row),
+                        x: rect.min.x,
+                        y: rect.min.y,
+                        width: rect.width(),
+                        height: rect.height(),
+                    })
+                    .collect();
+                (*id, cells)
+            })
+            .collect()
+    }
+
+    /// Each subscription's share of `changes`, leaving out those with none
+    pub fn notices(&self, changes: &[CellChanged], tick: u64) -> Vec<(u32, Vec<CellNotice>)> {
+        self.ranges
+            .iter()
+            .map(|(id, range)| {
+                let notices = changes
+                    .iter()
+                    .filter(|change| range.is_none_or(|range| range.contains(change.col, change.row)))
+                    .map(|change| CellNotice {
+                        cell: coord_to_name(change.col, change.row),
+                        old: json_value(&change.old),
+                        new: json_value(&change.new),
+                        tick,
+                        source: match change.source {
+                            ChangeSource::Tick => "tick",
+                            ChangeSource::Edit => "edit",
+                            ChangeSource::Feed => "feed",
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                (*id, notices)
+            })
+            .filter(|(_, notices)| !notices.is_empty())
+            .collect()
+    }
+}
+
+/// Called with a request's outcome once it's been served
+pub type Reply = Box<dyn FnOnce(Result<HostResponse, String>)>;
+/// Called with a subscription's notices for one frame
+pub type Listener = Box<dyn Fn(&[CellNotice])>;
+/// Called with an overlay's cells on screen
+pub type OverlayListener = Box<dyn Fn(&[OverlayCell])>;
+/// Called with the sheet's metrics, when due
+pub type MetricsListener = Box<dyn Fn(&SheetStats)>;
+/// Passes calls to a registered function on to the page
+pub type Caller = Box<dyn Fn(&HostCall)>;
+/// Copies a `mirror_frame` into the page's shared buffer
+pub type Mirror = Box<dyn Fn(&[f64])>;
+
+thread_local! {
+    /// Requests waiting for the next frame (wasm runs on one thread)
+    static PENDING: RefCell<Vec<(HostRequest, Reply)>> = RefCell::new(Vec::new());
+    /// Workbook the page imported, swapped in by `handle_file_buttons`
+    static IMPORTED: RefCell<Option<(GridState, TickControl)>> = const { RefCell::new(None) };
+    /// Where each subscription's notices go
+    static LISTENERS: RefCell<HashMap<u32, Listener>> = RefCell::new(HashMap::new());
+    /// Where each overlay's cells go
+    static OVERLAY_LISTENERS: RefCell<HashMap<u32, OverlayListener>> = RefCell::new(HashMap::new());
+    /// Where each metrics watcher's reports go
+    static METRICS_LISTENERS: RefCell<HashMap<u32, MetricsListener>> = RefCell::new(HashMap::new());
+    /// Who computes each registered function
+    static CALLERS: RefCell<HashMap<String, Caller>> = RefCell::new(HashMap::new());
+    /// Where visible values are mirrored, while the page wants them
+    static MIRROR: RefCell<Option<Mirror>> = const { RefCell::new(None) };
+}
+
+pub fn is_mirroring() -> bool {
+    MIRROR.with(|mirror| mirror.borrow().is_some())
+}
+
+/// Copy `frame` (from `mirror_frame`) to the page, if it's mirroring
+pub fn mirror(frame: &[f64]) {
+    MIRROR.with(|mirror| {
+        if let Some(mirror) = mirror.borrow().as_ref() {
+            mirror(frame);
+        }
+    });
+}
+
+/// The values of the visual cells from (`min_col`, `min_row`) on, as the
+/// value mirror lays them out: a header of `generation`, the top-left cell's
+/// column and row, then the columns and rows that follow, and then the values
+/// row by row (numbers as they are, booleans as 0 or 1, anything else NaN)
+/// Hidden rows and columns are skipped, like on screen
+pub fn mirror_frame(grid: &GridState, min_col: i32, min_row: i32, width: usize, height: usize, generation: u64) -> Vec<f64> {
+    let height = height.min(MIRROR_CELLS / width.max(1));
+    let (first_col, first_row) = grid.layout.to_logical(min_col, min_row);
+    let mut frame = vec![generation as f64, first_col as f64, first_row as f64, width as f64, height as f64];
+    for visual_row in min_row..min_row + height as i32 {
+        for visual_col in min_col..min_col + width as i32 {
+            let (col, row) = grid.layout.to_logical(visual_col, visual_row);
+            frame.push(match grid.get_cell(col, row).map(|cell| &cell.value) {
+                Some(Value::Int(i)) => *i as f64,
+                Some(Value::Float(f)) => *f,
+                Some(Value::Boolean(b)) => f64::from(u8::from(*b)),
+                _ => f64::NAN,
+            });
+        }
+    }
+    frame
+}
+
+/// Pass `call` on to the page; false if nothing there computes it
+pub fn send_call(call: &HostCall) -> bool {
+    CALLERS.with(|callers| match callers.borrow().get(&call.name) {
+        Some(caller) => {
+            caller(call);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Hand a subscription its notices
+pub fn deliver(id: u32, notices: &[CellNotice]) {
+    LISTENERS.with(|listeners| {
+        if let Some(listener) = listeners.borrow().get(&id) {
+            listener(notices);
+        }
+    });
+}
+
+/// Hand an overlay its cells on screen
+pub fn deliver_overlays(id: u32, cells: &[OverlayCell]) {
+    OVERLAY_LISTENERS.with(|listeners| {
+        if let Some(listener) = listeners.borrow().get(&id) {
+            listener(cells);
+        }
+    });
+}
+
+/// Hand a metrics watcher its report
+pub fn deliver_metrics(id: u32, metrics: &SheetStats) {
+    METRICS_LISTENERS.with(|listeners| {
+        if let Some(listener) = listeners.borrow().get(&id) {
+            listener(metrics);
+        }
+    });
+}
+
+/// Take the workbook the page imported, if it has since the last call
+pub fn take_imported() -> Option<(GridState, TickControl)> {
+    IMPORTED.with(|imported| imported.borrow_mut().take())
+}
+
+/// Take the requests made since the last call, in order
+pub fn take_pending() -> Vec<(HostRequest, Reply)> {
+    PENDING.with(|pending| std::mem::take(&mut *pending.borrow_mut()))
+}
+
+/// Carry out `request` against the sheet: the response, and any edit the
+/// caller should commit (so it's undoable and recalculates like typing)
+/// Tick requests act on `ticks` and `tick_timer` (`EvaluationTimer`'s) directly
+pub fn answer(
+    grid: &GridState,
+    subscriptions: &mut Subscriptions,
+    ticks: &mut TickControl,
+    tick_timer: &mut Timer,
+    request: &HostRequest,
+) -> Result<(HostResponse, Option<EditGroup>), String> {
+    match request {
+        HostRequest::GetCell { name } => {
+            let (col, row) = cell_at(grid, name)?;
+            Ok((HostResponse::Cell(cell_info(grid, col, row)), None))
+        }
+        HostRequest::SetCell { name, raw } => {
+            let (col, row) = cell_at(grid, name)?;
+            let mut group = EditGroup::new("Set cell");
+            group.set_raw(grid, col, row, raw.clone());
+            Ok((HostResponse::Done, Some(group)))
+        }
+        HostRequest::GetRange { range } => {
+            let range = bounded_range_at(grid, range)?;
+            let rows = (range.min_row..=range.max_row)
+                .map(|row| (range.min_col..=range.max_col).map(|col| cell_info(grid, col, row)).collect())
+                .collect();
+            Ok((HostResponse::Range(rows), None))
+        }
+        HostRequest::ClearRange { range } => {
+            let range = range_at(grid, range)?;
+            let mut group = EditGroup::new("Clear range");
+            // Only the occupied cells, so huge ranges cost what's in them
+            for ((col, row), _) in grid.iter_region(range) {
+                group.clear(grid, col, row);
+            }
+            Ok((HostResponse::Done, Some(group)))
+        }
+        HostRequest::Subscribe { range } => {
+            let range = range.as_deref().map(|range| range_at(grid, range)).transpose()?;
+            subscriptions.next_id += 1;
+            subscriptions.ranges.push((subscriptions.next_id, range));
+            Ok((HostResponse::Subscribed(subscriptions.next_id), None))
+        }
+        HostRequest::ShowOverlays { range } => {
+            let range = range_at(grid, range)?;
+            subscriptions.next_id += 1;
+            subscriptions.overlays.push((subscriptions.next_id, range));
+            Ok((HostResponse::Subscribed(subscriptions.next_id), None))
+        }
+        HostRequest::WatchMetrics { interval_ms } => {
+            let interval_ms = interval_ms.unwrap_or(METRICS_INTERVAL_MS);
+            if interval_ms == 0 {
+                return Err("Metrics interval must be at least 1ms".to_string());
+            }
+            subscriptions.next_id += 1;
+            let timer = Timer::new(Duration::from_millis(interval_ms as u64), TimerMode::Repeating);
+            subscriptions.metrics.push((subscriptions.next_id, timer));
+            Ok((HostResponse::Subscribed(subscriptions.next_id), None))
+        }
+        HostRequest::Unsubscribe { subscription } => {
+            subscriptions.ranges.retain(|(id, _)| id != subscription);
+            subscriptions.overlays.retain(|(id, _)| id != subscription);
+            subscriptions.metrics.retain(|(id, _)| id != subscription);
+            LISTENERS.with(|listeners| listeners.borrow_mut().remove(subscription));
+            OVERLAY_LISTENERS.with(|listeners| listeners.borrow_mut().remove(subscription));
+            METRICS_LISTENERS.with(|listeners| listeners.borrow_mut().remove(subscription));
+            Ok((HostResponse::Done, None))
+        }
+        HostRequest::Tick => {
+            ticks.manual_tick_requested = true;
+            Ok((HostResponse::Done, None))
+        }
+        HostRequest::SetAutoTick { enabled, interval_ms } => {
+            if let Some(interval_ms) = interval_ms {
+                if *interval_ms == 0 {
+                    return Err("Tick interval must be at least 1ms".to_string());
+                }
+                tick_timer.set_duration(Duration::from_millis(*interval_ms as u64));
+            }
+            ticks.auto_tick_enabled = *enabled;
+            Ok((HostResponse::Done, None))
+        }
+        HostRequest::GetTickCount => Ok((HostResponse::TickCount(ticks.tick_count), None)),
+        HostRequest::ExportWorkbook => Ok((HostResponse::Workbook(BASE64.encode(save_binary(grid, ticks)?)), None)),
+        HostRequest::ImportWorkbook { data } => {
+            let bytes = BASE64.decode(data).map_err(|e| format!("Not base64: {}", e))?;
+            let workbook = load_binary(&bytes)?;
+            IMPORTED.with(|imported| *imported.borrow_mut() = Some(workbook));
+            Ok((HostResponse::Done, None))
+        }
+        HostRequest::Screenshot { .. } => Err("Screenshots are taken by the app, not here".to_string()),
+        HostRequest::RegisterFunction { name } => {
+            grid.host_functions.register(name)?;
+            Ok((HostResponse::Done, None))
+        }
+        HostRequest::UnregisterFunction { name } => {
+            grid.host_functions.unregister(name);
+            CALLERS.with(|callers| callers.borrow_mut().remove(name));
+            Ok((HostResponse::Done, None))
+        }
+        HostRequest::AnswerCall { call, value, error } => {
+            let result = match error {
+                Some(message) => Err(message.clone()),
+                None => Ok(value.as_ref().map_or(Value::Empty, feeds::json_value)),
+            };
+            // Cells waiting on their first answer needn't wait for the next tick
+            if grid.host_functions.answer(*call, result)? {
+                ticks.manual_tick_requested = true;
+            }
+            Ok((HostResponse::Done, None))
+        }
+    }
+}
+
+/// `range_at`, refusing ranges over `MAX_RANGE_CELLS`
+pub fn bounded_range_at(grid: &GridState, range: &str) -> Result<CellRange, String> {
+    let range = range_at(grid, range)?;
+    // Spans of ranges reaching both ends of the sheet don't fit in an i32,
+    // and their product not even in an i64
+    let width = range.max_col as i64 - range.min_col as i64 + 1;
+    let height = range.max_row as i64 - range.min_row as i64 + 1;
+    if width.saturating_mul(height) > MAX_RANGE_CELLS {
+        return Err(format!("Range is over {} cells", MAX_RANGE_CELLS));
+    }
+    Ok(range)
+}
+
+fn range_at(grid: &GridState, range: &str) -> Result<CellRange, String> {
+    go_to_target(grid, range).ok_or_else(|| format!("Not a cell or range: \"{}\"", range))
+}
+
+fn cell_at(grid: &GridState, name: &str) -> Result<(i32, i32), String> {
+    let range = range_at(grid, name)?;
+    if range.min_col != range.max_col || range.min_row != range.max_row {
+        return Err(format!("Not a single cell: \"{}\"", name));
+    }
+    Ok((range.min_col, range.min_row))
+}
+
+fn cell_info(grid: &GridState, col: i32, row: i32) -> CellInfo {
+    let name = coord_to_name(col, row);
+    let Some(cell) = grid.get_cell(col, row).filter(|cell| !cell.raw.is_empty()) else {
+        return CellInfo { name, raw: String::new(), value: serde_json::Value::Null, text: String::new(), formula: false, error: None };
+    };
+    let text = match cell.display(&grid.theme.resolve(cell.style)) {
+        CellDisplay::Text(text) => text,
+        CellDisplay::Checkbox(checked) => checked.to_string(),
+    };
+    CellInfo {
+        name,
+        raw: cell.raw.clone(),
+        value: json_value(&cell.value),
+        text,
+        formula: cell.is_formula,
+        error: cell.error.then(|| cell.error_code.text().to_string()),
+    }
+}
+
+fn json_value(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Int(i) => (*i).into(),
+        Value::Float(f) => serde_json::Number::from_f64(*f).map_or(serde_json::Value::Null, serde_json::Value::Number),
+        Value::Boolean(b) => (*b).into(),
+        Value::String(s) => s.clone().into(),
+        Value::Empty => serde_json::Value::Null,
+        Value::Tuple(items) => items.iter().map(json_value).collect::<Vec<_>>().into(),
+    }
+}
+
+/// A call's arguments as a list (evalexpr passes several as a tuple)
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+fn call_args(args: &Value) -> Vec<serde_json::Value> {
+    match args {
+        Value::Tuple(items) => items.iter().map(json_value).collect(),
+        Value::Empty => Vec::new(),
+        arg => vec![json_value(arg)],
+    }
+}
+
+/// The exported functions: each returns a Promise settled on the next frame
+/// `post_message` takes any `HostMessage` and always resolves, with its
+/// `HostReply`; the others are shortcuts for one request each, resolving
+/// with the result (a cell, rows of cells, a subscription id or null) or
+/// rejecting with the error message
+#[cfg(target_arch = "wasm32")]
+pub mod web {
+    use wasm_bindgen::prelude::*;
+
+    use super::{
+        call_args, Engine, BASE64, parse_message, Caller, HostReply, HostRequest, HostResponse, Listener, MetricsListener, OverlayListener,
+        Mirror, Reply, CALLERS, LISTENERS, METRICS_LISTENERS, MIRROR, MIRROR_CELLS, MIRROR_HEADER, OVERLAY_LISTENERS, PENDING,
+        PROTOCOL_VERSION,
+    };
+
+    fn to_js(value: &impl serde::Serialize) -> JsValue {
+        let json = serde_json::to_string(value).unwrap_or_default();
+        js_sys::JSON::parse(&json).unwrap_or(JsValue::NULL)
+    }
+
+    /// Queue an answer to a function call, with nothing to reply to
+    fn answer_call(call: u64, answer: Result<JsValue, JsValue>) {
+        let json = |value: &JsValue| js_sys::JSON::stringify(value).ok().map(String::from);
+        let request = match answer {
+            Ok(value) => {
+                let value = json(&value).and_then(|json| serde_json::from_str(&json).ok());
+                HostRequest::AnswerCall { call, value, error: None }
+            }
+            Err(error) => {
+                let message = error.as_string().or_else(|| json(&error)).unwrap_or_else(|| "failed".to_string());
+                HostRequest::AnswerCall { call, value: None, error: Some(message) }
+            }
+        };
+        PENDING.with(|pending| pending.borrow_mut().push((request, Box::new(|_| {}))));
+    }
+
+    /// Once `request` succeeds, send what it asked to hear about to `callback`:
+    /// a subscription's notices, or calls to a registered function (answered
+    /// with whatever `callback` returns or resolves to); as `HostReply`s if
+    /// `as_replies`, with the page answering calls itself
+    fn listen(request: &HostRequest, response: &HostResponse, callback: js_sys::Function, as_replies: bool) {
+        match (request, response) {
+            (HostRequest::Subscribe { .. }, HostResponse::Subscribed(id)) => {
+                let id = *id;
+                let listener: Listener = Box::new(move |notices| {
+                    let payload = if as_replies {
+                        to_js(&HostReply::Notices { version: PROTOCOL_VERSION, subscription: id, notices: notices.to_vec() })
+                    } else {
+                        to_js(&notices)
+                    };
+                    let _ = callback.call1(&JsValue::NULL, &payload);
+                });
+                LISTENERS.with(|listeners| listeners.borrow_mut().insert(id, listener));
+            }
+            (HostRequest::ShowOverlays { .. }, HostResponse::Subscribed(id)) => {
+                let id = *id;
+                let listener: OverlayListener = Box::new(move |cells| {
+                    let payload = if as_replies {
+                        to_js(&HostReply::Overlays { version: PROTOCOL_VERSION, subscription: id, cells: cells.to_vec() })
+                    } else {
+                        to_js(&cells)
+                    };
+                    let _ = callback.call1(&JsValue::NULL, &payload);
+                });
+                OVERLAY_LISTENERS.with(|listeners| listeners.borrow_mut().insert(id, listener));
+            }
+            (HostRequest::WatchMetrics { .. }, HostResponse::Subscribed(id)) => {
+                let id = *id;
+                let listener: MetricsListener = Box::new(move |metrics| {
+                    let payload = if as_replies {
+                        to_js(&HostReply::Metrics { version: PROTOCOL_VERSION, subscription: id, metrics: metrics.clone() })
+                    } else {
+                        to_js(metrics)
+                    };
+                    let _ = callback.call1(&JsValue::NULL, &payload);
+                });
+                METRICS_LISTENERS.with(|listeners| listeners.borrow_mut().insert(id, listener));
+            }
+            (HostRequest::RegisterFunction { name }, _) => {
+                let caller: Caller = Box::new(move |call| {
+                    if as_replies {
+                        let reply = HostReply::Call {
+                            version: PROTOCOL_VERSION,
+                            call: call.id,
+                            name: call.name.clone(),
+                            args: call_args(&call.args),
+                        };
+                        let _ = callback.call1(&JsValue::NULL, &to_js(&reply));
+                        return;
+                    }
+                    let args: js_sys::Array = call_args(&call.args).iter().map(to_js).collect();
+                    let id = call.id;
+                    match callback.apply(&JsValue::NULL, &args) {
+                        Ok(result) => {
+                            let result = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&result));
+                            wasm_bindgen_futures::spawn_local(async move { answer_call(id, result.await) });
+                        }
+                        Err(error) => answer_call(id, Err(error)),
+                    }
+                });
+                CALLERS.with(|callers| callers.borrow_mut().insert(name.clone(), caller));
+            }
+            _ => {}
+        }
+    }
+
+    /// Queue `request`, handing its outcome to `settle` (given the Promise's
+    /// resolve and reject) once it's served; see `listen` for `listener`
+    fn submit(
+        request: HostRequest,
+        listener: Option<(js_sys::Function, bool)>,
+        settle: impl Fn(Result<HostResponse, String>, &js_sys::Function, &js_sys::Function) + 'static,
+    ) -> js_sys::Promise {
+        let settle = std::rc::Rc::new(settle);
+        js_sys::Promise::new(&mut |resolve, reject| {
+            let (settle, listener, served) = (settle.clone(), listener.clone(), request.clone());
+            let reply: Reply = Box::new(move |result| {
+                if let (Ok(response), Some((callback, as_replies))) = (&result, listener) {
+                    listen(&served, response, callback, as_replies);
+                }
+                settle(result, &resolve, &reject);
+            });
+            PENDING.with(|pending| pending.borrow_mut().push((request.clone(), reply)));
+        })
+    }
+
+    /// Resolve with the result, or reject with the error message
+    fn call(request: HostRequest, listener: Option<js_sys::Function>) -> js_sys::Promise {
+        submit(request, listener.map(|f| (f, false)), |result, resolve, reject| {
+            let _ = match result {
+                Ok(response) => resolve.call1(&JsValue::NULL, &to_js(&response)),
+                Err(message) => reject.call1(&JsValue::NULL, &JsValue::from_str(&message)),
+            };
+        })
+    }
+
+    /// Send a `HostMessage` (an object, or its JSON text); resolves with its
+    /// `HostReply`
+    /// A `subscribe` message's notices and a `registerFunction` message's
+    /// calls go to `listener`, as `notices` and `call` replies
+    #[wasm_bindgen(unchecked_return_type = "Promise<HostReply>")]
+    pub fn post_message(
+        #[wasm_bindgen(unchecked_param_type = "HostMessage | string")] message: JsValue,
+        #[wasm_bindgen(unchecked_param_type = "(reply: HostReply) => void")] listener: Option<js_sys::Function>,
+    ) -> js_sys::Promise {
+        let text = match message.as_string() {
+            Some(text) => text,
+            None => js_sys::JSON::stringify(&message).map(String::from).unwrap_or_default(),
+        };
+        match parse_message(&text) {
+            Ok(message) => {
+                let id = message.id;
+                submit(message.request, listener.map(|f| (f, true)), move |result, resolve, _| {
+                    let _ = resolve.call1(&JsValue::NULL, &to_js(&HostReply::new(id, result)));
+                })
+            }
+            Err((id, message)) => js_sys::Promise::resolve(&to_js(&HostReply::new(id, Err(message)))),
+        }
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<CellInfo>")]
+    pub fn get_cell(name: String) -> js_sys::Promise {
+        call(HostRequest::GetCell { name }, None)
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn set_cell(name: String, raw: String) -> js_sys::Promise {
+        call(HostRequest::SetCell { name, raw }, None)
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<CellInfo[][]>")]
+    pub fn get_range(range: String) -> js_sys::Promise {
+        call(HostRequest::GetRange { range }, None)
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn clear_range(range: String) -> js_sys::Promise {
+        call(HostRequest::ClearRange { range }, None)
+    }
+
+    /// Call `callback` with an array of `{cell, old, new, tick, source}` after
+    /// each frame that changes values in `range` (anywhere if it's omitted)
+    #[wasm_bindgen(unchecked_return_type = "Promise<number>")]
+    pub fn subscribe(
+        range: Option<String>,
+        #[wasm_bindgen(unchecked_param_type = "(notices: CellNotice[]) => void")] callback: js_sys::Function,
+    ) -> js_sys::Promise {
+        call(HostRequest::Subscribe { range }, Some(callback))
+    }
+
+    /// Draw the cells in `range` on the page instead: `callback` gets every
+    /// one on screen (`{name, raw, value, text, x, y, width, height, ...}`)
+    /// whenever any of them moves or changes, to position elements over the
+    /// canvas with; `unsubscribe` hands them back to the sheet
+    #[wasm_bindgen(unchecked_return_type = "Promise<number>")]
+    pub fn show_overlays(
+        range: String,
+        #[wasm_bindgen(unchecked_param_type = "(cells: OverlayCell[]) => void")] callback: js_sys::Function,
+    ) -> js_sys::Promise {
+        call(HostRequest::ShowOverlays { range }, Some(callback))
+    }
+
+    /// Call `callback` with the sheet's performance numbers (frame and tick
+    /// times, cell counts, SVG queue, memory) every `interval_ms`, a second if
+    /// omitted
+    #[wasm_bindgen(unchecked_return_type = "Promise<number>")]
+    pub fn watch_metrics(
+        interval_ms: Option<u32>,
+        #[wasm_bindgen(unchecked_param_type = "(metrics: SheetStats) => void")] callback: js_sys::Function,
+    ) -> js_sys::Promise {
+        call(HostRequest::WatchMetrics { interval_ms }, Some(callback))
+    }
+
+    /// The workbook in the binary format, to store wherever the page likes
+    #[wasm_bindgen(unchecked_return_type = "Promise<ArrayBuffer>")]
+    pub fn export_workbook() -> js_sys::Promise {
+        submit(HostRequest::ExportWorkbook, None, |result, resolve, reject| {
+            let bytes = result.and_then(|response| match response {
+                HostResponse::Workbook(data) => BASE64.decode(data).map_err(|e| e.to_string()),
+                _ => Err("No workbook".to_string()),
+            });
+            let _ = match bytes {
+                Ok(bytes) => resolve.call1(&JsValue::NULL, &js_sys::Uint8Array::from(bytes.as_slice()).buffer()),
+                Err(message) => reject.call1(&JsValue::NULL, &JsValue::from_str(&message)),
+            };
+        })
+    }
+
+    /// A PNG Blob of `range` as Export image draws it, or of the whole cells
+    /// on screen if omitted
+    #[wasm_bindgen(unchecked_return_type = "Promise<Blob>")]
+    pub fn screenshot(range: Option<String>) -> js_sys::Promise {
+        submit(HostRequest::Screenshot { range }, None, |result, resolve, reject| {
+            let blob = result
+                .and_then(|response| match response {
+                    HostResponse::Png(data) => BASE64.decode(data).map_err(|e| e.to_string()),
+                    _ => Err("No image".to_string()),
+                })
+                .and_then(|png| {
+                    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(png.as_slice()));
+                    let options = web_sys::BlobPropertyBag::new();
+                    options.set_type("image/png");
+                    web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+                        .map_err(|_| "Couldn't make the image".to_string())
+                });
+            let _ = match blob {
+                Ok(blob) => resolve.call1(&JsValue::NULL, &blob),
+                Err(message) => reject.call1(&JsValue::NULL, &JsValue::from_str(&message)),
+            };
+        })
+    }
+
+    /// Mirror the visible cells' values into a SharedArrayBuffer, rewritten
+    /// whenever they or the view change, for pages that read them every
+    /// frame: a `Float64Array` over it holds a generation counter, the
+    /// top-left cell's column and row, the columns and rows shown, then the
+    /// values row by row (NaN where there's no number)
+    /// Needs a cross-origin isolated page
+    #[wasm_bindgen]
+    pub fn mirror_values() -> Result<js_sys::SharedArrayBuffer, JsValue> {
+        let available = js_sys::Reflect::has(&js_sys::global(), &JsValue::from_str("SharedArrayBuffer")).unwrap_or(false);
+        if !available {
+            return Err(JsValue::from_str("SharedArrayBuffer needs a cross-origin isolated page"));
+        }
+        let buffer = js_sys::SharedArrayBuffer::new(((MIRROR_HEADER + MIRROR_CELLS) * 8) as u32);
+        let view = js_sys::Float64Array::new(&buffer);
+        let mirror: Mirror = Box::new(move |frame| view.subarray(0, frame.len() as u32).copy_from(frame));
+        MIRROR.with(|slot| *slot.borrow_mut() = Some(mirror));
+        Ok(buffer)
+    }
+
+    /// Stop writing to the buffer from `mirror_values`
+    #[wasm_bindgen]
+    pub fn stop_mirroring() {
+        MIRROR.with(|slot| *slot.borrow_mut() = None);
+    }
+
+    /// Replace the open workbook with one from `export_workbook`
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn import_workbook(buffer: js_sys::ArrayBuffer) -> js_sys::Promise {
+        let data = BASE64.encode(js_sys::Uint8Array::new(&buffer).to_vec());
+        call(HostRequest::ImportWorkbook { data }, None)
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn unsubscribe(subscription: u32) -> js_sys::Promise {
+        call(HostRequest::Unsubscribe { subscription }, None)
+    }
+
+    /// Evaluate one tick on the next frame
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn tick() -> js_sys::Promise {
+        call(HostRequest::Tick, None)
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn set_auto_tick(enabled: bool, interval_ms: Option<u32>) -> js_sys::Promise {
+        call(HostRequest::SetAutoTick { enabled, interval_ms }, None)
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<number>")]
+    pub fn get_tick_count() -> js_sys::Promise {
+        call(HostRequest::GetTickCount, None)
+    }
+
+    /// Let formulas call `name(...)`, computed by `callback` from the call's
+    /// arguments (it may return a Promise; a throw or rejection is the cell's
+    /// error)
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn register_function(
+        name: String,
+        #[wasm_bindgen(unchecked_param_type = "(...args: unknown[]) => unknown")] callback: js_sys::Function,
+    ) -> js_sys::Promise {
+        call(HostRequest::RegisterFunction { name }, Some(callback))
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "Promise<null>")]
+    pub fn unregister_function(name: String) -> js_sys::Promise {
+        call(HostRequest::UnregisterFunction { name }, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::undo::UndoStack;
+
+    #[test]
+    fn test_answer_requests() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1.5", "x"], ["true", ""]]);
+        let mut stack = UndoStack::default();
+        let (mut subscriptions, mut ticks, mut timer) = (Subscriptions::default(), TickControl::default(), Timer::default());
+        let mut serve = |grid: &mut GridState, request: HostRequest| {
+            let (response, edit) = answer(grid, &mut subscriptions, &mut ticks, &mut timer, &request)?;
+            if let Some(group) = edit {
+                stack.commit(grid, group);
+            }
+            Ok::<_, String>(response)
+        };
+
+        let Ok(HostResponse::Cell(cell)) = serve(&mut grid, HostRequest::GetCell { name: "a0".into() }) else { panic!("get") };
+        assert_eq!((cell.name.as_str(), cell.value.clone()), ("A0", serde_json::json!(1.5)));
+
+        serve(&mut grid, HostRequest::SetCell { name: "B1".into(), raw: "7".into() }).unwrap();
+        serve(&mut grid, HostRequest::ClearRange { range: "A0:A1".into() }).unwrap();
+        let Ok(HostResponse::Range(rows)) = serve(&mut grid, HostRequest::GetRange { range: "A0:B1".into() }) else { panic!("range") };
+        let values: Vec<Vec<_>> = rows.iter().map(|row| row.iter().map(|c| c.value.clone()).collect()).collect();
+        assert_eq!(values, vec![vec![serde_json::Value::Null, "x".into()], vec![serde_json::Value::Null, 7.into()]]);
+
+        assert!(serve(&mut grid, HostRequest::SetCell { name: "A0:B1".into(), raw: "1".into() }).is_err());
+        assert!(serve(&mut grid, HostRequest::GetCell { name: "nowhere".into() }).is_err());
+
+        // Ranges spanning most of the sheet are refused rather than overflowing
+        assert!(serve(&mut grid, HostRequest::GetRange { range: "A_2000000000:A2000000000".into() }).is_err());
+        assert!(serve(&mut grid, HostRequest::SetCell { name: "_ZZZZZZ_2000000000:ZZZZZZ2000000000".into(), raw: "1".into() }).is_err());
+
+        // Clearing costs the occupied cells, not the range's area
+        serve(&mut grid, HostRequest::ClearRange { range: "A0:ZZZZ99999999".into() }).unwrap();
+        assert!(grid.cells.is_empty());
+    }
+
+    #[test]
+    fn test_subscriptions_filter_by_range() {
+        let grid = GridState::new();
+        let (mut subscriptions, mut ticks, mut timer) = (Subscriptions::default(), TickControl::default(), Timer::default());
+        let mut subscribe = |range: Option<&str>| {
+            let request = HostRequest::Subscribe { range: range.map(str::to_string) };
+            match answer(&grid, &mut subscriptions, &mut ticks, &mut timer, &request) {
+                Ok((HostResponse::Subscribed(id), _)) => id,
+                other => panic!("{:?}", other.map(|(response, _)| response)),
+            }
+        };
+        let (all, column_b) = (subscribe(None), subscribe(Some("B0:B9")));
+
+        let change = |col, row| CellChanged { col, row, old: Value::Int(0), new: Value::Int(1), source: ChangeSource::Tick };
+        let notices = subscriptions.notices(&[change(0, 0), change(1, 3)], 12);
+        assert_eq!(notices.iter().map(|(id, n)| (*id, n.len())).collect::<Vec<_>>(), vec![(all, 2), (column_b, 1)]);
+        assert_eq!(notices[1].1[0], CellNotice { cell: "B3".into(), old: 0.into(), new: 1.into(), tick: 12, source: "tick" });
+    }
+
+    #[test]
+    fn test_overlays_cover_their_range() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["7", "8"]]);
+        let (mut subscriptions, mut ticks, mut timer) = (Subscriptions::default(), TickControl::default(), Timer::default());
+        let request = HostRequest::ShowOverlays { range: "B0:B4".into() };
+        let Ok((HostResponse::Subscribed(id), _)) = answer(&grid, &mut subscriptions, &mut ticks, &mut timer, &request) else {
+            panic!("not subscribed")
+        };
+        assert!(subscriptions.is_overlaid(1, 2) && !subscriptions.is_overlaid(0, 0));
+
+        let rect = |x| Rect::new(x, 20.0, x + 80.0, 50.0);
+        let overlays = subscriptions.overlay_cells(&grid, &[((0, 0), rect(0.0)), ((1, 0), rect(80.0))]);
+        assert_eq!(overlays.len(), 1);
+        let (overlay, cells) = &overlays[0];
+        assert_eq!((*overlay, cells.len()), (id, 1));
+        assert_eq!((cells[0].cell.text.as_str(), cells[0].x, cells[0].width), ("8", 80.0, 80.0));
+
+        answer(&grid, &mut subscriptions, &mut ticks, &mut timer, &HostRequest::Unsubscribe { subscription: id }).unwrap();
+        assert!(!subscriptions.is_overlaid(1, 2));
+    }
+
+    #[test]
+    fn test_metrics_come_on_their_interval() {
+        let grid = GridState::new();
+        let (mut subscriptions, mut ticks, mut timer) = (Subscriptions::default(), TickControl::default(), Timer::default());
+        let mut watch = |interval_ms| answer(&grid, &mut subscriptions, &mut ticks, &mut timer, &HostRequest::WatchMetrics { interval_ms });
+        let Ok((HostResponse::Subscribed(fast), _)) = watch(Some(250)) else { panic!("not subscribed") };
+        assert!(watch(Some(0)).is_err());
+        let Ok((HostResponse::Subscribed(slow), _)) = watch(None) else { panic!("not subscribed") };
+
+        assert!(subscriptions.due_metrics(Duration::from_millis(200)).is_empty());
+        assert_eq!(subscriptions.due_metrics(Duration::from_millis(100)), vec![fast]);
+        assert_eq!(subscriptions.due_metrics(Duration::from_millis(700)), vec![fast, slow]);
+    }
+
+    #[test]
+    fn test_workbook_export_and_import() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["7"]]);
+        let (mut subscriptions, mut ticks, mut timer) = (Subscriptions::default(), TickControl::default(), Timer::default());
+        let Ok((HostResponse::Workbook(data), _)) = answer(&grid, &mut subscriptions, &mut ticks, &mut timer, &HostRequest::ExportWorkbook)
+        else {
+            panic!("no workbook")
+        };
+
+        let fresh = GridState::new();
+        let mut send = |data: String| answer(&fresh, &mut subscriptions, &mut ticks, &mut timer, &HostRequest::ImportWorkbook { data });
+        assert!(send("not base64!".into()).is_err());
+        assert!(send(BASE64.encode(b"not a workbook")).is_err());
+        assert!(take_imported().is_none());
+        send(data).unwrap();
+        let (imported, _) = take_imported().unwrap();
+        assert_eq!(imported.get_cell(0, 0).unwrap().raw, "7");
+    }
+
+    #[test]
+    fn test_mirror_frame() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["9", "9"], ["1.5", "true"], ["4", "yes"]]);
+        grid.layout.rows.hide(0);
+
+        let frame = mirror_frame(&grid, 0, 0, 2, 2, 7);
+        // Row 0 is hidden, so the frame starts at A1
+        assert_eq!(frame[..MIRROR_HEADER], [7.0, 0.0, 1.0, 2.0, 2.0]);
+        assert_eq!(frame[MIRROR_HEADER..MIRROR_HEADER + 3], [1.5, 1.0, 4.0]);
+        assert!(frame[MIRROR_HEADER + 3].is_nan());
+        assert_eq!(mirror_frame(&grid, 0, 0, 100, 1000, 0)[4], (MIRROR_CELLS / 100) as f64);
+    }
+
+    #[test]
+    fn test_tick_requests() {
+        let grid = GridState::new();
+        let (mut subscriptions, mut ticks, mut timer) = (Subscriptions::default(), TickControl::default(), Timer::default());
+        let mut send = |request| answer(&grid, &mut subscriptions, &mut ticks, &mut timer, &request).map(|(response, _)| response);
+
+        send(HostRequest::Tick).unwrap();
+        send(HostRequest::SetAutoTick { enabled: true, interval_ms: Some(250) }).unwrap();
+        assert!(send(HostRequest::SetAutoTick { enabled: false, interval_ms: Some(0) }).is_err());
+        assert_eq!(send(HostRequest::GetTickCount), Ok(HostResponse::TickCount(0)));
+        assert!(ticks.manual_tick_requested && ticks.auto_tick_enabled);
+        assert_eq!(timer.duration(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_function_calls_round_trip() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["= PRICE(\"AAPL\", 2)"]]);
+        let (mut subscriptions, mut ticks, mut timer) = (Subscriptions::default(), TickControl::default(), Timer::default());
+        let mut send = |grid: &GridState, request| answer(grid, &mut subscriptions, &mut ticks, &mut timer, &request).map(|_| ());
+
+        send(&grid, HostRequest::RegisterFunction { name: "PRICE".into() }).unwrap();
+        grid.run_ticks(1);
+        let calls = grid.host_functions.take_calls();
+        assert_eq!(call_args(&calls[0].args), vec![serde_json::json!("AAPL"), serde_json::json!(2)]);
+
+        send(&grid, HostRequest::AnswerCall { call: calls[0].id, value: Some(serde_json::json!(190.5)), error: None }).unwrap();
+        grid.run_ticks(1);
+        assert_eq!(grid.get_cell(0, 0).unwrap().value, Value::Float(190.5));
+        assert!(ticks.manual_tick_requested);
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        let message = parse_message(r#"{"version": 1, "id": 7, "type": "setCell", "name": "B3", "raw": "=A0*2"}"#).unwrap();
+        assert_eq!(message.id, Some(7));
+        assert_eq!(message.request, HostRequest::SetCell { name: "B3".into(), raw: "=A0*2".into() });
+        let message = parse_message(r#"{"version": 1, "type": "subscribe"}"#).unwrap();
+        assert_eq!(message.request, HostRequest::Subscribe { range: None });
+
+        // Each failure still makes a reply, with the id when there is one
+        let error = |text: &str| parse_message(text).unwrap_err();
+        assert_eq!(error("{").0, None);
+        assert!(error(r#"{"version": 2, "id": 3, "type": "getCell", "name": "A0"}"#).1.contains("version 2"));
+        let (id, message) = error(r#"{"version": 1, "id": 4, "type": "launch"}"#);
+        assert!(id == Some(4) && message.contains("launch"));
+
+        let message = parse_message(r#"{"version": 1, "type": "setAutoTick", "enabled": true, "intervalMs": 50}"#).unwrap();
+        assert_eq!(message.request, HostRequest::SetAutoTick { enabled: true, interval_ms: Some(50) });
+
+        let reply = serde_json::to_value(HostReply::new(Some(4), Ok(HostResponse::Subscribed(2)))).unwrap();
+        assert_eq!(reply, serde_json::json!({"type": "ok", "version": 1, "id": 4, "result": 2}));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use evalexpr::{ContextWithMutableFunctions, EvalexprError, Function, HashMapContext, Value};
+
+use crate::formula::name_to_coord;
+
+/// A function name and its arguments written out: one distinct call
+type CallKey = (String, String);
+
+/// A call for the page to compute, sent in the resolution phase after a tick
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostCall {
+    pub id: u64,
+    pub name: String,
+    /// The call's arguments, as evalexpr passes them (a tuple for several)
+    pub args: Value,
+}
+
+#[derive(Default)]
+struct Calls {
+    names: BTreeSet<String>,
+    /// The page's latest answer to each call
+    answers: HashMap<CallKey, Result<Value, String>>,
+    /// Calls formulas made since the last `take_calls`
+    made: HashMap<CallKey, Value>,
+    /// Calls sent to the page and not answered yet, by id
+    in_flight: HashMap<u64, CallKey>,
+    next_id: u64,
+}
+
+/// Formula functions the embedding page registers (`FETCHPRICE("AAPL")`):
+/// formulas call them like built-ins, but the page computes the results
+/// A tick reads each call's latest answer; the calls it made then go to the
+/// page (`take_calls`), and their answers are used from the next tick on
+/// Until a call's first answer arrives its cell shows `#N/A`
+/// Clones share one registry, so it survives grid snapshots
+#[derive(Clone, Default)]
+pub struct HostFunctions(Arc<Mutex<Calls>>);
+
+impl HostFunctions {
+    pub fn register(&self, name: &str) -> Result<(), String> {
+        let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        // Names like `AB12` would read as cell references
+        if !valid || name_to_coord(name).is_some() {
+            return Err(format!("\"{}\" can't be a function name", name));
+        }
+        self.0.lock().unwrap().names.insert(name.to_string());
+        Ok(())
+    }
+
+    pub fn unregister(&self, name: &str) {
+        let mut calls = self.0.lock().unwrap();
+        calls.names.remove(name);
+        calls.answers.retain(|(called, _), _| called != name);
+    }
+
+    /// Make the registered functions callable from formulas evaluated in `context`
+    pub fn add_to(&self, context: &mut HashMapContext) {
+        for name in self.0.lock().unwrap().names.iter().cloned() {
+            let calls = self.0.clone();
+            let function = Function::new(move |args: &Value| {
+                let key = (name.clone(), args.to_string());
+                let mut calls = calls.lock().unwrap();
+                calls.made.entry(key.clone()).or_insert_with(|| args.clone());
+                match calls.answers.get(&key) {
+                    Some(Ok(value)) => Ok(value.clone()),
+                    Some(Err(message)) => Err(EvalexprError::CustomMessage(message.clone())),
+                    None => Err(EvalexprError::CustomMessage(format!("waiting for {}", name))),
+                }
+            });
+            let _ = context.set_function(name.clone(), function);
+        }
+    }
+
+    /// The resolution phase: the calls made since last time that aren't
+    /// already waiting on the page, each once
+    pub fn take_calls(&self) -> Vec<HostCall> {
+        let mut calls = self.0.lock().unwrap();
+        let waiting: HashSet<CallKey> = calls.in_flight.values().cloned().collect();
+        let made: Vec<(CallKey, Value)> = calls.made.drain().filter(|(key, _)| !waiting.contains(key)).collect();
+        made.into_iter()
+            .map(|(key, args)| {
+                calls.next_id += 1;
+                let id = calls.next_id;
+                let call = HostCall { id, name: key.0.clone(), args };
+                calls.in_flight.insert(id, key);
+                call
+            })
+            .collect()
+    }
+
+    /// Store the page's answer to call `id`; true if it's the call's first, so
+    /// cells waiting on it can be ticked again
+    pub fn answer(&self, id: u64, result: Result<Value, String>) -> Result<bool, String> {
+        let mut calls = self.0.lock().unwrap();
+        let key = calls.in_flight.remove(&id).ok_or_else(|| format!("No call {} is waiting", id))?;
+        Ok(calls.answers.insert(key, result).is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formula::evaluate_formula;
+
+    #[test]
+    fn test_calls_resolve_on_later_ticks() {
+        let functions = HostFunctions::default();
+        functions.register("FETCHPRICE").unwrap();
+        assert!(functions.register("AB12").is_err());
+        let mut context = HashMapContext::new();
+        functions.add_to(&mut context);
+
+        // Waits for the page until its first answer
+        assert!(evaluate_formula("FETCHPRICE(\"AAPL\") * 2", &context).is_err());
+        let calls = functions.take_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].args, Value::String("AAPL".into()));
+        assert!(functions.take_calls().is_empty());
+
+        assert_eq!(functions.answer(calls[0].id, Ok(Value::Int(5))), Ok(true));
+        assert_eq!(evaluate_formula("FETCHPRICE(\"AAPL\") * 2", &context), Ok(Value::Int(10)));
+
+        // Each tick's calls are asked again, the last answer standing meanwhile
+        let again = functions.take_calls();
+        assert_eq!(again.len(), 1);
+        assert_eq!(functions.answer(again[0].id, Ok(Value::Int(6))), Ok(false));
+        assert!(functions.answer(again[0].id, Ok(Value::Int(7))).is_err());
+    }
+}
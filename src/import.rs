@@ -0,0 +1,65 @@
+use crate::clipboard::parse_delimited;
+use crate::evaluator::TickControl;
+use crate::grid_state::GridState;
+use crate::{persist, xlsx};
+
+/// What a file brings into the app
+pub enum Imported {
+    /// A whole workbook (gregsheet JSON, .xlsx) that replaces the open one
+    Workbook(GridState, TickControl),
+    /// Rows of raw cell text (.csv, .tsv) written into the open sheet at A0
+    Rows(Vec<Vec<String>>),
+}
+
+/// Read a dropped file, picking the format from its extension
+pub fn import_file(name: &str, bytes: &[u8]) -> Result<Imported, String> {
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).unwrap_or_default();
+    let text = || String::from_utf8(bytes.to_vec()).map_err(|_| format!("{} isn't UTF-8 text", name));
+    match extension.as_str() {
+        "csv" => Ok(Imported::Rows(parse_delimited(&text()?, ','))),
+        "tsv" | "txt" => Ok(Imported::Rows(parse_delimited(&text()?, '\t'))),
+        "json" => persist::load_json(&text()?).map(|(grid, ticks)| Imported::Workbook(grid, ticks)),
+        "xlsx" => xlsx::import_xlsx(bytes).map(|grid| Imported::Workbook(grid, TickControl::default())),
+        _ => Err(format!("can't import {} (expected .csv, .tsv, .json or .xlsx)", name)),
+    }
+}
+
+/// Browsers don't expose dropped file paths: the page reads dropped files and
+/// hands them over through this export instead
+#[cfg(target_arch = "wasm32")]
+pub mod web {
+    use std::sync::Mutex;
+    use wasm_bindgen::prelude::*;
+
+    static DROPPED: Mutex<Vec<(String, Vec<u8>)>> = Mutex::new(Vec::new());
+
+    /// Import a file dropped on the page (on the next frame)
+    #[wasm_bindgen]
+    pub fn drop_file(name: String, bytes: Vec<u8>) {
+        DROPPED.lock().unwrap().push((name, bytes));
+    }
+
+    pub fn take_dropped() -> Vec<(String, Vec<u8>)> {
+        std::mem::take(&mut *DROPPED.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_by_extension() {
+        let Ok(Imported::Rows(rows)) = import_file("data.CSV", b"a,\"b,c\"\n1,2\n") else { panic!("csv") };
+        assert_eq!(rows, vec![vec!["a".to_string(), "b,c".to_string()], vec!["1".to_string(), "2".to_string()]]);
+
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["7"]]);
+        let saved = persist::save_json(&grid, &TickControl::default()).unwrap();
+        let Ok(Imported::Workbook(loaded, _)) = import_file("sheet.json", saved.as_bytes()) else { panic!("json") };
+        assert_eq!(loaded.get_cell(0, 0).unwrap().raw, "7");
+
+        assert!(import_file("notes.pdf", b"").is_err());
+        assert!(import_file("bad.csv", &[0xff, 0xfe]).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::cell::CellContent;
+use crate::evaluator::TickControl;
+use crate::events::CellChanged;
+use crate::grid_state::GridState;
+use crate::undo::apply_content;
+
+/// A cell's contents after a committed edit (None: cleared)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub col: i32,
+    pub row: i32,
+    pub content: Option<CellContent>,
+}
+
+/// Crash recovery: committed cell edits are appended to a journal that starts
+/// over at every full snapshot of the workbook, so the last snapshot with the
+/// journal replayed over it is the workbook as of the last edit
+/// Only cell contents are journaled; layout, header and theme changes wait for
+/// the next snapshot. Reading back is asynchronous in browsers, so replays
+/// arrive through `take_replay`
+/// With the `storage-sqlite` feature, native builds keep the workbook in SQLite
+/// instead (see `sqlite_store`): edits are upserted as they're journaled and
+/// snapshots only rewrite the sheet's settings
+#[derive(Resource, Default)]
+pub struct Journal {
+    replay: Arc<Mutex<Option<Vec<JournalEntry>>>>,
+}
+
+impl Journal {
+    /// Append one frame's edits as a single batch
+    pub fn append(&self, entries: &[JournalEntry]) {
+        if !entries.is_empty() {
+            backend::append(entries);
+        }
+    }
+
+    /// Save the workbook's current state and start the journal over
+    pub fn checkpoint(&self, grid: &GridState, ticks: &TickControl) {
+        backend::checkpoint(grid, ticks);
+    }
+
+    /// Save a workbook that replaced the open one (a load, or a fresh start)
+    pub fn replace(&self, grid: &GridState, ticks: &TickControl) {
+        backend::replace(grid, ticks);
+    }
+
+    /// Read the journal back for replaying over the restored snapshot
+    pub fn read(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            *self.replay.lock().unwrap() = Some(decode(&backend::read()));
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let replay = self.replay.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                *replay.lock().unwrap() = Some(decode(&backend::read().await));
+            });
+        }
+    }
+
+    /// The journal read back by `read`, once it has arrived
+    pub fn take_replay(&self) -> Option<Vec<JournalEntry>> {
+        self.replay.lock().unwrap().take()
+    }
+}
+
+/// A batch of entries as one line of JSON
+#[cfg(any(target_arch = "wasm32", not(feature = "storage-sqlite")))]
+fn encode(entries: &[JournalEntry]) -> Option<String> {
+    serde_json::to_string(entries).inspect_err(|e| warn!("Journal: {}", e)).ok()
+}
+
+/// A full snapshot of the workbook
+#[cfg(any(target_arch = "wasm32", not(feature = "storage-sqlite")))]
+fn snapshot(grid: &GridState, ticks: &TickControl) -> Option<Vec<u8>> {
+    crate::persist::save_binary(grid, ticks).inspect_err(|e| warn!("Snapshot failed: {}", e)).ok()
+}
+
+/// Entries from journal batches, in order
+/// A batch cut short by a crash mid-write doesn't parse and is skipped
+fn decode(batches: &[String]) -> Vec<JournalEntry> {
+    batches
+        .iter()
+        .filter_map(|batch| serde_json::from_str::<Vec<JournalEntry>>(batch).ok())
+        .flatten()
+        .collect()
+}
+
+/// Write journaled contents back into the grid
+pub fn replay(grid: &mut GridState, entries: &[JournalEntry]) -> Vec<CellChanged> {
+    entries
+        .iter()
+        .filter_map(|e| apply_content(grid, e.col, e.row, &e.content))
+        .collect()
+}
+
+/// Base name of the workbook's files natively, next to the app
+#[cfg(not(target_arch = "wasm32"))]
+const DEFAULT_WORKBOOK: &str = "gregsheet";
+
+#[cfg(not(target_arch = "wasm32"))]
+static WORKBOOK: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+/// Keep the workbook in `<base>.snapshot` and `<base>.journal` (or
+/// `<base>.sqlite`) instead of the default `gregsheet` files, e.g. so the sync
+/// server doesn't share them with a window on the same machine
+/// Only takes effect before the workbook is first read or written
+#[cfg(not(target_arch = "wasm32"))]
+pub fn use_workbook(base: &str) {
+    if WORKBOOK.set(base.to_string()).is_err() {
+        warn!("Workbook already chosen, staying with it");
+    }
+}
+
+/// Path of one of the workbook's files
+#[cfg(not(target_arch = "wasm32"))]
+fn workbook_path(extension: &str) -> String {
+    format!("{}.{}", WORKBOOK.get().map_or(DEFAULT_WORKBOOK, String::as_str), extension)
+}
+
+/// The last saved workbook, natively (browsers use `persist::web::restore_session`)
+#[cfg(not(target_arch = "wasm32"))]
+pub fn restore_snapshot() -> Option<(GridState, TickControl)> {
+    backend::restore()
+}
+
+/// Snapshot and journal as files next to the app, one JSON batch per line
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "storage-sqlite")))]
+mod backend {
+    use super::{encode, workbook_path, JournalEntry};
+    use crate::evaluator::TickControl;
+    use crate::grid_state::GridState;
+    use crate::persist;
+    use bevy::prelude::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    fn snapshot_path() -> String {
+        workbook_path("snapshot")
+    }
+
+    fn journal_path() -> String {
+        workbook_path("journal")
+    }
+
+    pub fn restore() -> Option<(GridState, TickControl)> {
+        let bytes = fs::read(snapshot_path()).ok()?;
+        persist::load_binary(&bytes)
+            .inspect_err(|e| warn!("Snapshot unreadable: {}", e))
+            .ok()
+    }
+
+    pub fn checkpoint(grid: &GridState, ticks: &TickControl) {
+        let Some(bytes) = super::snapshot(grid, ticks) else { return };
+        // Write aside and rename, so a crash mid-write keeps the old snapshot
+        let temp = format!("{}.tmp", snapshot_path());
+        match fs::write(&temp, bytes).and_then(|_| fs::rename(&temp, snapshot_path())) {
+            Ok(()) => {
+                let _ = fs::remove_file(journal_path());
+            }
+            Err(e) => warn!("Snapshot failed: {}", e),
+        }
+    }
+
+    pub fn replace(grid: &GridState, ticks: &TickControl) {
+        checkpoint(grid, ticks);
+    }
+
+    pub fn append(entries: &[JournalEntry]) {
+        let Some(batch) = encode(entries) else { return };
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(journal_path())
+            .and_then(|mut file| {
+                writeln!(file, "{}", batch)?;
+                file.sync_data()
+            });
+        if let Err(e) = written {
+            warn!("Journal: {}", e);
+        }
+    }
+
+    pub fn read() -> Vec<String> {
+        fs::read_to_string(journal_path())
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The workbook in SQLite, one row per cell: there's no separate journal, so
+/// nothing is left to replay
+#[cfg(all(not(target_arch = "wasm32"), feature = "storage-sqlite"))]
+mod backend {
+    use super::{workbook_path, JournalEntry};
+    use crate::evaluator::TickControl;
+    use crate::grid_state::GridState;
+    use crate::sqlite_store::SqliteStore;
+    use bevy::prelude::*;
+    use std::sync::Mutex;
+
+    /// Opened on first use
+    static STORE: Mutex<Option<SqliteStore>> = Mutex::new(None);
+
+    fn with_store<T>(f: impl FnOnce(&mut SqliteStore) -> Result<T, String>) -> Option<T> {
+        let mut store = STORE.lock().unwrap();
+        if store.is_none() {
+            *store = SqliteStore::open(&workbook_path("sqlite")).inspect_err(|e| warn!("SQLite store: {}", e)).ok();
+        }
+        f(store.as_mut()?).inspect_err(|e| warn!("SQLite store: {}", e)).ok()
+    }
+
+    pub fn restore() -> Option<(GridState, TickControl)> {
+        with_store(|store| store.load()).flatten()
+    }
+
+    /// Cells are already stored as they're edited; only the settings change
+    pub fn checkpoint(grid: &GridState, ticks: &TickControl) {
+        with_store(|store| store.write_settings(grid, ticks));
+    }
+
+    pub fn replace(grid: &GridState, ticks: &TickControl) {
+        with_store(|store| store.write_all(grid, ticks));
+    }
+
+    pub fn append(entries: &[JournalEntry]) {
+        with_store(|store| store.write_cells(entries));
+    }
+
+    pub fn read() -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Snapshot in localStorage (the autosave), journal in IndexedDB
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::{encode, JournalEntry};
+    use crate::documents::idb::{finish, js_error, object_store, JOURNAL};
+    use crate::evaluator::TickControl;
+    use crate::grid_state::GridState;
+    use bevy::prelude::*;
+    use wasm_bindgen::prelude::*;
+    use web_sys::IdbTransactionMode;
+
+    pub fn checkpoint(grid: &GridState, ticks: &TickControl) {
+        if let Some(bytes) = super::snapshot(grid, ticks) {
+            crate::persist::web::autosave(&bytes);
+            clear();
+        }
+    }
+
+    pub fn replace(grid: &GridState, ticks: &TickControl) {
+        checkpoint(grid, ticks);
+    }
+
+    pub fn append(entries: &[JournalEntry]) {
+        let Some(batch) = encode(entries) else { return };
+        wasm_bindgen_futures::spawn_local(async move {
+            let written = async {
+                let store = object_store(JOURNAL, IdbTransactionMode::Readwrite).await?;
+                finish(&store.add(&JsValue::from_str(&batch)).map_err(js_error)?).await
+            };
+            if let Err(e) = written.await {
+                warn!("Journal: {}", e);
+            }
+        });
+    }
+
+    fn clear() {
+        wasm_bindgen_futures::spawn_local(async {
+            let cleared = async {
+                let store = object_store(JOURNAL, IdbTransactionMode::Readwrite).await?;
+                finish(&store.clear().map_err(js_error)?).await
+            };
+            if let Err(e) = cleared.await {
+                warn!("Journal: {}", e);
+            }
+        });
+    }
+
+    pub async fn read() -> Vec<String> {
+        let batches = async {
+            let store = object_store(JOURNAL, IdbTransactionMode::Readonly).await?;
+            finish(&store.get_all().map_err(js_error)?).await
+        };
+        match batches.await {
+            Ok(batches) => js_sys::Array::from(&batches).iter().filter_map(|b| b.as_string()).collect(),
+            Err(e) => {
+                warn!("Journal: {}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::undo::{EditGroup, UndoStack};
+
+    #[test]
+    fn test_replay_recovers_edits() {
+        let sheet = || {
+            let mut grid = GridState::new();
+            grid.set_range((0, 0), [["1", "2"]]);
+            grid
+        };
+        let (mut snapshot, mut grid) = (sheet(), sheet());
+        let mut stack = UndoStack::default();
+
+        let mut group = EditGroup::new("Edit");
+        group.set_raw(&grid, 0, 0, "10".to_string());
+        group.clear(&grid, 1, 0);
+        stack.commit(&mut grid, group);
+        let mut group = EditGroup::new("Edit");
+        group.set_raw(&grid, 0, 1, "= A0 + 1".to_string());
+        stack.commit(&mut grid, group);
+        stack.undo(&mut grid);
+        assert!(stack.has_applied());
+
+        let mut batches: Vec<String> = vec![serde_json::to_string(&stack.take_applied()).unwrap()];
+        assert!(!stack.has_applied());
+        // Torn final write
+        batches.push("[{\"col\":3,\"row\"".to_string());
+
+        let entries = decode(&batches);
+        assert_eq!(entries.len(), 4);
+        replay(&mut snapshot, &entries);
+        assert_eq!(snapshot.get_cell(0, 0).unwrap().raw, "10");
+        assert!(snapshot.get_cell(1, 0).is_none());
+        assert!(snapshot.get_cell(0, 1).is_none(), "the undo is journaled too");
+    }
+}
@@ -0,0 +1,401 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Hidden lines (rows or columns) along one axis
+/// Hidden lines keep their cells and formula references; they're only skipped
+/// when mapping between on-screen (visual) and sheet (logical) indices
+/// Lines hidden by the user, by a table filter and by collapsed outline groups
+/// are tracked separately so e.g. clearing a filter doesn't unhide the user's lines
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HiddenLines {
+    hidden: BTreeSet<i32>,
+    filtered: BTreeSet<i32>,
+    groups: Vec<OutlineGroup>,
+    /// Lines inside collapsed groups (kept in sync with `groups`)
+    collapsed: BTreeSet<i32>,
+}
+
+/// A run of lines that can be collapsed and expanded as one (an outline group)
+/// Groups may nest; a line stays hidden while any group containing it is collapsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutlineGroup {
+    pub start: i32,
+    pub end: i32,
+    pub collapsed: bool,
+}
+
+impl OutlineGroup {
+    pub fn contains(&self, index: i32) -> bool {
+        (self.start..=self.end).contains(&index)
+    }
+}
+
+impl HiddenLines {
+    pub fn is_hidden(&self, index: i32) -> bool {
+        self.hidden.contains(&index) || self.filtered.contains(&index) || self.collapsed.contains(&index)
+    }
+
+    pub fn hide(&mut self, index: i32) {
+        self.hidden.insert(index);
+    }
+
+    pub fn unhide(&mut self, index: i32) {
+        self.hidden.remove(&index);
+    }
+
+    /// Lines currently hidden by a filter
+    pub fn filtered(&self) -> &BTreeSet<i32> {
+        &self.filtered
+    }
+
+    /// Replace the filter-hidden lines
+    pub fn set_filtered(&mut self, filtered: BTreeSet<i32>) {
+        self.filtered = filtered;
+    }
+
+    /// Outline groups, in the order they were created
+    pub fn groups(&self) -> &[OutlineGroup] {
+        &self.groups
+    }
+
+    /// Group lines `start..=end`; regrouping an existing run is a no-op
+    pub fn group(&mut self, start: i32, end: i32) {
+        let (start, end) = (start.min(end), start.max(end));
+        if !self.groups.iter().any(|g| (g.start, g.end) == (start, end)) {
+            self.groups.push(OutlineGroup { start, end, collapsed: false });
+        }
+    }
+
+    /// Remove every group lying within `start..=end`, showing their lines again
+    pub fn ungroup(&mut self, start: i32, end: i32) {
+        self.groups.retain(|g| g.start < start || g.end > end);
+        self.refresh_collapsed();
+    }
+
+    /// Collapse or expand a group
+    pub fn toggle_group(&mut self, index: usize) {
+        if let Some(group) = self.groups.get_mut(index) {
+            group.collapsed = !group.collapsed;
+            self.refresh_collapsed();
+        }
+    }
+
+    /// Nesting depth of a line: how many groups contain it
+    pub fn outline_level(&self, index: i32) -> usize {
+        self.groups.iter().filter(|g| g.contains(index)).count()
+    }
+
+    fn refresh_collapsed(&mut self) {
+        self.collapsed = self
+            .groups
+            .iter()
+            .filter(|g| g.collapsed)
+            .flat_map(|g| g.start..=g.end)
+            .collect();
+    }
+
+    /// All hidden indices (user, filter and outline) in ascending order
+    fn all_hidden(&self) -> impl Iterator<Item = i32> + '_ {
+        let mut sources = [self.hidden.iter().peekable(), self.filtered.iter().peekable(), self.collapsed.iter().peekable()];
+        std::iter::from_fn(move || {
+            let next = sources.iter_mut().filter_map(|s| s.peek().copied()).min().copied()?;
+            for source in &mut sources {
+                if source.peek() == Some(&&next) {
+                    source.next();
+                }
+            }
+            Some(next)
+        })
+    }
+
+    /// Logical index shown at a visual position
+    /// Hidden lines collapse toward the origin, so line 0 stays put whichever
+    /// side of it lines are hidden on and negative indices mirror positive ones
+    pub fn to_logical(&self, visual: i32) -> i32 {
+        let mut logical = visual;
+        if visual >= 0 {
+            for h in self.all_hidden().filter(|&h| h >= 0) {
+                if h > logical {
+                    break;
+                }
+                logical += 1;
+            }
+        } else {
+            let negative: Vec<i32> = self.all_hidden().filter(|&h| h < 0).collect();
+            for &h in negative.iter().rev() {
+                if h < logical {
+                    break;
+                }
+                logical -= 1;
+            }
+        }
+        logical
+    }
+
+    /// Visual position of a logical index, None if it's hidden
+    pub fn to_visual(&self, logical: i32) -> Option<i32> {
+        if self.is_hidden(logical) {
+            return None;
+        }
+        let between = self.all_hidden().filter(|&h| if logical >= 0 { (0..logical).contains(&h) } else { h > logical && h < 0 });
+        let skipped = between.count() as i32;
+        Some(if logical >= 0 { logical - skipped } else { logical + skipped })
+    }
+
+    /// True if hidden lines sit directly before this (visible) line,
+    /// which is where the "hidden range" marker is drawn
+    pub fn hidden_before(&self, logical: i32) -> bool {
+        self.is_hidden(logical - 1)
+    }
+
+    /// Map every user-hidden index and outline group through `f`, dropping those
+    /// it returns None for (keeps them attached to their data across inserts/deletes)
+    /// A group survives as long as both of its ends do
+    pub fn remap(&mut self, f: impl Fn(i32) -> Option<i32>) {
+        self.hidden = self.hidden.iter().filter_map(|&i| f(i)).collect();
+        self.groups = self
+            .groups
+            .iter()
+            .filter_map(|g| Some(OutlineGroup { start: f(g.start)?, end: f(g.end)?, ..*g }))
+            .collect();
+        self.refresh_collapsed();
+    }
+}
+
+/// Row/column layout of the sheet
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SheetLayout {
+    pub rows: HiddenLines,
+    pub cols: HiddenLines,
+    /// Number of leading (visual) columns pinned to the left edge while panning
+    pub frozen_cols: i32,
+    /// Number of leading (visual) rows pinned to the top edge while panning
+    pub frozen_rows: i32,
+    /// Widths in pixels of the columns that differ from the default, by logical column
+    #[serde(deserialize_with = "crate::persist::since::v7")]
+    pub col_widths: BTreeMap<i32, u32>,
+}
+
+impl SheetLayout {
+    /// Logical cell shown at a visual (col, row)
+    pub fn to_logical(&self, col: i32, row: i32) -> (i32, i32) {
+        (self.cols.to_logical(col), self.rows.to_logical(row))
+    }
+
+    /// Visual position of a logical cell, None if its row or column is hidden
+    pub fn to_visual(&self, col: i32, row: i32) -> Option<(i32, i32)> {
+        Some((self.cols.to_visual(col)?, self.rows.to_visual(row)?))
+    }
+
+    /// Map every resized column through `f` (see `HiddenLines::remap`)
+    pub fn remap_col_widths(&mut self, f: impl Fn(i32) -> Option<i32>) {
+        self.col_widths = self.col_widths.iter().filter_map(|(&col, &width)| Some((f(col)?, width))).collect();
+    }
+
+    /// Where each visual column starts, given the default width
+    pub fn column_offsets(&self, default_width: f32) -> ColumnOffsets {
+        let mut resized: Vec<(i32, f32)> = self
+            .col_widths
+            .iter()
+            .filter_map(|(&col, &width)| Some((self.cols.to_visual(col)?, width as f32)))
+            .filter(|&(visual, _)| visual >= 0)
+            .collect();
+        resized.sort_by_key(|&(visual, _)| visual);
+
+        let mut extra = 0.0;
+        let spans = resized
+            .into_iter()
+            .map(|(visual, width)| {
+                let left = visual as f32 * default_width + extra;
+                extra += width - default_width;
+                (visual, left, width)
+            })
+            .collect();
+        ColumnOffsets { default_width, spans }
+    }
+
+    /// Visual cells backing each slot of the viewport GPU buffers, in buffer order:
+    /// the scrolling viewport (row-major), then the frozen-column pane, the
+    /// frozen-row pane and the frozen corner. Must match the indexing in grid.wgsl
+    pub fn viewport_slots(&self, min_col: i32, min_row: i32, width: i32, height: i32) -> Vec<(i32, i32)> {
+        let (fc, fr) = (self.frozen_cols.max(0), self.frozen_rows.max(0));
+        let rows = |start: i32, count: i32, cols: std::ops::Range<i32>| {
+            (start..start + count).flat_map(move |row| cols.clone().map(move |col| (col, row)))
+        };
+
+        let mut slots = Vec::with_capacity(((width + fc) * (height + fr)) as usize);
+        slots.extend(rows(min_row, height, min_col..min_col + width));
+        slots.extend(rows(min_row, height, 0..fc));
+        slots.extend(rows(0, fr, min_col..min_col + width));
+        slots.extend(rows(0, fr, 0..fc));
+        slots
+    }
+}
+
+/// Column edges in world units with some columns resized: a prefix sum over
+/// the resized visible columns, with default-width columns in between
+/// Columns left of the origin always have the default width
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnOffsets {
+    default_width: f32,
+    /// Resized columns in order: (visual column, left edge, width)
+    spans: Vec<(i32, f32, f32)>,
+}
+
+impl ColumnOffsets {
+    /// Left edge of a visual column
+    pub fn left(&self, visual: i32) -> f32 {
+        match self.spans.partition_point(|span| span.0 < visual).checked_sub(1) {
+            Some(i) => {
+                let (col, left, width) = self.spans[i];
+                left + width + (visual - col - 1) as f32 * self.default_width
+            }
+            None => visual as f32 * self.default_width,
+        }
+    }
+
+    pub fn width(&self, visual: i32) -> f32 {
+        match self.spans.binary_search_by_key(&visual, |span| span.0) {
+            Ok(i) => self.spans[i].2,
+            Err(_) => self.default_width,
+        }
+    }
+
+    /// Visual column containing world x (binary search over the resized columns)
+    pub fn col_at(&self, x: f32) -> i32 {
+        match self.spans.partition_point(|span| span.1 <= x).checked_sub(1) {
+            Some(i) => {
+                let (col, left, width) = self.spans[i];
+                if x < left + width {
+                    col
+                } else {
+                    col + 1 + ((x - left - width) / self.default_width).floor() as i32
+                }
+            }
+            None => (x / self.default_width).floor() as i32,
+        }
+    }
+
+    /// Number of resized columns, i.e. triples in `to_words`
+    pub fn resized_count(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// The spans as the shader's offsets buffer: (column, left, width) triples,
+    /// padded to one triple as storage buffers can't be empty
+    pub fn to_words(&self) -> Vec<f32> {
+        let mut words: Vec<f32> = self.spans.iter().flat_map(|&(col, left, width)| [col as f32, left, width]).collect();
+        words.resize(words.len().max(3), 0.0);
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visual_logical_roundtrip() {
+        let mut lines = HiddenLines::default();
+        lines.hide(2);
+        lines.hide(3);
+        lines.hide(6);
+
+        assert_eq!(lines.to_logical(0), 0);
+        assert_eq!(lines.to_logical(2), 4);
+        assert_eq!(lines.to_logical(4), 7);
+        assert_eq!(lines.to_visual(3), None);
+        assert_eq!(lines.to_visual(7), Some(4));
+        assert!(lines.hidden_before(4));
+        assert!(!lines.hidden_before(5));
+
+        for visual in 0..20 {
+            assert_eq!(lines.to_visual(lines.to_logical(visual)), Some(visual));
+        }
+
+        // Lines hidden above the origin collapse upward, leaving line 0 in place
+        lines.hide(-2);
+        assert_eq!(lines.to_logical(0), 0);
+        assert_eq!(lines.to_logical(-2), -3);
+        assert_eq!(lines.to_visual(-3), Some(-2));
+        for visual in -20..20 {
+            assert_eq!(lines.to_visual(lines.to_logical(visual)), Some(visual));
+        }
+
+        // Filtered lines combine with user-hidden ones
+        lines.set_filtered([3, 4].into_iter().collect());
+        assert_eq!(lines.to_logical(2), 5);
+        assert_eq!(lines.to_visual(7), Some(3));
+    }
+
+    #[test]
+    fn test_viewport_slots_with_frozen_panes() {
+        let layout = SheetLayout { frozen_cols: 1, frozen_rows: 1, ..Default::default() };
+        let slots = layout.viewport_slots(10, 20, 3, 2);
+
+        assert_eq!(slots.len(), 3 * 2 + 2 + 3 + 1);
+        assert_eq!(slots[0], (10, 20));
+        assert_eq!(slots[5], (12, 21));
+        // Left pane, top pane, corner
+        assert_eq!(slots[6..8], [(0, 20), (0, 21)]);
+        assert_eq!(slots[8..11], [(10, 0), (11, 0), (12, 0)]);
+        assert_eq!(slots[11], (0, 0));
+
+        assert_eq!(SheetLayout::default().viewport_slots(0, 0, 2, 2).len(), 4);
+    }
+
+    #[test]
+    fn test_column_offsets() {
+        let mut layout = SheetLayout::default();
+        layout.col_widths.insert(1, 200);
+        layout.col_widths.insert(4, 40);
+        // Hidden columns take no space, resized or not
+        layout.col_widths.insert(2, 500);
+        layout.cols.hide(2);
+        let columns = layout.column_offsets(80.0);
+
+        // Visual columns: 0 (80), 1 (200), 2 = logical 3 (80), 3 = logical 4 (40), 4 (80)
+        assert_eq!(columns.resized_count(), 2);
+        let lefts: Vec<f32> = (-1..6).map(|col| columns.left(col)).collect();
+        assert_eq!(lefts, [-80.0, 0.0, 80.0, 280.0, 360.0, 400.0, 480.0]);
+        assert_eq!(columns.width(1), 200.0);
+        assert_eq!(columns.width(2), 80.0);
+
+        for (x, col) in [(-1.0, -1), (79.0, 0), (80.0, 1), (279.0, 1), (300.0, 2), (399.0, 3), (400.0, 4), (1000.0, 11)] {
+            assert_eq!(columns.col_at(x), col, "x = {}", x);
+        }
+        assert_eq!(SheetLayout::default().column_offsets(80.0).to_words(), [0.0; 3]);
+    }
+
+    #[test]
+    fn test_outline_groups() {
+        let mut lines = HiddenLines::default();
+        lines.group(2, 6);
+        lines.group(3, 4);
+        lines.hide(4);
+        assert_eq!(lines.outline_level(3), 2);
+        assert_eq!(lines.outline_level(6), 1);
+
+        // Collapsing the inner group combines with the user-hidden line inside it
+        lines.toggle_group(1);
+        assert_eq!(lines.to_logical(3), 5);
+        lines.toggle_group(0);
+        assert_eq!(lines.to_logical(2), 7);
+
+        // Expanding the outer group leaves the inner one collapsed
+        lines.toggle_group(0);
+        assert!(lines.is_hidden(3));
+        assert!(!lines.is_hidden(5));
+
+        // Groups follow inserted lines and go away when ungrouped
+        lines.remap(|i| Some(if i >= 1 { i + 10 } else { i }));
+        assert_eq!((lines.groups()[1].start, lines.groups()[1].end), (13, 14));
+        assert!(lines.is_hidden(13));
+        lines.ungroup(10, 20);
+        assert!(lines.groups().is_empty());
+        assert!(!lines.is_hidden(13));
+        assert!(lines.is_hidden(14), "user-hidden lines stay hidden");
+    }
+}
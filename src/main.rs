@@ -5,44 +5,199 @@ use bevy::{
     render::storage::ShaderStorageBuffer,
     shader::ShaderRef,
     sprite_render::{Material2d, Material2dPlugin},
+    window::FileDragAndDrop,
 };
 
+mod accessibility;
+mod camera_motion;
 mod cell;
+mod cell_store;
+mod chart;
+mod crdt;
+mod documents;
+mod edit_buffer;
+mod export;
+mod feeds;
+mod glyph_atlas;
 mod gpu_cell;
 mod grid_state;
 mod formula;
 mod evaluator;
 mod demo;
+mod dependencies;
+mod deep_link;
+mod diagnostics;
 mod svg_renderer;
+mod texture_layers;
+mod touch;
+mod events;
+mod history;
+mod gpu_eval;
+mod eval_worker;
+mod undo;
+mod clipboard;
+mod grid_ops;
+mod layout;
+mod minimap;
+mod navigation;
+mod selection;
+mod filter;
+mod validation;
+mod headers;
+mod heatmap;
+mod host_api;
+mod host_functions;
+mod import;
+mod journal;
+mod persist;
+#[cfg(not(target_arch = "wasm32"))]
+mod server;
+#[cfg(all(not(target_arch = "wasm32"), feature = "storage-sqlite"))]
+mod sqlite_store;
+mod sync;
+mod theme;
+mod xlsx;
 
 use grid_state::GridState;
-use svg_renderer::{SvgRenderer, SvgRenderRequest};
+use svg_renderer::{RenderTarget, SvgRenderer, SvgRenderRequest};
 use bevy::render::render_resource::{TextureDimension, TextureFormat, Extent3d};
 use bevy::asset::RenderAssetUsages;
 use evaluator::{TickControl, EvaluationTimer, tick_evaluation_system};
+use events::CellChanged;
+use history::TickHistory;
+use undo::{EditGroup, UndoStack};
+use clipboard::Clipboard;
+use documents::{DocumentEvent, DocumentStore};
+use journal::Journal;
+use cell::{BorderLine, BorderSide, CellDisplay, CellStyle, HorizontalAlign};
+use gpu_cell::GpuCell;
+use bevy::camera::Viewport;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::window::Ime;
+use bevy::diagnostic::{DiagnosticPath, DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::camera::visibility::RenderLayers;
+use bevy::window::{CursorIcon, SystemCursorIcon};
+use bevy::text::TextLayoutInfo;
+use bevy::ui::{RelativeCursorPosition, UiGlobalTransform, UiSystems};
+use bevy::a11y::AccessibilityNode;
+use bevy::a11y::accesskit::{Live, Node as AccessNode, Role};
 
-const GRID_COLS: i32 = 128;
-const GRID_ROWS: i32 = 128;
+/// Pick up the last session from its snapshot and journal (browsers ask
+/// first); otherwise start on the demo sheet
+fn restore_workbook() -> (GridState, TickControl, Journal) {
+    #[cfg(target_arch = "wasm32")]
+    let restored = persist::web::restore_session();
+    #[cfg(not(target_arch = "wasm32"))]
+    let restored = journal::restore_snapshot();
+    let journal = Journal::default();
+    let (grid, ticks) = match restored {
+        Some(restored) => {
+            journal.read();
+            restored
+        }
+        None => {
+            let mut grid = GridState::new();
+            demo::setup_demo_data(&mut grid);
+            let ticks = TickControl::default();
+            journal.replace(&grid, &ticks);
+            (grid, ticks)
+        }
+    };
+    (grid, ticks, journal)
+}
+
+/// Host the workbook for sync clients without a window (`--serve`, see
+/// `server`): ticks run on their timer, and the clients attach as views
+/// The workbook is kept in its own files (`--workbook`)
+#[cfg(not(target_arch = "wasm32"))]
+fn run_server(address: String) {
+    journal::use_workbook(&server::workbook_from_args());
+    let (grid, mut ticks, journal) = restore_workbook();
+    ticks.auto_tick_enabled = true;
+    let server = match server::SyncServer::listen(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Can't serve on {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+    App::new()
+        .add_plugins((
+            MinimalPlugins.set(bevy::app::ScheduleRunnerPlugin::run_loop(std::time::Duration::from_millis(10))),
+            bevy::log::LogPlugin::default(),
+        ))
+        .insert_resource(grid)
+        .insert_resource(ticks)
+        .insert_resource(journal)
+        .insert_resource(server)
+        .insert_resource(eval_worker::EvalWorker::new())
+        .insert_resource(EvaluationTimer::default())
+        .add_message::<CellChanged>()
+        .add_systems(Update, (
+            server::serve_clients.before(tick_evaluation_system),
+            tick_evaluation_system,
+            server::send_tick_values.after(tick_evaluation_system),
+            autosave_workbook,
+        ))
+        .run();
+}
 
 fn main() {
+    // `--serve [address] [--workbook path]` runs headless, hosting the workbook instead
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(address) = server::address_from_args() {
+        return run_server(address);
+    }
+
     let mut app = App::new();
     app.add_plugins((
-        DefaultPlugins,
+        DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                // On the web, follow the page as the browser window resizes
+                // (and its devicePixelRatio changes) instead of keeping the
+                // canvas's first size
+                fit_canvas_to_parent: true,
+                ..default()
+            }),
+            ..default()
+        }),
         Material2dPlugin::<SpreadsheetGridMaterial>::default(),
-    ))
-    .insert_resource({
-        let mut grid = GridState::new();
-        demo::setup_demo_data(&mut grid);
-        grid
-    });
+        FrameTimeDiagnosticsPlugin::default(),
+    ));
+
+    let (grid, ticks, journal) = restore_workbook();
+    app.insert_resource(grid).insert_resource(ticks).insert_resource(journal);
 
     app.insert_resource(SvgRenderer::new());
+
+    // Keep in step with a sync server, if one's given
+    let mut sync_client = sync::SyncClient::default();
+    if let Some(url) = sync::startup_param("sync") {
+        sync_client.connect(url);
+    }
+    app.insert_resource(sync_client);
+
+    // Threads aren't available on wasm, where ticks evaluate inline
+    #[cfg(not(target_arch = "wasm32"))]
+    app.insert_resource(eval_worker::EvalWorker::new());
     app.insert_resource(DragState::default())
-    .insert_resource(TickControl::default())
     .insert_resource(EvaluationTimer::default())
     .insert_resource(EditingState::default())
     .insert_resource(LensState::default())
-    .add_systems(Startup, (setup, setup_ui))
+    .insert_resource(CameraMotion::default())
+    .insert_resource(SplitView::default())
+    .insert_resource(OverlayData::default())
+    .insert_resource(BorderPen::default())
+    .insert_resource(TickHistory::default())
+    .insert_resource(UndoStack::default())
+    .insert_resource(Clipboard::default())
+    .insert_resource(DocumentStore::default())
+    .insert_resource(feeds::FeedRunner::default())
+    .insert_resource(host_api::Subscriptions::default())
+    .add_message::<CellChanged>()
+    .add_systems(Startup, (setup, setup_ui, open_deep_link.after(setup), gpu_eval::init))
+    .add_systems(PreUpdate, toolbar_keyboard_focus.after(UiSystems::Focus))
     .add_systems(Update, (
         tick_evaluation_system,
         update_grid_to_camera,
@@ -52,12 +207,73 @@ fn main() {
         handle_lens_buttons,
         update_tick_button_text,
         update_lens_button_text,
+        handle_history_buttons,
+        update_history_text,
+        handle_undo_buttons,
+        handle_undo_shortcuts,
         handle_keyboard_input,
+        navigate_active_cell,
         handle_editor_input,
+        handle_ime_input,
         update_editor_display,
         apply_camera_actions,
         sync_grid_buffer,
         manage_svg_cells
+    ))
+    .add_systems(Update, (
+        handle_clipboard_shortcuts,
+        handle_fill_shortcuts,
+        handle_structure_buttons,
+        handle_format_buttons,
+        open_context_menu,
+        handle_context_menu,
+        apply_table_filter,
+        open_filter_dropdown,
+        handle_filter_dropdown,
+        open_validation_picker,
+        handle_validation_picker,
+        handle_protect_button,
+        update_header_gutters,
+        handle_outline_toggles,
+        handle_header_clicks,
+        toggle_checkbox_cells,
+        update_cell_tooltip,
+        handle_file_buttons,
+        handle_document_picker,
+        handle_name_box.after(handle_editor_input).after(navigate_active_cell),
+    ))
+    .add_systems(Update, (
+        journal_edits,
+        autosave_workbook,
+        handle_export_button,
+        update_data_feeds,
+        update_minimap,
+        handle_minimap_clicks,
+        update_charts,
+        handle_border_buttons,
+        draw_trace_arrows,
+        update_split_view,
+        update_stats_overlay,
+        blink_editor_caret,
+        grab_pan,
+        handle_touch,
+        click_formula_bar.before(handle_editor_input),
+        announce_active_cell,
+        update_deep_link,
+    ))
+    // Collaboration with other clients (see `sync`)
+    .add_systems(Update, (
+        apply_remote_edits.before(tick_evaluation_system),
+        share_presence.after(apply_remote_edits),
+        draw_presence.after(apply_remote_edits),
+    ))
+    // The embedding page's API (see `host_api`)
+    .add_systems(Update, (
+        serve_host_requests,
+        notify_host_subscribers.after(serve_host_requests),
+        send_host_calls.after(tick_evaluation_system),
+        stream_host_overlays.after(serve_host_requests),
+        mirror_host_values.after(serve_host_requests).after(tick_evaluation_system),
     ));
 
     app.run();
@@ -65,8 +281,22 @@ fn main() {
 
 #[derive(Resource, Default)]
 struct EditingState {
-    pub active_cell: Option<(i32, i32)>,
-    pub buffer: String,
+    pub buffer: edit_buffer::EditBuffer,
+    /// Why the last commit was refused or flagged, shown in the formula bar
+    pub message: Option<String>,
+    /// Text an input method is still composing (CJK, dead keys), not yet in
+    /// the buffer
+    pub composing: String,
+    /// In edit mode (typing started, or F2 / double-click), so the arrow keys
+    /// move the caret rather than the active cell; otherwise typing replaces
+    /// the cell's contents
+    pub editing: bool,
+    /// What's typed into the name box while it has focus (Ctrl+G or a click);
+    /// keys go there instead of the cell
+    pub name_box: Option<String>,
+    /// The button keyboard focus is on (F6), keys work the toolbar rather
+    /// than the sheet
+    pub toolbar_focus: Option<Entity>,
 }
 
 #[derive(Resource)]
@@ -75,6 +305,13 @@ struct LensState {
     pub show_position: bool,
     pub show_formula: bool,
     pub show_grid: bool,
+    /// Shade every other row across the whole sheet
+    pub show_stripes: bool,
+    /// Color numeric cells by value
+    pub show_heatmap: bool,
+    pub heat_scale: heatmap::HeatScale,
+    /// Arrows from the active cell to its precedents and dependents
+    pub show_trace: bool,
 }
 
 impl Default for LensState {
@@ -84,28 +321,84 @@ impl Default for LensState {
             show_position: false,
             show_formula: false,
             show_grid: true,
+            show_stripes: false,
+            show_heatmap: false,
+            heat_scale: heatmap::HeatScale::default(),
+            show_trace: false,
         }
     }
 }
 
+/// The formula bar: clicking it edits the active cell there
+#[derive(Component)]
+struct FormulaBar;
+
 #[derive(Component)]
 struct EditorText;
 
+/// The name box left of the formula bar: shows the active cell's name, and
+/// takes a cell, range or label to go to
+#[derive(Component)]
+struct NameBox;
+
+#[derive(Component)]
+struct NameBoxText;
+
+/// Offscreen status node screen readers follow (through AccessKit): the active
+/// cell's name, value and formula
+#[derive(Component)]
+struct ScreenReaderStatus;
+
+/// Parts of the formula bar after the text before the selection (which is in
+/// `EditorText` itself): the caret sits on whichever side of the selection
+/// it's on
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum EditorSpan {
+    LeadCaret,
+    Selected,
+    TrailCaret,
+    /// The rest of the text, then anything being composed and any messages
+    After,
+}
+
+/// Caret blinks per second in the formula bar
+const CARET_BLINK_RATE: f32 = 2.0;
+
+/// Where input method candidate windows open: just under the formula bar
+const IME_POSITION: Vec2 = Vec2::new(245.0, 50.0);
+
+#[derive(Component)]
+struct HistoryText;
+
 #[derive(Component)]
 enum LensButton {
     Value,
     Position,
     Formula,
     Grid,
+    Stripes,
+    Heatmap,
+    Trace,
 }
 
-// Track drag state to toggle cells only once per drag
+// Track drag state: a drag selects the rectangle from where it started
 #[derive(Resource, Default)]
 struct DragState {
     is_dragging: bool,
-    toggled_cells: std::collections::HashSet<(i32, i32)>,
+    /// Where a selecting drag started (the active cell), and the corner it has reached
+    select_from: Option<(i32, i32)>,
+    select_to: Option<(i32, i32)>,
+    /// Cell where a drag of the selection border started (moving the selection)
+    move_anchor: Option<(i32, i32)>,
+    /// The selection whose fill handle is being dragged
+    fill_source: Option<grid_state::CellRange>,
+    /// The last click's cell and time, to spot double-clicks
+    last_click: Option<((i32, i32), f64)>,
 }
 
+/// Most seconds between the two clicks of a double-click
+const DOUBLE_CLICK_TIME: f64 = 0.4;
+
 // --- Material Definition ---
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
 struct SpreadsheetGridMaterial {
@@ -115,16 +408,48 @@ struct SpreadsheetGridMaterial {
     viewport_size: Vec2,
     #[uniform(0)]
     cell_size: Vec2,
+    /// Minor gridline width in screen pixels
     #[uniform(0)]
     line_width: f32,
     #[uniform(0)]
     color_bg: LinearRgba,
     #[uniform(0)]
     color_line: LinearRgba,
+    /// Size of the viewport buffers in cells; the sheet itself is unbounded
     #[uniform(0)]
     grid_dimensions: Vec2,
     #[uniform(0)]
     show_grid: f32,
+    /// Frozen (columns, rows), pinned to the left/top edge
+    #[uniform(0)]
+    frozen_panes: Vec2,
+    /// Seconds, for animating the copy source's marching ants
+    #[uniform(0)]
+    time: f32,
+    /// Shade every other row (zebra striping); tables declared with "Format as
+    /// table" are banded regardless
+    #[uniform(0)]
+    stripes: f32,
+    /// Heatmap lens: numeric cells colored by where they fall in `heat_range`
+    #[uniform(0)]
+    heatmap: f32,
+    /// (min, max) of the heatmap's color scale
+    #[uniform(0)]
+    heat_range: Vec2,
+    /// Cells between major gridlines
+    #[uniform(0)]
+    major_every: f32,
+    /// Major gridline width in screen pixels (`line_width` is the minor one)
+    #[uniform(0)]
+    major_line_width: f32,
+    #[uniform(0)]
+    color_major_line: LinearRgba,
+    /// World units per screen pixel, kept up to date with the camera's zoom
+    #[uniform(0)]
+    pixel_size: f32,
+    /// Resized columns in `col_offsets`
+    #[uniform(0)]
+    resized_cols: f32,
     #[storage(1, read_only)]
     cell_data: Handle<ShaderStorageBuffer>,
     #[texture(2, dimension = "2d_array")]
@@ -132,6 +457,22 @@ struct SpreadsheetGridMaterial {
     rich_cell_textures: Handle<Image>,
     #[storage(4, read_only)]
     rich_cell_indices: Handle<ShaderStorageBuffer>,
+    /// Coverage atlas of the glyphs the shader draws plain text with
+    #[texture(5)]
+    #[sampler(6)]
+    glyph_atlas: Handle<Image>,
+    /// `glyph_atlas::TEXT_STRIDE` words per viewport slot
+    #[storage(7, read_only)]
+    cell_text: Handle<ShaderStorageBuffer>,
+    /// `gpu_cell::BORDER_STRIDE` words per viewport slot
+    #[storage(8, read_only)]
+    cell_borders: Handle<ShaderStorageBuffer>,
+    /// Where resized columns start (see `layout::ColumnOffsets::to_words`)
+    #[storage(9, read_only)]
+    col_offsets: Handle<ShaderStorageBuffer>,
+    /// One `OverlayData` word per viewport slot, for the host's overlay.wgsl
+    #[storage(10, read_only)]
+    overlay_data: Handle<ShaderStorageBuffer>,
 }
 
 impl Material2d for SpreadsheetGridMaterial {
@@ -140,23 +481,180 @@ impl Material2d for SpreadsheetGridMaterial {
     }
 }
 
+/// The main pane's grid backdrop, which all editing goes through
 #[derive(Component)]
 struct GridBackdrop;
 
+/// The main sheet camera (the UI draws over it)
+#[derive(Component)]
+struct MainCamera;
+
+/// A grid backdrop and the camera it shows the sheet through: the main pane's,
+/// or the watch pane's while the view is split
+#[derive(Component)]
+struct GridPane {
+    camera: Entity,
+}
+
+/// The watch pane's camera and backdrop (see `SplitView`)
+#[derive(Component)]
+struct WatchPane;
+
+/// Render layer of the watch pane, so each camera only sees its own backdrop
+const WATCH_LAYER: usize = 1;
+
+/// Per-cell words for a host application's grid overlay, keyed by logical
+/// (column, row) and handed to `overlay_color` in assets/shaders/overlay.wgsl
+/// (0 for cells not listed)
+#[derive(Resource, Default)]
+struct OverlayData {
+    pub cells: std::collections::HashMap<(i32, i32), u32>,
+}
+
+/// Whether the window is split into the main pane and a watch pane
+/// The watch pane keeps showing where the main view was when it was split,
+/// so an output region stays in sight while editing somewhere else
+#[derive(Resource, Default)]
+struct SplitView {
+    pub enabled: bool,
+}
+
+/// One column or row name (or outline toggle) in the header gutters
+#[derive(Component)]
+struct HeaderGutterLabel;
+
+/// The column or row a gutter label names (logical index); clicking it
+/// selects the whole line
+#[derive(Component, Clone, Copy, PartialEq)]
+struct HeaderLine {
+    axis: grid_ops::Axis,
+    index: i32,
+}
+
+/// Overview of the sheet in the bottom-right corner; clicking it moves the
+/// view there
+#[derive(Component)]
+struct Minimap;
+
+/// The minimap's box around the used cells
+#[derive(Component)]
+struct MinimapUsed;
+
+/// The minimap's box for the current view
+#[derive(Component)]
+struct MinimapView;
+
+/// Performance stats panel in the top-right corner, toggled with F3
+#[derive(Component)]
+struct StatsOverlay;
+
+/// Seconds between stats overlay refreshes
+const STATS_REFRESH: f32 = 0.5;
+
+/// Sprite drawing one of `GridState::charts`
+#[derive(Component)]
+struct ChartSprite;
+
+/// Charts are rasterized at this multiple of their size, to stay sharp when zoomed in
+const CHART_SCALE: f32 = 2.0;
+
+/// Minimap size and distance from the window's bottom-right corner, in pixels
+const MINIMAP_SIZE: Vec2 = Vec2::new(180.0, 120.0);
+const MINIMAP_MARGIN: f32 = 10.0;
+
+/// A gutter label as laid out by `update_header_gutters`
+#[derive(Clone, PartialEq)]
+struct GutterLabel {
+    text: String,
+    bounds: Rect,
+    /// Either a line name or an outline toggle
+    line: Option<HeaderLine>,
+    toggle: Option<OutlineToggle>,
+    /// The line has selected cells
+    highlighted: bool,
+}
+
+/// Expand/collapse button for an outline group, shown in the header gutter
+/// next to the line after the group
+#[derive(Component, Clone, Copy, PartialEq)]
+struct OutlineToggle {
+    axis: grid_ops::Axis,
+    group: usize,
+}
+
+/// Side of an outline toggle button, in pixels
+const OUTLINE_TOGGLE_SIZE: f32 = 14.0;
+
+/// Height of the column gutter along the top edge, in pixels
+const COLUMN_GUTTER_HEIGHT: f32 = 20.0;
+/// Pixels either side of a column's right edge that count as its boundary
+const COLUMN_EDGE_GRAB: f32 = 5.0;
+/// Width of the row gutter along the left edge, in pixels
+const ROW_GUTTER_WIDTH: f32 = 48.0;
+
 // Coordinate transformation utilities
 // Single source of truth for world_pos -> (col, row)
-fn world_pos_to_cell(world_pos: Vec2, cell_size: Vec2) -> (i32, i32) {
-    let col = (world_pos.x / cell_size.x).floor() as i32;
+// Columns come from their offsets, as they can be resized; rows are `cell_size.y` tall
+fn world_pos_to_cell(world_pos: Vec2, cell_size: Vec2, columns: &layout::ColumnOffsets) -> (i32, i32) {
+    let col = columns.col_at(world_pos.x);
     let row = (-world_pos.y / cell_size.y).floor() as i32;
     (col, row)
 }
 
+/// Undo the frozen-pane mapping: a point over a frozen pane is moved back to
+/// the sheet origin it displays (mirrors the remapping in grid.wgsl)
+fn pane_world_pos(mat: &SpreadsheetGridMaterial, columns: &layout::ColumnOffsets, world_pos: Vec2) -> Vec2 {
+    let frozen_size = Vec2::new(columns.left(mat.frozen_panes.x as i32), mat.frozen_panes.y * mat.cell_size.y);
+    let from_left = world_pos.x - mat.viewport_bottom_left.x;
+    let from_top = mat.viewport_bottom_left.y + mat.viewport_size.y - world_pos.y;
+
+    let mut pos = world_pos;
+    if from_left < frozen_size.x {
+        pos.x = from_left;
+    }
+    if from_top < frozen_size.y {
+        pos.y = -from_top;
+    }
+    pos
+}
+
+/// A visual cell's rect in world space, where its pane draws it (frozen rows
+/// and columns stay at the viewport's top and left)
+fn cell_world_rect(mat: &SpreadsheetGridMaterial, columns: &layout::ColumnOffsets, visual_col: i32, visual_row: i32) -> Rect {
+    let mut top_left = Vec2::new(columns.left(visual_col), -(visual_row as f32) * mat.cell_size.y);
+    if (visual_col as f32) < mat.frozen_panes.x {
+        top_left.x += mat.viewport_bottom_left.x;
+    }
+    if (visual_row as f32) < mat.frozen_panes.y {
+        top_left.y += mat.viewport_bottom_left.y + mat.viewport_size.y;
+    }
+    Rect::from_corners(top_left, top_left + Vec2::new(columns.width(visual_col), -mat.cell_size.y))
+}
+
 // Camera update types (interactions)
 #[derive(Component, Clone, Copy, Debug)]
 enum CameraAction {
     Zoom(f32),      // multiply scale by this factor
     Pan(Vec2),      // translate by this amount (in scaled units)
+    CenterOn(Vec2), // move to this world position
     Reset,
+    ZoomToFit,      // show the whole used range
+}
+
+/// Eases the camera toward where the camera actions sent it
+#[derive(Resource)]
+struct CameraMotion {
+    /// Seconds a move takes (0 jumps straight there)
+    pub duration: f32,
+    /// Zoom and scroll limits, None to roam freely
+    pub bounds: Option<camera_motion::CameraBounds>,
+    tween: Option<camera_motion::CameraTween>,
+}
+
+impl Default for CameraMotion {
+    fn default() -> Self {
+        Self { duration: 0.25, bounds: Some(camera_motion::CameraBounds::default()), tween: None }
+    }
 }
 
 // Camera control components
@@ -169,6 +667,9 @@ enum CameraButton {
     PanUp,
     PanDown,
     Reset,
+    ZoomToFit,
+    /// Toggles the split view (see `SplitView`) rather than moving the camera
+    Split,
 }
 
 #[derive(Component)]
@@ -177,6 +678,191 @@ enum TickButton {
     AutoTickToggle,
 }
 
+#[derive(Component)]
+enum UndoButton {
+    Undo,
+    Redo,
+}
+
+#[derive(Component)]
+enum StructureButton {
+    InsertRows,
+    DeleteRows,
+    InsertColumns,
+    DeleteColumns,
+}
+
+/// Save or reload the workbook (`persist::SAVE_PATH` natively, an in-memory slot on wasm),
+/// open the document picker, or export the view as a PNG
+#[derive(Component)]
+enum FileButton {
+    Save,
+    Load,
+    Documents,
+    ExportImage,
+}
+
+/// Overlay listing the named documents in the `DocumentStore`
+#[derive(Component)]
+struct DocumentPicker;
+
+/// Buttons in the document picker
+#[derive(Component, Clone)]
+enum DocumentAction {
+    /// Save the open workbook under a name (asked for in browsers)
+    SaveAs,
+    Open(String),
+    Delete(String),
+    Close,
+}
+
+#[derive(Component)]
+enum FormatButton {
+    Bold,
+    Italic,
+    Align,
+    TextColor,
+    Background,
+    NumberFormat,
+    Lock,
+    NamedStyle,
+    Clear,
+}
+
+/// Borders section of the toolbar: sides to draw with the border pen, and the
+/// pen's line and color
+#[derive(Component, Clone, Copy, PartialEq)]
+enum BorderButton {
+    Top,
+    Right,
+    Bottom,
+    Left,
+    /// Around the outside of the selection
+    Outline,
+    /// Every side of every selected cell
+    All,
+    Clear,
+    Line,
+    Color,
+}
+
+/// Line and color the border buttons draw with
+#[derive(Resource)]
+struct BorderPen {
+    line: BorderLine,
+    color: Option<[u8; 3]>,
+}
+
+impl Default for BorderPen {
+    fn default() -> Self {
+        Self { line: BorderLine::Thin, color: None }
+    }
+}
+
+/// Label of the pen button, showing the pen's line in its color
+#[derive(Component)]
+struct BorderPenLabel;
+
+/// Toggles sheet protection
+#[derive(Component)]
+struct ProtectButton;
+
+/// Right-click menu over the grid, acting on the selection it was opened for
+#[derive(Component)]
+struct ContextMenu {
+    target: grid_state::CellRange,
+}
+
+#[derive(Component, Clone, Copy)]
+enum ContextMenuAction {
+    HideRows,
+    HideColumns,
+    UnhideRows,
+    UnhideColumns,
+    FreezePanes,
+    UnfreezePanes,
+    FilterTable,
+    RemoveFilter,
+    LabelColumns,
+    LabelRows,
+    GroupRows,
+    GroupColumns,
+    UngroupRows,
+    UngroupColumns,
+    CopyMarkdown,
+    CopyHtml,
+    BindFeed,
+    UnbindFeed,
+    FormatTable,
+    ClearTableFormat,
+    FixHeatScale,
+    AutoHeatScale,
+    Chart(chart::ChartKind),
+    RemoveCharts,
+}
+
+/// Overlay with the full contents of the hovered cell
+#[derive(Component)]
+struct CellTooltip;
+
+/// Name tag over another client's active cell (see `sync::Peer`)
+#[derive(Component)]
+struct PresenceTag {
+    client: u64,
+    name: String,
+}
+
+/// Colors other clients show in, picked by client id
+const PRESENCE_PALETTE: [[u8; 3]; 6] =
+    [[0xe5, 0x39, 0x35], [0x8e, 0x24, 0xaa], [0x00, 0x89, 0x7b], [0xf4, 0x51, 0x1e], [0x39, 0x49, 0xab], [0x7c, 0xb3, 0x42]];
+
+fn presence_color(client: u64) -> Color {
+    let [r, g, b] = PRESENCE_PALETTE[(client % PRESENCE_PALETTE.len() as u64) as usize];
+    Color::srgb_u8(r, g, b)
+}
+
+/// How long the cursor must rest on a cell before its tooltip shows, in seconds
+const TOOLTIP_DELAY: f32 = 0.6;
+
+/// Cell under the cursor and when the cursor arrived on it
+#[derive(Default)]
+struct HoverState {
+    cell: Option<(i32, i32)>,
+    since: f32,
+    shown: bool,
+}
+
+/// Value list opened from a filtered table's header arrow
+#[derive(Component)]
+struct FilterDropdown;
+
+/// Allowed-value list opened from a list-validated cell's arrow
+#[derive(Component)]
+struct ValidationPicker;
+
+/// One allowed value in the validation picker
+#[derive(Component)]
+struct PickerOption {
+    col: i32,
+    row: i32,
+    value: String,
+}
+
+/// One entry in the filter dropdown: toggles a value, or clears the column's filter
+#[derive(Component)]
+struct FilterOption {
+    col: i32,
+    value: Option<String>,
+}
+
+#[derive(Component)]
+enum HistoryButton {
+    Oldest,
+    Back,
+    Forward,
+    Resume,
+}
+
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -184,28 +870,48 @@ fn setup(
     mut images: ResMut<Assets<Image>>,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
 ) {
-    commands.spawn((Camera2d, Transform::from_xyz(0.0, 0.0, 0.0)));
+    let camera = commands
+        .spawn((Camera2d, Transform::from_xyz(0.0, 0.0, 0.0), MainCamera, IsDefaultUiCamera))
+        .id();
 
     // Initialize with empty/dummy data, will be updated by sync_grid_buffer
-    let buffer_handle = buffers.add(ShaderStorageBuffer::from(vec![0u32]));
+    let buffer_handle = buffers.add(ShaderStorageBuffer::from(vec![0u32; gpu_cell::GpuCell::STRIDE]));
 
     // Initialize rich cell indices with -1 (small buffer initially)
     let indices_handle = buffers.add(ShaderStorageBuffer::from(vec![-1i32]));
 
-    // Initialize dummy texture array (1x1, 2 layers to force D2Array view)
-    let dummy_texture = Image::new(
+    // Persistent rich-cell texture array, filled layer by layer by manage_svg_cells
+    // Kept in the main world too so layers can be written in place
+    let dummy_texture = Image::new_fill(
         Extent3d {
-            width: 1,
-            height: 1,
-            depth_or_array_layers: 2,
+            width: texture_layers::LAYER_WIDTH,
+            height: texture_layers::LAYER_HEIGHT,
+            depth_or_array_layers: texture_layers::INITIAL_LAYERS,
         },
         TextureDimension::D2,
-        vec![0, 0, 0, 0, 0, 0, 0, 0], // Transparent (2 pixels)
+        &[0, 0, 0, 0],
         TextureFormat::Rgba8UnormSrgb,
-        RenderAssetUsages::RENDER_WORLD,
+        RenderAssetUsages::default(),
     );
     let texture_handle = images.add(dummy_texture);
 
+    let glyph_atlas = images.add(Image::new(
+        Extent3d {
+            width: glyph_atlas::ATLAS_WIDTH,
+            height: glyph_atlas::ATLAS_HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        glyph_atlas::render_atlas(),
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    ));
+    let text_handle = buffers.add(ShaderStorageBuffer::from(vec![0u32; glyph_atlas::TEXT_STRIDE]));
+    let borders_handle = buffers.add(ShaderStorageBuffer::from(vec![0u32; gpu_cell::BORDER_STRIDE]));
+    // One empty (column, left, width) triple until a column is resized
+    let offsets_handle = buffers.add(ShaderStorageBuffer::from(vec![0.0f32; 3]));
+    let overlay_handle = buffers.add(ShaderStorageBuffer::from(vec![0u32]));
+
     commands.spawn((
         Mesh2d(meshes.add(Rectangle::new(1.0, 1.0))),
         MeshMaterial2d(materials.add(SpreadsheetGridMaterial {
@@ -215,104 +921,435 @@ fn setup(
             line_width: 1.0,
             color_bg: LinearRgba::WHITE,
             color_line: LinearRgba::gray(0.8),
-            grid_dimensions: Vec2::new(GRID_COLS as f32, GRID_ROWS as f32),
+            // Viewport size in cells, set each frame by sync_grid_buffer
+            grid_dimensions: Vec2::ZERO,
             show_grid: 1.0,
+            frozen_panes: Vec2::ZERO,
+            time: 0.0,
+            stripes: 0.0,
+            heatmap: 0.0,
+            heat_range: Vec2::new(0.0, 1.0),
+            major_every: 5.0,
+            major_line_width: 2.0,
+            color_major_line: LinearRgba::gray(0.6),
+            pixel_size: 1.0,
+            resized_cols: 0.0,
             cell_data: buffer_handle,
             rich_cell_textures: texture_handle,
             rich_cell_indices: indices_handle,
+            glyph_atlas,
+            cell_text: text_handle,
+            cell_borders: borders_handle,
+            col_offsets: offsets_handle,
+            overlay_data: overlay_handle,
         })),
         Transform::from_xyz(0.0, 0.0, -100.0),
         GridBackdrop,
+        GridPane { camera },
+        UploadedViewport::default(),
     ));
 }
 
 fn update_grid_to_camera(
-    camera_q: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
-    mut grid_q: Query<(&mut Transform, &MeshMaterial2d<SpreadsheetGridMaterial>), With<GridBackdrop>>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    mut grid_q: Query<(&mut Transform, &MeshMaterial2d<SpreadsheetGridMaterial>, &GridPane)>,
     mut materials: ResMut<Assets<SpreadsheetGridMaterial>>,
 ) {
-    let Ok((camera, cam_transform)) = camera_q.single() else { return };
-    let Ok((mut grid_transform, grid_handle)) = grid_q.single_mut() else { return };
+    for (mut grid_transform, grid_handle, pane) in &mut grid_q {
+        let Ok((camera, cam_transform)) = camera_q.get(pane.camera) else { continue };
+        let Some(rect) = camera.logical_viewport_rect() else { continue };
+        let min_world = camera.viewport_to_world_2d(cam_transform, rect.min).ok();
+        let max_world = camera.viewport_to_world_2d(cam_transform, rect.max).ok();
 
-    let Some(rect) = camera.logical_viewport_rect() else { return };
-    let min_world = camera.viewport_to_world_2d(cam_transform, rect.min).ok();
-    let max_world = camera.viewport_to_world_2d(cam_transform, rect.max).ok();
+        if let (Some(min), Some(max)) = (min_world, max_world) {
+            let size = (max - min).abs();
+            let center = (min + max) / 2.0;
+
+            grid_transform.translation.x = center.x;
+            grid_transform.translation.y = center.y;
+            grid_transform.scale = size.extend(1.0);
+
+            if let Some(mat) = materials.get_mut(&grid_handle.0) {
+                let bottom_left = Vec2::new(min.x.min(max.x), min.y.min(max.y));
+
+                mat.viewport_bottom_left = bottom_left;
+                mat.viewport_size = size;
+                mat.pixel_size = size.x / rect.width().max(1.0);
+            }
+        }
+    }
+}
+
+/// Left and right halves of a window, for the main and watch panes
+fn split_viewports(window: UVec2) -> (Viewport, Viewport) {
+    let half = UVec2::new(window.x / 2, window.y).max(UVec2::ONE);
+    let left = Viewport { physical_position: UVec2::ZERO, physical_size: half, ..default() };
+    let right = Viewport { physical_position: UVec2::new(half.x, 0), physical_size: half, ..default() };
+    (left, right)
+}
+
+/// Open or close the watch pane when the split is toggled, and keep the two
+/// panes side by side as the window resizes
+fn update_split_view(
+    mut commands: Commands,
+    split: Res<SplitView>,
+    window_q: Query<&Window>,
+    mut main_q: Query<(&mut Camera, &Transform), (With<MainCamera>, Without<WatchPane>)>,
+    mut watch_q: Query<&mut Camera, (With<WatchPane>, Without<MainCamera>)>,
+    watch_panes: Query<Entity, With<WatchPane>>,
+    grid_q: Query<(&Mesh2d, &MeshMaterial2d<SpreadsheetGridMaterial>, &Transform), With<GridBackdrop>>,
+    mut materials: ResMut<Assets<SpreadsheetGridMaterial>>,
+    mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+) {
+    let Ok((mut main_camera, main_transform)) = main_q.single_mut() else { return };
+    let Ok(window) = window_q.single() else { return };
+    let (left, right) = split_viewports(window.physical_size());
 
-    if let (Some(min), Some(max)) = (min_world, max_world) {
-        let size = (max - min).abs();
-        let center = (min + max) / 2.0;
+    if split.is_changed() {
+        for entity in &watch_panes {
+            commands.entity(entity).despawn();
+        }
+        main_camera.viewport = None;
+        if !split.enabled {
+            return;
+        }
 
-        grid_transform.translation.x = center.x;
-        grid_transform.translation.y = center.y;
-        grid_transform.scale = size.extend(1.0);
+        let Ok((mesh, grid_handle, grid_transform)) = grid_q.single() else { return };
+        let Some(mut watch_mat) = materials.get(&grid_handle.0).cloned() else { return };
+        // Its own viewport buffers, sharing the textures
+        watch_mat.cell_data = buffers.add(ShaderStorageBuffer::from(vec![0u32; GpuCell::STRIDE]));
+        watch_mat.rich_cell_indices = buffers.add(ShaderStorageBuffer::from(vec![-1i32]));
+        watch_mat.cell_text = buffers.add(ShaderStorageBuffer::from(vec![0u32; glyph_atlas::TEXT_STRIDE]));
+        watch_mat.cell_borders = buffers.add(ShaderStorageBuffer::from(vec![0u32; gpu_cell::BORDER_STRIDE]));
+        watch_mat.col_offsets = buffers.add(ShaderStorageBuffer::from(vec![0.0f32; 3]));
+        watch_mat.overlay_data = buffers.add(ShaderStorageBuffer::from(vec![0u32]));
 
-        if let Some(mat) = materials.get_mut(&grid_handle.0) {
-            let bottom_left = Vec2::new(min.x.min(max.x), min.y.min(max.y));
+        // Starts out showing what the main pane shows now
+        let camera = commands
+            .spawn((
+                Camera2d,
+                Camera { order: 1, viewport: Some(right.clone()), ..default() },
+                *main_transform,
+                RenderLayers::layer(WATCH_LAYER),
+                WatchPane,
+            ))
+            .id();
+        commands.spawn((
+            mesh.clone(),
+            MeshMaterial2d(materials.add(watch_mat)),
+            *grid_transform,
+            RenderLayers::layer(WATCH_LAYER),
+            WatchPane,
+            GridPane { camera },
+            UploadedViewport::default(),
+        ));
+    }
 
-            mat.viewport_bottom_left = bottom_left;
-            mat.viewport_size = size;
+    if !split.enabled {
+        return;
+    }
+    let same = |current: &Option<Viewport>, wanted: &Viewport| {
+        current.as_ref().is_some_and(|v| v.physical_position == wanted.physical_position && v.physical_size == wanted.physical_size)
+    };
+    if !same(&main_camera.viewport, &left) {
+        main_camera.viewport = Some(left);
+    }
+    for mut camera in &mut watch_q {
+        if !same(&camera.viewport, &right) {
+            camera.viewport = Some(right.clone());
         }
     }
 }
 
+/// Toggle the stats overlay with F3 and refresh it every `STATS_REFRESH`
+/// seconds, and report the same numbers to the page's metrics watchers
+fn update_stats_overlay(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    diagnostics: Res<DiagnosticsStore>,
+    grid_state: Res<GridState>,
+    tick_control: Res<TickControl>,
+    svg_renderer: Res<SvgRenderer>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridPane>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    buffers: Res<Assets<ShaderStorageBuffer>>,
+    images: Res<Assets<Image>>,
+    mut subscriptions: ResMut<host_api::Subscriptions>,
+    mut overlay_q: Query<(&mut Text, &mut Node), With<StatsOverlay>>,
+    mut since_refresh: Local<f32>,
+) {
+    let Ok((mut text, mut node)) = overlay_q.single_mut() else { return };
+    let toggled = keyboard.just_pressed(KeyCode::F3);
+    if toggled {
+        node.display = if node.display == Display::None { Display::Flex } else { Display::None };
+    }
+    let due = if subscriptions.has_metrics() { subscriptions.due_metrics(time.delta()) } else { Vec::new() };
+    let shown = node.display != Display::None;
+    *since_refresh += time.delta_secs();
+    let refresh = shown && (toggled || *since_refresh >= STATS_REFRESH);
+    if !refresh && due.is_empty() {
+        return;
+    }
+
+    let reading = |path: &DiagnosticPath| diagnostics.get(path).and_then(|d| d.smoothed());
+    let buffer_bytes = |handle: &Handle<ShaderStorageBuffer>| {
+        buffers.get(handle).and_then(|b| b.data.as_ref()).map_or(0, Vec::len)
+    };
+    let mut gpu_bytes = 0;
+    // Panes share their textures, so those count once
+    let mut textures = std::collections::HashSet::new();
+    for mat in grid_q.iter().filter_map(|handle| materials.get(&handle.0)) {
+        gpu_bytes += [&mat.cell_data, &mat.rich_cell_indices, &mat.cell_text, &mat.cell_borders, &mat.col_offsets, &mat.overlay_data]
+            .into_iter()
+            .map(buffer_bytes)
+            .sum::<usize>();
+        textures.insert(mat.rich_cell_textures.id());
+    }
+    gpu_bytes += textures
+        .into_iter()
+        .filter_map(|id| images.get(id).and_then(|image| image.data.as_ref()))
+        .map(Vec::len)
+        .sum::<usize>();
+
+    let stats = diagnostics::SheetStats {
+        fps: reading(&FrameTimeDiagnosticsPlugin::FPS),
+        frame_ms: reading(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+        cells: grid_state.cells.len(),
+        formulas: grid_state.cells.iter().filter(|(_, cell)| cell.is_formula).count(),
+        tick_ms: tick_control.last_tick_ms,
+        changed_last_tick: tick_control.last_tick_changes,
+        svg_cached: svg_renderer.pixel_cache.len(),
+        svg_cache_bytes: svg_renderer.pixel_cache.values().map(Vec::len).sum(),
+        svg_pending: svg_renderer.pending_renders.len(),
+        gpu_bytes,
+    };
+    for id in due {
+        host_api::deliver_metrics(id, &stats);
+    }
+    if refresh {
+        *since_refresh = 0.0;
+        **text = stats.to_text();
+    }
+}
+
 fn grid_interaction(
     window_q: Query<&Window>,
-    camera_q: Query<(&Camera, &GlobalTransform)>,
-    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
     materials: Res<Assets<SpreadsheetGridMaterial>>,
     mouse_btn: Res<ButtonInput<MouseButton>>,
     mut grid_state: ResMut<GridState>,
     mut drag_state: ResMut<DragState>,
     mut editing_state: ResMut<EditingState>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+    overlay_q: Query<&Interaction, Or<(With<HeaderLine>, With<Minimap>, With<NameBox>, With<FormulaBar>)>>,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
 ) {
     let Ok((camera, cam_transform)) = camera_q.single() else { return };
     let Ok(window) = window_q.single() else { return };
     let Ok(grid_handle) = grid_q.single() else { return };
     let Some(mat) = materials.get(&grid_handle.0) else { return };
 
+    // Space+drag pans the view instead (see `grab_pan`)
+    if keyboard.pressed(KeyCode::Space) && !editing_state.editing {
+        drag_state.is_dragging = false;
+        return;
+    }
+
+    // Header gutters select whole lines, the minimap moves the view and the
+    // name box takes a cell to go to (see `handle_header_clicks`,
+    // `handle_minimap_clicks`, `handle_name_box` and `click_formula_bar`)
+    if mouse_btn.just_pressed(MouseButton::Left) && overlay_q.iter().any(|i| *i != Interaction::None) {
+        return;
+    }
+
     // --- onMouseDown Handler ---
     if mouse_btn.just_pressed(MouseButton::Left) {
         drag_state.is_dragging = true;
-        drag_state.toggled_cells.clear();
+        drag_state.select_from = None;
     }
 
     // --- onMouseUp Handler ---
     if mouse_btn.just_released(MouseButton::Left) {
         drag_state.is_dragging = false;
-        drag_state.toggled_cells.clear();
+        drag_state.select_from = None;
     }
 
-    if let Some(cursor_pos) = window.cursor_position() {
+    // Clicks over the watch pane (see `SplitView`) don't reach the sheet
+    let in_view = |pos: &Vec2| camera.logical_viewport_rect().is_none_or(|rect| rect.contains(*pos));
+    if let Some(cursor_pos) = window.cursor_position().filter(in_view) {
         // Calculate world position
         if let Ok(world_pos) = camera.viewport_to_world_2d(cam_transform, cursor_pos) {
-            let (col, row) = world_pos_to_cell(world_pos, mat.cell_size);
+            let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+            let world_pos = pane_world_pos(mat, &columns, world_pos);
+            let (visual_col, visual_row) = world_pos_to_cell(world_pos, mat.cell_size, &columns);
+            let (col, row) = grid_state.layout.to_logical(visual_col, visual_row);
+
+            // --- Drag the fill handle to fill the cells it's stretched over ---
+            if mouse_btn.just_pressed(MouseButton::Left) {
+                let tolerance = 4.0 * cam_transform.compute_transform().scale.x;
+                let handle = grid_state.fill_handle().and_then(|(col, row)| grid_state.layout.to_visual(col, row));
+                if let Some((handle_col, handle_row)) = handle {
+                    let corner = Vec2::new(
+                        columns.left(handle_col) + columns.width(handle_col),
+                        -(handle_row + 1) as f32 * mat.cell_size.y,
+                    );
+                    if (world_pos - corner).abs().max_element() <= tolerance {
+                        drag_state.fill_source = grid_state.selection_bounds();
+                        drag_state.is_dragging = false;
+                        return;
+                    }
+                }
+            }
+            if let Some(source) = drag_state.fill_source {
+                // The selection shows the range the fill will cover
+                let target = grid_ops::fill_handle_target(source, (col, row));
+                let covered = target.map_or(source, |(range, _)| range);
+                if grid_state.selection_bounds() != Some(covered) {
+                    grid_state.selected.set(covered);
+                }
+                if mouse_btn.just_released(MouseButton::Left) {
+                    drag_state.fill_source = None;
+                    if let Some((range, direction)) = target.filter(|_| !history.is_scrubbing()) {
+                        let group = grid_ops::fill(&grid_state, range, direction);
+                        cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+                        sync_editor_buffer(&mut editing_state, &grid_state);
+                    }
+                }
+                return;
+            }
 
+            // --- Drag the selection border to move it ---
             if mouse_btn.just_pressed(MouseButton::Left) {
-                // Select cell
-                grid_state.selected.clear();
+                let tolerance = 4.0 * cam_transform.compute_transform().scale.x;
+                let visual_selection = grid_state
+                    .selected
+                    .iter()
+                    .filter_map(|(col, row)| grid_state.layout.to_visual(col, row))
+                    .collect();
+                if on_selection_border(&visual_selection, world_pos, mat.cell_size, &columns, tolerance) {
+                    drag_state.move_anchor = Some((col, row));
+                    drag_state.is_dragging = false;
+                    return;
+                }
+            }
+            if mouse_btn.just_released(MouseButton::Left) {
+                if let Some((anchor_col, anchor_row)) = drag_state.move_anchor.take() {
+                    let (dx, dy) = (col - anchor_col, row - anchor_row);
+                    if (dx, dy) != (0, 0) && !history.is_scrubbing() {
+                        let sources: Vec<(i32, i32)> = grid_state.selected.iter().collect();
+                        let group = clipboard::move_cells(&grid_state, &sources, dx, dy);
+                        cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+                        shift_selection(&mut grid_state, &mut editing_state, dx, dy);
+                    }
+                    return;
+                }
+            }
+            if drag_state.move_anchor.is_some() {
+                return;
+            }
+
+            // Shift+click stretches the selection from the active cell
+            let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+            if mouse_btn.just_pressed(MouseButton::Left) && shift && grid_state.active.is_some() {
+                grid_state.extend_selection((col, row));
+                drag_state.select_from = grid_state.active;
+                drag_state.select_to = Some((col, row));
+            } else if mouse_btn.just_pressed(MouseButton::Left) {
+                // Select cell; Ctrl+click starts another range beside the
+                // selection (which a drag then stretches)
+                if !ctrl_pressed(&keyboard) {
+                    grid_state.selected.clear();
+                }
                 grid_state.selected.insert((col, row));
+                grid_state.selection_end = None;
+                drag_state.select_from = Some((col, row));
+                drag_state.select_to = Some((col, row));
                 
                 // Activate editing
-                editing_state.active_cell = Some((col, row));
-                if let Some(cell) = grid_state.get_cell(col, row) {
-                    editing_state.buffer = cell.raw.clone();
+                grid_state.active = Some((col, row));
+                sync_editor_buffer(&mut editing_state, &grid_state);
+
+                // A double-click edits the existing contents, like F2
+                let now = time.elapsed_secs_f64();
+                let double = drag_state
+                    .last_click
+                    .is_some_and(|(cell, at)| cell == (col, row) && now - at <= DOUBLE_CLICK_TIME);
+                if double {
+                    editing_state.editing = true;
+                    drag_state.last_click = None;
                 } else {
-                    editing_state.buffer = String::new();
+                    drag_state.last_click = Some(((col, row), now));
                 }
             }
 
-            // --- Toggle cells while dragging ---
-            if drag_state.is_dragging {
-                let cell_coord = (col, row);
-                // Only toggle if we haven't toggled this cell yet during this drag
-                if !drag_state.toggled_cells.contains(&cell_coord) {
-                    drag_state.toggled_cells.insert(cell_coord);
-                    // Add to selection
-                    grid_state.selected.insert(cell_coord);
-                }
+            // --- Select the rectangle while dragging ---
+            if drag_state.is_dragging && drag_state.select_from.is_some() && drag_state.select_to != Some((col, row)) {
+                drag_state.select_to = Some((col, row));
+                grid_state.extend_selection((col, row));
             }
         }
     }
+
+    // A fill handle let go of off the sheet fills nothing
+    if mouse_btn.just_released(MouseButton::Left) {
+        if let Some(source) = drag_state.fill_source.take() {
+            grid_state.selected.set(source);
+        }
+    }
+}
+
+/// True if `world_pos` lies within `tolerance` of the selection's bounding-box border
+/// (`selected` is in visual coordinates)
+fn on_selection_border(
+    selected: &std::collections::HashSet<(i32, i32)>,
+    world_pos: Vec2,
+    cell_size: Vec2,
+    columns: &layout::ColumnOffsets,
+    tolerance: f32,
+) -> bool {
+    let (Some(min_col), Some(max_col), Some(min_row), Some(max_row)) = (
+        selected.iter().map(|c| c.0).min(),
+        selected.iter().map(|c| c.0).max(),
+        selected.iter().map(|c| c.1).min(),
+        selected.iter().map(|c| c.1).max(),
+    ) else {
+        return false;
+    };
+
+    // Rows grow downwards, so the top edge has the larger y
+    let left = columns.left(min_col);
+    let right = columns.left(max_col + 1);
+    let top = -(min_row as f32) * cell_size.y;
+    let bottom = -((max_row + 1) as f32) * cell_size.y;
+
+    let in_outer = world_pos.x >= left - tolerance
+        && world_pos.x <= right + tolerance
+        && world_pos.y >= bottom - tolerance
+        && world_pos.y <= top + tolerance;
+    let in_inner = world_pos.x > left + tolerance
+        && world_pos.x < right - tolerance
+        && world_pos.y > bottom + tolerance
+        && world_pos.y < top - tolerance;
+
+    in_outer && !in_inner
+}
+
+/// Move the selection and active cell along with moved content
+fn shift_selection(grid_state: &mut GridState, editing_state: &mut EditingState, dx: i32, dy: i32) {
+    grid_state.selected = grid_state.selected.map_ranges(|range| {
+        Some(grid_state::CellRange::new(
+            (range.min_col + dx, range.min_row + dy),
+            (range.max_col + dx, range.max_row + dy),
+        ))
+    });
+    if let Some((col, row)) = grid_state.active {
+        grid_state.active = Some((col + dx, row + dy));
+    }
+    sync_editor_buffer(editing_state, grid_state);
 }
 
 fn setup_ui(mut commands: Commands) {
@@ -337,9 +1374,19 @@ fn setup_ui(mut commands: Commands) {
                     create_button(parent, "Zoom In (+)", CameraButton::ZoomIn);
                     create_button(parent, "Zoom Out (-)", CameraButton::ZoomOut);
                     create_button(parent, "Reset", CameraButton::Reset);
+                    create_button(parent, "Fit Used Range", CameraButton::ZoomToFit);
+                    create_button(parent, "Split View", CameraButton::Split);
                     parent.spawn(Node { height: Val::Px(20.0), ..default() });
                     create_tick_button(parent, "Tick", TickButton::ManualTick);
                     create_tick_button(parent, "Auto Tick: OFF", TickButton::AutoTickToggle);
+
+                    parent.spawn(Node { height: Val::Px(20.0), ..default() });
+                    create_undo_button(parent, "Undo", UndoButton::Undo);
+                    create_undo_button(parent, "Redo", UndoButton::Redo);
+                    create_structure_button(parent, "+ Rows", StructureButton::InsertRows);
+                    create_structure_button(parent, "- Rows", StructureButton::DeleteRows);
+                    create_structure_button(parent, "+ Cols", StructureButton::InsertColumns);
+                    create_structure_button(parent, "- Cols", StructureButton::DeleteColumns);
                     
                     parent.spawn(Node { height: Val::Px(20.0), ..default() });
                     // Lens controls
@@ -347,16 +1394,50 @@ fn setup_ui(mut commands: Commands) {
                     create_lens_button(parent, "Pos: OFF", LensButton::Position);
                     create_lens_button(parent, "Formula: OFF", LensButton::Formula);
                     create_lens_button(parent, "Grid: ON", LensButton::Grid);
+                    create_lens_button(parent, "Stripes: OFF", LensButton::Stripes);
+                    create_lens_button(parent, "Heat: OFF", LensButton::Heatmap);
+                    create_lens_button(parent, "Trace: OFF", LensButton::Trace);
+
+                    parent.spawn(Node { height: Val::Px(20.0), ..default() });
+                    create_file_button(parent, "Save", FileButton::Save);
+                    create_file_button(parent, "Load", FileButton::Load);
+                    create_file_button(parent, "Documents", FileButton::Documents);
+                    create_file_button(parent, "Export image", FileButton::ExportImage);
                 });
 
-            // Formula Bar (Top Center)
+            // Name box (Top, left of the formula bar)
             parent
                 .spawn((
+                    Button,
                     Node {
                         position_type: PositionType::Absolute,
                         left: Val::Px(150.0),
                         top: Val::Px(10.0),
-                        width: Val::Px(400.0),
+                        width: Val::Px(80.0),
+                        height: Val::Px(40.0),
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(5.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+                    NameBox,
+                ))
+                .with_child((
+                    Text::new(""),
+                    TextFont { font_size: 16.0, ..default() },
+                    TextColor(Color::WHITE),
+                    NameBoxText,
+                ));
+
+            // Formula Bar (Top Center)
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(240.0),
+                        top: Val::Px(10.0),
+                        width: Val::Px(310.0),
                         height: Val::Px(40.0),
                         align_items: AlignItems::Center,
                         justify_content: JustifyContent::Start,
@@ -365,18 +1446,201 @@ fn setup_ui(mut commands: Commands) {
                     },
                     BackgroundColor(Color::srgb(0.1, 0.1, 0.1)), // Dark background
                     BorderColor::from(Color::srgb(0.3, 0.3, 0.3)),
+                    FormulaBar,
                 ))
                 .with_child((
                     Text::new("Formula: "),
                     TextFont { font_size: 16.0, ..default() },
                     TextColor(Color::WHITE),
                 ))
-                .with_child((
-                    Text::new(""),
-                    TextFont { font_size: 16.0, ..default() },
-                    TextColor(Color::WHITE),
-                    EditorText,
-                ));
+                .with_children(|parent| {
+                    parent
+                        .spawn((
+                            Text::new(""),
+                            TextFont { font_size: 16.0, ..default() },
+                            TextColor(Color::WHITE),
+                            RelativeCursorPosition::default(),
+                            EditorText,
+                        ))
+                        .with_children(|text| {
+                            for span in [EditorSpan::LeadCaret, EditorSpan::Selected, EditorSpan::TrailCaret, EditorSpan::After] {
+                                let color = if span == EditorSpan::Selected { Color::srgb(1.0, 0.85, 0.3) } else { Color::WHITE };
+                                text.spawn((TextSpan::new(""), TextFont { font_size: 16.0, ..default() }, TextColor(color), span));
+                            }
+                        });
+                });
+
+            // Formatting toolbar (Top, right of the formula bar)
+            parent
+                .spawn(Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(560.0),
+                    top: Val::Px(10.0),
+                    column_gap: Val::Px(5.0),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    create_format_button(parent, "B", FormatButton::Bold);
+                    create_format_button(parent, "I", FormatButton::Italic);
+                    create_format_button(parent, "Align", FormatButton::Align);
+                    create_format_button(parent, "Color", FormatButton::TextColor);
+                    create_format_button(parent, "Fill", FormatButton::Background);
+                    create_format_button(parent, "123", FormatButton::NumberFormat);
+                    create_format_button(parent, "Lock", FormatButton::Lock);
+                    create_format_button(parent, "Style", FormatButton::NamedStyle);
+                    create_format_button(parent, "Clear", FormatButton::Clear);
+                    parent
+                        .spawn((
+                            Button,
+                            Node {
+                                width: Val::Px(110.0),
+                                height: Val::Px(40.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+                            ProtectButton,
+                        ))
+                        .with_child((
+                            Text::new("Protect: OFF"),
+                            TextFont {
+                                font_size: 14.0,
+                                ..default()
+                            },
+                            TextColor(Color::WHITE),
+                        ));
+                });
+
+            // Borders (below the formatting toolbar)
+            parent
+                .spawn(Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(560.0),
+                    top: Val::Px(55.0),
+                    column_gap: Val::Px(5.0),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    create_border_button(parent, "Top", BorderButton::Top);
+                    create_border_button(parent, "Right", BorderButton::Right);
+                    create_border_button(parent, "Bottom", BorderButton::Bottom);
+                    create_border_button(parent, "Left", BorderButton::Left);
+                    create_border_button(parent, "Box", BorderButton::Outline);
+                    create_border_button(parent, "All", BorderButton::All);
+                    create_border_button(parent, "None", BorderButton::Clear);
+                    create_border_button(parent, "Ink", BorderButton::Color);
+                    parent
+                        .spawn((
+                            Button,
+                            Node {
+                                width: Val::Px(70.0),
+                                height: Val::Px(30.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+                            BorderButton::Line,
+                        ))
+                        .with_child((
+                            Text::new("Thin"),
+                            TextFont {
+                                font_size: 14.0,
+                                ..default()
+                            },
+                            TextColor(Color::WHITE),
+                            BorderPenLabel,
+                        ));
+                });
+
+            // Minimap (Bottom Right)
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        position_type: PositionType::Absolute,
+                        right: Val::Px(MINIMAP_MARGIN),
+                        bottom: Val::Px(MINIMAP_MARGIN),
+                        width: Val::Px(MINIMAP_SIZE.x),
+                        height: Val::Px(MINIMAP_SIZE.y),
+                        overflow: Overflow::clip(),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.8)),
+                    Minimap,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Node { position_type: PositionType::Absolute, ..default() },
+                        BackgroundColor(Color::srgb(0.45, 0.6, 0.85)),
+                        MinimapUsed,
+                    ));
+                    parent.spawn((
+                        Node {
+                            position_type: PositionType::Absolute,
+                            border: UiRect::all(Val::Px(1.0)),
+                            ..default()
+                        },
+                        BorderColor::from(Color::WHITE),
+                        MinimapView,
+                    ));
+                });
+
+            // Stats overlay (Top Right), hidden until F3
+            parent.spawn((
+                Text::new(""),
+                TextFont { font_size: 12.0, ..default() },
+                TextColor(Color::WHITE),
+                Node {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    padding: UiRect::all(Val::Px(6.0)),
+                    display: Display::None,
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.85)),
+                StatsOverlay,
+            ));
+
+            // Screen reader status: takes no space, only its label matters
+            parent.spawn((
+                Node { position_type: PositionType::Absolute, width: Val::Px(0.0), height: Val::Px(0.0), ..default() },
+                AccessibilityNode::from({
+                    let mut node = AccessNode::new(Role::Status);
+                    node.set_live(Live::Polite);
+                    node
+                }),
+                ScreenReaderStatus,
+            ));
+
+            // History timeline (Bottom Center)
+            parent
+                .spawn((
+                    Node {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(150.0),
+                        bottom: Val::Px(10.0),
+                        column_gap: Val::Px(10.0),
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(5.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+                ))
+                .with_children(|parent| {
+                    create_history_button(parent, "<< Oldest", HistoryButton::Oldest);
+                    create_history_button(parent, "< Back", HistoryButton::Back);
+                    create_history_button(parent, "Fwd >", HistoryButton::Forward);
+                    create_history_button(parent, "Resume", HistoryButton::Resume);
+                    parent.spawn((
+                        Text::new("Live"),
+                        TextFont { font_size: 14.0, ..default() },
+                        TextColor(Color::WHITE),
+                        HistoryText,
+                    ));
+                });
 
             // Right panel - Pan controls
             parent
@@ -475,11 +1739,155 @@ fn create_lens_button(parent: &mut ChildSpawnerCommands, label: &str, button_typ
         ));
 }
 
+fn create_undo_button(parent: &mut ChildSpawnerCommands, label: &str, button_type: UndoButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(120.0),
+                height: Val::Px(40.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.4, 0.2, 0.4)),
+            button_type,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+fn create_structure_button(parent: &mut ChildSpawnerCommands, label: &str, button_type: StructureButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(120.0),
+                height: Val::Px(30.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.4, 0.2, 0.4)),
+            button_type,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+fn create_file_button(parent: &mut ChildSpawnerCommands, label: &str, button_type: FileButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(120.0),
+                height: Val::Px(30.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.4, 0.3)),
+            button_type,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+fn create_format_button(parent: &mut ChildSpawnerCommands, label: &str, button_type: FormatButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(50.0),
+                height: Val::Px(40.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+            button_type,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+fn create_border_button(parent: &mut ChildSpawnerCommands, label: &str, button_type: BorderButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(50.0),
+                height: Val::Px(30.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+            button_type,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+fn create_history_button(parent: &mut ChildSpawnerCommands, label: &str, button_type: HistoryButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(90.0),
+                height: Val::Px(30.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.5, 0.35, 0.15)),
+            button_type,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
 fn handle_lens_buttons(
     interaction_query: Query<(&Interaction, &LensButton), Changed<Interaction>>,
     mut lens_state: ResMut<LensState>,
     mut materials: ResMut<Assets<SpreadsheetGridMaterial>>,
-    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridPane>>,
 ) {
     for (interaction, button) in &interaction_query {
         if *interaction == Interaction::Pressed {
@@ -489,12 +1897,23 @@ fn handle_lens_buttons(
                 LensButton::Formula => lens_state.show_formula = !lens_state.show_formula,
                 LensButton::Grid => {
                     lens_state.show_grid = !lens_state.show_grid;
-                    if let Ok(grid_handle) = grid_q.single() {
+                    for grid_handle in &grid_q {
                         if let Some(mat) = materials.get_mut(&grid_handle.0) {
                             mat.show_grid = if lens_state.show_grid { 1.0 } else { 0.0 };
                         }
                     }
                 }
+                LensButton::Stripes => {
+                    lens_state.show_stripes = !lens_state.show_stripes;
+                    for grid_handle in &grid_q {
+                        if let Some(mat) = materials.get_mut(&grid_handle.0) {
+                            mat.stripes = if lens_state.show_stripes { 1.0 } else { 0.0 };
+                        }
+                    }
+                }
+                // The range is kept up to date by sync_grid_buffer
+                LensButton::Heatmap => lens_state.show_heatmap = !lens_state.show_heatmap,
+                LensButton::Trace => lens_state.show_trace = !lens_state.show_trace,
             }
         }
     }
@@ -512,6 +1931,13 @@ fn update_lens_button_text(
             LensButton::Position => format!("Pos: {}", if lens_state.show_position { "ON" } else { "OFF" }),
             LensButton::Formula => format!("Formula: {}", if lens_state.show_formula { "ON" } else { "OFF" }),
             LensButton::Grid => format!("Grid: {}", if lens_state.show_grid { "ON" } else { "OFF" }),
+            LensButton::Stripes => format!("Stripes: {}", if lens_state.show_stripes { "ON" } else { "OFF" }),
+            LensButton::Heatmap => match (lens_state.show_heatmap, lens_state.heat_scale.is_auto()) {
+                (false, _) => "Heat: OFF".to_string(),
+                (true, true) => "Heat: AUTO".to_string(),
+                (true, false) => "Heat: FIXED".to_string(),
+            },
+            LensButton::Trace => format!("Trace: {}", if lens_state.show_trace { "ON" } else { "OFF" }),
         };
         for child in children {
             if let Ok(mut text) = text_query.get_mut(*child) {
@@ -523,11 +1949,16 @@ fn update_lens_button_text(
 
 fn handle_camera_buttons(
     interaction_query: Query<(&Interaction, &CameraButton), Changed<Interaction>>,
+    mut split: ResMut<SplitView>,
     mut commands: Commands,
 ) {
     for (interaction, button_type) in &interaction_query {
         if *interaction == Interaction::Pressed {
             let action = match button_type {
+                CameraButton::Split => {
+                    split.enabled = !split.enabled;
+                    continue;
+                }
                 CameraButton::ZoomIn => CameraAction::Zoom(0.8),
                 CameraButton::ZoomOut => CameraAction::Zoom(1.25),
                 CameraButton::PanLeft => CameraAction::Pan(Vec2::new(-100.0, 0.0)),
@@ -535,6 +1966,7 @@ fn handle_camera_buttons(
                 CameraButton::PanUp => CameraAction::Pan(Vec2::new(0.0, 100.0)),
                 CameraButton::PanDown => CameraAction::Pan(Vec2::new(0.0, -100.0)),
                 CameraButton::Reset => CameraAction::Reset,
+                CameraButton::ZoomToFit => CameraAction::ZoomToFit,
             };
             commands.spawn(action);
         }
@@ -582,238 +2014,3285 @@ fn update_tick_button_text(
     }
 }
 
-fn handle_keyboard_input(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut commands: Commands,
+fn handle_undo_buttons(
+    interaction_query: Query<(&Interaction, &UndoButton), Changed<Interaction>>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
 ) {
-    if keyboard.just_pressed(KeyCode::Equal) || keyboard.just_pressed(KeyCode::NumpadAdd) {
-        commands.spawn(CameraAction::Zoom(0.8));
+    if history.is_scrubbing() {
+        return;
     }
-    if keyboard.just_pressed(KeyCode::Minus) || keyboard.just_pressed(KeyCode::NumpadSubtract) {
-        commands.spawn(CameraAction::Zoom(1.25));
+    for (interaction, button_type) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            let changes = match button_type {
+                UndoButton::Undo => undo_stack.undo(&mut grid_state),
+                UndoButton::Redo => undo_stack.redo(&mut grid_state),
+            };
+            cell_changed.write_batch(changes);
+            sync_editor_buffer(&mut editing_state, &grid_state);
+        }
     }
-    // Pan controls...
-    if keyboard.just_pressed(KeyCode::ArrowUp) { commands.spawn(CameraAction::Pan(Vec2::new(0.0, 100.0))); }
-    if keyboard.just_pressed(KeyCode::ArrowDown) { commands.spawn(CameraAction::Pan(Vec2::new(0.0, -100.0))); }
-    if keyboard.just_pressed(KeyCode::ArrowLeft) { commands.spawn(CameraAction::Pan(Vec2::new(-100.0, 0.0))); }
-    if keyboard.just_pressed(KeyCode::ArrowRight) { commands.spawn(CameraAction::Pan(Vec2::new(100.0, 0.0))); }
 }
 
-fn handle_editor_input(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut editing_state: ResMut<EditingState>,
+/// Insert/delete whole rows or columns spanned by the selection
+fn handle_structure_buttons(
+    interaction_query: Query<(&Interaction, &StructureButton), Changed<Interaction>>,
+    mut undo_stack: ResMut<UndoStack>,
     mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+    mut sync_client: ResMut<sync::SyncClient>,
+    journal: Res<Journal>,
 ) {
-    if editing_state.active_cell.is_none() {
+    if history.is_scrubbing() {
         return;
     }
+    for (interaction, button_type) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(bounds) = grid_state.selection_bounds() else { continue };
 
-    if keyboard.just_pressed(KeyCode::Enter) {
-        // Commit
-        if let Some((col, row)) = editing_state.active_cell {
-            grid_state.get_cell_mut_or_create(col, row).set_raw(editing_state.buffer.clone());
-        }
-        return;
-    }
-
-    if keyboard.just_pressed(KeyCode::Backspace) {
-        editing_state.buffer.pop();
-    }
-
-    // Basic key mapping for demo purposes
-    for key in keyboard.get_just_pressed() {
-        let char = match key {
-            KeyCode::KeyA => Some('A'),
-            KeyCode::KeyB => Some('B'),
-            KeyCode::KeyC => Some('C'),
-            KeyCode::KeyD => Some('D'),
-            KeyCode::Digit0 => Some('0'),
-            KeyCode::Digit1 => Some('1'),
-            KeyCode::Digit2 => Some('2'),
-            KeyCode::Digit3 => Some('3'),
-            KeyCode::Digit4 => Some('4'),
-            KeyCode::Digit5 => Some('5'),
-            KeyCode::Digit6 => Some('6'),
-            KeyCode::Digit7 => Some('7'),
-            KeyCode::Digit8 => Some('8'),
-            KeyCode::Digit9 => Some('9'),
-            KeyCode::Space => Some(' '),
-            KeyCode::Equal | KeyCode::NumpadEqual => Some('='),
-            KeyCode::NumpadAdd => Some('+'),
-            KeyCode::Minus | KeyCode::NumpadSubtract => Some('-'),
-            _ => None,
+        let (axis, at, count) = match button_type {
+            StructureButton::InsertRows => (grid_ops::Axis::Row, bounds.min_row, bounds.height()),
+            StructureButton::DeleteRows => (grid_ops::Axis::Row, bounds.min_row, -bounds.height()),
+            StructureButton::InsertColumns => (grid_ops::Axis::Column, bounds.min_col, bounds.width()),
+            StructureButton::DeleteColumns => (grid_ops::Axis::Column, bounds.min_col, -bounds.width()),
         };
-        
-        if let Some(c) = char {
-            editing_state.buffer.push(c);
+
+        let group = grid_ops::shift_sheet(&grid_state, axis, at, count);
+        if group.touches_locked(&grid_state) {
+            continue;
         }
+        if sync_client.is_syncing() {
+            // Other clients get the line edit itself rather than the cells it
+            // moves, and nothing undoes it: the steps before it are dropped,
+            // since they'd land on the wrong lines
+            sync_client.shift_lines(axis, at, count);
+            let entries: Vec<journal::JournalEntry> =
+                group.edits.iter().map(|e| journal::JournalEntry { col: e.col, row: e.row, content: e.after.clone() }).collect();
+            cell_changed.write_batch(journal::replay(&mut grid_state, &entries));
+            journal.append(&entries);
+            undo_stack.clear_history();
+            grid_ops::remap_sheet(&mut grid_state, axis, at, count);
+        } else {
+            // The commit moves headers, widths, rules and the like too, and
+            // undoing it puts them back
+            cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+            grid_ops::remap_selection(&mut grid_state, axis, at, count);
+        }
+        sync_editor_buffer(&mut editing_state, &grid_state);
     }
 }
 
-fn update_editor_display(
-    editing_state: Res<EditingState>,
-    mut query: Query<&mut Text, With<EditorText>>,
+/// Text colors cycled by the "Color" button (None = default black)
+const TEXT_PALETTE: [Option<[u8; 3]>; 4] = [None, Some([0xc6, 0x28, 0x28]), Some([0x15, 0x65, 0xc0]), Some([0x2e, 0x7d, 0x32])];
+
+/// Background colors cycled by the "Fill" button (None = grid background)
+const BACKGROUND_PALETTE: [Option<[u8; 3]>; 4] = [None, Some([0xff, 0xf5, 0x9d]), Some([0xc8, 0xe6, 0xc9]), Some([0xbb, 0xde, 0xfb])];
+
+/// Next entry after `current` in a palette, wrapping around
+fn next_in_palette(palette: &[Option<[u8; 3]>], current: Option<[u8; 3]>) -> Option<[u8; 3]> {
+    let index = palette.iter().position(|c| *c == current).map_or(0, |i| i + 1);
+    palette[index % palette.len()]
+}
+
+/// Toggle sheet protection and keep the button label in sync
+fn handle_protect_button(
+    interaction_query: Query<(&Interaction, &Children), (Changed<Interaction>, With<ProtectButton>)>,
+    mut text_query: Query<&mut Text>,
+    mut grid_state: ResMut<GridState>,
+    history: Res<TickHistory>,
 ) {
-    for mut text in &mut query {
-        if let Some((col, row)) = editing_state.active_cell {
-            **text = format!("({}, {}): {}", col, row, editing_state.buffer);
-        } else {
-            **text = "Select a cell".to_string();
+    if history.is_scrubbing() {
+        return;
+    }
+    for (interaction, children) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        grid_state.protected = !grid_state.protected;
+        for child in children {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                **text = protect_label(grid_state.protected);
+            }
         }
     }
 }
 
-fn apply_camera_actions(
-    mut camera_q: Query<&mut Transform, With<Camera2d>>,
-    actions_q: Query<(Entity, &CameraAction)>,
-    mut commands: Commands,
-) {
-    let Ok(mut camera_transform) = camera_q.single_mut() else { return };
+/// Seconds between autosaves (only sheets changed since the last one are written)
+const AUTOSAVE_INTERVAL: f32 = 10.0;
+
+/// Periodically snapshot the workbook (restarting the journal) so a crash or
+/// tab refresh doesn't lose it
+fn autosave_workbook(
+    grid_state: Res<GridState>,
+    tick_control: Res<TickControl>,
+    journal: Res<Journal>,
+    time: Res<Time>,
+    mut since_save: Local<f32>,
+    mut dirty: Local<bool>,
+) {
+    *dirty |= grid_state.is_changed();
+    *since_save += time.delta_secs();
+    if !*dirty || *since_save < AUTOSAVE_INTERVAL {
+        return;
+    }
+    journal.checkpoint(&grid_state, &tick_control);
+    *since_save = 0.0;
+    *dirty = false;
+}
+
+/// Keep the feed runner in step with the sheet's bound cells, and write the
+/// values that arrived into them
+/// Feed values replace the cell's text (so ticks keep them) without an undo step
+fn update_data_feeds(
+    mut runner: ResMut<feeds::FeedRunner>,
+    mut grid_state: ResMut<GridState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    if grid_state.is_changed() {
+        runner.sync(&grid_state.feeds);
+    }
+    // Updates wait in the runner while history is scrubbed
+    if history.is_scrubbing() {
+        return;
+    }
+    let changes: Vec<CellChanged> = runner.poll().into_iter().filter_map(|update| feeds::apply_update(&mut grid_state, update)).collect();
+    cell_changed.write_batch(changes);
+}
+
+/// Journal each frame's committed edits (and send them to the sync server),
+/// and apply the journal read back at startup over the restored snapshot
+fn journal_edits(
+    mut undo_stack: ResMut<UndoStack>,
+    journal: Res<Journal>,
+    mut sync_client: ResMut<sync::SyncClient>,
+    mut grid_state: ResMut<GridState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+) {
+    if let Some(entries) = journal.take_replay() {
+        cell_changed.write_batch(journal::replay(&mut grid_state, &entries));
+    }
+    if undo_stack.has_applied() {
+        let applied = undo_stack.take_applied();
+        journal.append(&applied);
+        sync_client.send(&applied);
+    }
+}
+
+/// Apply the edits other clients made (see `sync`) between ticks, winning
+/// over this one's where they're newer, and the values a server's ticks
+/// computed
+/// They aren't undoable here, and wait while the history is being scrubbed;
+/// lines they insert or delete (or a whole sheet from the server) drop the
+/// undo history, whose steps would land on the wrong cells
+fn apply_remote_edits(
+    mut sync_client: ResMut<sync::SyncClient>,
+    mut grid_state: ResMut<GridState>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut tick_control: ResMut<TickControl>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    mut editing_state: ResMut<EditingState>,
+    history: Res<TickHistory>,
+) {
+    if history.is_scrubbing() {
+        return;
+    }
+    let edits = sync_client.receive();
+    if edits.is_empty() {
+        return;
+    }
+    for edit in edits {
+        cell_changed.write_batch(sync::apply(&mut grid_state, &edit));
+        match edit {
+            sync::RemoteEdit::Shift { .. } | sync::RemoteEdit::Sheet(_) => undo_stack.clear_history(),
+            sync::RemoteEdit::Values { tick, .. } => tick_control.tick_count = tick,
+            sync::RemoteEdit::Contents(_) => {}
+        }
+    }
+    if !editing_state.editing {
+        sync_editor_buffer(&mut editing_state, &grid_state);
+    }
+}
+
+/// Export the selected range as a PNG, or the cells in view when at most one
+/// cell is selected, drawn the way the current lenses show them
+fn handle_export_button(
+    interaction_query: Query<(&Interaction, &FileButton), Changed<Interaction>>,
+    grid_state: Res<GridState>,
+    lens_state: Res<LensState>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+) {
+    let pressed = interaction_query
+        .iter()
+        .any(|(interaction, button)| *interaction == Interaction::Pressed && matches!(button, FileButton::ExportImage));
+    if !pressed {
+        return;
+    }
+
+    let (cols, rows) = match grid_state.selection_bounds() {
+        Some(range) if grid_state.selected.len() > 1 => export::shown_lines(&grid_state, range),
+        _ => {
+            let Ok((camera, cam_transform)) = camera_q.single() else { return };
+            let Ok(grid_handle) = grid_q.single() else { return };
+            let Some(mat) = materials.get(&grid_handle.0) else { return };
+            let Some(lines) = visible_lines(&grid_state, camera, cam_transform, mat) else { return };
+            lines
+        }
+    };
+    if let Err(e) = sheet_png(&grid_state, &lens_state, &cols, &rows).and_then(export::write_png) {
+        warn!("Export failed: {}", e);
+    }
+}
+
+/// The logical columns and rows wholly on screen, in on-screen order
+fn visible_lines(
+    grid_state: &GridState,
+    camera: &Camera,
+    cam_transform: &GlobalTransform,
+    mat: &SpreadsheetGridMaterial,
+) -> Option<(Vec<i32>, Vec<i32>)> {
+    let rect = camera.logical_viewport_rect()?;
+    let min = camera.viewport_to_world_2d(cam_transform, rect.min).ok()?;
+    let max = camera.viewport_to_world_2d(cam_transform, rect.max).ok()?;
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let (left, right) = (min.x.min(max.x), min.x.max(max.x));
+    let min_col = columns.col_at(left);
+    let min_col = if columns.left(min_col) < left { min_col + 1 } else { min_col };
+    let max_col = columns.col_at(right);
+    let max_col = if columns.left(max_col) + columns.width(max_col) > right { max_col - 1 } else { max_col };
+    let min_row = (-min.y.max(max.y) / mat.cell_size.y).ceil() as i32;
+    let max_row = (-min.y.min(max.y) / mat.cell_size.y).floor() as i32 - 1;
+    let cols = (min_col..=max_col).map(|c| grid_state.layout.cols.to_logical(c)).collect();
+    let rows = (min_row..=max_row).map(|r| grid_state.layout.rows.to_logical(r)).collect();
+    Some((cols, rows))
+}
+
+/// The given columns and rows rendered to a PNG, as Export image saves them
+fn sheet_png(grid_state: &GridState, lens_state: &LensState, cols: &[i32], rows: &[i32]) -> Result<Vec<u8>, String> {
+    let svg = export::sheet_svg(grid_state, cols, rows, |cell, col, row| {
+        let style = grid_state.theme.resolve(cell.style);
+        generate_svg(cell, &style, col, row, lens_state, validation::is_flagged(grid_state, col, row), false).unwrap_or_default()
+    });
+    export::render_png(&svg)
+}
+
+/// Open/close the document picker and run its buttons
+/// The picker is rebuilt whenever the store's listing changes
+fn handle_document_picker(
+    mut commands: Commands,
+    file_q: Query<(&Interaction, &FileButton), Changed<Interaction>>,
+    action_q: Query<(&Interaction, &DocumentAction), Changed<Interaction>>,
+    picker_q: Query<Entity, With<DocumentPicker>>,
+    documents: Res<DocumentStore>,
+    grid_state: Res<GridState>,
+    tick_control: Res<TickControl>,
+) {
+    let toggled = file_q
+        .iter()
+        .any(|(interaction, button)| *interaction == Interaction::Pressed && matches!(button, FileButton::Documents));
+    let mut open = !picker_q.is_empty();
+    if toggled {
+        open = !open;
+        if open {
+            documents.list();
+        }
+    }
+
+    let pressed = action_q
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, action)| action.clone());
+    match pressed {
+        Some(DocumentAction::SaveAs) => {
+            let suggested = documents.current.clone().unwrap_or_else(|| documents.unused_name());
+            if let Some(name) = documents::ask_name(&suggested) {
+                match persist::save_json(&grid_state, &tick_control) {
+                    Ok(json) => documents.save(&name, json),
+                    Err(e) => warn!("Save failed: {}", e),
+                }
+            }
+        }
+        Some(DocumentAction::Open(name)) => {
+            documents.open(&name);
+            open = false;
+        }
+        Some(DocumentAction::Delete(name)) => documents.delete(&name),
+        Some(DocumentAction::Close) => open = false,
+        None => {}
+    }
+
+    let rebuild = toggled || pressed.is_some() || documents.is_changed();
+    if !rebuild {
+        return;
+    }
+    for picker in &picker_q {
+        commands.entity(picker).despawn();
+    }
+    if !open {
+        return;
+    }
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(150.0),
+                top: Val::Px(60.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+            GlobalZIndex(5),
+            DocumentPicker,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node { column_gap: Val::Px(4.0), ..default() })
+                .with_children(|row| {
+                    create_document_button(row, "Save as...", 130.0, DocumentAction::SaveAs);
+                    create_document_button(row, "Close", 60.0, DocumentAction::Close);
+                });
+            if documents.names.is_empty() {
+                parent.spawn((
+                    Text::new("No saved documents"),
+                    TextFont {
+                        font_size: 14.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.7, 0.7, 0.7)),
+                ));
+            }
+            for name in &documents.names {
+                let label = if documents.current.as_ref() == Some(name) {
+                    format!("* {}", name)
+                } else {
+                    name.clone()
+                };
+                parent
+                    .spawn(Node { column_gap: Val::Px(4.0), ..default() })
+                    .with_children(|row| {
+                        create_document_button(row, &label, 160.0, DocumentAction::Open(name.clone()));
+                        create_document_button(row, "x", 30.0, DocumentAction::Delete(name.clone()));
+                    });
+            }
+        });
+}
+
+fn create_document_button(parent: &mut ChildSpawnerCommands, label: &str, width: f32, action: DocumentAction) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(width),
+                height: Val::Px(26.0),
+                padding: UiRect::horizontal(Val::Px(6.0)),
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.2)),
+            action,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+fn protect_label(protected: bool) -> String {
+    format!("Protect: {}", if protected { "ON" } else { "OFF" })
+}
+
+/// Save the workbook, or replace it with the last save, an opened document or
+/// a file dropped on the window (.csv/.tsv files are written in at A0 instead)
+/// Loading starts a fresh session: undo steps and tick history belong to the old sheet
+fn handle_file_buttons(
+    interaction_query: Query<(&Interaction, &FileButton), Changed<Interaction>>,
+    mut dropped: MessageReader<FileDragAndDrop>,
+    mut documents: ResMut<DocumentStore>,
+    protect_q: Query<&Children, With<ProtectButton>>,
+    mut text_query: Query<&mut Text>,
+    mut grid_state: ResMut<GridState>,
+    mut tick_control: ResMut<TickControl>,
+    mut editing_state: ResMut<EditingState>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut history: ResMut<TickHistory>,
+    journal: Res<Journal>,
+    mut cell_changed: MessageWriter<CellChanged>,
+) {
+    // The workbook to switch to, or why it couldn't be read
+    let mut loaded: Option<Result<(GridState, TickControl), String>> = None;
+    for (interaction, button_type) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button_type {
+            FileButton::Save => {
+                let saved = persist::save_json(&grid_state, &tick_control).and_then(persist::write_save);
+                if let Err(e) = saved {
+                    warn!("Save failed: {}", e);
+                }
+            }
+            FileButton::Load => loaded = Some(persist::read_save().and_then(|text| persist::load_json(&text))),
+            // Handled by `handle_document_picker` and `handle_export_button`
+            FileButton::Documents | FileButton::ExportImage => {}
+        }
+    }
+    for event in documents.poll() {
+        match event {
+            DocumentEvent::Listed(names) => documents.names = names,
+            DocumentEvent::Opened { name, json } => {
+                loaded = Some(persist::load_json(&json));
+                documents.current = Some(name);
+            }
+            DocumentEvent::Saved(name) => {
+                documents.current = Some(name);
+                documents.list();
+            }
+            DocumentEvent::Deleted(name) => {
+                if documents.current.as_ref() == Some(&name) {
+                    documents.current = None;
+                }
+                documents.list();
+            }
+            DocumentEvent::Failed(e) => warn!("Document store: {}", e),
+        }
+    }
+    // Dropped files: Bevy reports paths natively, the page hands over contents on wasm
+    let mut files: Vec<(String, Result<Vec<u8>, String>)> = Vec::new();
+    for event in dropped.read() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            let name = path_buf.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            files.push((name, std::fs::read(path_buf).map_err(|e| e.to_string())));
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    files.extend(import::web::take_dropped().into_iter().map(|(name, bytes)| (name, Ok(bytes))));
+    for (name, bytes) in files {
+        match bytes.and_then(|bytes| import::import_file(&name, &bytes)) {
+            Ok(import::Imported::Workbook(grid, ticks)) => loaded = Some(Ok((grid, ticks))),
+            Ok(import::Imported::Rows(rows)) if !history.is_scrubbing() => {
+                let mut group = clipboard::paste_rows(rows, &grid_state, (0, 0));
+                group.label = "Import".to_string();
+                cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+                sync_editor_buffer(&mut editing_state, &grid_state);
+            }
+            Ok(import::Imported::Rows(_)) => {}
+            Err(e) => loaded = Some(Err(e)),
+        }
+    }
+    // Workbooks handed over by the page
+    #[cfg(target_arch = "wasm32")]
+    if let Some(text) = persist::web::take_pending() {
+        loaded = Some(persist::load_json(&text));
+    }
+    if let Some(workbook) = host_api::take_imported() {
+        loaded = Some(Ok(workbook));
+    }
+
+    let (grid, ticks) = match loaded {
+        Some(Ok(loaded)) => loaded,
+        Some(Err(e)) => {
+            warn!("Load failed: {}", e);
+            return;
+        }
+        None => return,
+    };
+    // Functions the page registered stay registered
+    let host_functions = grid_state.host_functions.clone();
+    *grid_state = grid;
+    grid_state.host_functions = host_functions;
+    *tick_control = ticks;
+    *undo_stack = UndoStack::default();
+    *history = TickHistory::default();
+    *editing_state = EditingState::default();
+    // The journal only makes sense over a snapshot of this workbook
+    journal.replace(&grid_state, &tick_control);
+
+    for children in &protect_q {
+        for child in children {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                **text = protect_label(grid_state.protected);
+            }
+        }
+    }
+}
+
+/// Apply formatting to every selected cell as one undo step
+/// Toggles and cycles are based on the active cell (or any selected cell)
+fn handle_format_buttons(
+    interaction_query: Query<(&Interaction, &FormatButton), Changed<Interaction>>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut grid_state: ResMut<GridState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    if history.is_scrubbing() {
+        return;
+    }
+    for (interaction, button_type) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(anchor) = grid_state
+            .active
+            .filter(|c| grid_state.selected.contains(c))
+            .or_else(|| grid_state.selected.iter().min_by_key(|(col, row)| (*row, *col)))
+        else {
+            continue;
+        };
+        let theme = &grid_state.theme;
+        let current = theme.resolve(grid_state.get_cell(anchor.0, anchor.1).map(|c| c.style).unwrap_or_default());
+        let next_named = theme.next_id(current.named);
+
+        let update = |style: &mut CellStyle| match button_type {
+            FormatButton::Bold => style.bold = !current.bold,
+            FormatButton::Italic => style.italic = !current.italic,
+            FormatButton::Align => {
+                style.align = match current.align {
+                    HorizontalAlign::Left => HorizontalAlign::Center,
+                    HorizontalAlign::Center => HorizontalAlign::Right,
+                    HorizontalAlign::Right => HorizontalAlign::Left,
+                }
+            }
+            FormatButton::TextColor => style.text_color = next_in_palette(&TEXT_PALETTE, current.text_color),
+            FormatButton::Background => style.background = next_in_palette(&BACKGROUND_PALETTE, current.background),
+            FormatButton::NumberFormat => style.number_format = current.number_format.next(),
+            FormatButton::Lock => style.locked = !current.locked,
+            // Following a named style replaces the cell's own formatting
+            FormatButton::NamedStyle => {
+                *style = CellStyle { named: next_named, borders: style.borders, locked: style.locked, ..Default::default() }
+            }
+            FormatButton::Clear => *style = CellStyle::default(),
+        };
+
+        let mut selected: Vec<(i32, i32)> = grid_state.selected.iter().collect();
+        selected.sort_by_key(|(col, row)| (*row, *col));
+
+        let mut group = EditGroup::new("Format");
+        for (col, row) in selected {
+            let existing = grid_state.get_cell(col, row);
+            let mut style = existing.map(|c| c.style).unwrap_or_default();
+            // Direct formatting detaches a cell from its named style, starting from what it showed
+            if !matches!(button_type, FormatButton::Lock | FormatButton::NamedStyle) {
+                style = CellStyle { named: None, ..grid_state.theme.resolve(style) };
+            }
+            update(&mut style);
+            // Don't create empty cells just to hold default formatting
+            if existing.is_none() && style == CellStyle::default() {
+                continue;
+            }
+            group.set_style(&grid_state, col, row, style);
+        }
+        cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+    }
+}
+
+/// Draw borders on the selection with the border pen as one undo step, or
+/// change the pen
+/// Side buttons follow the selection's outline, so each selected range gets
+/// its own edge
+fn handle_border_buttons(
+    interaction_query: Query<(&Interaction, &BorderButton), Changed<Interaction>>,
+    mut pen: ResMut<BorderPen>,
+    mut label_q: Query<(&mut Text, &mut TextColor), With<BorderPenLabel>>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut grid_state: ResMut<GridState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            BorderButton::Line => pen.line = pen.line.next(),
+            BorderButton::Color => pen.color = next_in_palette(&TEXT_PALETTE, pen.color),
+            _ => {}
+        }
+        if matches!(button, BorderButton::Line | BorderButton::Color) {
+            if let Ok((mut text, mut color)) = label_q.single_mut() {
+                text.0 = format!("{:?}", pen.line);
+                color.0 = pen.color.map_or(Color::WHITE, |[r, g, b]| Color::srgb_u8(r, g, b));
+            }
+            continue;
+        }
+        if history.is_scrubbing() {
+            continue;
+        }
+
+        let side = match button {
+            BorderButton::Clear => BorderSide::default(),
+            _ => BorderSide { line: pen.line, color: pen.color },
+        };
+        let mut selected: Vec<(i32, i32)> = grid_state.selected.iter().collect();
+        selected.sort_by_key(|(col, row)| (*row, *col));
+
+        let mut group = EditGroup::new("Borders");
+        for &(col, row) in &selected {
+            let outline = gpu_cell::border_edges(|dx, dy| grid_state.selected.contains(&(col + dx, row + dy)));
+            let edges = match button {
+                BorderButton::Top => outline & GpuCell::EDGE_TOP,
+                BorderButton::Right => outline & GpuCell::EDGE_RIGHT,
+                BorderButton::Bottom => outline & GpuCell::EDGE_BOTTOM,
+                BorderButton::Left => outline & GpuCell::EDGE_LEFT,
+                BorderButton::Outline => outline,
+                _ => GpuCell::EDGE_TOP | GpuCell::EDGE_RIGHT | GpuCell::EDGE_BOTTOM | GpuCell::EDGE_LEFT,
+            };
+            if edges == 0 {
+                continue;
+            }
+            let existing = grid_state.get_cell(col, row);
+            let mut style = existing.map(|c| c.style).unwrap_or_default();
+            let borders = &mut style.borders;
+            for (edge, border) in [
+                (GpuCell::EDGE_TOP, &mut borders.top),
+                (GpuCell::EDGE_RIGHT, &mut borders.right),
+                (GpuCell::EDGE_BOTTOM, &mut borders.bottom),
+                (GpuCell::EDGE_LEFT, &mut borders.left),
+            ] {
+                if edges & edge != 0 {
+                    *border = side;
+                }
+            }
+            // Don't create empty cells just to hold default formatting
+            if existing.is_none() && style == CellStyle::default() {
+                continue;
+            }
+            group.set_style(&grid_state, col, row, style);
+        }
+        cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+    }
+}
+
+/// Right-click opens the context menu for the selection (or the clicked cell)
+fn open_context_menu(
+    mut commands: Commands,
+    window_q: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    mut grid_state: ResMut<GridState>,
+    menu_q: Query<Entity, With<ContextMenu>>,
+) {
+    if !mouse_btn.just_pressed(MouseButton::Right) {
+        return;
+    }
+    let Ok(window) = window_q.single() else { return };
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+    let Some(cursor_pos) = window.cursor_position() else { return };
+    let Ok(world_pos) = camera.viewport_to_world_2d(cam_transform, cursor_pos) else { return };
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let world_pos = pane_world_pos(mat, &columns, world_pos);
+
+    for menu in &menu_q {
+        commands.entity(menu).despawn();
+    }
+
+    // Right-clicking outside the selection selects the clicked cell first
+    let (visual_col, visual_row) = world_pos_to_cell(world_pos, mat.cell_size, &columns);
+    let clicked = grid_state.layout.to_logical(visual_col, visual_row);
+    if !grid_state.selected.contains(&clicked) {
+        grid_state.selected.clear();
+        grid_state.selected.insert(clicked);
+    }
+    let Some(target) = grid_state.selection_bounds() else { return };
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(cursor_pos.x),
+                top: Val::Px(cursor_pos.y),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(2.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+            ContextMenu { target },
+        ))
+        .with_children(|parent| {
+            create_context_menu_button(parent, "Hide rows", ContextMenuAction::HideRows);
+            create_context_menu_button(parent, "Hide columns", ContextMenuAction::HideColumns);
+            create_context_menu_button(parent, "Unhide rows", ContextMenuAction::UnhideRows);
+            create_context_menu_button(parent, "Unhide columns", ContextMenuAction::UnhideColumns);
+            create_context_menu_button(parent, "Freeze panes", ContextMenuAction::FreezePanes);
+            create_context_menu_button(parent, "Unfreeze panes", ContextMenuAction::UnfreezePanes);
+            create_context_menu_button(parent, "Filter table", ContextMenuAction::FilterTable);
+            create_context_menu_button(parent, "Remove filter", ContextMenuAction::RemoveFilter);
+            create_context_menu_button(parent, "Format as table", ContextMenuAction::FormatTable);
+            create_context_menu_button(parent, "Clear table format", ContextMenuAction::ClearTableFormat);
+            create_context_menu_button(parent, "Heat scale from selection", ContextMenuAction::FixHeatScale);
+            create_context_menu_button(parent, "Auto heat scale", ContextMenuAction::AutoHeatScale);
+            create_context_menu_button(parent, "Line chart", ContextMenuAction::Chart(chart::ChartKind::Line));
+            create_context_menu_button(parent, "Bar chart", ContextMenuAction::Chart(chart::ChartKind::Bar));
+            create_context_menu_button(parent, "Scatter chart", ContextMenuAction::Chart(chart::ChartKind::Scatter));
+            create_context_menu_button(parent, "Remove charts", ContextMenuAction::RemoveCharts);
+            create_context_menu_button(parent, "Label columns", ContextMenuAction::LabelColumns);
+            create_context_menu_button(parent, "Label rows", ContextMenuAction::LabelRows);
+            create_context_menu_button(parent, "Group rows", ContextMenuAction::GroupRows);
+            create_context_menu_button(parent, "Group columns", ContextMenuAction::GroupColumns);
+            create_context_menu_button(parent, "Ungroup rows", ContextMenuAction::UngroupRows);
+            create_context_menu_button(parent, "Ungroup columns", ContextMenuAction::UngroupColumns);
+            create_context_menu_button(parent, "Copy as Markdown", ContextMenuAction::CopyMarkdown);
+            create_context_menu_button(parent, "Copy as HTML", ContextMenuAction::CopyHtml);
+            create_context_menu_button(parent, "Bind feed", ContextMenuAction::BindFeed);
+            create_context_menu_button(parent, "Unbind feed", ContextMenuAction::UnbindFeed);
+        });
+}
+
+fn create_context_menu_button(parent: &mut ChildSpawnerCommands, label: &str, action: ContextMenuAction) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(130.0),
+                height: Val::Px(26.0),
+                padding: UiRect::horizontal(Val::Px(6.0)),
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.2)),
+            action,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+/// Run the chosen context menu action, closing the menu on any left click
+fn handle_context_menu(
+    mut commands: Commands,
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    action_q: Query<(&Interaction, &ContextMenuAction)>,
+    menu_q: Query<(Entity, &ContextMenu)>,
+    mut grid_state: ResMut<GridState>,
+    mut lens_state: ResMut<LensState>,
+    history: Res<TickHistory>,
+) {
+    let Ok((menu_entity, menu)) = menu_q.single() else { return };
+    if !mouse_btn.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let target = menu.target;
+    // While history is scrubbed only the actions that leave the sheet alone
+    // (the heatmap scale and copying) go through; the rest just close the menu
+    let pressed = action_q
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, action)| *action)
+        .filter(|action| {
+            !history.is_scrubbing()
+                || matches!(
+                    action,
+                    ContextMenuAction::FixHeatScale
+                        | ContextMenuAction::AutoHeatScale
+                        | ContextMenuAction::CopyMarkdown
+                        | ContextMenuAction::CopyHtml
+                )
+        });
+
+    match pressed {
+        // The selection becomes the table; its first row is the header
+        Some(ContextMenuAction::FilterTable) => grid_state.table_filter = Some(filter::TableFilter::new(target)),
+        Some(ContextMenuAction::RemoveFilter) => grid_state.table_filter = None,
+        // Header row plus banded rows, like a filtered table
+        Some(ContextMenuAction::FormatTable) => grid_state.banded = Some(target),
+        Some(ContextMenuAction::ClearTableFormat) => grid_state.banded = None,
+        // Pin the heatmap's scale to the numbers in the selection
+        Some(ContextMenuAction::FixHeatScale) => {
+            let values: Vec<f32> = grid_state
+                .iter_region(target)
+                .filter_map(|(_, cell)| match cell.value {
+                    evalexpr::Value::Int(i) => Some(i as f32),
+                    evalexpr::Value::Float(f) => Some(f as f32),
+                    _ => None,
+                })
+                .collect();
+            let (min, max) = heatmap::HeatScale::default().resolve(values);
+            lens_state.heat_scale = heatmap::HeatScale::fixed(min, max);
+            lens_state.show_heatmap = true;
+        }
+        Some(ContextMenuAction::AutoHeatScale) => lens_state.heat_scale = heatmap::HeatScale::default(),
+        Some(ContextMenuAction::Chart(kind)) => grid_state.charts.push(chart::Chart::new(kind, target)),
+        // Charts plotting or sitting on the selection
+        Some(ContextMenuAction::RemoveCharts) => grid_state
+            .charts
+            .retain(|chart| !chart.source.intersects(target) && !target.contains(chart.anchor.0, chart.anchor.1)),
+        // Name each column after its cell in the selection's first row (blank clears)
+        Some(ContextMenuAction::LabelColumns) => {
+            for col in target.min_col..=target.max_col {
+                let label = filter::display_text(&grid_state, col, target.min_row);
+                grid_state.headers.set_col_label(col, &label);
+            }
+        }
+        // Name each row after its cell in the selection's first column
+        Some(ContextMenuAction::LabelRows) => {
+            for row in target.min_row..=target.max_row {
+                let label = filter::display_text(&grid_state, target.min_col, row);
+                grid_state.headers.set_row_label(row, &label);
+            }
+        }
+        // Shown values as a table for issues, docs and emails
+        Some(ContextMenuAction::CopyMarkdown) => {
+            clipboard::system::set_text(&clipboard::to_markdown(&clipboard::display_rows(&grid_state, target)));
+        }
+        Some(ContextMenuAction::CopyHtml) => {
+            clipboard::system::set_text(&clipboard::to_html(&clipboard::display_rows(&grid_state, target)));
+        }
+        // Each selected cell holding a feed spec (`URL [pointer] [seconds]`) is bound to it
+        Some(ContextMenuAction::BindFeed) => {
+            let bound: Vec<feeds::DataFeed> = target
+                .iter()
+                .filter_map(|(col, row)| feeds::DataFeed::parse(col, row, &grid_state.get_cell(col, row)?.raw))
+                .collect();
+            grid_state.feeds.retain(|f| !bound.iter().any(|b| (b.col, b.row) == (f.col, f.row)));
+            grid_state.feeds.extend(bound);
+        }
+        Some(ContextMenuAction::UnbindFeed) => grid_state.feeds.retain(|f| !target.contains(f.col, f.row)),
+        _ => {}
+    }
+
+    let layout = &mut grid_state.layout;
+    match pressed {
+        Some(ContextMenuAction::HideRows) => (target.min_row..=target.max_row).for_each(|row| layout.rows.hide(row)),
+        Some(ContextMenuAction::HideColumns) => (target.min_col..=target.max_col).for_each(|col| layout.cols.hide(col)),
+        Some(ContextMenuAction::UnhideRows) => unhide_span(&mut layout.rows, target.min_row, target.max_row),
+        Some(ContextMenuAction::UnhideColumns) => unhide_span(&mut layout.cols, target.min_col, target.max_col),
+        Some(ContextMenuAction::FreezePanes) => {
+            // Freeze everything above and left of the selection's top-left cell
+            let (col, row) = layout.to_visual(target.min_col, target.min_row).unwrap_or((0, 0));
+            layout.frozen_cols = col.max(0);
+            layout.frozen_rows = row.max(0);
+        }
+        Some(ContextMenuAction::UnfreezePanes) => {
+            layout.frozen_cols = 0;
+            layout.frozen_rows = 0;
+        }
+        Some(ContextMenuAction::GroupRows) => layout.rows.group(target.min_row, target.max_row),
+        Some(ContextMenuAction::GroupColumns) => layout.cols.group(target.min_col, target.max_col),
+        Some(ContextMenuAction::UngroupRows) => layout.rows.ungroup(target.min_row, target.max_row),
+        Some(ContextMenuAction::UngroupColumns) => layout.cols.ungroup(target.min_col, target.max_col),
+        Some(
+            ContextMenuAction::FilterTable
+            | ContextMenuAction::RemoveFilter
+            | ContextMenuAction::FormatTable
+            | ContextMenuAction::ClearTableFormat
+            | ContextMenuAction::FixHeatScale
+            | ContextMenuAction::AutoHeatScale
+            | ContextMenuAction::Chart(_)
+            | ContextMenuAction::RemoveCharts
+            | ContextMenuAction::LabelColumns
+            | ContextMenuAction::LabelRows
+            | ContextMenuAction::CopyMarkdown
+            | ContextMenuAction::CopyHtml
+            | ContextMenuAction::BindFeed
+            | ContextMenuAction::UnbindFeed,
+        )
+        | None => {}
+    }
+    commands.entity(menu_entity).despawn();
+}
+
+/// Expand or collapse an outline group from its gutter toggle
+fn handle_outline_toggles(
+    interaction_query: Query<(&Interaction, &OutlineToggle), Changed<Interaction>>,
+    mut grid_state: ResMut<GridState>,
+) {
+    for (interaction, toggle) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let lines = match toggle.axis {
+            grid_ops::Axis::Row => &mut grid_state.layout.rows,
+            grid_ops::Axis::Column => &mut grid_state.layout.cols,
+        };
+        lines.toggle_group(toggle.group);
+    }
+}
+
+/// Clicking a column or row name in the gutters selects the whole line, as far
+/// as the used cells or the view reach
+/// Double-clicking a column name's right edge fits the column to its widest
+/// value instead (see `grid_ops::fit_column_width`)
+fn handle_header_clicks(
+    interaction_query: Query<(&Interaction, &HeaderLine, &RelativeCursorPosition, &ComputedNode), Changed<Interaction>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    time: Res<Time>,
+    mut last_edge_click: Local<Option<(i32, f64)>>,
+) {
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+
+    for (interaction, line, cursor, node) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let width = node.size().x * node.inverse_scale_factor();
+        let on_edge = line.axis == grid_ops::Axis::Column
+            && cursor.normalized.is_some_and(|p| (0.5 - p.x) * width <= COLUMN_EDGE_GRAB);
+        if on_edge {
+            let now = time.elapsed_secs_f64();
+            if last_edge_click.is_some_and(|(col, at)| col == line.index && now - at <= DOUBLE_CLICK_TIME) {
+                // Back to the default width when it'd fit anyway, or there's nothing there
+                match grid_ops::fit_column_width(&grid_state, line.index) {
+                    Some(fit) if fit != mat.cell_size.x as u32 => grid_state.layout.col_widths.insert(line.index, fit),
+                    _ => grid_state.layout.col_widths.remove(&line.index),
+                };
+                *last_edge_click = None;
+            } else {
+                *last_edge_click = Some((line.index, now));
+            }
+            continue;
+        }
+        // First and last line of the view (frozen lines show from 0), in
+        // logical lines; the active cell goes on the first
+        let layout = &grid_state.layout;
+        let (view, first) = match line.axis {
+            grid_ops::Axis::Column => {
+                let top = mat.viewport_bottom_left.y + mat.viewport_size.y;
+                let first = if layout.frozen_rows > 0 { 0 } else { (-top / mat.cell_size.y).ceil() as i32 };
+                let bottom = (-mat.viewport_bottom_left.y / mat.cell_size.y).ceil() as i32;
+                let first = layout.rows.to_logical(first);
+                ((first, layout.rows.to_logical(bottom)), (line.index, first))
+            }
+            grid_ops::Axis::Row => {
+                let columns = layout.column_offsets(mat.cell_size.x);
+                let left = mat.viewport_bottom_left.x;
+                let partial = columns.col_at(left);
+                let first = if layout.frozen_cols > 0 {
+                    0
+                } else if columns.left(partial) < left {
+                    partial + 1
+                } else {
+                    partial
+                };
+                let right = columns.col_at(left + mat.viewport_size.x) + 1;
+                let first = layout.cols.to_logical(first);
+                ((first, layout.cols.to_logical(right)), (first, line.index))
+            }
+        };
+        grid_state.select_line(line.axis, line.index, view);
+        grid_state.active = Some(first);
+        sync_editor_buffer(&mut editing_state, &grid_state);
+    }
+}
+
+/// Frame the used cells and the view on the minimap
+fn update_minimap(
+    grid_state: Res<GridState>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut used_q: Query<(&mut Node, &mut Visibility), (With<MinimapUsed>, Without<MinimapView>)>,
+    mut view_q: Query<&mut Node, (With<MinimapView>, Without<MinimapUsed>)>,
+    mut used: Local<Option<Rect>>,
+) {
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+    // Scanning every cell is only worth it when they've changed
+    if grid_state.is_changed() {
+        *used = minimap::used_world_rect(&grid_state, mat.cell_size);
+    }
+    let view = Rect::from_corners(mat.viewport_bottom_left, mat.viewport_bottom_left + mat.viewport_size);
+    let frame = minimap::MinimapFrame::new(*used, view, MINIMAP_SIZE.x / MINIMAP_SIZE.y);
+
+    let place = |node: &mut Node, rect: Rect| {
+        node.left = Val::Percent(rect.min.x * 100.0);
+        node.top = Val::Percent(rect.min.y * 100.0);
+        node.width = Val::Percent(rect.width() * 100.0);
+        node.height = Val::Percent(rect.height() * 100.0);
+    };
+    if let Ok((mut node, mut visibility)) = used_q.single_mut() {
+        match *used {
+            Some(rect) => {
+                place(&mut node, frame.to_map(rect));
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+    if let Ok(mut node) = view_q.single_mut() {
+        place(&mut node, frame.to_map(view));
+    }
+}
+
+/// Keep a sprite per chart, redrawn when its data or placement changes
+/// Charts live in world space, so they pan and zoom with the grid
+/// Rendering goes through the `SvgRenderer` thread: a chart keeps showing its
+/// last image until the new one is ready
+fn update_charts(
+    mut commands: Commands,
+    grid_state: Res<GridState>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut svg_renderer: ResMut<SvgRenderer>,
+    // Per chart: the image it should show (by render hash, with its SVG) and where
+    mut wanted: Local<Vec<Option<(u64, String, Rect)>>>,
+    // Per chart: the image its sprite shows and where
+    mut shown: Local<Vec<Option<(u64, Rect, Entity)>>>,
+    // Renders asked for and not yet shown, dropped from the cache once superseded
+    mut requested: Local<std::collections::HashSet<u64>>,
+) {
+    if grid_state.is_changed() {
+        let Ok(grid_handle) = grid_q.single() else { return };
+        let Some(mat) = materials.get(&grid_handle.0) else { return };
+
+        // Charts anchored on a hidden line are hidden with it
+        let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+        *wanted = grid_state
+            .charts
+            .iter()
+            .map(|chart| {
+                let (col, row) = grid_state.layout.to_visual(chart.anchor.0, chart.anchor.1)?;
+                let left = columns.left(col);
+                let size = Vec2::new(columns.left(col + chart.size.0) - left, chart.size.1 as f32 * mat.cell_size.y);
+                if size.x < 1.0 || size.y < 1.0 {
+                    return None;
+                }
+                let top_left = Vec2::new(left, -row as f32 * mat.cell_size.y);
+                let rect = Rect::from_corners(top_left, top_left + Vec2::new(size.x, -size.y));
+                let svg = chart.to_svg(&grid_state, size.x, size.y);
+                let hash = seahash::hash(format!("{}x{}:{}", size.x, size.y, svg).as_bytes());
+                Some((hash, svg, rect))
+            })
+            .collect();
+    }
+
+    // Ask for what's missing, one render in flight per chart
+    for (index, (hash, svg, rect)) in wanted.iter().enumerate().filter_map(|(i, w)| Some((i, w.as_ref()?))) {
+        let target = RenderTarget::Chart(index);
+        if svg_renderer.is_cached(*hash) || svg_renderer.is_pending(target) {
+            continue;
+        }
+        let size = rect.size();
+        svg_renderer.request_render(SvgRenderRequest {
+            target,
+            svg: svg.clone(),
+            width: (size.x * CHART_SCALE) as u32,
+            height: (size.y * CHART_SCALE) as u32,
+            content_hash: *hash,
+        });
+        requested.insert(*hash);
+    }
+
+    // Swap in images that are ready
+    for (index, target) in wanted.iter().enumerate() {
+        if shown.len() <= index {
+            shown.push(None);
+        }
+        let current = shown[index];
+        let Some((hash, _, rect)) = target else {
+            if let Some((old, _, entity)) = current {
+                commands.entity(entity).despawn();
+                svg_renderer.pixel_cache.remove(&old);
+                shown[index] = None;
+            }
+            continue;
+        };
+        if current.is_some_and(|(h, r, _)| h == *hash && r == *rect) {
+            continue;
+        }
+        // Above the grid, later charts over earlier ones
+        let transform = Transform::from_translation(rect.center().extend(-50.0 + index as f32 * 0.01));
+        match current {
+            // Moved, same image
+            Some((h, _, entity)) if h == *hash => {
+                commands.entity(entity).insert(transform);
+            }
+            _ => {
+                let Some(pixels) = svg_renderer.pixel_cache.get(hash) else { continue };
+                let size = rect.size();
+                let image = images.add(Image::new(
+                    Extent3d { width: (size.x * CHART_SCALE) as u32, height: (size.y * CHART_SCALE) as u32, depth_or_array_layers: 1 },
+                    TextureDimension::D2,
+                    pixels.clone(),
+                    TextureFormat::Rgba8UnormSrgb,
+                    RenderAssetUsages::RENDER_WORLD,
+                ));
+                let sprite = Sprite { image, custom_size: Some(size), ..default() };
+                let entity = match current {
+                    Some((_, _, entity)) => commands.entity(entity).insert((sprite, transform)).id(),
+                    None => commands.spawn((ChartSprite, sprite, transform)).id(),
+                };
+                requested.remove(hash);
+                if let Some((old, _, _)) = current {
+                    svg_renderer.pixel_cache.remove(&old);
+                }
+                shown[index] = Some((*hash, *rect, entity));
+            }
+        }
+    }
+    // Deleted charts
+    for (hash, _, entity) in shown.drain(wanted.len()..).flatten() {
+        commands.entity(entity).despawn();
+        svg_renderer.pixel_cache.remove(&hash);
+    }
+
+    // Renders overtaken by newer ones before they were shown
+    let wanted_hashes: std::collections::HashSet<u64> = wanted.iter().flatten().map(|(hash, _, _)| *hash).collect();
+    requested.retain(|hash| {
+        let stale = !wanted_hashes.contains(hash) && svg_renderer.is_cached(*hash);
+        if stale {
+            svg_renderer.pixel_cache.remove(hash);
+        }
+        !stale
+    });
+}
+
+/// Trace lens: arrows into the active cell from the cells it reads (blue), and
+/// out of it to the formulas reading it (orange)
+fn draw_trace_arrows(
+    mut gizmos: Gizmos,
+    grid_state: Res<GridState>,
+    lens_state: Res<LensState>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut graph: Local<dependencies::DependencyGraph>,
+) {
+    if !lens_state.show_trace {
+        return;
+    }
+    // The graph goes stale while tracing is off, so rebuild when it comes on too
+    if grid_state.is_changed() || lens_state.is_changed() {
+        *graph = dependencies::DependencyGraph::build(&grid_state);
+    }
+    let Some((col, row)) = grid_state.active else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+
+    // Cells on hidden lines have nowhere to point
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let center = |col: i32, row: i32| {
+        let (col, row) = grid_state.layout.to_visual(col, row)?;
+        Some(Vec2::new(columns.left(col) + columns.width(col) / 2.0, -(row as f32 + 0.5) * mat.cell_size.y))
+    };
+    let Some(active) = center(col, row) else { return };
+
+    let precedent_color = Color::srgb(0.1, 0.35, 0.85);
+    for &(from_col, from_row) in graph.precedents(col, row) {
+        let Some(from) = center(from_col, from_row) else { continue };
+        gizmos.circle_2d(from, 4.0, precedent_color);
+        gizmos.arrow_2d(from, active, precedent_color);
+    }
+    let dependent_color = Color::srgb(0.9, 0.45, 0.1);
+    for &(to_col, to_row) in graph.dependents(col, row) {
+        let Some(to) = center(to_col, to_row) else { continue };
+        gizmos.arrow_2d(active, to, dependent_color);
+    }
+}
+
+/// Tell the other clients (see `sync`) where the active cell and selection
+/// are: when they move, and every `sync::PRESENCE_INTERVAL` seconds anyway
+fn share_presence(
+    time: Res<Time>,
+    grid_state: Res<GridState>,
+    mut sync_client: ResMut<sync::SyncClient>,
+    mut shared: Local<Option<(Option<(i32, i32)>, Vec<grid_state::CellRange>)>>,
+    mut since: Local<f32>,
+) {
+    if !sync_client.is_syncing() {
+        return;
+    }
+    *since += time.delta_secs();
+    let place = (grid_state.active, grid_state.selected.ranges().to_vec());
+    if shared.as_ref() == Some(&place) && *since < sync::PRESENCE_INTERVAL {
+        return;
+    }
+    sync_client.share_presence(place.0, &place.1);
+    *shared = Some(place);
+    *since = 0.0;
+}
+
+/// Outline the other clients' selections (thin) and active cells (thick) in
+/// their colors, with their name tagged on top of each active cell
+fn draw_presence(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    grid_state: Res<GridState>,
+    sync_client: Res<sync::SyncClient>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut tag_q: Query<(Entity, &PresenceTag, &mut Node)>,
+) {
+    let peers = if sync_client.is_syncing() { sync_client.peers() } else { Vec::new() };
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Some(mat) = grid_q.single().ok().and_then(|handle| materials.get(&handle.0)) else { return };
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    // Cells on hidden lines aren't drawn
+    let world_rect = |(col, row): (i32, i32)| {
+        let (col, row) = grid_state.layout.to_visual(col, row)?;
+        Some(cell_world_rect(mat, &columns, col, row))
+    };
+    let origin = camera.logical_viewport_rect().map_or(Vec2::ZERO, |rect| rect.min);
+
+    // Where each tag goes on screen: just above its cell's top left corner
+    let mut tags: Vec<(&sync::Peer, Vec2)> = Vec::new();
+    for peer in &peers {
+        let color = presence_color(peer.client);
+        for range in &peer.ranges {
+            let corners = world_rect((range.min_col, range.min_row)).zip(world_rect((range.max_col, range.max_row)));
+            if let Some((first, last)) = corners {
+                let rect = first.union(last);
+                gizmos.rect_2d(rect.center(), rect.size(), color.with_alpha(0.7));
+            }
+        }
+        let Some(rect) = peer.active.and_then(world_rect) else { continue };
+        gizmos.rect_2d(rect.center(), rect.size(), color);
+        gizmos.rect_2d(rect.center(), rect.size() - Vec2::splat(2.0), color);
+        if let Ok(corner) = camera.world_to_viewport(cam_transform, Vec2::new(rect.min.x, rect.max.y).extend(0.0)) {
+            tags.push((peer, corner + origin - Vec2::new(0.0, 16.0)));
+        }
+    }
+
+    for (entity, tag, mut node) in &mut tag_q {
+        match tags.iter().position(|(peer, _)| peer.client == tag.client && peer.name == tag.name) {
+            Some(index) => {
+                let (_, at) = tags.swap_remove(index);
+                node.left = Val::Px(at.x);
+                node.top = Val::Px(at.y);
+            }
+            None => commands.entity(entity).despawn(),
+        }
+    }
+    for (peer, at) in tags {
+        commands
+            .spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(at.x),
+                    top: Val::Px(at.y),
+                    padding: UiRect::axes(Val::Px(4.0), Val::Px(1.0)),
+                    ..default()
+                },
+                BackgroundColor(presence_color(peer.client)),
+                GlobalZIndex(5),
+                PresenceTag { client: peer.client, name: peer.name.clone() },
+            ))
+            .with_child((
+                Text::new(peer.name.clone()),
+                TextFont {
+                    font_size: 11.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+    }
+}
+
+/// Clicking (or dragging) on the minimap centers the view on that spot
+fn handle_minimap_clicks(
+    mut commands: Commands,
+    window_q: Query<&Window>,
+    minimap_q: Query<&Interaction, With<Minimap>>,
+    grid_state: Res<GridState>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+) {
+    let Ok(interaction) = minimap_q.single() else { return };
+    if *interaction != Interaction::Pressed {
+        return;
+    }
+    let Ok(window) = window_q.single() else { return };
+    let Some(cursor) = window.cursor_position() else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+
+    // The minimap is pinned to the bottom-right corner
+    let top_left = Vec2::new(window.width(), window.height()) - MINIMAP_MARGIN - MINIMAP_SIZE;
+    let at = ((cursor - top_left) / MINIMAP_SIZE).clamp(Vec2::ZERO, Vec2::ONE);
+    let view = Rect::from_corners(mat.viewport_bottom_left, mat.viewport_bottom_left + mat.viewport_size);
+    let used = minimap::used_world_rect(&grid_state, mat.cell_size);
+    let frame = minimap::MinimapFrame::new(used, view, MINIMAP_SIZE.x / MINIMAP_SIZE.y);
+    commands.spawn(CameraAction::CenterOn(frame.to_world(at)));
+}
+
+/// Clicking the checkbox of a boolean cell flips it (as a normal, undoable edit)
+/// Only literal `true`/`false` entries toggle; formula results are left to the formula
+fn toggle_checkbox_cells(
+    window_q: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    if !mouse_btn.just_pressed(MouseButton::Left) || history.is_scrubbing() {
+        return;
+    }
+    let Ok(window) = window_q.single() else { return };
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+    let Some(cursor_pos) = window.cursor_position() else { return };
+    let Ok(world_pos) = camera.viewport_to_world_2d(cam_transform, cursor_pos) else { return };
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let world_pos = pane_world_pos(mat, &columns, world_pos);
+
+    let (visual_col, visual_row) = world_pos_to_cell(world_pos, mat.cell_size, &columns);
+    let (col, row) = grid_state.layout.to_logical(visual_col, visual_row);
+    let Some(cell) = grid_state.get_cell(col, row) else { return };
+    let evalexpr::Value::Boolean(checked) = cell.value else { return };
+    if cell.is_formula {
+        return;
+    }
+
+    // Only the glyph itself is clickable, so the rest of the cell still just selects it
+    let x_in_cell = world_pos.x - columns.left(visual_col);
+    let left = checkbox_left(grid_state.theme.resolve(cell.style).align);
+    if !(left..=left + CHECKBOX_SIZE).contains(&x_in_cell) {
+        return;
+    }
+
+    let mut group = EditGroup::new("Toggle");
+    group.set_raw(&grid_state, col, row, (!checked).to_string());
+    cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+    sync_editor_buffer(&mut editing_state, &grid_state);
+}
+
+/// Show the hovered cell's full formula, value and error once the cursor rests on it
+/// (cells only have room for a truncated value)
+fn update_cell_tooltip(
+    mut commands: Commands,
+    window_q: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    tooltip_q: Query<Entity, With<CellTooltip>>,
+    grid_state: Res<GridState>,
+    time: Res<Time>,
+    mut hover: Local<HoverState>,
+) {
+    let Ok(window) = window_q.single() else { return };
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let cursor_pos = window.cursor_position();
+    let hovered = cursor_pos
+        .and_then(|pos| camera.viewport_to_world_2d(cam_transform, pos).ok())
+        .map(|world_pos| {
+            let (visual_col, visual_row) = world_pos_to_cell(pane_world_pos(mat, &columns, world_pos), mat.cell_size, &columns);
+            grid_state.layout.to_logical(visual_col, visual_row)
+        });
+
+    let now = time.elapsed_secs();
+    if hovered != hover.cell {
+        *hover = HoverState { cell: hovered, since: now, shown: false };
+    } else if hover.shown && grid_state.is_changed() {
+        // Refresh the contents (e.g. the value changed on a tick)
+        hover.shown = false;
+    }
+    if hover.shown {
+        return;
+    }
+    for tooltip in &tooltip_q {
+        commands.entity(tooltip).despawn();
+    }
+
+    let (Some(cursor_pos), Some((col, row))) = (cursor_pos, hover.cell) else { return };
+    if now - hover.since < TOOLTIP_DELAY {
+        return;
+    }
+    hover.shown = true;
+    let Some(text) = tooltip_text(&grid_state, col, row) else { return };
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(cursor_pos.x + 12.0),
+                top: Val::Px(cursor_pos.y + 16.0),
+                max_width: Val::Px(360.0),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.92)),
+            GlobalZIndex(10),
+            CellTooltip,
+        ))
+        .with_child((
+            Text::new(text),
+            TextFont {
+                font_size: 13.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+/// Tooltip contents for a cell: its name, raw formula, displayed value and any
+/// error; None for empty cells
+fn tooltip_text(grid_state: &GridState, col: i32, row: i32) -> Option<String> {
+    let cell = grid_state.get_cell(col, row).filter(|c| !c.raw.is_empty())?;
+    let mut lines = vec![crate::formula::coord_to_name(col, row)];
+    if cell.is_formula {
+        lines.push(cell.raw.clone());
+    }
+    if let Some(summary) = error_summary(grid_state, col, row) {
+        lines.push(summary);
+    } else {
+        let style = grid_state.theme.resolve(cell.style);
+        lines.push(format!("Value: {}", style.number_format.format_value(&cell.value)));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Code and reason for a cell in error: "#NAME?: unknown column [Qty]"
+fn error_summary(grid_state: &GridState, col: i32, row: i32) -> Option<String> {
+    let cell = grid_state.get_cell(col, row).filter(|c| c.error)?;
+    let reason = evaluator::formula_error(grid_state, col, row).unwrap_or_else(|| "evaluation failed".to_string());
+    Some(format!("{}: {}", cell.error_code.text(), reason))
+}
+
+/// Re-evaluate the table filter so rows follow value changes from edits and ticks
+fn apply_table_filter(mut grid_state: ResMut<GridState>) {
+    let filtered = grid_state
+        .table_filter
+        .as_ref()
+        .map(|f| f.filtered_rows(&grid_state))
+        .unwrap_or_default();
+    // Only touch the grid when the set changes, so change detection stays quiet
+    if *grid_state.layout.rows.filtered() != filtered {
+        grid_state.layout.rows.set_filtered(filtered);
+    }
+}
+
+/// Clicking the arrow on a filtered table's header cell opens its value list
+fn open_filter_dropdown(
+    mut commands: Commands,
+    window_q: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    grid_state: Res<GridState>,
+) {
+    if !mouse_btn.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(table) = &grid_state.table_filter else { return };
+    let Ok(window) = window_q.single() else { return };
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+    let Some((cursor_pos, (col, row))) = dropdown_arrow_under_cursor(window, camera, cam_transform, mat, &grid_state) else {
+        return;
+    };
+    if !table.is_header(col, row) {
+        return;
+    }
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(cursor_pos.x),
+                top: Val::Px(cursor_pos.y + 10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(2.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+            FilterDropdown,
+        ))
+        .with_children(|parent| {
+            create_filter_option(parent, "(Show all)".to_string(), FilterOption { col, value: None });
+            for value in table.column_values(&grid_state, col) {
+                let check = if table.allows_value(col, &value) { "[x]" } else { "[ ]" };
+                let label = format!("{} {}", check, if value.is_empty() { "(blank)" } else { &value });
+                create_filter_option(parent, label, FilterOption { col, value: Some(value) });
+            }
+        });
+}
+
+/// The cell whose dropdown arrow (rightmost 16 units of the cell) is under the
+/// cursor, along with the cursor's screen position
+fn dropdown_arrow_under_cursor(
+    window: &Window,
+    camera: &Camera,
+    cam_transform: &GlobalTransform,
+    mat: &SpreadsheetGridMaterial,
+    grid_state: &GridState,
+) -> Option<(Vec2, (i32, i32))> {
+    let cursor_pos = window.cursor_position()?;
+    let world_pos = camera.viewport_to_world_2d(cam_transform, cursor_pos).ok()?;
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let world_pos = pane_world_pos(mat, &columns, world_pos);
+
+    let (visual_col, visual_row) = world_pos_to_cell(world_pos, mat.cell_size, &columns);
+    let x_in_cell = world_pos.x - columns.left(visual_col);
+    if x_in_cell < columns.width(visual_col) - 16.0 {
+        return None;
+    }
+    Some((cursor_pos, grid_state.layout.to_logical(visual_col, visual_row)))
+}
+
+/// Clicking the arrow on a list-validated cell opens a picker of allowed values
+fn open_validation_picker(
+    mut commands: Commands,
+    window_q: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    grid_state: Res<GridState>,
+) {
+    if !mouse_btn.just_pressed(MouseButton::Left) || grid_state.validations.is_empty() {
+        return;
+    }
+    let Ok(window) = window_q.single() else { return };
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
+    let Some((cursor_pos, (col, row))) = dropdown_arrow_under_cursor(window, camera, cam_transform, mat, &grid_state) else {
+        return;
+    };
+    // Filter headers have their own dropdown
+    if grid_state.table_filter.as_ref().is_some_and(|t| t.is_header(col, row)) {
+        return;
+    }
+    let Some(choices) = validation::validation_at(&grid_state, col, row).and_then(|v| v.choices()) else {
+        return;
+    };
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(cursor_pos.x),
+                top: Val::Px(cursor_pos.y + 10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(2.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+            ValidationPicker,
+        ))
+        .with_children(|parent| {
+            for value in choices {
+                create_picker_option(parent, PickerOption { col, row, value: value.clone() });
+            }
+        });
+}
+
+fn create_picker_option(parent: &mut ChildSpawnerCommands, option: PickerOption) {
+    let label = option.value.clone();
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(130.0),
+                height: Val::Px(24.0),
+                padding: UiRect::horizontal(Val::Px(6.0)),
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.2)),
+            option,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+/// Commit the picked value as a normal (undoable) edit, closing the picker on any left click
+fn handle_validation_picker(
+    mut commands: Commands,
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    option_q: Query<(&Interaction, &PickerOption)>,
+    picker_q: Query<Entity, With<ValidationPicker>>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    let Ok(picker) = picker_q.single() else { return };
+    if !mouse_btn.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let picked = option_q.iter().find(|(interaction, _)| **interaction == Interaction::Pressed);
+    if let Some((_, option)) = picked.filter(|_| !history.is_scrubbing()) {
+        let mut group = EditGroup::new("Edit");
+        group.set_raw(&grid_state, option.col, option.row, option.value.clone());
+        cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+        sync_editor_buffer(&mut editing_state, &grid_state);
+    }
+    commands.entity(picker).despawn();
+}
+
+fn create_filter_option(parent: &mut ChildSpawnerCommands, label: String, option: FilterOption) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(130.0),
+                height: Val::Px(24.0),
+                padding: UiRect::horizontal(Val::Px(6.0)),
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.2)),
+            option,
+        ))
+        .with_child((
+            Text::new(label),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+/// Apply the picked filter option, closing the dropdown on any left click
+fn handle_filter_dropdown(
+    mut commands: Commands,
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    option_q: Query<(&Interaction, &FilterOption)>,
+    dropdown_q: Query<Entity, With<FilterDropdown>>,
+    mut grid_state: ResMut<GridState>,
+) {
+    let Ok(dropdown) = dropdown_q.single() else { return };
+    if !mouse_btn.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let picked = option_q.iter().find(|(interaction, _)| **interaction == Interaction::Pressed);
+    if let (Some((_, option)), Some(mut table)) = (picked, grid_state.table_filter.clone()) {
+        match &option.value {
+            Some(value) => table.toggle_value(&grid_state, option.col, value),
+            None => {
+                table.criteria.remove(&option.col);
+            }
+        }
+        grid_state.table_filter = Some(table);
+    }
+    commands.entity(dropdown).despawn();
+}
+
+/// Unhide lines inside `min..=max` plus the hidden block directly before `min`
+/// (the one whose marker sits on the selection's edge)
+fn unhide_span(lines: &mut layout::HiddenLines, min: i32, max: i32) {
+    let mut start = min;
+    while lines.is_hidden(start - 1) {
+        start -= 1;
+    }
+    for index in start..=max {
+        lines.unhide(index);
+    }
+}
+
+fn handle_undo_shortcuts(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    // Mid-edit the typed text would be lost to the undo
+    if !ctrl_pressed(&keyboard) || !keyboard.just_pressed(KeyCode::KeyZ) || history.is_scrubbing() || editing_state.editing {
+        return;
+    }
+
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let changes = if shift {
+        undo_stack.redo(&mut grid_state)
+    } else {
+        undo_stack.undo(&mut grid_state)
+    };
+    cell_changed.write_batch(changes);
+    sync_editor_buffer(&mut editing_state, &grid_state);
+}
+
+fn handle_clipboard_shortcuts(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut clipboard: ResMut<Clipboard>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    // Keys go to the name box while it has focus
+    if editing_state.name_box.is_some() {
+        return;
+    }
+
+    let ctrl = ctrl_pressed(&keyboard);
+    let copy = ctrl && keyboard.just_pressed(KeyCode::KeyC);
+    let cut = ctrl && keyboard.just_pressed(KeyCode::KeyX);
+    // In edit mode they copy and cut the editor's selected text instead
+    if editing_state.editing && (copy || cut) {
+        let buffer = &mut editing_state.buffer;
+        if let Some(range) = buffer.selection() {
+            clipboard::system::set_text(&buffer.text()[range]);
+            if cut {
+                buffer.insert("");
+            }
+        }
+    } else if copy || cut {
+        if let Some(contents) = clipboard::copy_selection(&grid_state) {
+            let tsv = clipboard::to_tsv(&contents);
+            clipboard::system::set_cells(&tsv, &clipboard::to_clipboard_html(&contents));
+            clipboard.exported = Some(tsv);
+            clipboard.contents = Some(contents);
+            clipboard.is_cut = cut;
+        }
+    }
+
+    // The system clipboard's text and HTML
+    // (on wasm reading is async, so Ctrl+V starts a read a later frame picks up)
+    #[cfg(not(target_arch = "wasm32"))]
+    let pasted = (ctrl && keyboard.just_pressed(KeyCode::KeyV))
+        .then(|| (clipboard::system::get_text(), clipboard::system::get_html()));
+    #[cfg(target_arch = "wasm32")]
+    if ctrl && keyboard.just_pressed(KeyCode::KeyV) {
+        clipboard::system::request_paste();
+    }
+    #[cfg(target_arch = "wasm32")]
+    let pasted = clipboard::system::take_pasted().map(|(text, html)| (Some(text), html));
+
+    // In edit mode pasting types the clipboard's text at the caret
+    if editing_state.editing {
+        if let Some((Some(text), _)) = pasted {
+            editing_state.buffer.insert(&text);
+        }
+        return;
+    }
+
+    if let Some((text, html)) = pasted.filter(|_| !history.is_scrubbing()) {
+        let Some(target) = grid_state.active else { return };
+        // Cells copied from a sheet (this one or another window's) come back
+        // whole; anything else is text, split into cells as TSV
+        let cells = html.as_deref().and_then(clipboard::from_clipboard_html);
+        let ours = match &cells {
+            Some(cells) => clipboard.contents.as_ref() == Some(cells),
+            None => text.is_some() && text == clipboard.exported,
+        };
+        if !ours {
+            let group = match (cells, text) {
+                (Some(cells), _) => clipboard::paste(&cells, &grid_state, target),
+                (None, Some(text)) => clipboard::paste_text(&text, &grid_state, target),
+                (None, None) => return,
+            };
+            cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+            sync_editor_buffer(&mut editing_state, &grid_state);
+            return;
+        }
+        let Some(contents) = &clipboard.contents else { return };
+
+        if clipboard.is_cut {
+            // Pasting a cut moves the cells (once), fixing up references to them
+            let (dx, dy) = (target.0 - contents.origin.0, target.1 - contents.origin.1);
+            let sources: Vec<(i32, i32)> = contents
+                .cells
+                .iter()
+                .map(|c| (contents.origin.0 + c.dx, contents.origin.1 + c.dy))
+                .collect();
+            let group = clipboard::move_cells(&grid_state, &sources, dx, dy);
+            cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+            clipboard.contents = None;
+            clipboard.is_cut = false;
+        } else {
+            let group = clipboard::paste(contents, &grid_state, target);
+            cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+        }
+        sync_editor_buffer(&mut editing_state, &grid_state);
+    }
+}
+
+fn handle_fill_shortcuts(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    history: Res<TickHistory>,
+) {
+    // Mid-edit the typed text would be lost to the fill
+    if !ctrl_pressed(&keyboard) || history.is_scrubbing() || editing_state.editing {
+        return;
+    }
+
+    let direction = if keyboard.just_pressed(KeyCode::KeyD) {
+        grid_ops::FillDirection::Down
+    } else if keyboard.just_pressed(KeyCode::KeyR) {
+        grid_ops::FillDirection::Right
+    } else {
+        return;
+    };
+
+    if let Some(group) = grid_ops::fill_selection(&grid_state, direction) {
+        cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+        sync_editor_buffer(&mut editing_state, &grid_state);
+    }
+}
+
+/// True while Ctrl (or Cmd on macOS) is held
+fn ctrl_pressed(keyboard: &ButtonInput<KeyCode>) -> bool {
+    keyboard.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ])
+}
+
+/// True while either Alt (Option on macOS) is held
+fn alt_pressed(keyboard: &ButtonInput<KeyCode>) -> bool {
+    keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight])
+}
+
+/// Refresh the edit buffer after the active cell, or its contents, changed
+fn sync_editor_buffer(editing_state: &mut EditingState, grid_state: &GridState) {
+    if let Some((col, row)) = grid_state.active {
+        editing_state.buffer.set(grid_state.get_cell(col, row).map(|c| c.raw.clone()).unwrap_or_default());
+    }
+    editing_state.editing = false;
+}
+
+fn handle_history_buttons(
+    interaction_query: Query<(&Interaction, &HistoryButton), Changed<Interaction>>,
+    mut history: ResMut<TickHistory>,
+    mut grid_state: ResMut<GridState>,
+    mut tick_control: ResMut<TickControl>,
+) {
+    for (interaction, button_type) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            match button_type {
+                HistoryButton::Oldest => history.scrub_to(0, &mut grid_state),
+                HistoryButton::Back => history.step(-1, &mut grid_state),
+                HistoryButton::Forward => history.step(1, &mut grid_state),
+                HistoryButton::Resume => {
+                    if let Some(tick) = history.resume() {
+                        tick_control.tick_count = tick;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn update_history_text(
+    history: Res<TickHistory>,
+    mut query: Query<&mut Text, With<HistoryText>>,
+) {
+    if !history.is_changed() {
+        return;
+    }
+    for mut text in &mut query {
+        **text = match (history.cursor(), history.current_tick()) {
+            (Some(index), Some(tick)) => format!("Tick {} ({}/{}) - edits paused", tick, index + 1, history.len()),
+            _ => format!("Live ({} ticks recorded)", history.len()),
+        };
+    }
+}
+
+fn handle_keyboard_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    grid_state: Res<GridState>,
+    editing_state: Res<EditingState>,
+    mut commands: Commands,
+) {
+    if editing_state.toolbar_focus.is_some() {
+        return;
+    }
+    if keyboard.just_pressed(KeyCode::Equal) || keyboard.just_pressed(KeyCode::NumpadAdd) {
+        commands.spawn(CameraAction::Zoom(0.8));
+    }
+    if keyboard.just_pressed(KeyCode::Minus) || keyboard.just_pressed(KeyCode::NumpadSubtract) {
+        commands.spawn(CameraAction::Zoom(1.25));
+    }
+    // Alt+Arrow pans; plain arrows move the active cell (see
+    // `navigate_active_cell`), and only pan while there's none
+    if grid_state.active.is_some() && !alt_pressed(&keyboard) {
+        return;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) { commands.spawn(CameraAction::Pan(Vec2::new(0.0, 100.0))); }
+    if keyboard.just_pressed(KeyCode::ArrowDown) { commands.spawn(CameraAction::Pan(Vec2::new(0.0, -100.0))); }
+    if keyboard.just_pressed(KeyCode::ArrowLeft) { commands.spawn(CameraAction::Pan(Vec2::new(-100.0, 0.0))); }
+    if keyboard.just_pressed(KeyCode::ArrowRight) { commands.spawn(CameraAction::Pan(Vec2::new(100.0, 0.0))); }
+}
+
+/// Arrow keys move the active cell, Ctrl+Arrow to the edge of a block of data,
+/// and the view scrolls just far enough to keep it in sight
+/// With Shift they move the selection's far corner instead, growing or
+/// shrinking it around the active cell
+fn navigate_active_cell(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut commands: Commands,
+) {
+    // Once typing has started the arrows belong to the caret
+    if editing_state.editing || editing_state.name_box.is_some() || editing_state.toolbar_focus.is_some() || alt_pressed(&keyboard) {
+        return;
+    }
+    let extend = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let Some(from) = (if extend { grid_state.selection_corner() } else { grid_state.active }) else { return };
+    let arrows = [
+        (KeyCode::ArrowLeft, (-1, 0)),
+        (KeyCode::ArrowRight, (1, 0)),
+        (KeyCode::ArrowUp, (0, -1)),
+        (KeyCode::ArrowDown, (0, 1)),
+    ];
+    let Some(step) = arrows.into_iter().find(|(key, _)| keyboard.just_pressed(*key)).map(|(_, step)| step) else {
+        return;
+    };
+    let to = if ctrl_pressed(&keyboard) {
+        navigation::jump(&grid_state, from, step)
+    } else {
+        navigation::step(&grid_state, from, step)
+    };
+    if extend {
+        grid_state.extend_selection(to);
+    } else {
+        grid_state.selected.clear();
+        grid_state.selected.insert(to);
+        grid_state.active = Some(to);
+        grid_state.selection_end = None;
+        sync_editor_buffer(&mut editing_state, &grid_state);
+    }
+
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Some(mat) = grid_q.single().ok().and_then(|handle| materials.get(&handle.0)) else { return };
+    let Some((visual_col, visual_row)) = grid_state.layout.to_visual(to.0, to.1) else { return };
+    let Some(rect) = camera.logical_viewport_rect() else { return };
+    let (Ok(corner_a), Ok(corner_b)) = (
+        camera.viewport_to_world_2d(cam_transform, rect.min),
+        camera.viewport_to_world_2d(cam_transform, rect.max),
+    ) else {
+        return;
+    };
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let cell_top = -visual_row as f32 * mat.cell_size.y;
+    let cell = Rect::new(
+        columns.left(visual_col),
+        cell_top - mat.cell_size.y,
+        columns.left(visual_col) + columns.width(visual_col),
+        cell_top,
+    );
+    // Frozen panes cover the view's top left, and cells in them never scroll
+    let mut view = Rect::from_corners(corner_a, corner_b);
+    view.min.x += columns.left(grid_state.layout.frozen_cols.max(0));
+    view.max.y -= grid_state.layout.frozen_rows.max(0) as f32 * mat.cell_size.y;
+    let mut shift = camera_motion::scroll_into_view(view, cell);
+    if visual_col < grid_state.layout.frozen_cols {
+        shift.x = 0.0;
+    }
+    if visual_row < grid_state.layout.frozen_rows {
+        shift.y = 0.0;
+    }
+    if shift != Vec2::ZERO {
+        commands.spawn(CameraAction::CenterOn(cam_transform.translation().truncate() + shift));
+    }
+}
+
+fn handle_editor_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut key_events: MessageReader<KeyboardInput>,
+    mut editing_state: ResMut<EditingState>,
+    mut grid_state: ResMut<GridState>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    mut undo_stack: ResMut<UndoStack>,
+    history: Res<TickHistory>,
+) {
+    // Edits are disabled while scrubbing through history, and keys go to the
+    // name box or toolbar while they have focus
+    let focus_elsewhere = editing_state.name_box.is_some() || editing_state.toolbar_focus.is_some();
+    if grid_state.active.is_none() || history.is_scrubbing() || focus_elsewhere {
+        key_events.clear();
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::Enter) {
+        key_events.clear();
+        // Commit
+        if let Some((col, row)) = grid_state.active {
+            if grid_state.is_locked(col, row) {
+                editing_state.message = Some("Cell is locked".to_string());
+                return;
+            }
+            let checked = validation::validation_at(&grid_state, col, row)
+                .map(|v| (v.on_invalid, v.check(&grid_state, editing_state.buffer.text())));
+            editing_state.message = None;
+            if let Some((action, Err(reason))) = checked {
+                editing_state.message = Some(match action {
+                    validation::InvalidAction::Reject => format!("Rejected: {}", reason),
+                    validation::InvalidAction::Flag => format!("Invalid: {}", reason),
+                });
+                if action == validation::InvalidAction::Reject {
+                    return;
+                }
+            }
+
+            let mut group = EditGroup::new("Edit");
+            group.set_raw(&grid_state, col, row, editing_state.buffer.text().to_string());
+            for change in undo_stack.commit(&mut grid_state, group) {
+                cell_changed.write(change);
+            }
+            editing_state.editing = false;
+        }
+        return;
+    }
+
+    // Outside edit mode Delete clears the selected cells, as one undo step
+    if !editing_state.editing && keyboard.just_pressed(KeyCode::Delete) {
+        key_events.clear();
+        let group = grid_ops::clear_selection(&grid_state);
+        if group.touches_locked(&grid_state) {
+            editing_state.message = Some("Cell is locked".to_string());
+            return;
+        }
+        cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+        sync_editor_buffer(&mut editing_state, &grid_state);
+        return;
+    }
+
+    // F2 edits the existing contents, caret at the end
+    if keyboard.just_pressed(KeyCode::F2) {
+        editing_state.editing = true;
+        editing_state.buffer.end(false);
+    }
+
+    // Cancel: the cell keeps its raw text and the editor closes; outside
+    // edit mode there is nothing to cancel
+    if keyboard.just_pressed(KeyCode::Escape) {
+        key_events.clear();
+        if !editing_state.editing && editing_state.composing.is_empty() {
+            return;
+        }
+        sync_editor_buffer(&mut editing_state, &grid_state);
+        editing_state.composing.clear();
+        editing_state.message = None;
+        return;
+    }
+
+    // Typed text, so every character the layout produces works, shifted
+    // symbols and key repeat included; Ctrl/Cmd combinations are shortcuts
+    // Caret keys extend the selection with Shift, once typing has started
+    // (before that the arrows move the active cell)
+    let shortcut = ctrl_pressed(&keyboard);
+    let select = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for event in key_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        let caret_keys = editing_state.editing;
+        let buffer = &mut editing_state.buffer;
+        match &event.logical_key {
+            // Outside edit mode Backspace starts a fresh, empty edit
+            Key::Backspace if !caret_keys => buffer.set(String::new()),
+            Key::Backspace => buffer.delete_back(),
+            Key::Delete => buffer.delete_forward(),
+            Key::ArrowLeft if caret_keys => buffer.left(select),
+            Key::ArrowRight if caret_keys => buffer.right(select),
+            Key::Home if caret_keys => buffer.home(select),
+            Key::End if caret_keys => buffer.end(select),
+            Key::ArrowLeft | Key::ArrowRight | Key::ArrowUp | Key::ArrowDown | Key::Home | Key::End => continue,
+            _ if shortcut => continue,
+            _ => {
+                let typed: String = event.text.iter().flat_map(|text| text.chars()).filter(|c| !c.is_control()).collect();
+                if typed.is_empty() {
+                    continue;
+                }
+                // Space outside edit mode is held to pan (see `grab_pan`)
+                if !caret_keys && typed == " " {
+                    continue;
+                }
+                // Typing on a selected cell replaces its contents
+                if !caret_keys {
+                    buffer.set(String::new());
+                }
+                buffer.insert(&typed);
+            }
+        }
+        editing_state.message = None;
+        editing_state.editing = true;
+    }
+}
+
+/// Input method composition: turned on while a cell can be edited, with the
+/// text being composed shown in the formula bar until it's committed
+fn handle_ime_input(
+    mut ime_events: MessageReader<Ime>,
+    mut window_q: Query<&mut Window>,
+    mut editing_state: ResMut<EditingState>,
+    grid_state: Res<GridState>,
+    history: Res<TickHistory>,
+) {
+    let editable = grid_state.active.is_some() && !history.is_scrubbing();
+    if let Ok(mut window) = window_q.single_mut() {
+        if window.ime_enabled != editable {
+            window.ime_enabled = editable;
+            window.ime_position = IME_POSITION;
+        }
+    }
+
+    for event in ime_events.read() {
+        match event {
+            Ime::Preedit { value, .. } if editable => {
+                if editing_state.composing != *value {
+                    editing_state.composing = value.clone();
+                }
+            }
+            Ime::Commit { value, .. } if editable => {
+                if !editing_state.editing {
+                    editing_state.buffer.set(String::new());
+                }
+                editing_state.buffer.insert(value);
+                editing_state.composing.clear();
+                editing_state.message = None;
+                editing_state.editing = true;
+            }
+            Ime::Disabled { .. } if !editing_state.composing.is_empty() => editing_state.composing.clear(),
+            _ => {}
+        }
+    }
+}
+
+fn update_editor_display(
+    editing_state: Res<EditingState>,
+    grid_state: Res<GridState>,
+    mut query: Query<&mut Text, With<EditorText>>,
+    mut spans: Query<(&EditorSpan, &mut TextSpan)>,
+) {
+    // Error reasons re-evaluate the formula, so only rebuild on changes
+    if !editing_state.is_changed() && !grid_state.is_changed() {
+        return;
+    }
+    let Ok(mut text) = query.single_mut() else { return };
+    // The name box shows which cell this is
+    let Some((col, row)) = grid_state.active else {
+        **text = "Select a cell".to_string();
+        for (_, mut span) in &mut spans {
+            span.clear();
+        }
+        return;
+    };
+
+    let buffer = &editing_state.buffer;
+    let caret = buffer.caret();
+    let selection = buffer.selection().unwrap_or(caret..caret);
+    **text = buffer.text()[..selection.start].to_string();
+
+    let mut after = buffer.text()[selection.end..].to_string();
+    if !editing_state.composing.is_empty() {
+        after.push_str(&format!("«{}»", editing_state.composing));
+    }
+    if let Some(message) = &editing_state.message {
+        after.push_str(&format!("  ({})", message));
+    }
+    // The full error for a cell showing an error code
+    if let Some(summary) = error_summary(&grid_state, col, row) {
+        after.push_str(&format!("  [{}]", summary));
+    }
+
+    let caret_first = caret == selection.start;
+    for (part, mut span) in &mut spans {
+        **span = match part {
+            EditorSpan::LeadCaret if caret_first => "|".to_string(),
+            EditorSpan::TrailCaret if !caret_first => "|".to_string(),
+            EditorSpan::LeadCaret | EditorSpan::TrailCaret => String::new(),
+            EditorSpan::Selected => buffer.text()[selection.clone()].to_string(),
+            EditorSpan::After => after.clone(),
+        };
+    }
+}
+
+/// The name box: Ctrl+G or a click focuses it, Enter goes to what was typed
+/// (see `navigation::go_to_target`) and Escape backs out
+/// Runs after the cell editor, so the Enter or Escape closing it isn't seen
+/// there too
+fn handle_name_box(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut key_events: MessageReader<KeyboardInput>,
+    interaction_q: Query<&Interaction, (Changed<Interaction>, With<NameBox>)>,
+    mut text_q: Query<&mut Text, With<NameBoxText>>,
+    mut editing_state: ResMut<EditingState>,
+    mut grid_state: ResMut<GridState>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut commands: Commands,
+) {
+    let clicked = interaction_q.iter().any(|i| *i == Interaction::Pressed);
+    let go_to = ctrl_pressed(&keyboard) && keyboard.just_pressed(KeyCode::KeyG);
+    if (clicked || go_to) && editing_state.name_box.is_none() {
+        editing_state.name_box = Some(String::new());
+        key_events.clear();
+    }
+
+    if let Some(mut typed) = editing_state.name_box.clone() {
+        let (mut close, mut go) = (false, false);
+        for event in key_events.read() {
+            if event.state != ButtonState::Pressed {
+                continue;
+            }
+            match &event.logical_key {
+                Key::Enter => (close, go) = (true, true),
+                Key::Escape => close = true,
+                Key::Backspace => {
+                    typed.pop();
+                }
+                _ => typed.extend(event.text.iter().flat_map(|text| text.chars()).filter(|c| !c.is_control())),
+            }
+        }
+        if go {
+            match navigation::go_to_target(&grid_state, &typed) {
+                Some(range) => {
+                    let corner = (range.max_col, range.max_row);
+                    grid_state.selected.set(range);
+                    grid_state.active = Some((range.min_col, range.min_row));
+                    grid_state.selection_end = Some(corner);
+                    sync_editor_buffer(&mut editing_state, &grid_state);
+                    editing_state.message = None;
+
+                    // Bring the cell into the middle of the view
+                    let mat = grid_q.single().ok().and_then(|handle| materials.get(&handle.0));
+                    let visual = grid_state.layout.to_visual(range.min_col, range.min_row);
+                    if let (Some(mat), Some((visual_col, visual_row))) = (mat, visual) {
+                        let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+                        let center = Vec2::new(
+                            columns.left(visual_col) + columns.width(visual_col) / 2.0,
+                            -(visual_row as f32 + 0.5) * mat.cell_size.y,
+                        );
+                        commands.spawn(CameraAction::CenterOn(center));
+                    }
+                }
+                None => editing_state.message = Some(format!("Nothing called \"{}\"", typed.trim())),
+            }
+        }
+        let next = (!close).then_some(typed);
+        if editing_state.name_box != next {
+            editing_state.name_box = next;
+        }
+    } else {
+        key_events.clear();
+    }
+
+    if !editing_state.is_changed() && !grid_state.is_changed() {
+        return;
+    }
+    let Ok(mut text) = text_q.single_mut() else { return };
+    let shown = match (&editing_state.name_box, grid_state.active) {
+        (Some(typed), _) => format!("{}|", typed),
+        (None, Some((col, row))) => formula::coord_to_name(col, row),
+        (None, None) => String::new(),
+    };
+    if **text != shown {
+        **text = shown;
+    }
+}
+
+/// Clicking the formula bar starts editing the active cell there, with the
+/// caret put where it was clicked (Shift+click selects up to it)
+fn click_formula_bar(
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    bar_q: Query<&Interaction, With<FormulaBar>>,
+    text_q: Query<(&RelativeCursorPosition, &ComputedNode, &TextLayoutInfo), With<EditorText>>,
+    mut editing_state: ResMut<EditingState>,
+    grid_state: Res<GridState>,
+    history: Res<TickHistory>,
+) {
+    if !mouse_btn.just_pressed(MouseButton::Left) || grid_state.active.is_none() || history.is_scrubbing() {
+        return;
+    }
+    if !bar_q.iter().any(|i| *i == Interaction::Pressed) {
+        return;
+    }
+    editing_state.editing = true;
+    editing_state.name_box = None;
+
+    let Ok((cursor, node, layout)) = text_q.single() else { return };
+    let Some(normalized) = cursor.normalized else { return };
+    // Layout positions are in physical pixels from the text's left edge,
+    // like the node's size; `normalized` is relative to its center
+    let x = (normalized.x + 0.5) * node.size().x;
+    let buffer = &editing_state.buffer;
+    let selection = buffer.selection().unwrap_or(buffer.caret()..buffer.caret());
+    // Glyphs belong to the spans laid out by `update_editor_display`: the
+    // text before the selection, the lead caret, the selection, the trail
+    // caret, then the rest (plus any messages, past the end of the text)
+    let offset = |span: usize, byte: usize| match span {
+        0 => Some(byte),
+        2 => Some(selection.start + byte),
+        4 => Some(selection.end + byte),
+        _ => None,
+    };
+    let position = layout
+        .glyphs
+        .iter()
+        .filter(|glyph| x < glyph.position.x)
+        .find_map(|glyph| offset(glyph.span_index, glyph.byte_index))
+        .unwrap_or(buffer.text().len());
+    let select = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    editing_state.buffer.place_caret(position, select);
+}
+
+/// Answer API calls (cells, subscriptions, ticks, screenshots) from the page
+/// embedding the web build (see `host_api`); edits go through the undo stack
+/// like typed ones
+fn serve_host_requests(
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut undo_stack: ResMut<UndoStack>,
+    mut cell_changed: MessageWriter<CellChanged>,
+    mut subscriptions: ResMut<host_api::Subscriptions>,
+    mut ticks: ResMut<TickControl>,
+    mut tick_timer: ResMut<EvaluationTimer>,
+    lens_state: Res<LensState>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    history: Res<TickHistory>,
+) {
+    const PAUSED: &str = "Edits are paused while scrubbing history";
+    for (request, reply) in host_api::take_pending() {
+        let answered = match &request {
+            // Like the UI, the page can't change the sheet while history is scrubbed
+            host_api::HostRequest::ImportWorkbook { .. } | host_api::HostRequest::Tick if history.is_scrubbing() => {
+                Err(PAUSED.to_string())
+            }
+            // Rendered here, where the view is known
+            host_api::HostRequest::Screenshot { range } => {
+                let lines = match range {
+                    Some(range) => host_api::bounded_range_at(&grid_state, range).map(|range| export::shown_lines(&grid_state, range)),
+                    None => camera_q
+                        .single()
+                        .ok()
+                        .zip(grid_q.single().ok().and_then(|handle| materials.get(&handle.0)))
+                        .and_then(|((camera, cam_transform), mat)| visible_lines(&grid_state, camera, cam_transform, mat))
+                        .ok_or_else(|| "Nothing on screen".to_string()),
+                };
+                lines
+                    .and_then(|(cols, rows)| sheet_png(&grid_state, &lens_state, &cols, &rows))
+                    .map(|png| (host_api::HostResponse::png(&png), None))
+            }
+            _ => host_api::answer(&grid_state, &mut subscriptions, &mut ticks, &mut tick_timer.timer, &request),
+        };
+        let result = answered.and_then(|(response, edit)| {
+            if let Some(group) = edit {
+                if history.is_scrubbing() {
+                    return Err(PAUSED.to_string());
+                }
+                if group.touches_locked(&grid_state) {
+                    return Err("Cell is locked".to_string());
+                }
+                cell_changed.write_batch(undo_stack.commit(&mut grid_state, group));
+                if !editing_state.editing {
+                    sync_editor_buffer(&mut editing_state, &grid_state);
+                }
+            }
+            Ok(response)
+        });
+        reply(result);
+    }
+}
+
+/// Send the page's subscriptions the value changes from this frame's ticks and
+/// edits
+fn notify_host_subscribers(
+    mut changes: MessageReader<CellChanged>,
+    subscriptions: Res<host_api::Subscriptions>,
+    ticks: Res<TickControl>,
+) {
+    let changes: Vec<CellChanged> = changes.read().cloned().collect();
+    if changes.is_empty() {
+        return;
+    }
+    for (id, notices) in subscriptions.notices(&changes, ticks.tick_count) {
+        host_api::deliver(id, &notices);
+    }
+}
+
+/// Send the page the calls formulas made to the functions it registered
+fn send_host_calls(grid_state: Res<GridState>) {
+    for call in grid_state.host_functions.take_calls() {
+        if !host_api::send_call(&call) {
+            let _ = grid_state.host_functions.answer(call.id, Err(format!("Nothing computes {}", call.name)));
+        }
+    }
+}
+
+/// Send overlays (see `HostRequest::ShowOverlays`) their cells on the main
+/// pane's screen, in CSS pixels, whenever any moved or changed
+fn stream_host_overlays(
+    grid_state: Res<GridState>,
+    subscriptions: Res<host_api::Subscriptions>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    grid_q: Query<(&MeshMaterial2d<SpreadsheetGridMaterial>, &GridPane), With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut sent: Local<std::collections::HashMap<u32, Vec<host_api::OverlayCell>>>,
+) {
+    if !subscriptions.has_overlays() {
+        sent.clear();
+        return;
+    }
+    let Ok((handle, pane)) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&handle.0) else { return };
+    let Ok((camera, cam_transform)) = camera_q.get(pane.camera) else { return };
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let Some((min_col, min_row, width, height)) = pane_viewport(camera, cam_transform, mat, &columns) else { return };
+    let origin = camera.logical_viewport_rect().map_or(Vec2::ZERO, |rect| rect.min);
+
+    let mut on_screen: Vec<((i32, i32), Rect)> = Vec::new();
+    for (visual_col, visual_row) in grid_state.layout.viewport_slots(min_col, min_row, width, height) {
+        let cell = grid_state.layout.to_logical(visual_col, visual_row);
+        // Frozen cells come up twice, landing in the same place
+        if !subscriptions.is_overlaid(cell.0, cell.1) || on_screen.iter().any(|(seen, _)| *seen == cell) {
+            continue;
+        }
+        let world = cell_world_rect(mat, &columns, visual_col, visual_row);
+        let corners = [world.min, world.max].map(|corner| camera.world_to_viewport(cam_transform, corner.extend(0.0)));
+        if let [Ok(a), Ok(b)] = corners {
+            on_screen.push((cell, Rect::from_corners(a + origin, b + origin)));
+        }
+    }
+
+    for (id, cells) in subscriptions.overlay_cells(&grid_state, &on_screen) {
+        if sent.get(&id) != Some(&cells) {
+            host_api::deliver_overlays(id, &cells);
+            sent.insert(id, cells);
+        }
+    }
+}
+
+/// Keep the page's value mirror (see `host_api::mirror_frame`) in step with
+/// the sheet and the main pane's view
+fn mirror_host_values(
+    grid_state: Res<GridState>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut mirrored: Local<Option<(i32, i32, i32, i32)>>,
+    mut generation: Local<u64>,
+) {
+    if !host_api::is_mirroring() {
+        *mirrored = None;
+        return;
+    }
+    let Ok((camera, cam_transform)) = camera_q.single() else { return };
+    let Some(mat) = grid_q.single().ok().and_then(|handle| materials.get(&handle.0)) else { return };
+    let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+    let Some(view) = pane_viewport(camera, cam_transform, mat, &columns) else { return };
+    if *mirrored == Some(view) && !grid_state.is_changed() {
+        return;
+    }
+    *mirrored = Some(view);
+    *generation += 1;
+    let (min_col, min_row, width, height) = view;
+    let frame = host_api::mirror_frame(&grid_state, min_col.max(0), min_row.max(0), width as usize, height as usize, *generation);
+    host_api::mirror(&frame);
+}
+
+/// Keep the screen reader status (and on the web, the page's ARIA live region)
+/// describing the active cell
+fn announce_active_cell(
+    grid_state: Res<GridState>,
+    mut status_q: Query<&mut AccessibilityNode, With<ScreenReaderStatus>>,
+    mut last: Local<String>,
+) {
+    if !grid_state.is_changed() {
+        return;
+    }
+    let description = match grid_state.active {
+        Some((col, row)) => accessibility::describe_cell(&grid_state, col, row),
+        None => "No cell selected".to_string(),
+    };
+    if *last == description {
+        return;
+    }
+    if let Ok(mut status) = status_q.single_mut() {
+        status.set_label(description.as_str());
+    }
+    #[cfg(target_arch = "wasm32")]
+    accessibility::announce(&description);
+    *last = description;
+}
+
+/// Keyboard access to every button: F6 moves focus to the toolbar, Tab and
+/// Shift+Tab (or the arrows) go between buttons in reading order, Enter or
+/// Space presses the focused one, and Escape or F6 goes back to the sheet
+/// A press sets the button's `Interaction` as a click would, so its usual
+/// handler runs; this runs after UI focus so the press is released next frame
+fn toolbar_keyboard_focus(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut editing_state: ResMut<EditingState>,
+    mut buttons: Query<(Entity, &UiGlobalTransform, &InheritedVisibility, &mut Interaction), With<Button>>,
+    mut pressed: Local<Option<Entity>>,
+    mut commands: Commands,
+) {
+    if let Some(entity) = pressed.take() {
+        if let Ok((.., mut interaction)) = buttons.get_mut(entity) {
+            *interaction = Interaction::None;
+        }
+    }
+    if editing_state.toolbar_focus.is_none() && !keyboard.just_pressed(KeyCode::F6) {
+        return;
+    }
+
+    // Reading order: rows of buttons top to bottom, each left to right
+    let mut order: Vec<(Entity, Vec2)> = buttons
+        .iter()
+        .filter(|(_, _, visibility, _)| visibility.get())
+        .map(|(entity, transform, ..)| (entity, transform.translation))
+        .collect();
+    order.sort_by(|(_, a), (_, b)| {
+        (a.y / 10.0).round().total_cmp(&(b.y / 10.0).round()).then_with(|| a.x.total_cmp(&b.x))
+    });
+
+    let focused = editing_state.toolbar_focus;
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let index = focused.and_then(|focused| order.iter().position(|(entity, _)| *entity == focused));
+    let next = if keyboard.just_pressed(KeyCode::F6) {
+        if focused.is_some() { None } else { order.first().map(|(entity, _)| *entity) }
+    } else if let Some(i) = index {
+        let forward = (keyboard.just_pressed(KeyCode::Tab) && !shift)
+            || keyboard.any_just_pressed([KeyCode::ArrowRight, KeyCode::ArrowDown]);
+        let back = (keyboard.just_pressed(KeyCode::Tab) && shift)
+            || keyboard.any_just_pressed([KeyCode::ArrowLeft, KeyCode::ArrowUp]);
+        if keyboard.just_pressed(KeyCode::Escape) {
+            None
+        } else if forward {
+            Some(order[(i + 1) % order.len()].0)
+        } else if back {
+            Some(order[(i + order.len() - 1) % order.len()].0)
+        } else {
+            if keyboard.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter, KeyCode::Space]) {
+                if let Ok((.., mut interaction)) = buttons.get_mut(order[i].0) {
+                    *interaction = Interaction::Pressed;
+                    *pressed = Some(order[i].0);
+                }
+            }
+            focused
+        }
+    } else {
+        // The focused button went away (its menu closed): back to the first
+        focused.and(order.first().map(|(entity, _)| *entity))
+    };
+
+    if next != focused {
+        if let Some(old) = focused {
+            if let Ok(mut entity) = commands.get_entity(old) {
+                entity.remove::<Outline>();
+            }
+        }
+        if let Some(new) = next {
+            commands.entity(new).insert(Outline::new(Val::Px(2.0), Val::Px(1.0), Color::srgb(1.0, 0.85, 0.3)));
+        }
+        editing_state.toolbar_focus = next;
+    }
+}
+
+/// Blink the formula bar's caret while editing; it's hidden otherwise
+fn blink_editor_caret(time: Res<Time>, editing_state: Res<EditingState>, mut spans: Query<(&EditorSpan, &mut TextColor)>) {
+    let blink_on = (time.elapsed_secs() * CARET_BLINK_RATE) as u32 % 2 == 0;
+    let alpha = if editing_state.editing && blink_on { 1.0 } else { 0.0 };
+    for (part, mut color) in &mut spans {
+        if matches!(part, EditorSpan::LeadCaret | EditorSpan::TrailCaret) && color.0.alpha() != alpha {
+            color.0.set_alpha(alpha);
+        }
+    }
+}
+
+fn apply_camera_actions(
+    mut camera_q: Query<(&Camera, &mut Transform), With<MainCamera>>,
+    actions_q: Query<(Entity, &CameraAction)>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    grid_state: Res<GridState>,
+    time: Res<Time>,
+    mut motion: ResMut<CameraMotion>,
+    mut commands: Commands,
+) {
+    let Ok((camera, mut camera_transform)) = camera_q.single_mut() else { return };
+    let current = camera_motion::CameraPose {
+        center: camera_transform.translation.truncate(),
+        scale: camera_transform.scale.x,
+    };
+    // Actions build on where the camera is already headed, so quick presses add up
+    let mut target = motion.tween.map_or(current, |tween| tween.target());
+    let mut moved = false;
+    let cell_size = grid_q
+        .single()
+        .ok()
+        .and_then(|handle| materials.get(&handle.0))
+        .map_or(Vec2::new(80.0, 30.0), |mat| mat.cell_size);
     for (entity, action) in &actions_q {
         match action {
-            CameraAction::Zoom(factor) => { camera_transform.scale *= *factor; }
-            CameraAction::Pan(delta) => {
-                camera_transform.translation.x += delta.x * camera_transform.scale.x;
-                camera_transform.translation.y += delta.y * camera_transform.scale.y;
-            }
-            CameraAction::Reset => {
-                camera_transform.translation = Vec3::ZERO;
-                camera_transform.scale = Vec3::ONE;
+            CameraAction::Zoom(factor) => target.scale *= *factor,
+            CameraAction::Pan(delta) => target.center += *delta * target.scale,
+            CameraAction::CenterOn(pos) => target.center = *pos,
+            CameraAction::Reset => target = camera_motion::CameraPose { center: Vec2::ZERO, scale: 1.0 },
+            CameraAction::ZoomToFit => {
+                let used = minimap::used_world_rect(&grid_state, cell_size);
+                if let (Some(used), Some(viewport)) = (used, camera.logical_viewport_size()) {
+                    target = camera_motion::CameraPose::fitting(used, viewport, 0.05);
+                }
             }
         }
+        moved = true;
         commands.entity(entity).despawn();
     }
+    if moved {
+        if let Some(bounds) = motion.bounds {
+            target = bounds.clamp(target, minimap::used_world_rect(&grid_state, cell_size));
+        }
+        motion.tween = Some(camera_motion::CameraTween::new(current, target, motion.duration));
+    }
+
+    let Some(tween) = motion.tween.as_mut() else { return };
+    let pose = tween.advance(time.delta_secs());
+    if tween.is_done() {
+        motion.tween = None;
+    }
+    camera_transform.translation.x = pose.center.x;
+    camera_transform.translation.y = pose.center.y;
+    camera_transform.scale = Vec3::new(pose.scale, pose.scale, 1.0);
+}
+
+/// Open on the view a shared link describes (see `deep_link`)
+fn open_deep_link(
+    mut camera_q: Query<&mut Transform, With<MainCamera>>,
+    mut grid_state: ResMut<GridState>,
+    documents: Res<DocumentStore>,
+) {
+    let Some(link) = deep_link::read() else { return };
+    if let Ok(mut camera_transform) = camera_q.single_mut() {
+        camera_transform.translation.x = link.center.x;
+        camera_transform.translation.y = link.center.y;
+        camera_transform.scale = Vec3::new(link.scale, link.scale, 1.0);
+    }
+    if let Some(name) = link.document.filter(|name| documents.current.as_ref() != Some(name)) {
+        documents.open(&name);
+    }
+    if let Some(active) = link.active {
+        grid_state.active = Some(active);
+    }
+    grid_state.selected.clear();
+    for range in link.ranges {
+        grid_state.selected.add(range);
+    }
+}
+
+/// Keep the page's URL pointing at the current view, once it's been still
+/// for `deep_link::IDLE_DELAY` seconds
+fn update_deep_link(
+    time: Res<Time>,
+    camera_q: Query<&Transform, With<MainCamera>>,
+    grid_state: Res<GridState>,
+    documents: Res<DocumentStore>,
+    mut seen: Local<Option<deep_link::ViewLink>>,
+    mut still: Local<f32>,
+    mut written: Local<Option<deep_link::ViewLink>>,
+) {
+    let Ok(camera_transform) = camera_q.single() else { return };
+    let link = deep_link::ViewLink {
+        center: camera_transform.translation.truncate(),
+        scale: camera_transform.scale.x,
+        document: documents.current.clone(),
+        active: grid_state.active,
+        ranges: grid_state.selected.ranges().to_vec(),
+    };
+    if seen.as_ref() != Some(&link) {
+        *seen = Some(link);
+        *still = 0.0;
+        return;
+    }
+    *still += time.delta_secs();
+    if *still >= deep_link::IDLE_DELAY && *written != *seen {
+        deep_link::write(&link);
+        *written = Some(link);
+    }
+}
+
+/// Grab-and-drag panning: the middle button, or the left one with Space held,
+/// drags the sheet along with the cursor
+fn grab_pan(
+    mouse_btn: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    editing_state: Res<EditingState>,
+    window_q: Query<(Entity, &Window)>,
+    mut camera_q: Query<&mut Transform, With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    grid_state: Res<GridState>,
+    mut motion: ResMut<CameraMotion>,
+    mut last_cursor: Local<Option<Vec2>>,
+    mut shown: Local<Option<SystemCursorIcon>>,
+    mut commands: Commands,
+) {
+    let Ok((window_entity, window)) = window_q.single() else { return };
+    let Ok(mut camera_transform) = camera_q.single_mut() else { return };
+    let space_held = keyboard.pressed(KeyCode::Space) && !editing_state.editing;
+    let grabbing = mouse_btn.pressed(MouseButton::Middle) || (space_held && mouse_btn.pressed(MouseButton::Left));
+
+    let cursor = window.cursor_position().filter(|_| grabbing);
+    if let (Some(last), Some(cursor)) = (*last_cursor, cursor) {
+        // Screen y points down, world y up
+        let delta = cursor - last;
+        let scale = camera_transform.scale.x;
+        let mut pose = camera_motion::CameraPose {
+            center: camera_transform.translation.truncate() + Vec2::new(-delta.x, delta.y) * scale,
+            scale,
+        };
+        if let Some(bounds) = motion.bounds {
+            let cell_size = grid_q
+                .single()
+                .ok()
+                .and_then(|handle| materials.get(&handle.0))
+                .map_or(Vec2::new(80.0, 30.0), |mat| mat.cell_size);
+            pose = bounds.clamp(pose, minimap::used_world_rect(&grid_state, cell_size));
+        }
+        // The sheet follows the cursor directly, cancelling any eased move
+        motion.tween = None;
+        camera_transform.translation.x = pose.center.x;
+        camera_transform.translation.y = pose.center.y;
+    }
+    *last_cursor = cursor;
+
+    let icon = if grabbing {
+        SystemCursorIcon::Grabbing
+    } else if space_held {
+        SystemCursorIcon::Grab
+    } else {
+        SystemCursorIcon::Default
+    };
+    if *shown != Some(icon) {
+        commands.entity(window_entity).insert(CursorIcon::from(icon));
+        *shown = Some(icon);
+    }
+}
+
+/// Touch: a tap selects, a long press edits the cell, one finger drags the
+/// sheet and two pinch-zoom around their midpoint
+fn handle_touch(
+    touches: Res<Touches>,
+    time: Res<Time>,
+    mut camera_q: Query<(&Camera, &GlobalTransform, &mut Transform), With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    mut grid_state: ResMut<GridState>,
+    mut editing_state: ResMut<EditingState>,
+    mut motion: ResMut<CameraMotion>,
+    mut gesture: Local<Option<touch::TouchGesture>>,
+) {
+    let Ok((camera, cam_global, mut cam_transform)) = camera_q.single_mut() else { return };
+    let Some(mat) = grid_q.single().ok().and_then(|handle| materials.get(&handle.0)) else { return };
+    let now = time.elapsed_secs_f64();
+    let cell_at = |position: Vec2| {
+        let world_pos = camera.viewport_to_world_2d(cam_global, position).ok()?;
+        let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+        let (col, row) = world_pos_to_cell(pane_world_pos(mat, &columns, world_pos), mat.cell_size, &columns);
+        Some(grid_state.layout.to_logical(col, row))
+    };
+
+    for finger in touches.iter_just_pressed() {
+        if gesture.is_none() {
+            *gesture = Some(touch::TouchGesture::new(finger.id(), finger.position(), now));
+        }
+    }
+
+    let pose = camera_motion::CameraPose { center: cam_transform.translation.truncate(), scale: cam_transform.scale.x };
+    // Screen y points down, world y up
+    let panned = |by: Vec2| camera_motion::CameraPose { center: pose.center + Vec2::new(-by.x, by.y) * pose.scale, ..pose };
+    let mut moved = None;
+    let mut pressed_cell = None;
+    let fingers: Vec<_> = touches.iter().collect();
+    match fingers.as_slice() {
+        [first, second, ..] => {
+            let pinch = touch::pinch(
+                [first.previous_position(), second.previous_position()],
+                [first.position(), second.position()],
+            );
+            if let Some(gesture) = gesture.as_mut() {
+                gesture.dragged = true;
+            }
+            let next = panned(pinch.pan);
+            moved = Some(match camera.viewport_to_world_2d(cam_global, pinch.centroid) {
+                // The point under the midpoint moved with the pan
+                Ok(anchor) => next.zoomed_about(anchor + next.center - pose.center, pinch.zoom),
+                Err(_) => next,
+            });
+        }
+        [finger] => {
+            if let Some(gesture) = gesture.as_mut().filter(|gesture| gesture.id == finger.id()) {
+                gesture.moved_to(finger.position());
+                if gesture.dragged {
+                    moved = Some(panned(finger.delta()));
+                } else if gesture.long_press_due(now) {
+                    pressed_cell = cell_at(finger.position()).map(|cell| (cell, true));
+                }
+            }
+        }
+        [] => {}
+    }
+
+    for finger in touches.iter_just_released().chain(touches.iter_just_canceled()) {
+        let Some(ended) = gesture.filter(|gesture| gesture.id == finger.id()) else { continue };
+        if ended.is_tap() && touches.just_released(finger.id()) {
+            pressed_cell = cell_at(finger.position()).map(|cell| (cell, false));
+        }
+        *gesture = None;
+    }
+
+    if let Some((cell, edit)) = pressed_cell {
+        grid_state.selected.clear();
+        grid_state.selected.insert(cell);
+        grid_state.active = Some(cell);
+        sync_editor_buffer(&mut editing_state, &grid_state);
+        editing_state.editing = edit;
+    }
+
+    if let Some(mut next) = moved {
+        if let Some(bounds) = motion.bounds {
+            next = bounds.clamp(next, minimap::used_world_rect(&grid_state, mat.cell_size));
+        }
+        // Fingers move the sheet directly, cancelling any eased move
+        motion.tween = None;
+        cam_transform.translation.x = next.center.x;
+        cam_transform.translation.y = next.center.y;
+        cam_transform.scale = Vec3::new(next.scale, next.scale, 1.0);
+    }
+}
+
+/// The visual cells a pane's camera shows, as (min_col, min_row, width, height)
+/// with a cell of slack on the far sides
+fn pane_viewport(
+    camera: &Camera,
+    cam_transform: &GlobalTransform,
+    mat: &SpreadsheetGridMaterial,
+    columns: &layout::ColumnOffsets,
+) -> Option<(i32, i32, i32, i32)> {
+    let rect = camera.logical_viewport_rect()?;
+    let min = camera.viewport_to_world_2d(cam_transform, rect.min).ok()?;
+    let max = camera.viewport_to_world_2d(cam_transform, rect.max).ok()?;
+    let bottom_left = Vec2::new(min.x.min(max.x), min.y.min(max.y));
+    let top_right = Vec2::new(min.x.max(max.x), min.y.max(max.y));
+
+    let min_col = columns.col_at(bottom_left.x);
+    let max_col = columns.col_at(top_right.x) + 1;
+    let min_row = (-top_right.y / mat.cell_size.y).floor() as i32;
+    let max_row = (-bottom_left.y / mat.cell_size.y).ceil() as i32;
+    Some((min_col, min_row, max_col - min_col + 1, max_row - min_row + 1))
 }
 
 fn sync_grid_buffer(
     grid_state: Res<GridState>,
-    camera_q: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
-    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>>,
+    clipboard: Res<Clipboard>,
+    lens_state: Res<LensState>,
+    overlay: Res<OverlayData>,
+    time: Res<Time>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    mut grid_q: Query<(&MeshMaterial2d<SpreadsheetGridMaterial>, &GridPane, &mut UploadedViewport)>,
     mut materials: ResMut<Assets<SpreadsheetGridMaterial>>,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
 ) {
-    let Ok((camera, cam_transform)) = camera_q.single() else { return };
-    let Ok(grid_handle) = grid_q.single() else { return };
-    let Some(mat) = materials.get_mut(&grid_handle.0) else { return };
+    // The copy source stays outlined until it's pasted (cuts) or replaced
+    let copied = clipboard
+        .contents
+        .as_ref()
+        .map(|c| c.cells.iter().map(|cell| (c.origin.0 + cell.dx, c.origin.1 + cell.dy)).collect())
+        .unwrap_or_default();
+
+    for (grid_handle, pane, mut uploaded) in &mut grid_q {
+        let Ok((camera, cam_transform)) = camera_q.get(pane.camera) else { continue };
+        let Some(mat) = materials.get_mut(&grid_handle.0) else { continue };
+        let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+        let Some(viewport) = pane_viewport(camera, cam_transform, mat, &columns) else { continue };
+        let (min_col, min_row, width, height) = viewport;
+
+        mat.grid_dimensions = Vec2::new(width as f32, height as f32);
+        mat.frozen_panes = Vec2::new(
+            grid_state.layout.frozen_cols.max(0) as f32,
+            grid_state.layout.frozen_rows.max(0) as f32,
+        );
+
+        mat.time = time.elapsed_secs_wrapped();
+
+        // Nothing to upload unless the view moved or what it shows changed
+        let moved = uploaded.viewport != Some(viewport);
+        let changed = grid_state.is_changed() || clipboard.is_changed() || lens_state.is_changed() || overlay.is_changed();
+        if !moved && !changed {
+            continue;
+        }
+        uploaded.viewport = Some(viewport);
+
+        mat.resized_cols = columns.resized_count() as f32;
+        let col_offsets = columns.to_words();
+        if uploaded.col_offsets != col_offsets {
+            if let Some(buffer) = buffers.get_mut(&mat.col_offsets) {
+                buffer.set_data(col_offsets.as_slice());
+            }
+            uploaded.col_offsets = col_offsets;
+        }
+
+        let gpu_data = grid_state.to_gpu_cells_viewport(min_col, min_row, width, height, &copied);
+        mat.heatmap = if lens_state.show_heatmap { 1.0 } else { 0.0 };
+        if lens_state.show_heatmap {
+            let (min, max) = lens_state.heat_scale.resolve(gpu_cell::numeric_values(&gpu_data).map(|v| v as f32));
+            mat.heat_range = Vec2::new(min, max);
+        }
+        upload_words(&mut buffers, &mat.cell_data, &mut uploaded.cells, gpu_data);
+        let borders = grid_state.to_gpu_borders_viewport(min_col, min_row, width, height);
+        upload_words(&mut buffers, &mat.cell_borders, &mut uploaded.borders, borders);
+        let overlay_words = grid_state
+            .layout
+            .viewport_slots(min_col, min_row, width, height)
+            .into_iter()
+            .map(|(col, row)| overlay.cells.get(&grid_state.layout.to_logical(col, row)).copied().unwrap_or(0))
+            .collect();
+        upload_words(&mut buffers, &mat.overlay_data, &mut uploaded.overlay, overlay_words);
+    }
+}
+
+/// What was last uploaded for a pane, so unchanged frames skip the upload and
+/// small edits only rewrite the words they touch
+#[derive(Component, Default)]
+struct UploadedViewport {
+    /// (min_col, min_row, width, height), in visual cells
+    viewport: Option<(i32, i32, i32, i32)>,
+    cells: Vec<u32>,
+    borders: Vec<u32>,
+    col_offsets: Vec<f32>,
+    overlay: Vec<u32>,
+    /// Logical cell in each slot, for the rich-cell layers (see manage_svg_cells)
+    rich_cells: Vec<(i32, i32)>,
+    text: Vec<u32>,
+}
 
+/// Write `words` to a storage buffer, patching only the span that differs from
+/// the last upload when the size is unchanged
+fn upload_words(
+    buffers: &mut Assets<ShaderStorageBuffer>,
+    handle: &Handle<ShaderStorageBuffer>,
+    last: &mut Vec<u32>,
+    words: Vec<u32>,
+) {
+    let Some(span) = gpu_cell::dirty_span(last, &words) else { return };
+    let Some(buffer) = buffers.get_mut(handle) else { return };
+    match buffer.data.as_mut() {
+        Some(data) if last.len() == words.len() && data.len() == words.len() * 4 => {
+            data[span.start * 4..span.end * 4].copy_from_slice(bytemuck::cast_slice(&words[span]));
+        }
+        _ => buffer.set_data(words.as_slice()),
+    }
+    *last = words;
+}
+
+/// Groups whose toggle goes next to a visible line: those ending between it
+/// and the visible line before it
+fn outline_toggles_at(lines: &layout::HiddenLines, visual: i32, logical: i32) -> Vec<(usize, layout::OutlineGroup)> {
+    let previous = lines.to_logical(visual - 1);
+    lines
+        .groups()
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, g)| previous <= g.end && g.end < logical)
+        .collect()
+}
+
+/// Rebuild the header gutter labels when the visible columns/rows, their names,
+/// their outline groups or the selection change
+/// Labels are screen-space nodes lined up with the cells below/beside them,
+/// frozen panes included; lines with selected cells are highlighted
+fn update_header_gutters(
+    mut commands: Commands,
+    camera_q: Query<&Camera, With<MainCamera>>,
+    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>, With<GridBackdrop>>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
+    grid_state: Res<GridState>,
+    label_q: Query<Entity, With<HeaderGutterLabel>>,
+    mut last_labels: Local<Vec<GutterLabel>>,
+) {
+    let Ok(camera) = camera_q.single() else { return };
+    let Ok(grid_handle) = grid_q.single() else { return };
+    let Some(mat) = materials.get(&grid_handle.0) else { return };
     let Some(rect) = camera.logical_viewport_rect() else { return };
-    let min_world = camera.viewport_to_world_2d(cam_transform, rect.min).ok();
-    let max_world = camera.viewport_to_world_2d(cam_transform, rect.max).ok();
 
-    if let (Some(min), Some(max)) = (min_world, max_world) {
-        let bottom_left = Vec2::new(min.x.min(max.x), min.y.min(max.y));
-        let top_right = Vec2::new(min.x.max(max.x), min.y.max(max.y));
+    // Screen pixels per world unit, with offsets measured from the viewport's top-left
+    let scale = rect.size() / mat.viewport_size;
+    let size = mat.cell_size;
+    let left = mat.viewport_bottom_left.x;
+    let top = mat.viewport_bottom_left.y + mat.viewport_size.y;
+    let layout = &grid_state.layout;
+    let columns = layout.column_offsets(size.x);
+    let frozen = Vec2::new(columns.left(layout.frozen_cols.max(0)), mat.frozen_panes.y * size.y);
 
-        let min_col = (bottom_left.x / mat.cell_size.x).floor() as i32;
-        let max_col = (top_right.x / mat.cell_size.x).ceil() as i32;
-        let min_row = (-top_right.y / mat.cell_size.y).floor() as i32;
-        let max_row = (-bottom_left.y / mat.cell_size.y).ceil() as i32;
+    let mut labels = Vec::new();
+    let toggle_label = |g: &layout::OutlineGroup, bounds: Rect, toggle: OutlineToggle| GutterLabel {
+        text: if g.collapsed { "+" } else { "-" }.to_string(),
+        bounds,
+        line: None,
+        toggle: Some(toggle),
+        highlighted: false,
+    };
+    let selected_cols: std::collections::HashSet<i32> = grid_state.selected.iter().map(|c| c.0).collect();
+    let selected_rows: std::collections::HashSet<i32> = grid_state.selected.iter().map(|c| c.1).collect();
 
-        let width = max_col - min_col + 1;
-        let height = max_row - min_row + 1;
+    // Scrolling columns not covered by the frozen pane, then the frozen ones
+    let min_col = columns.col_at(left);
+    let max_col = columns.col_at(left + mat.viewport_size.x) + 1;
+    let scrolling = (min_col..=max_col)
+        .map(|col| (col, columns.left(col) - left))
+        .filter(|&(_, x)| frozen.x == 0.0 || x >= frozen.x);
+    let pinned = (0..layout.frozen_cols.max(0)).map(|col| (col, columns.left(col)));
+    let mut toggles = Vec::new();
+    for (visual_col, x) in scrolling.chain(pinned) {
+        let col = layout.cols.to_logical(visual_col);
+        labels.push(GutterLabel {
+            text: grid_state.headers.col_label(col),
+            bounds: Rect::new(x * scale.x, 0.0, (x + columns.width(visual_col)) * scale.x, COLUMN_GUTTER_HEIGHT),
+            line: Some(HeaderLine { axis: grid_ops::Axis::Column, index: col }),
+            toggle: None,
+            highlighted: selected_cols.contains(&col),
+        });
 
-        mat.grid_dimensions = Vec2::new(width as f32, height as f32);
+        for (k, (group, g)) in outline_toggles_at(&layout.cols, visual_col, col).into_iter().enumerate() {
+            let min = Vec2::new(x * scale.x + 2.0 + k as f32 * (OUTLINE_TOGGLE_SIZE + 2.0), 3.0);
+            let toggle = OutlineToggle { axis: grid_ops::Axis::Column, group };
+            toggles.push(toggle_label(&g, Rect::from_corners(min, min + OUTLINE_TOGGLE_SIZE), toggle));
+        }
+    }
+
+    let min_row = (-top / size.y).floor() as i32;
+    let max_row = (-mat.viewport_bottom_left.y / size.y).ceil() as i32;
+    let scrolling = (min_row..=max_row)
+        .map(|row| (row, top + row as f32 * size.y))
+        .filter(|&(_, y)| frozen.y == 0.0 || y >= frozen.y);
+    let pinned = (0..layout.frozen_rows.max(0)).map(|row| (row, row as f32 * size.y));
+    for (visual_row, y) in scrolling.chain(pinned) {
+        let row = layout.rows.to_logical(visual_row);
+        labels.push(GutterLabel {
+            text: grid_state.headers.row_label(row),
+            bounds: Rect::new(0.0, y * scale.y, ROW_GUTTER_WIDTH, (y + size.y) * scale.y),
+            line: Some(HeaderLine { axis: grid_ops::Axis::Row, index: row }),
+            toggle: None,
+            highlighted: selected_rows.contains(&row),
+        });
+
+        for (k, (group, g)) in outline_toggles_at(&layout.rows, visual_row, row).into_iter().enumerate() {
+            let min = Vec2::new(2.0 + k as f32 * (OUTLINE_TOGGLE_SIZE + 2.0), y * scale.y + 2.0);
+            let toggle = OutlineToggle { axis: grid_ops::Axis::Row, group };
+            toggles.push(toggle_label(&g, Rect::from_corners(min, min + OUTLINE_TOGGLE_SIZE), toggle));
+        }
+    }
+    // Toggles go last so they draw over the labels
+    labels.extend(toggles);
+
+    if *last_labels == labels {
+        return;
+    }
 
-        if let Some(buffer) = buffers.get_mut(&mat.cell_data) {
-            let gpu_data = grid_state.to_gpu_cells_viewport(min_col, min_row, width, height);
-            buffer.set_data(gpu_data.as_slice());
+    for entity in &label_q {
+        commands.entity(entity).despawn();
+    }
+    for GutterLabel { text, bounds, line, toggle, highlighted } in &labels {
+        let background = if toggle.is_some() {
+            Color::srgb(0.7, 0.7, 0.7)
+        } else if *highlighted {
+            Color::srgba(0.7, 0.8, 0.95, 0.95)
+        } else {
+            Color::srgba(0.9, 0.9, 0.9, 0.9)
+        };
+        let mut label = commands.spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(bounds.min.x),
+                top: Val::Px(bounds.min.y),
+                width: Val::Px(bounds.width()),
+                height: Val::Px(bounds.height()),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            BackgroundColor(background),
+            // Keep the gutters under the toolbars
+            GlobalZIndex(-1),
+            HeaderGutterLabel,
+        ));
+        label.with_child((
+            Text::new(text.clone()),
+            TextFont { font_size: 12.0, ..default() },
+            TextColor(Color::BLACK),
+        ));
+        if let Some(toggle) = toggle {
+            label.insert((Button, *toggle));
+        }
+        if let Some(line) = line {
+            label.insert((Button, RelativeCursorPosition::default(), *line));
         }
     }
+    *last_labels = labels;
 }
 
 fn manage_svg_cells(
     mut svg_renderer: ResMut<SvgRenderer>,
     grid_state: Res<GridState>,
     lens_state: Res<LensState>,
-    camera_q: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
-    grid_q: Query<&MeshMaterial2d<SpreadsheetGridMaterial>>,
-    mut materials: ResMut<Assets<SpreadsheetGridMaterial>>,
+    subscriptions: Res<host_api::Subscriptions>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    mut grid_q: Query<(&MeshMaterial2d<SpreadsheetGridMaterial>, &GridPane, &mut UploadedViewport)>,
+    materials: Res<Assets<SpreadsheetGridMaterial>>,
     mut images: ResMut<Assets<Image>>,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
-    mut last_visible_rich_cells: Local<Vec<(i32, i32)>>,
+    mut layers: Local<texture_layers::LayerAllocator>,
 ) {
-    let Ok((camera, cam_transform)) = camera_q.single() else { return };
-    let Ok(grid_handle) = grid_q.single() else { return };
-    let Some(mat) = materials.get_mut(&grid_handle.0) else { return };
+    // Logical cell shown in each viewport buffer slot, per pane
+    let mut pane_cells = Vec::new();
+    let mut visibility_changed = false;
 
-    let Some(rect) = camera.logical_viewport_rect() else { return };
-    let min_world = camera.viewport_to_world_2d(cam_transform, rect.min).ok();
-    let max_world = camera.viewport_to_world_2d(cam_transform, rect.max).ok();
-
-    let mut current_visible_cells = Vec::new();
-    let mut min_col = 0;
-    let mut min_row = 0;
-    let mut width = 0;
-    let mut height = 0;
-
-    if let (Some(min), Some(max)) = (min_world, max_world) {
-        let bottom_left = Vec2::new(min.x.min(max.x), min.y.min(max.y));
-        let top_right = Vec2::new(min.x.max(max.x), min.y.max(max.y));
-
-        min_col = (bottom_left.x / mat.cell_size.x).floor() as i32;
-        let max_col = (top_right.x / mat.cell_size.x).ceil() as i32;
-        min_row = (-top_right.y / mat.cell_size.y).floor() as i32;
-        let max_row = (-bottom_left.y / mat.cell_size.y).ceil() as i32;
-        
-        width = max_col - min_col + 1;
-        height = max_row - min_row + 1;
-
-        for row in min_row..=max_row {
-            for col in min_col..=max_col {
+    for (grid_handle, pane, mut uploaded) in &mut grid_q {
+        let mut current_visible_cells = Vec::new();
+        // Plain text the shader draws itself, per slot
+        let mut text_data = Vec::new();
+        let Some(mat) = materials.get(&grid_handle.0) else {
+            pane_cells.push(current_visible_cells);
+            continue;
+        };
+        let columns = grid_state.layout.column_offsets(mat.cell_size.x);
+        let viewport = camera_q
+            .get(pane.camera)
+            .ok()
+            .and_then(|(camera, cam_transform)| pane_viewport(camera, cam_transform, mat, &columns));
+
+        if let Some((min_col, min_row, width, height)) = viewport {
+            let mut cells = grid_state.cells.reader();
+            for (visual_col, visual_row) in grid_state.layout.viewport_slots(min_col, min_row, width, height) {
+                let (col, row) = grid_state.layout.to_logical(visual_col, visual_row);
                 current_visible_cells.push((col, row));
-                
-                if let Some(cell) = grid_state.get_cell(col, row) {
-                    let svg = generate_svg(cell, col, row, &lens_state);
+                let mut text_slot = [0u32; glyph_atlas::TEXT_STRIDE];
+
+                // The page draws overlaid cells itself
+                if let Some(cell) = cells.get(col, row).filter(|_| !subscriptions.is_overlaid(col, row)) {
+                    let flagged = validation::is_flagged(&grid_state, col, row);
+                    let style = grid_state.theme.resolve(cell.style);
+                    let text = gpu_text(cell, &style, col, row, &lens_state);
+                    if let Some(text) = &text {
+                        text_slot = glyph_atlas::encode(text, &style);
+                    }
+                    text_data.extend_from_slice(&text_slot);
+
+                    let Some(svg) = generate_svg(cell, &style, col, row, &lens_state, flagged, text.is_some()) else { continue };
                     let hash = seahash::hash(svg.as_bytes());
 
                     if !svg_renderer.is_cached(hash) {
                         svg_renderer.request_render(SvgRenderRequest {
-                            cell_coord: (col, row),
+                            target: RenderTarget::Cell(col, row),
                             svg,
                             width: 80,
                             height: 30,
                             content_hash: hash,
                         });
                     }
+                } else {
+                    text_data.extend_from_slice(&text_slot);
                 }
             }
         }
+
+        if !text_data.is_empty() && uploaded.text != text_data {
+            if let Some(buffer) = buffers.get_mut(&mat.cell_text) {
+                buffer.set_data(text_data.as_slice());
+            }
+            uploaded.text = text_data;
+        }
+        visibility_changed |= uploaded.rich_cells != current_visible_cells;
+        pane_cells.push(current_visible_cells);
     }
 
+    // Charts' renders come back here too (see `update_charts`)
     let results = svg_renderer.poll_results();
-    let results_received = !results.is_empty();
-    
-    current_visible_cells.sort();
-    let visibility_changed = *last_visible_rich_cells != current_visible_cells;
-
-    if results_received || visibility_changed {
-        *last_visible_rich_cells = current_visible_cells.clone();
-
-        let mut texture_data = Vec::new();
-        let mut index_map = vec![-1i32; (width * height) as usize];
-        let mut layer_count = 0;
-        let mut hash_to_layer = std::collections::HashMap::new();
-
-        for (col, row) in &current_visible_cells {
-            let rel_x = col - min_col;
-            let rel_y = row - min_row;
-            if rel_x < 0 || rel_x >= width || rel_y < 0 || rel_y >= height { continue; }
-            
-            let viewport_idx = (rel_y * width + rel_x) as usize;
-
-            if let Some(cell) = grid_state.get_cell(*col, *row) {
-                let svg = generate_svg(cell, *col, *row, &lens_state);
+    let results_received = results.iter().any(|result| matches!(result.target, RenderTarget::Cell(..)));
+
+    // Panes share the texture array, so they're all reassigned together: a
+    // layer in use in any pane is never recycled
+    if !(results_received || visibility_changed) {
+        return;
+    }
+    // Layers assigned new contents, whose pixels need copying in
+    let mut fresh_layers = Vec::new();
+    let capacity = layers.capacity();
+    layers.begin_frame();
+    let mut textures = None;
+
+    let mut cells = grid_state.cells.reader();
+    for ((grid_handle, _, mut uploaded), current_visible_cells) in grid_q.iter_mut().zip(pane_cells) {
+        let Some(mat) = materials.get(&grid_handle.0) else { continue };
+        textures = Some(mat.rich_cell_textures.clone());
+
+        let mut index_map = vec![-1i32; current_visible_cells.len()];
+        for (viewport_idx, (col, row)) in current_visible_cells.iter().enumerate() {
+            if let Some(cell) = cells.get(*col, *row) {
+                let flagged = validation::is_flagged(&grid_state, *col, *row);
+                let style = grid_state.theme.resolve(cell.style);
+                let on_gpu = gpu_text(cell, &style, *col, *row, &lens_state).is_some();
+                let Some(svg) = generate_svg(cell, &style, *col, *row, &lens_state, flagged, on_gpu) else { continue };
                 let hash = seahash::hash(svg.as_bytes());
-                
-                if let Some(buffer) = svg_renderer.pixel_cache.get(&hash) {
-                    if let Some(&existing_layer) = hash_to_layer.get(&hash) {
-                        index_map[viewport_idx] = existing_layer as i32;
-                    } else {
-                        texture_data.extend_from_slice(buffer);
-                        index_map[viewport_idx] = layer_count;
-                        hash_to_layer.insert(hash, layer_count);
-                        layer_count += 1;
-                    }
+                if !svg_renderer.is_cached(hash) {
+                    continue;
+                }
+
+                // Out of layers: double the array, up to the GPU's limit
+                let mut assigned = layers.assign(hash);
+                if assigned.is_none() && layers.capacity() < texture_layers::MAX_LAYERS {
+                    layers.grow((layers.capacity() * 2).min(texture_layers::MAX_LAYERS));
+                    assigned = layers.assign(hash);
+                }
+                let Some((layer, fresh)) = assigned else { continue };
+                index_map[viewport_idx] = layer as i32;
+                if fresh {
+                    fresh_layers.push((layer, hash));
                 }
             }
         }
@@ -821,36 +5300,75 @@ fn manage_svg_cells(
         if let Some(buffer) = buffers.get_mut(&mat.rich_cell_indices) {
              buffer.set_data(index_map.as_slice());
         }
+        uploaded.rich_cells = current_visible_cells;
+    }
 
-        if layer_count > 0 {
-             // Force at least 2 layers to ensure D2Array view
-             let final_layer_count = if layer_count == 1 { 2 } else { layer_count };
-             if layer_count == 1 {
-                 // Pad with empty layer
-                 texture_data.resize(texture_data.len() * 2, 0);
-             }
-
-             let texture_array = Image::new(
-                Extent3d {
-                    width: 80,
-                    height: 30,
-                    depth_or_array_layers: final_layer_count as u32,
-                },
-                TextureDimension::D2,
-                texture_data,
-                TextureFormat::Rgba8UnormSrgb,
-                RenderAssetUsages::RENDER_WORLD,
-            );
-            mat.rich_cell_textures = images.add(texture_array);
+    let resized = layers.capacity() != capacity;
+    let Some(textures) = textures.filter(|_| resized || !fresh_layers.is_empty()) else { return };
+    let Some(image) = images.get_mut(&textures) else { return };
+    if resized {
+        // Appends empty layers, leaving the existing ones in place
+        image.resize(Extent3d {
+            width: texture_layers::LAYER_WIDTH,
+            height: texture_layers::LAYER_HEIGHT,
+            depth_or_array_layers: layers.capacity(),
+        });
+    }
+    let data = image.data.get_or_insert_default();
+    for (layer, hash) in fresh_layers {
+        let start = layer as usize * texture_layers::LAYER_BYTES;
+        if let (Some(pixels), Some(dest)) =
+            (svg_renderer.pixel_cache.get(&hash), data.get_mut(start..start + texture_layers::LAYER_BYTES))
+        {
+            dest.copy_from_slice(pixels);
         }
     }
 }
 
-fn generate_svg(cell: &crate::cell::Cell, col: i32, row: i32, lens_state: &LensState) -> String {
+/// Side of the checkbox drawn for boolean cells, in cell units
+const CHECKBOX_SIZE: f32 = 14.0;
+
+/// Left edge of a boolean cell's checkbox within the 80-wide cell
+fn checkbox_left(align: HorizontalAlign) -> f32 {
+    match align {
+        HorizontalAlign::Left => 4.0,
+        HorizontalAlign::Center => 40.0 - CHECKBOX_SIZE / 2.0,
+        HorizontalAlign::Right => 76.0 - CHECKBOX_SIZE,
+    }
+}
+
+/// Demo cells with hand-drawn SVG content
+fn is_rich_demo(col: i32, row: i32) -> bool {
+    (col == 0 && row == 2) || (col == 1 && row == 2)
+}
+
+/// Text the grid shader draws straight from the glyph atlas: plain values that
+/// fit (see `glyph_atlas::fits`), colored from the cell buffer
+fn gpu_text(cell: &crate::cell::Cell, style: &CellStyle, col: i32, row: i32, lens_state: &LensState) -> Option<String> {
+    if !lens_state.show_value || is_rich_demo(col, row) {
+        return None;
+    }
+    match cell.display(style) {
+        CellDisplay::Text(text) if glyph_atlas::fits(&text) => Some(text),
+        _ => None,
+    }
+}
+
+/// SVG for what the shader can't draw itself, None when there's nothing left
+/// `gpu_text`: the value is drawn by the shader (see `gpu_text`), so leave it out
+fn generate_svg(
+    cell: &crate::cell::Cell,
+    style: &CellStyle,
+    col: i32,
+    row: i32,
+    lens_state: &LensState,
+    flagged: bool,
+    gpu_text: bool,
+) -> Option<String> {
     let mut elements = String::new();
 
     // 1. Base Content (Value or Rich)
-    let is_rich = (col == 0 && row == 2) || (col == 1 && row == 2);
+    let is_rich = is_rich_demo(col, row);
     
     if is_rich && lens_state.show_value {
         // Use custom SVG body for rich cells
@@ -859,17 +5377,45 @@ fn generate_svg(cell: &crate::cell::Cell, col: i32, row: i32, lens_state: &LensS
         } else if col == 1 && row == 2 {
             elements.push_str(r##"<circle cx="15" cy="15" r="8" fill="#4caf50"/><text x="30" y="20" font-family="sans-serif" font-size="12" fill="#333">Active</text>"##);
         }
-    } else if lens_state.show_value {
-        // Default text rendering
-        let text = match &cell.value {
-            evalexpr::Value::Int(i) => i.to_string(),
-            evalexpr::Value::Float(f) => format!("{:.2}", f),
-            evalexpr::Value::String(s) => s.clone(),
-            evalexpr::Value::Boolean(b) => b.to_string(),
-            evalexpr::Value::Empty => "".to_string(),
-            evalexpr::Value::Tuple(_) => "Tuple".to_string(),
+    } else if let (true, CellDisplay::Checkbox(checked)) = (lens_state.show_value, cell.display(style)) {
+        let x = checkbox_left(style.align);
+        let stroke = style
+            .text_color
+            .map(|[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
+            .unwrap_or_else(|| "#555555".to_string());
+        elements.push_str(&format!(r##"<rect x="{}" y="8" width="{s}" height="{s}" rx="2" fill="white" stroke="{}" stroke-width="1.5"/>"##, x, stroke, s = CHECKBOX_SIZE));
+        if checked {
+            elements.push_str(&format!(r##"<path d="M{} 15 l3 3.5 l6 -7.5" stroke="{}" stroke-width="2" fill="none"/>"##, x + 3.5, stroke));
+        }
+    } else if lens_state.show_value && !gpu_text {
+        // Default text rendering (errors show their code, in red)
+        let text = match cell.display(style) {
+            CellDisplay::Text(text) => text,
+            CellDisplay::Checkbox(_) => String::new(),
         };
-        elements.push_str(&format!(r##"<text x="40" y="20" font-family="sans-serif" font-size="14" fill="black" text-anchor="middle">{}</text>"##, text));
+        let (x, anchor) = match style.align {
+            HorizontalAlign::Left => (4, "start"),
+            HorizontalAlign::Center => (40, "middle"),
+            HorizontalAlign::Right => (76, "end"),
+        };
+        let fill = style
+            .text_color
+            .filter(|_| !cell.error)
+            .map(|[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
+            .unwrap_or_else(|| if cell.error { "#b71c1c" } else { "black" }.to_string());
+        let weight = if style.bold { r#" font-weight="bold""# } else { "" };
+        let slant = if style.italic { r#" font-style="italic""# } else { "" };
+        elements.push_str(&format!(r##"<text x="{}" y="20" font-family="sans-serif" font-size="14" fill="{}" text-anchor="{}"{}{}>{}</text>"##, x, fill, anchor, weight, slant, text));
+    }
+
+    // Locked cells get a small padlock in the top-right corner
+    if style.locked {
+        elements.push_str(r##"<path d="M71 5 V3.5 a2.5 2.5 0 0 1 5 0 V5" stroke="#9e9e9e" fill="none"/><rect x="70" y="5" width="7" height="5" fill="#9e9e9e"/>"##);
+    }
+
+    // Entries breaking a validation rule get a red top-left corner
+    if flagged {
+        elements.push_str(r##"<path d="M0 0 H8 L0 8 Z" fill="#d32f2f"/>"##);
     }
 
     // 2. Position Lens
@@ -886,5 +5432,8 @@ fn generate_svg(cell: &crate::cell::Cell, col: i32, row: i32, lens_state: &LensS
         elements.push_str(&format!(r##"<text x="2" y="28" font-family="sans-serif" font-size="8" fill="blue">{}</text>"##, formula));
     }
 
-    format!(r##"<svg xmlns="http://www.w3.org/2000/svg" width="80" height="30">{}</svg>"##, elements)
+    if elements.is_empty() {
+        return None;
+    }
+    Some(format!(r##"<svg xmlns="http://www.w3.org/2000/svg" width="80" height="30">{}</svg>"##, elements))
 }
@@ -0,0 +1,89 @@
+use bevy::math::{IRect, IVec2, Rect, Vec2};
+
+use crate::grid_state::GridState;
+
+/// Fraction of the framed area left as margin around the used range and view
+const MARGIN: f32 = 0.1;
+
+/// World-space area of cells with contents (in visual positions, hidden lines
+/// skipped), None for an empty sheet
+pub fn used_world_rect(grid: &GridState, cell_size: Vec2) -> Option<Rect> {
+    let mut visual = grid
+        .cells
+        .keys()
+        .filter_map(|(col, row)| grid.layout.to_visual(col, row))
+        .map(IVec2::from);
+    let first = visual.next()?;
+    let cells = visual.fold(IRect::from_corners(first, first), |bounds, cell| bounds.union_point(cell));
+    // Rows grow downward, so the sheet's top edge has the larger y
+    Some(Rect::new(
+        cells.min.x as f32 * cell_size.x,
+        -(cells.max.y + 1) as f32 * cell_size.y,
+        (cells.max.x + 1) as f32 * cell_size.x,
+        -cells.min.y as f32 * cell_size.y,
+    ))
+}
+
+/// The world area a minimap shows: the used range and the view together,
+/// padded and widened to the map's aspect ratio
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinimapFrame {
+    pub world: Rect,
+}
+
+impl MinimapFrame {
+    /// `aspect`: the map's width over its height
+    pub fn new(used: Option<Rect>, view: Rect, aspect: f32) -> Self {
+        let area = used.map_or(view, |used| used.union(view));
+        let mut size = area.size() * (1.0 + 2.0 * MARGIN);
+        if size.x / size.y < aspect {
+            size.x = size.y * aspect;
+        } else {
+            size.y = size.x / aspect;
+        }
+        Self { world: Rect::from_center_size(area.center(), size) }
+    }
+
+    /// A world rect as fractions of the map, measured from its top-left
+    pub fn to_map(&self, rect: Rect) -> Rect {
+        let size = self.world.size();
+        Rect::new(
+            (rect.min.x - self.world.min.x) / size.x,
+            (self.world.max.y - rect.max.y) / size.y,
+            (rect.max.x - self.world.min.x) / size.x,
+            (self.world.max.y - rect.min.y) / size.y,
+        )
+    }
+
+    /// World point under a spot on the map (fractions from its top-left)
+    pub fn to_world(&self, at: Vec2) -> Vec2 {
+        let size = self.world.size();
+        Vec2::new(self.world.min.x + at.x * size.x, self.world.max.y - at.y * size.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_covers_used_range_and_view() {
+        let mut grid = GridState::new();
+        assert_eq!(used_world_rect(&grid, Vec2::new(80.0, 30.0)), None);
+        grid.set_range((0, 0), [["1", "", "3"]]);
+        grid.set_range((1, 9), [["x"]]);
+        let used = used_world_rect(&grid, Vec2::new(80.0, 30.0)).unwrap();
+        assert_eq!(used, Rect::new(0.0, -300.0, 240.0, 0.0));
+
+        let view = Rect::new(400.0, -100.0, 480.0, 0.0);
+        let frame = MinimapFrame::new(Some(used), view, 1.5);
+        let size = frame.world.size();
+        assert!((size.x / size.y - 1.5).abs() < 1e-4);
+        let map = frame.to_map(view);
+        assert!(map.min.x > 0.0 && map.max.x < 1.0 && map.min.y > 0.0 && map.max.y < 1.0);
+
+        // Round trip through a point on the map
+        let center = frame.to_world(map.center());
+        assert!((center - view.center()).length() < 1e-3);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::formula::name_to_coord;
+use crate::grid_state::{CellRange, GridState};
+
+/// The cell one step from `from` along `step`, moving in visual order so hidden
+/// rows and columns are skipped
+pub fn step(grid: &GridState, from: (i32, i32), step: (i32, i32)) -> (i32, i32) {
+    match grid.layout.to_visual(from.0, from.1) {
+        Some((col, row)) => grid.layout.to_logical(col + step.0, row + step.1),
+        None => (from.0 + step.0, from.1 + step.1),
+    }
+}
+
+/// Ctrl+Arrow: inside a block of filled cells, the block's last cell along
+/// `step`; otherwise the next filled cell, or the used range's edge if there's
+/// none ahead
+pub fn jump(grid: &GridState, from: (i32, i32), step: (i32, i32)) -> (i32, i32) {
+    let Some(start) = grid.layout.to_visual(from.0, from.1) else {
+        return self::step(grid, from, step);
+    };
+    let filled = |(col, row): (i32, i32)| {
+        let (col, row) = grid.layout.to_logical(col, row);
+        grid.get_cell(col, row).is_some_and(|cell| !cell.raw.is_empty())
+    };
+    let advance = |(col, row): (i32, i32)| (col + step.0, row + step.1);
+    let Some((min, max)) = used_visual_bounds(grid) else {
+        return grid.layout.to_logical(advance(start).0, advance(start).1);
+    };
+    // Whether `pos` is at or past the used range's edge, so nothing lies ahead
+    let at_edge = |(col, row): (i32, i32)| {
+        (step.0 > 0 && col >= max.0)
+            || (step.0 < 0 && col <= min.0)
+            || (step.1 > 0 && row >= max.1)
+            || (step.1 < 0 && row <= min.1)
+    };
+
+    let mut pos = start;
+    if filled(pos) && filled(advance(pos)) {
+        while filled(advance(pos)) {
+            pos = advance(pos);
+        }
+    } else {
+        pos = advance(pos);
+        while !filled(pos) && !at_edge(pos) {
+            pos = advance(pos);
+        }
+    }
+    grid.layout.to_logical(pos.0, pos.1)
+}
+
+/// Where the name box (Go To) sends the selection: a cell (`BZ250`), a range
+/// (`A1:C5`), a structured reference (`[Price]5`), or a column or row label,
+/// meaning that line out to its last used cell
+pub fn go_to_target(grid: &GridState, text: &str) -> Option<CellRange> {
+    let text = text.trim();
+    let cell = |name: &str| {
+        let resolved = grid.headers.resolve(name.trim()).ok()?;
+        name_to_coord(&resolved.to_ascii_uppercase())
+    };
+    if let Some((from, to)) = text.split_once(':') {
+        return Some(CellRange::new(cell(from)?, cell(to)?));
+    }
+    if let Some((col, row)) = cell(text) {
+        return Some(CellRange::cell(col, row));
+    }
+    let last_used = |along: fn((i32, i32)) -> i32| grid.cells.keys().map(along).max().unwrap_or(0).max(0);
+    if let Some(col) = grid.headers.find_col(text) {
+        return Some(CellRange::new((col, 0), (col, last_used(|(_, row)| row))));
+    }
+    let row = grid.headers.find_row(text)?;
+    Some(CellRange::new((0, row), (last_used(|(col, _)| col), row)))
+}
+
+/// Corners of the filled cells' visual positions, None for an empty sheet
+fn used_visual_bounds(grid: &GridState) -> Option<((i32, i32), (i32, i32))> {
+    grid.cells
+        .iter()
+        .filter(|(_, cell)| !cell.raw.is_empty())
+        .filter_map(|((col, row), _)| grid.layout.to_visual(col, row))
+        .fold(None, |bounds, (col, row)| match bounds {
+            None => Some(((col, row), (col, row))),
+            Some((min, max)) => Some(((col.min(min.0), row.min(min.1)), (col.max(max.0), row.max(max.1)))),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arrow_steps_and_jumps() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["a", "b", "c", "", "", "f"]]);
+        grid.set_range((0, 4), [["x"]]);
+
+        // From inside a block to its end, then on to the next block
+        assert_eq!(jump(&grid, (0, 0), (1, 0)), (2, 0));
+        assert_eq!(jump(&grid, (2, 0), (1, 0)), (5, 0));
+        assert_eq!(jump(&grid, (5, 0), (-1, 0)), (2, 0));
+        // Down from an isolated cell to the next filled one
+        assert_eq!(jump(&grid, (0, 0), (0, 1)), (0, 4));
+        // Nothing ahead: stop at the used range's edge, or just step past it
+        assert_eq!(jump(&grid, (1, 1), (0, 1)), (1, 4));
+        assert_eq!(jump(&grid, (0, 4), (0, 1)), (0, 5));
+
+        // Hidden lines are stepped over
+        grid.layout.cols.hide(1);
+        assert_eq!(step(&grid, (0, 0), (1, 0)), (2, 0));
+        assert_eq!(step(&grid, (2, 3), (-1, 0)), (0, 3));
+    }
+
+    #[test]
+    fn test_go_to_target() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["Item", "Price"], ["a", "3"], ["b", "4"]]);
+        grid.headers.set_col_label(1, "Price");
+        grid.headers.set_row_label(2, "Last");
+
+        assert_eq!(go_to_target(&grid, " bz250 "), Some(CellRange::cell(77, 250)));
+        assert_eq!(go_to_target(&grid, "C5:A1"), Some(CellRange::new((0, 1), (2, 5))));
+        assert_eq!(go_to_target(&grid, "[Price]2"), Some(CellRange::cell(1, 2)));
+        assert_eq!(go_to_target(&grid, "Price"), Some(CellRange::new((1, 0), (1, 2))));
+        assert_eq!(go_to_target(&grid, "Last"), Some(CellRange::new((0, 2), (1, 2))));
+        assert_eq!(go_to_target(&grid, "Nowhere"), None);
+    }
+}
@@ -0,0 +1,571 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::evaluator::TickControl;
+use crate::grid_state::GridState;
+
+/// Tag identifying gregsheet files
+pub const FORMAT: &str = "gregsheet";
+
+/// Current save format version
+/// Bump it (and add a step to `MIGRATIONS`, plus a fixture) whenever the saved
+/// shape changes; binary saves can't absorb even defaulted fields, so mark
+/// those with `since`
+pub const FORMAT_VERSION: u32 = 7;
+
+/// A step upgrading a saved document by one version, on the raw JSON so it
+/// doesn't depend on today's types
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// `MIGRATIONS[i]` turns a version `i + 1` document into version `i + 2`
+const MIGRATIONS: [Migration; 6] = [v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7];
+
+/// v2 renamed the envelope's `version` to `format_version`
+/// (the sheet itself only gained fields with defaults: themes, data feeds)
+fn v1_to_v2(doc: &mut Map<String, Value>) -> Result<(), String> {
+    doc.remove("version");
+    Ok(())
+}
+
+/// v3 added the sheet's `banded` table region, which defaults to none
+/// (bumped for binary saves, see `since`)
+fn v2_to_v3(_doc: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// v4 added the sheet's `charts`, which default to none
+fn v3_to_v4(_doc: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// v5 added per-side `borders` to cell styles, which default to none
+fn v4_to_v5(_doc: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// v6 added cells' `error_code`, which defaults to the generic error
+fn v5_to_v6(_doc: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// v7 added the layout's `col_widths`, which default to none resized
+fn v6_to_v7(_doc: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// Oldest version `load_binary` reads: the first with a binary format
+const FIRST_BINARY_VERSION: u32 = 2;
+
+/// First bytes of a binary save
+const BINARY_MAGIC: &[u8; 4] = b"GSHB";
+
+/// Where the Save and Load buttons keep the workbook (native builds)
+#[cfg(not(target_arch = "wasm32"))]
+pub const SAVE_PATH: &str = "gregsheet.json";
+
+/// What's actually written to disk: the sheet wrapped with format and version tags
+#[derive(Serialize, Deserialize)]
+struct Envelope<T, K> {
+    format: String,
+    format_version: u32,
+    sheet: T,
+    /// Tick settings (auto tick, tick count); older files without them load with the defaults
+    #[serde(default)]
+    ticks: K,
+}
+
+/// Serialize the workbook (cells, styles, header names, settings and tick state) to JSON
+pub fn save_json(grid: &GridState, ticks: &TickControl) -> Result<String, String> {
+    let envelope = Envelope { format: FORMAT.to_string(), format_version: FORMAT_VERSION, sheet: grid, ticks };
+    serde_json::to_string_pretty(&envelope).map_err(|e| e.to_string())
+}
+
+/// `save_json`'s envelope as a JSON value, for stores that keep part of it
+/// elsewhere (the SQLite store keeps the cells in their own table)
+pub fn envelope_value(grid: &GridState, ticks: &TickControl) -> Result<Value, String> {
+    let envelope = Envelope { format: FORMAT.to_string(), format_version: FORMAT_VERSION, sheet: grid, ticks };
+    serde_json::to_value(&envelope).map_err(|e| e.to_string())
+}
+
+/// Load a workbook saved by `save_json` with any version of gregsheet
+/// Older files are migrated one version at a time; files from newer versions
+/// are refused rather than half-read
+pub fn load_json(text: &str) -> Result<(GridState, TickControl), String> {
+    load_document(serde_json::from_str(text).map_err(|e| e.to_string())?)
+}
+
+/// `load_json` for an envelope that's already parsed (see `envelope_value`)
+pub fn load_document(mut doc: Map<String, Value>) -> Result<(GridState, TickControl), String> {
+    let format = doc.get("format").and_then(Value::as_str).unwrap_or_default();
+    if format != FORMAT {
+        return Err(format!("not a gregsheet file (format {:?})", format));
+    }
+    // v1 called it `version`
+    let version = doc
+        .get("format_version")
+        .or_else(|| doc.get("version"))
+        .and_then(Value::as_u64)
+        .filter(|v| *v >= 1)
+        .ok_or("missing format version")? as u32;
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "saved by a newer version (format v{}, this build reads up to v{})",
+            version, FORMAT_VERSION
+        ));
+    }
+    for migrate in &MIGRATIONS[version as usize - 1..] {
+        migrate(&mut doc)?;
+    }
+    doc.insert("format_version".to_string(), FORMAT_VERSION.into());
+
+    let envelope: Envelope<GridState, TickControl> = serde_json::from_value(Value::Object(doc)).map_err(|e| e.to_string())?;
+    Ok((envelope.sheet, envelope.ticks))
+}
+
+/// Body of a binary save, after the magic and version byte
+#[derive(Serialize, Deserialize)]
+struct Binary<T, K> {
+    sheet: T,
+    ticks: K,
+}
+
+/// Serialize the workbook to the compact binary format (postcard)
+/// Much smaller and faster than JSON for large sheets, but not self-describing:
+/// files are `BINARY_MAGIC`, one version byte, then the body
+pub fn save_binary(grid: &GridState, ticks: &TickControl) -> Result<Vec<u8>, String> {
+    let mut bytes = BINARY_MAGIC.to_vec();
+    bytes.push(FORMAT_VERSION as u8);
+    postcard::to_extend(&Binary { sheet: grid, ticks }, bytes).map_err(|e| e.to_string())
+}
+
+/// Load a workbook saved by `save_binary`
+/// The format has no field names, so older versions are read by skipping the
+/// fields added since (see `since`)
+pub fn load_binary(bytes: &[u8]) -> Result<(GridState, TickControl), String> {
+    let body = bytes.strip_prefix(BINARY_MAGIC).ok_or("not a gregsheet binary file")?;
+    let (&version, body) = body.split_first().ok_or("truncated file")?;
+    let version = version as u32;
+    if !(FIRST_BINARY_VERSION..=FORMAT_VERSION).contains(&version) {
+        return Err(format!(
+            "binary format v{} can't be read (this build reads v{} to v{})",
+            version, FIRST_BINARY_VERSION, FORMAT_VERSION
+        ));
+    }
+    let binary: Binary<GridState, TickControl> =
+        since::reading(version, || postcard::from_bytes(body)).map_err(|e| e.to_string())?;
+    Ok((binary.sheet, binary.ticks))
+}
+
+/// Store a save where `read_save` will find it
+pub fn write_save(text: String) -> Result<(), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let result = std::fs::write(SAVE_PATH, text).map_err(|e| format!("{}: {}", SAVE_PATH, e));
+    #[cfg(target_arch = "wasm32")]
+    let result = {
+        web::store(text);
+        Ok(())
+    };
+    result
+}
+
+/// The last save written by `write_save`
+pub fn read_save() -> Result<String, String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let result = std::fs::read_to_string(SAVE_PATH).map_err(|e| format!("{}: {}", SAVE_PATH, e));
+    #[cfg(target_arch = "wasm32")]
+    let result = web::saved().ok_or_else(|| "nothing saved yet".to_string());
+    result
+}
+
+/// Browsers have no filesystem: saves go to an in-memory slot that the page
+/// reads (`save_json`) and fills (`load_json`) through these exports
+#[cfg(target_arch = "wasm32")]
+pub mod web {
+    use std::sync::Mutex;
+    use wasm_bindgen::prelude::*;
+
+    static SAVED: Mutex<Option<String>> = Mutex::new(None);
+    /// Workbook handed over by the page, applied on the next frame
+    static PENDING: Mutex<Option<String>> = Mutex::new(None);
+
+    pub fn store(text: String) {
+        *SAVED.lock().unwrap() = Some(text);
+    }
+
+    pub fn saved() -> Option<String> {
+        SAVED.lock().unwrap().clone()
+    }
+
+    pub fn take_pending() -> Option<String> {
+        PENDING.lock().unwrap().take()
+    }
+
+    /// The last workbook saved with the Save button
+    #[wasm_bindgen(js_name = save_json)]
+    pub fn js_save_json() -> Option<String> {
+        saved()
+    }
+
+    /// Replace the open workbook with one saved earlier
+    #[wasm_bindgen(js_name = load_json)]
+    pub fn js_load_json(text: String) {
+        *PENDING.lock().unwrap() = Some(text);
+    }
+
+    /// localStorage key holding the autosaved workbook
+    const AUTOSAVE_KEY: &str = "gregsheet.autosave";
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    /// Keep a copy of the workbook (a `save_binary` save) that survives a tab refresh
+    /// localStorage only holds strings, so each byte is stored as one char
+    /// Fails quietly when storage is unavailable (private mode, quota)
+    pub fn autosave(bytes: &[u8]) {
+        if let Some(storage) = local_storage() {
+            let text: String = bytes.iter().map(|&b| b as char).collect();
+            let _ = storage.set_item(AUTOSAVE_KEY, &text);
+        }
+    }
+
+    /// The autosaved workbook, if the user chooses to restore it
+    /// Declining discards it so the prompt doesn't come back
+    pub fn restore_session() -> Option<(crate::grid_state::GridState, crate::evaluator::TickControl)> {
+        let storage = local_storage()?;
+        let text = storage.get_item(AUTOSAVE_KEY).ok()??;
+        let restore = web_sys::window()?
+            .confirm_with_message("Restore your previous session?")
+            .unwrap_or(false);
+        if !restore {
+            let _ = storage.remove_item(AUTOSAVE_KEY);
+            return None;
+        }
+        let bytes: Option<Vec<u8>> = text.chars().map(|c| u8::try_from(c).ok()).collect();
+        // Autosaves from before the binary format are JSON
+        match bytes.map(|bytes| super::load_binary(&bytes)) {
+            Some(Ok(loaded)) => Some(loaded),
+            _ => super::load_json(&text).ok(),
+        }
+    }
+}
+
+/// Serde adapter for evalexpr values, which don't implement serde themselves
+/// Use with `#[serde(with = "crate::persist::value_serde")]`
+pub mod value_serde {
+    use evalexpr::Value;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Saved mirror of `evalexpr::Value`
+    #[derive(Serialize, Deserialize)]
+    enum SavedValue {
+        Empty,
+        Int(i64),
+        Float(f64),
+        Boolean(bool),
+        String(String),
+        Tuple(Vec<SavedValue>),
+    }
+
+    impl From<&Value> for SavedValue {
+        fn from(value: &Value) -> Self {
+            match value {
+                Value::Empty => SavedValue::Empty,
+                Value::Int(i) => SavedValue::Int(*i),
+                Value::Float(f) => SavedValue::Float(*f),
+                Value::Boolean(b) => SavedValue::Boolean(*b),
+                Value::String(s) => SavedValue::String(s.clone()),
+                Value::Tuple(t) => SavedValue::Tuple(t.iter().map(SavedValue::from).collect()),
+            }
+        }
+    }
+
+    impl From<SavedValue> for Value {
+        fn from(value: SavedValue) -> Self {
+            match value {
+                SavedValue::Empty => Value::Empty,
+                SavedValue::Int(i) => Value::Int(i),
+                SavedValue::Float(f) => Value::Float(f),
+                SavedValue::Boolean(b) => Value::Boolean(b),
+                SavedValue::String(s) => Value::String(s),
+                SavedValue::Tuple(t) => Value::Tuple(t.into_iter().map(Value::from).collect()),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+        SavedValue::from(value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        SavedValue::deserialize(deserializer).map(Value::from)
+    }
+}
+
+/// Fields added to the saved shape since the binary format
+/// Binary saves are read positionally, so a field a save's version didn't have
+/// must be skipped rather than read; JSON saves just leave it out
+/// Use with `#[serde(deserialize_with = "crate::persist::since::v3")]` (the
+/// version that added the field) alongside a default
+pub mod since {
+    use serde::{Deserialize, Deserializer};
+    use std::cell::Cell;
+
+    thread_local! {
+        /// Version of the binary save being read
+        static READING: Cell<u32> = const { Cell::new(super::FORMAT_VERSION) };
+    }
+
+    /// Run `read` as reading a save of `version`
+    pub fn reading<T>(version: u32, read: impl FnOnce() -> T) -> T {
+        let outer = READING.replace(version);
+        let result = read();
+        READING.set(outer);
+        result
+    }
+
+    fn field<'de, D: Deserializer<'de>, T: Deserialize<'de> + Default>(added: u32, deserializer: D) -> Result<T, D::Error> {
+        if READING.get() < added {
+            Ok(T::default())
+        } else {
+            T::deserialize(deserializer)
+        }
+    }
+
+    pub fn v3<'de, D: Deserializer<'de>, T: Deserialize<'de> + Default>(deserializer: D) -> Result<T, D::Error> {
+        field(3, deserializer)
+    }
+
+    pub fn v4<'de, D: Deserializer<'de>, T: Deserialize<'de> + Default>(deserializer: D) -> Result<T, D::Error> {
+        field(4, deserializer)
+    }
+
+    pub fn v5<'de, D: Deserializer<'de>, T: Deserialize<'de> + Default>(deserializer: D) -> Result<T, D::Error> {
+        field(5, deserializer)
+    }
+
+    pub fn v6<'de, D: Deserializer<'de>, T: Deserialize<'de> + Default>(deserializer: D) -> Result<T, D::Error> {
+        field(6, deserializer)
+    }
+
+    pub fn v7<'de, D: Deserializer<'de>, T: Deserialize<'de> + Default>(deserializer: D) -> Result<T, D::Error> {
+        field(7, deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::NumberFormat;
+    use crate::grid_state::CellRange;
+    use crate::validation::{InvalidAction, Validation, ValidationRule};
+    use evalexpr::Value;
+
+    #[test]
+    fn test_roundtrip_keeps_the_whole_model() {
+        let mut grid = GridState::new();
+        grid.set_range((-1, -1), [["4", "= _A_1 * 2", "hi"]]);
+        grid.run_ticks(1);
+        grid.get_cell_mut_or_create(0, -1).style.number_format = NumberFormat::Currency;
+        grid.get_cell_mut_or_create(0, -1).style.locked = true;
+        grid.layout.rows.hide(3);
+        grid.layout.frozen_cols = 1;
+        grid.headers.set_col_label(0, "Price");
+        grid.table_filter = Some(crate::filter::TableFilter::new(CellRange::new((0, 0), (2, 5))));
+        grid.validations.push(Validation {
+            range: CellRange::cell(4, 4),
+            rule: ValidationRule::Range { min: Some(0.0), max: None },
+            on_invalid: InvalidAction::Flag,
+        });
+        grid.protected = true;
+        grid.selected.insert((0, 0));
+        let theme_id = grid.theme.find("Input").unwrap();
+        grid.get_cell_mut_or_create(1, -1).style.named = Some(theme_id);
+        grid.theme.restyle(theme_id, crate::cell::CellStyle { italic: true, ..Default::default() });
+        let ticks = TickControl { auto_tick_enabled: true, manual_tick_requested: true, tick_count: 42, ..Default::default() };
+
+        let (loaded, loaded_ticks) = load_json(&save_json(&grid, &ticks).unwrap()).unwrap();
+
+        assert_eq!(loaded.cells.len(), 3);
+        let cell = loaded.get_cell(0, -1).unwrap();
+        assert_eq!(cell.raw, "= _A_1 * 2");
+        assert_eq!(cell.value, Value::Int(8));
+        assert!(cell.is_formula);
+        assert_eq!(cell.style, grid.get_cell(0, -1).unwrap().style);
+        assert_eq!(loaded.get_cell(1, -1).unwrap().value, Value::String("hi".to_string()));
+        assert_eq!(loaded.layout, grid.layout);
+        assert_eq!(loaded.headers, grid.headers);
+        assert_eq!(loaded.table_filter, grid.table_filter);
+        assert_eq!(loaded.validations, grid.validations);
+        assert!(loaded.protected);
+        assert_eq!(loaded.theme, grid.theme);
+        assert_eq!(loaded.get_cell(1, -1).unwrap().style.named, Some(theme_id));
+        assert!(loaded_ticks.auto_tick_enabled);
+        assert_eq!(loaded_ticks.tick_count, 42);
+        // The selection and pending manual ticks are session state
+        assert!(loaded.selected.is_empty());
+        assert!(!loaded_ticks.manual_tick_requested);
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let mut grid = GridState::new();
+        grid.set_range((-2, 0), [["1.5", "= _B0 * 2", "true"]]);
+        grid.run_ticks(1);
+        grid.get_cell_mut_or_create(-2, 0).style.bold = true;
+        grid.headers.set_row_label(0, "Total");
+        let ticks = TickControl { tick_count: 9, ..Default::default() };
+
+        let bytes = save_binary(&grid, &ticks).unwrap();
+        assert!(bytes.starts_with(b"GSHB"));
+        assert!(bytes.len() < save_json(&grid, &ticks).unwrap().len());
+
+        let (loaded, loaded_ticks) = load_binary(&bytes).unwrap();
+        assert_eq!(loaded.get_cell(-1, 0).unwrap().value, Value::Float(3.0));
+        assert_eq!(loaded.get_cell(0, 0).unwrap().value, Value::Boolean(true));
+        assert!(loaded.get_cell(-2, 0).unwrap().style.bold);
+        assert_eq!(loaded.headers, grid.headers);
+        assert_eq!(loaded_ticks.tick_count, 9);
+
+        let mut newer = bytes.clone();
+        newer[4] += 1;
+        assert!(load_binary(&newer).is_err());
+        assert!(load_binary(b"{}").is_err());
+        assert!(load_binary(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_envelope_is_checked() {
+        let saved = save_json(&GridState::new(), &TickControl::default()).unwrap();
+        let current = format!("\"format_version\": {}", FORMAT_VERSION);
+        assert!(saved.contains(&current));
+
+        let newer = saved.replace(&current, "\"format_version\": 99");
+        assert!(load_json(&newer).unwrap_err().contains("newer version"));
+
+        let foreign = saved.replace("\"gregsheet\"", "\"other\"");
+        assert!(load_json(&foreign).is_err());
+        assert!(load_json(r#"{"format": "gregsheet", "sheet": {}}"#).is_err());
+
+        // Missing sections fall back to defaults
+        let sparse = r#"{"format": "gregsheet", "version": 1, "sheet": {"cells": [[[2, 3], {"raw": "7"}]]}}"#;
+        let (loaded, ticks) = load_json(sparse).unwrap();
+        assert_eq!(loaded.get_cell(2, 3).unwrap().raw, "7");
+        assert!(!loaded.protected);
+        assert_eq!(ticks.tick_count, 0);
+    }
+
+    #[test]
+    fn test_every_version_loads() {
+        // One fixture per format version, as that version's build saved it
+        let fixtures = [
+            include_str!("../fixtures/workbook_v1.json"),
+            include_str!("../fixtures/workbook_v2.json"),
+            include_str!("../fixtures/workbook_v3.json"),
+            include_str!("../fixtures/workbook_v4.json"),
+            include_str!("../fixtures/workbook_v5.json"),
+            include_str!("../fixtures/workbook_v6.json"),
+            include_str!("../fixtures/workbook_v7.json"),
+        ];
+        assert_eq!(fixtures.len(), FORMAT_VERSION as usize, "add a fixture for the new version");
+
+        for fixture in fixtures {
+            let (grid, ticks) = load_json(fixture).unwrap();
+            let cell = grid.get_cell(0, 0).unwrap();
+            assert_eq!(cell.value, Value::Int(4));
+            assert!(cell.style.bold);
+            assert_eq!(cell.style.number_format, NumberFormat::Fixed(1));
+            assert_eq!(grid.get_cell(1, 0).unwrap().raw, "= A0 * 2");
+            assert!(grid.get_cell(1, 0).unwrap().style.locked);
+            assert_eq!(grid.get_cell(0, 1).unwrap().style.named, Some(0));
+            assert!(grid.layout.rows.is_hidden(3));
+            assert_eq!(grid.layout.frozen_rows, 1);
+            assert_eq!(grid.validations.len(), 1);
+            assert!(grid.protected);
+            assert_eq!(grid.headers.col_label(0), "Qty");
+            assert_eq!(ticks.tick_count, 12);
+
+            // Migrated files save as the current version
+            assert!(save_json(&grid, &ticks).unwrap().contains(&format!("\"format_version\": {}", FORMAT_VERSION)));
+        }
+
+        // Fields added since v1 load with their defaults
+        let (v1, _) = load_json(fixtures[0]).unwrap();
+        assert_eq!(v1.theme, crate::theme::Theme::default());
+        assert!(v1.feeds.is_empty());
+        let (v2, ticks) = load_json(fixtures[1]).unwrap();
+        assert_eq!(v2.theme.styles[0].name, "Accent");
+        assert_eq!(v2.feeds[0].pointer, "/price");
+        assert!(ticks.auto_tick_enabled);
+        let (v3, _) = load_json(fixtures[2]).unwrap();
+        assert_eq!(v3.banded, Some(CellRange::new((0, 0), (1, 4))));
+        assert!(v3.charts.is_empty());
+        let (v4, _) = load_json(fixtures[3]).unwrap();
+        assert_eq!(v4.charts[0].kind, crate::chart::ChartKind::Bar);
+        let (v5, _) = load_json(fixtures[4]).unwrap();
+        assert_eq!(v5.get_cell(1, 0).unwrap().style.borders.bottom.line, crate::cell::BorderLine::Thick);
+        let (v6, _) = load_json(fixtures[5]).unwrap();
+        assert_eq!(v6.get_cell(1, 0).unwrap().error_code, crate::cell::ErrorCode::DivZero);
+        let (v7, _) = load_json(fixtures[6]).unwrap();
+        assert_eq!(v7.layout.col_widths.get(&1), Some(&140));
+    }
+
+    #[test]
+    fn test_v6_binary_loads() {
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["4", "= A0 * 2"]]);
+        grid.run_ticks(1);
+        grid.layout.frozen_rows = 1;
+        grid.banded = Some(CellRange::new((0, 0), (1, 3)));
+        let ticks = TickControl { tick_count: 5, ..Default::default() };
+
+        // The sheet as v6 wrote it: the layout without `col_widths`
+        let layout = &grid.layout;
+        let sheet = (
+            &grid.cells,
+            (&layout.rows, &layout.cols, layout.frozen_cols, layout.frozen_rows),
+            &grid.table_filter,
+            &grid.banded,
+            &grid.validations,
+            grid.protected,
+            &grid.headers,
+            &grid.theme,
+            &grid.feeds,
+            &grid.charts,
+        );
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.push(6);
+        let bytes = postcard::to_extend(&(sheet, &ticks), bytes).unwrap();
+
+        let (loaded, loaded_ticks) = load_binary(&bytes).unwrap();
+        assert_eq!(loaded.get_cell(1, 0).unwrap().value, Value::Int(8));
+        assert_eq!(loaded.layout.frozen_rows, 1);
+        assert!(loaded.layout.col_widths.is_empty());
+        assert_eq!(loaded.banded, grid.banded);
+        assert_eq!(loaded_ticks.tick_count, 5);
+    }
+
+    #[test]
+    fn test_older_binary_saves_skip_new_fields() {
+        #[derive(Serialize)]
+        struct Before {
+            kept: u8,
+            after: u8,
+        }
+        #[derive(Deserialize)]
+        struct Since {
+            kept: u8,
+            #[serde(deserialize_with = "since::v3", default)]
+            added: Option<u8>,
+            after: u8,
+        }
+
+        let old = postcard::to_stdvec(&Before { kept: 1, after: 2 }).unwrap();
+        let read: Since = since::reading(2, || postcard::from_bytes(&old)).unwrap();
+        assert_eq!((read.kept, read.added, read.after), (1, None, 2));
+
+        let new = postcard::to_stdvec(&(1u8, Some(5u8), 2u8)).unwrap();
+        let read: Since = postcard::from_bytes(&new).unwrap();
+        assert_eq!((read.kept, read.added, read.after), (1, Some(5), 2));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::grid_state::CellRange;
+
+/// The selected cells, as rectangles: usually one, more after Ctrl+click
+/// The last range is the one being worked on: dragging and Shift+click
+/// reshape it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    ranges: Vec<CellRange>,
+}
+
+impl Selection {
+    pub fn ranges(&self) -> &[CellRange] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Select just `range`
+    pub fn set(&mut self, range: CellRange) {
+        self.ranges = vec![range];
+    }
+
+    /// Add `range` alongside what's already selected
+    pub fn add(&mut self, range: CellRange) {
+        self.ranges.push(range);
+    }
+
+    /// Add a single cell alongside what's already selected
+    pub fn insert(&mut self, cell: (i32, i32)) {
+        self.add(CellRange::cell(cell.0, cell.1));
+    }
+
+    /// Replace the last range (or select `range` if there's none)
+    pub fn set_last(&mut self, range: CellRange) {
+        match self.ranges.last_mut() {
+            Some(last) => *last = range,
+            None => self.ranges.push(range),
+        }
+    }
+
+    pub fn contains(&self, cell: &(i32, i32)) -> bool {
+        self.ranges.iter().any(|range| range.contains(cell.0, cell.1))
+    }
+
+    /// Every selected cell, once even where ranges overlap
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.ranges.iter().enumerate().flat_map(move |(i, range)| {
+            range.iter().filter(move |&(col, row)| !self.ranges[..i].iter().any(|r| r.contains(col, row)))
+        })
+    }
+
+    /// Number of selected cells
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// The selection with each range mapped through `f` (None drops it)
+    pub fn map_ranges(&self, f: impl FnMut(CellRange) -> Option<CellRange>) -> Self {
+        Self { ranges: self.ranges.iter().copied().filter_map(f).collect() }
+    }
+
+    /// Bounding box of all the ranges
+    pub fn bounds(&self) -> Option<CellRange> {
+        self.ranges.iter().copied().reduce(|a, b| CellRange {
+            min_col: a.min_col.min(b.min_col),
+            min_row: a.min_row.min(b.min_row),
+            max_col: a.max_col.max(b.max_col),
+            max_row: a.max_row.max(b.max_row),
+        })
+    }
+}
+
+impl Extend<(i32, i32)> for Selection {
+    fn extend<I: IntoIterator<Item = (i32, i32)>>(&mut self, cells: I) {
+        for cell in cells {
+            self.insert(cell);
+        }
+    }
+}
+
+impl FromIterator<(i32, i32)> for Selection {
+    fn from_iter<I: IntoIterator<Item = (i32, i32)>>(cells: I) -> Self {
+        let mut selection = Self::default();
+        selection.extend(cells);
+        selection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges_overlap_once() {
+        let mut selection = Selection::default();
+        selection.set(CellRange::new((0, 0), (1, 1)));
+        selection.add(CellRange::new((1, 1), (2, 1)));
+        assert_eq!(selection.len(), 5);
+        assert!(selection.contains(&(2, 1)) && !selection.contains(&(2, 0)));
+        assert_eq!(selection.bounds(), Some(CellRange::new((0, 0), (2, 1))));
+
+        // Reshaping the last range leaves the others alone
+        selection.set_last(CellRange::new((5, 5), (5, 6)));
+        assert_eq!(selection.iter().collect::<Vec<_>>(), vec![(0, 0), (1, 0), (0, 1), (1, 1), (5, 5), (5, 6)]);
+    }
+}
@@ -0,0 +1,251 @@
+use bevy::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::crdt::{LineOp, Replica};
+use crate::events::{CellChanged, ChangeSource};
+use crate::evaluator::TickControl;
+use crate::grid_state::GridState;
+use crate::journal::{self, Journal, JournalEntry};
+use crate::sync::{self, Channel, RemoteEdit, SyncMessage, ValueOp};
+
+/// Where `--serve` listens when no address follows it: this machine only
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8737";
+/// Base name of the served workbook's files without `--workbook`, apart from
+/// the ones a window on the same machine keeps (see `journal::use_workbook`)
+const DEFAULT_WORKBOOK: &str = "gregsheet-server";
+
+/// The address to serve on, if started with `--serve [address]`
+/// Serving to other machines takes an explicit address, like `0.0.0.0:8737`
+pub fn address_from_args() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let at = args.iter().position(|arg| arg == "--serve")?;
+    Some(args.get(at + 1).filter(|arg| !arg.starts_with("--")).cloned().unwrap_or_else(|| DEFAULT_ADDRESS.to_string()))
+}
+
+/// Where the served workbook is kept: `--workbook <path>` names its files
+/// (`<path>.snapshot` and so on)
+pub fn workbook_from_args() -> String {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == "--workbook")
+        .and_then(|at| args.get(at + 1))
+        .filter(|arg| !arg.starts_with("--"))
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_WORKBOOK.to_string())
+}
+
+/// Hosts the workbook for sync clients (see `sync`): what they send is
+/// applied here and passed on to the others, newcomers are greeted with the
+/// whole sheet, and every tick's values go out to everyone
+/// One thread per client trades its messages; the sheet is only touched by
+/// the systems below
+#[derive(Resource)]
+pub struct SyncServer {
+    replica: Replica,
+    /// Every line edit so far, for bringing newcomers' line identities in step
+    lines: Vec<LineOp>,
+    /// Connections accepted since the last frame
+    joined: Arc<Mutex<Vec<Arc<Channel>>>>,
+    clients: Vec<Arc<Channel>>,
+}
+
+impl SyncServer {
+    /// Start accepting clients on `address`
+    pub fn listen(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let joined: Arc<Mutex<Vec<Arc<Channel>>>> = Arc::default();
+        let accepted = joined.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let channel = Arc::<Channel>::default();
+                accepted.lock().unwrap().push(channel.clone());
+                thread::spawn(move || serve(stream, channel));
+            }
+        });
+        info!("Serving the workbook on ws://{}", address);
+        Ok(Self { replica: Replica::new(0), lines: Vec::new(), joined, clients: Vec::new() })
+    }
+
+    fn post(channel: &Channel, message: &SyncMessage) {
+        if let Ok(text) = serde_json::to_string(message) {
+            channel.outgoing.lock().unwrap().push(text);
+        }
+    }
+
+    /// Pass a client's message on to the other clients, returning whether it
+    /// went: only edits and presence do, since the sheet and the tick values
+    /// come from the server alone
+    fn relay(&self, from: usize, message: &SyncMessage) -> bool {
+        match message {
+            SyncMessage::Cells { .. } | SyncMessage::Lines { .. } | SyncMessage::Presence(_) => {}
+            SyncMessage::Sheet { .. } | SyncMessage::Values { .. } => {
+                warn!("Sync server: ignoring a sheet or values sent by a client");
+                return false;
+            }
+        }
+        for (_, other) in self.clients.iter().enumerate().filter(|(other, _)| *other != from) {
+            Self::post(other, message);
+        }
+        true
+    }
+
+    /// The whole sheet, then every formula's value
+    fn greet(&self, channel: &Channel, grid: &GridState, tick: u64) {
+        let entries: Vec<JournalEntry> = grid
+            .cells
+            .iter()
+            .map(|((col, row), cell)| JournalEntry { col, row, content: Some(cell.content()) })
+            .collect();
+        Self::post(channel, &SyncMessage::Sheet { lines: self.lines.clone(), cells: self.replica.snapshot(&entries) });
+        let values = grid
+            .cells
+            .iter()
+            .filter(|(_, cell)| cell.is_formula)
+            .map(|((col, row), cell)| ValueOp { cell: self.replica.cell_id(col, row), value: cell.value.clone() })
+            .collect();
+        Self::post(channel, &SyncMessage::Values { tick, values });
+    }
+}
+
+/// Trade messages with one client until it goes
+fn serve(stream: TcpStream, channel: Arc<Channel>) {
+    match tungstenite::accept(stream) {
+        Ok(mut socket) => {
+            let _ = socket.get_ref().set_read_timeout(Some(sync::POLL));
+            sync::pump(&mut socket, &channel);
+        }
+        Err(e) => warn!("Sync server: handshake failed: {}", e),
+    }
+    channel.stop.store(true, Ordering::Relaxed);
+}
+
+/// Greet new clients, then apply what each client sent and pass it on to the
+/// rest, before the tick evaluates
+/// Line edits checkpoint the workbook (the journal only holds cell contents)
+pub fn serve_clients(
+    mut server: ResMut<SyncServer>,
+    mut grid_state: ResMut<GridState>,
+    tick_control: Res<TickControl>,
+    journal: Res<Journal>,
+    mut cell_changed: MessageWriter<CellChanged>,
+) {
+    if let Some(entries) = journal.take_replay() {
+        cell_changed.write_batch(journal::replay(&mut grid_state, &entries));
+    }
+    let joined = std::mem::take(&mut *server.joined.lock().unwrap());
+    for channel in joined {
+        server.greet(&channel, &grid_state, tick_control.tick_count);
+        server.clients.push(channel);
+    }
+    server.clients.retain(|channel| !channel.stop.load(Ordering::Relaxed));
+
+    let server = &mut *server;
+    for (index, channel) in server.clients.iter().enumerate() {
+        let incoming = std::mem::take(&mut *channel.incoming.lock().unwrap());
+        for text in incoming {
+            let message = match serde_json::from_str::<SyncMessage>(&text) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Sync server: unreadable message: {}", e);
+                    continue;
+                }
+            };
+            if let SyncMessage::Lines { op } = &message {
+                if !op.is_valid() {
+                    warn!("Sync server: ignoring an invalid line edit: {:?}", op);
+                    continue;
+                }
+            }
+            // Passed on as parsed, so only well-formed messages reach the others
+            if !server.relay(index, &message) {
+                continue;
+            }
+            match message {
+                SyncMessage::Cells { cells } => {
+                    let entries = server.replica.remote_cells(cells);
+                    journal.append(&entries);
+                    cell_changed.write_batch(sync::apply(&mut grid_state, &RemoteEdit::Contents(entries)));
+                }
+                SyncMessage::Lines { op } => {
+                    server.lines.push(op.clone());
+                    for (axis, at, count) in server.replica.remote_lines(op) {
+                        cell_changed.write_batch(sync::apply(&mut grid_state, &RemoteEdit::Shift { axis, at, count }));
+                    }
+                    journal.checkpoint(&grid_state, &tick_control);
+                }
+                // Only for the other clients
+                SyncMessage::Presence(_) | SyncMessage::Sheet { .. } | SyncMessage::Values { .. } => {}
+            }
+        }
+    }
+}
+
+/// Send every client the values each tick changed
+pub fn send_tick_values(server: Res<SyncServer>, tick_control: Res<TickControl>, mut changes: MessageReader<CellChanged>) {
+    let values: Vec<ValueOp> = changes
+        .read()
+        .filter(|change| change.source == ChangeSource::Tick)
+        .map(|change| ValueOp { cell: server.replica.cell_id(change.col, change.row), value: change.new.clone() })
+        .collect();
+    if values.is_empty() {
+        return;
+    }
+    let message = SyncMessage::Values { tick: tick_control.tick_count, values };
+    for channel in &server.clients {
+        SyncServer::post(channel, &message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::CellContent;
+    use crate::grid_ops::Axis;
+
+    #[test]
+    fn test_newcomers_get_the_sheet() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(0, 2).set_raw("= A3 * 2".to_string());
+        let mut server = SyncServer { replica: Replica::new(0), lines: Vec::new(), joined: Arc::default(), clients: Vec::new() };
+
+        // Some client put a row in above row 1
+        let op = Replica::new(1).local_lines(Axis::Row, 1, 1, 100);
+        server.lines.push(op.clone());
+        for (axis, at, count) in server.replica.remote_lines(op) {
+            sync::apply(&mut grid, &RemoteEdit::Shift { axis, at, count });
+        }
+
+        let channel = Channel::default();
+        server.greet(&channel, &grid, 7);
+        let messages = channel.outgoing.lock().unwrap().clone();
+        let Ok(SyncMessage::Sheet { lines, cells }) = serde_json::from_str::<SyncMessage>(&messages[0]) else {
+            panic!("no sheet in {:?}", messages);
+        };
+        // A newcomer's lines line up with the server's, so the cells land where they are there
+        let mut newcomer = Replica::new(2);
+        for op in lines {
+            newcomer.remote_lines(op);
+        }
+        let content = Some(CellContent { raw: "= A4 * 2".to_string(), ..Default::default() });
+        assert_eq!(newcomer.remote_cells(cells), vec![JournalEntry { col: 0, row: 3, content }]);
+        assert!(matches!(serde_json::from_str::<SyncMessage>(&messages[1]), Ok(SyncMessage::Values { tick: 7, .. })));
+    }
+
+    #[test]
+    fn test_only_edits_and_presence_are_relayed() {
+        let clients: Vec<Arc<Channel>> = vec![Arc::default(), Arc::default()];
+        let server = SyncServer { replica: Replica::new(0), lines: Vec::new(), joined: Arc::default(), clients: clients.clone() };
+
+        // A client can't replace everyone's sheet or values
+        assert!(!server.relay(0, &SyncMessage::Sheet { lines: Vec::new(), cells: Vec::new() }));
+        assert!(!server.relay(0, &SyncMessage::Values { tick: 1, values: Vec::new() }));
+        assert!(clients[1].outgoing.lock().unwrap().is_empty());
+
+        assert!(server.relay(0, &SyncMessage::Cells { cells: Vec::new() }));
+        assert_eq!(clients[1].outgoing.lock().unwrap().len(), 1);
+        assert!(clients[0].outgoing.lock().unwrap().is_empty(), "nothing goes back to the sender");
+    }
+}
@@ -0,0 +1,185 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{Map, Value};
+
+use crate::cell::{CellContent, CellStyle};
+use crate::evaluator::TickControl;
+use crate::grid_state::GridState;
+use crate::journal::JournalEntry;
+use crate::persist::{envelope_value, load_document, FORMAT};
+use crate::undo::apply_content;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS cells (
+        col INTEGER NOT NULL,
+        row INTEGER NOT NULL,
+        raw TEXT NOT NULL,
+        style TEXT NOT NULL,
+        PRIMARY KEY (col, row)
+    ) WITHOUT ROWID;
+    CREATE TABLE IF NOT EXISTS settings (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        json TEXT NOT NULL
+    );
+";
+
+/// A workbook in SQLite: one row per cell, so saving an edit only touches the
+/// cells it changed, plus one JSON row for everything else (layout, headers,
+/// theme, tick settings), in the save envelope so it migrates like a save file
+/// Formula results aren't stored; they're recomputed on the next tick
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+fn db_error(e: rusqlite::Error) -> String {
+    e.to_string()
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        Self::new(Connection::open(path).map_err(db_error)?)
+    }
+
+    fn new(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+        Ok(Self { conn })
+    }
+
+    /// Upsert (or delete, for cleared cells) the journaled cells in one transaction
+    pub fn write_cells(&mut self, entries: &[JournalEntry]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        put_cells(&tx, entries)?;
+        tx.commit().map_err(db_error)
+    }
+
+    /// Store everything but the cells
+    pub fn write_settings(&self, grid: &GridState, ticks: &TickControl) -> Result<(), String> {
+        put_settings(&self.conn, grid, ticks)
+    }
+
+    /// Replace the stored workbook with this one
+    pub fn write_all(&mut self, grid: &GridState, ticks: &TickControl) -> Result<(), String> {
+        let entries: Vec<JournalEntry> = grid
+            .cells
+            .iter()
+            .map(|((col, row), cell)| JournalEntry { col, row, content: Some(cell.content()) })
+            .collect();
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute("DELETE FROM cells", []).map_err(db_error)?;
+        put_cells(&tx, &entries)?;
+        put_settings(&tx, grid, ticks)?;
+        tx.commit().map_err(db_error)
+    }
+
+    /// The stored workbook, None if nothing has been stored yet
+    pub fn load(&self) -> Result<Option<(GridState, TickControl)>, String> {
+        let settings: Option<String> = self
+            .conn
+            .query_row("SELECT json FROM settings WHERE id = 0", [], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?;
+        let Some(settings) = settings else { return Ok(None) };
+        let mut settings: Map<String, Value> = serde_json::from_str(&settings).map_err(|e| e.to_string())?;
+        // Rows stored before the settings had an envelope are format v1
+        if !settings.contains_key("format") {
+            settings.insert("format".to_string(), FORMAT.into());
+            settings.insert("format_version".to_string(), 1.into());
+        }
+        let (mut grid, ticks) = load_document(settings)?;
+
+        let mut query = self.conn.prepare("SELECT col, row, raw, style FROM cells").map_err(db_error)?;
+        let rows = query
+            .query_map([], |row| {
+                Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
+            })
+            .map_err(db_error)?;
+        for row in rows {
+            let (col, row, raw, style) = row.map_err(db_error)?;
+            let style: CellStyle = serde_json::from_str(&style).map_err(|e| e.to_string())?;
+            apply_content(&mut grid, col, row, &Some(CellContent { raw, style }));
+        }
+        Ok(Some((grid, ticks)))
+    }
+}
+
+fn put_cells(conn: &Connection, entries: &[JournalEntry]) -> Result<(), String> {
+    let mut upsert = conn
+        .prepare_cached(
+            "INSERT INTO cells (col, row, raw, style) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (col, row) DO UPDATE SET raw = excluded.raw, style = excluded.style",
+        )
+        .map_err(db_error)?;
+    let mut delete = conn.prepare_cached("DELETE FROM cells WHERE col = ?1 AND row = ?2").map_err(db_error)?;
+    for entry in entries {
+        match &entry.content {
+            Some(content) => {
+                let style = serde_json::to_string(&content.style).map_err(|e| e.to_string())?;
+                upsert.execute(params![entry.col, entry.row, content.raw, style]).map_err(db_error)?;
+            }
+            None => {
+                delete.execute(params![entry.col, entry.row]).map_err(db_error)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn put_settings(conn: &Connection, grid: &GridState, ticks: &TickControl) -> Result<(), String> {
+    let mut settings = envelope_value(grid, ticks)?;
+    if let Some(sheet) = settings["sheet"].as_object_mut() {
+        sheet.remove("cells");
+    }
+    let settings = settings.to_string();
+    conn.execute("INSERT OR REPLACE INTO settings (id, json) VALUES (0, ?1)", params![settings])
+        .map_err(db_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evalexpr::Value;
+
+    #[test]
+    fn test_cells_upsert_incrementally() {
+        let mut store = SqliteStore::new(Connection::open_in_memory().unwrap()).unwrap();
+        assert!(store.load().unwrap().is_none());
+
+        let mut grid = GridState::new();
+        grid.set_range((0, 0), [["1", "= A0 + 1", "x"]]);
+        grid.headers.set_col_label(0, "Qty");
+        let ticks = TickControl { tick_count: 4, ..Default::default() };
+        store.write_all(&grid, &ticks).unwrap();
+
+        let bold = CellStyle { bold: true, ..Default::default() };
+        store
+            .write_cells(&[
+                JournalEntry { col: 0, row: 0, content: Some(CellContent { raw: "5".into(), style: bold }) },
+                JournalEntry { col: 2, row: 0, content: None },
+            ])
+            .unwrap();
+
+        let (loaded, loaded_ticks) = store.load().unwrap().unwrap();
+        let cell = loaded.get_cell(0, 0).unwrap();
+        assert_eq!((cell.value.clone(), cell.style.bold), (Value::Int(5), true));
+        assert_eq!(loaded.get_cell(1, 0).unwrap().raw, "= A0 + 1");
+        assert!(loaded.get_cell(2, 0).is_none());
+        assert_eq!(loaded.headers, grid.headers);
+        assert_eq!(loaded_ticks.tick_count, 4);
+
+        let stored: String = store.conn.query_row("SELECT json FROM settings", [], |row| row.get(0)).unwrap();
+        assert!(stored.contains(&format!("\"format_version\":{}", crate::persist::FORMAT_VERSION)));
+    }
+
+    #[test]
+    fn test_settings_without_envelope_load() {
+        let store = SqliteStore::new(Connection::open_in_memory().unwrap()).unwrap();
+        let settings = r#"{"sheet": {"protected": true}, "ticks": {"tick_count": 9}}"#;
+        store.conn.execute("INSERT INTO settings (id, json) VALUES (0, ?1)", params![settings]).unwrap();
+        store.conn.execute("INSERT INTO cells (col, row, raw, style) VALUES (0, 0, '3', '{}')", []).unwrap();
+
+        let (loaded, ticks) = store.load().unwrap().unwrap();
+        assert!(loaded.protected);
+        assert_eq!(ticks.tick_count, 9);
+        assert_eq!(loaded.get_cell(0, 0).unwrap().raw, "3");
+    }
+}
@@ -9,14 +9,22 @@ pub struct SvgRenderer {
     result_rx: Receiver<SvgRenderResult>,
 
     /// Tracks pending render requests to avoid duplicates
-    pub pending_renders: HashSet<(i32, i32)>,
+    pub pending_renders: HashSet<RenderTarget>,
 
     /// Caches rendered RGBA buffers by content hash
     pub pixel_cache: HashMap<u64, Vec<u8>>,
 }
 
+/// What a render is for: one in flight per target
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderTarget {
+    Cell(i32, i32),
+    /// Index into `GridState::charts`
+    Chart(usize),
+}
+
 pub struct SvgRenderRequest {
-    pub cell_coord: (i32, i32),
+    pub target: RenderTarget,
     pub svg: String,
     pub width: u32,
     pub height: u32,
@@ -24,7 +32,7 @@ pub struct SvgRenderRequest {
 }
 
 pub struct SvgRenderResult {
-    pub cell_coord: (i32, i32),
+    pub target: RenderTarget,
     pub rgba_buffer: Vec<u8>,
     pub width: u32,
     pub height: u32,
@@ -49,8 +57,8 @@ impl SvgRenderer {
     }
 
     pub fn request_render(&mut self, req: SvgRenderRequest) {
-        if !self.pending_renders.contains(&req.cell_coord) {
-            self.pending_renders.insert(req.cell_coord);
+        if !self.pending_renders.contains(&req.target) {
+            self.pending_renders.insert(req.target);
             let _ = self.request_tx.send(req);
         }
     }
@@ -58,7 +66,7 @@ impl SvgRenderer {
     pub fn poll_results(&mut self) -> Vec<SvgRenderResult> {
         let mut results = Vec::new();
         while let Ok(res) = self.result_rx.try_recv() {
-            self.pending_renders.remove(&res.cell_coord);
+            self.pending_renders.remove(&res.target);
             
             // Cache the result
             self.pixel_cache.insert(res.content_hash, res.rgba_buffer.clone());
@@ -70,6 +78,10 @@ impl SvgRenderer {
     pub fn is_cached(&self, hash: u64) -> bool {
         self.pixel_cache.contains_key(&hash)
     }
+
+    pub fn is_pending(&self, target: RenderTarget) -> bool {
+        self.pending_renders.contains(&target)
+    }
 }
 
 fn render_loop(rx: Receiver<SvgRenderRequest>, tx: Sender<SvgRenderResult>) {
@@ -85,7 +97,7 @@ fn render_loop(rx: Receiver<SvgRenderRequest>, tx: Sender<SvgRenderResult>) {
         // If rendering failed (empty buffer), we might want to send a placeholder or error
         // For now, we assume it works or returns a blank buffer
         let _ = tx.send(SvgRenderResult {
-            cell_coord: req.cell_coord,
+            target: req.target,
             rgba_buffer: buffer,
             width: req.width,
             height: req.height,
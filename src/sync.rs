@@ -0,0 +1,528 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::crdt::{CellId, CellOp, LineOp, Replica};
+use crate::events::{CellChanged, ChangeSource};
+use crate::grid_ops::{self, Axis};
+use crate::grid_state::{CellRange, GridState};
+use crate::journal::{self, JournalEntry};
+use crate::undo::apply_content;
+use evalexpr::Value;
+
+/// Seconds to wait before reconnecting a dropped connection
+const RECONNECT_DELAY: f32 = 3.0;
+
+/// Seconds between presence messages while nothing moves, so the others know
+/// this client is still there
+pub const PRESENCE_INTERVAL: f32 = 2.0;
+
+/// Milliseconds without word from a client after which it's taken to be gone
+const PRESENCE_TIMEOUT: u64 = 6000;
+
+/// What goes over the wire, one JSON text message each
+/// The server (see `server`) relays every client's messages to the others,
+/// greets a newly connected client with the whole `sheet`, and sends the
+/// `values` each of its ticks computes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SyncMessage {
+    Cells { cells: Vec<CellOp> },
+    Lines { op: LineOp },
+    Presence(Presence),
+    /// Every line edit so far, then every cell, replacing what's here
+    Sheet { lines: Vec<LineOp>, cells: Vec<CellOp> },
+    Values { tick: u64, values: Vec<ValueOp> },
+}
+
+/// A formula's value as the server computed it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueOp {
+    pub cell: CellId,
+    #[serde(with = "crate::persist::value_serde")]
+    pub value: Value,
+}
+
+/// Where a client's active cell and selection are, by line identity so they
+/// stay put as lines shift
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Presence {
+    pub client: u64,
+    pub name: String,
+    pub active: Option<CellId>,
+    /// Corners of each selected range
+    #[serde(default)]
+    pub ranges: Vec<[CellId; 2]>,
+}
+
+/// Another client's place in the sheet here
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peer {
+    pub client: u64,
+    pub name: String,
+    pub active: Option<(i32, i32)>,
+    pub ranges: Vec<CellRange>,
+}
+
+/// Something another client did, as it applies to the sheet here
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteEdit {
+    /// Cell contents that win over what's here
+    Contents(Vec<JournalEntry>),
+    /// Lines inserted (`count > 0`) or deleted, as `grid_ops::shift_lines`
+    /// takes them
+    Shift { axis: Axis, at: i32, count: i32 },
+    /// The whole sheet, replacing every cell here
+    Sheet(Vec<JournalEntry>),
+    /// Formula values from the server's tick `tick`
+    Values { tick: u64, values: Vec<((i32, i32), Value)> },
+}
+
+/// Apply another client's edit to the sheet
+pub fn apply(grid: &mut GridState, edit: &RemoteEdit) -> Vec<CellChanged> {
+    match edit {
+        RemoteEdit::Contents(entries) => journal::replay(grid, entries),
+        RemoteEdit::Shift { axis, at, count } => {
+            let group = grid_ops::shift_lines(grid, *axis, *at, *count);
+            let changes = group.edits.iter().filter_map(|e| apply_content(grid, e.col, e.row, &e.after)).collect();
+            grid_ops::remap_sheet(grid, *axis, *at, *count);
+            changes
+        }
+        RemoteEdit::Sheet(entries) => {
+            let cleared: Vec<JournalEntry> = grid
+                .cells
+                .iter()
+                .map(|((col, row), _)| JournalEntry { col, row, content: None })
+                .collect();
+            let mut changes = journal::replay(grid, &cleared);
+            changes.extend(journal::replay(grid, entries));
+            changes
+        }
+        RemoteEdit::Values { values, .. } => values
+            .iter()
+            .filter_map(|((col, row), value)| {
+                let cell = grid.cells.get_evaluated_mut(&(*col, *row)).filter(|cell| cell.is_formula)?;
+                let old = std::mem::replace(&mut cell.value, value.clone());
+                (old != *value).then(|| CellChanged { col: *col, row: *row, old, new: value.clone(), source: ChangeSource::Tick })
+            })
+            .collect(),
+    }
+}
+
+/// Carries sync messages to and from the other clients
+pub trait SyncTransport: Send + Sync {
+    /// Queue a message, sent once the transport can
+    fn send(&self, text: String);
+    /// The messages that arrived since the last call, in order
+    fn receive(&self) -> Vec<String>;
+}
+
+/// Text messages to and from the other end, shared with the connection
+#[derive(Default)]
+pub(crate) struct Channel {
+    pub(crate) outgoing: Mutex<Vec<String>>,
+    pub(crate) incoming: Mutex<Vec<String>>,
+    /// Set to close the connection, and by the connection once it's closed
+    pub(crate) stop: AtomicBool,
+}
+
+/// A sync server over a WebSocket (a thread natively, the browser's event
+/// loop on wasm), reconnecting when dropped
+/// Messages queued while disconnected are sent once the connection is back
+pub struct WebSocketTransport {
+    channel: Arc<Channel>,
+}
+
+impl WebSocketTransport {
+    pub fn connect(url: String) -> Self {
+        let channel = Arc::<Channel>::default();
+        backend::start(url, channel.clone());
+        Self { channel }
+    }
+}
+
+impl SyncTransport for WebSocketTransport {
+    fn send(&self, text: String) {
+        self.channel.outgoing.lock().unwrap().push(text);
+    }
+
+    fn receive(&self) -> Vec<String> {
+        std::mem::take(&mut *self.channel.incoming.lock().unwrap())
+    }
+}
+
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        self.channel.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Keeps the workbook in step with other clients through a transport, with
+/// a `Replica` merging everyone's edits (see `crdt`)
+#[derive(Resource)]
+pub struct SyncClient {
+    pub replica: Replica,
+    /// What the others see this client as
+    pub name: String,
+    transport: Option<Box<dyn SyncTransport>>,
+    /// The others' latest presence, and when it came (milliseconds)
+    peers: HashMap<u64, (Presence, u64)>,
+}
+
+impl Default for SyncClient {
+    fn default() -> Self {
+        let client = backend::client_id();
+        let name = startup_param("name").unwrap_or_else(|| format!("Guest {}", client % 1000));
+        Self { replica: Replica::new(client), name, transport: None, peers: HashMap::new() }
+    }
+}
+
+impl SyncClient {
+    /// Sync through `transport`, dropping any earlier one
+    pub fn attach(&mut self, transport: Box<dyn SyncTransport>) {
+        self.transport = Some(transport);
+    }
+
+    /// Connect to the sync server at `url`
+    pub fn connect(&mut self, url: String) {
+        self.attach(Box::new(WebSocketTransport::connect(url)));
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    fn post(&self, message: &SyncMessage) {
+        if let (Some(transport), Ok(text)) = (&self.transport, serde_json::to_string(message)) {
+            transport.send(text);
+        }
+    }
+
+    /// Send contents committed here
+    pub fn send(&mut self, entries: &[JournalEntry]) {
+        if !self.is_syncing() || entries.is_empty() {
+            return;
+        }
+        let cells = self.replica.local_cells(entries, backend::now_ms());
+        self.post(&SyncMessage::Cells { cells });
+    }
+
+    /// Send lines inserted or deleted here (just before they're shifted in
+    /// the sheet; the cells that moves aren't sent themselves)
+    pub fn shift_lines(&mut self, axis: Axis, at: i32, count: i32) {
+        if !self.is_syncing() || count == 0 {
+            return;
+        }
+        let op = self.replica.local_lines(axis, at, count, backend::now_ms());
+        self.post(&SyncMessage::Lines { op });
+    }
+
+    /// Tell the others where this client's active cell and selection are
+    pub fn share_presence(&mut self, active: Option<(i32, i32)>, ranges: &[CellRange]) {
+        if !self.is_syncing() {
+            return;
+        }
+        let replica = &self.replica;
+        let presence = Presence {
+            client: replica.client,
+            name: self.name.clone(),
+            active: active.map(|(col, row)| replica.cell_id(col, row)),
+            ranges: ranges
+                .iter()
+                .map(|r| [replica.cell_id(r.min_col, r.min_row), replica.cell_id(r.max_col, r.max_row)])
+                .collect(),
+        };
+        self.post(&SyncMessage::Presence(presence));
+    }
+
+    /// The other clients heard from lately, by client id; a range loses any
+    /// corner that's been deleted, and goes with it
+    pub fn peers(&self) -> Vec<Peer> {
+        let now = backend::now_ms();
+        let mut peers: Vec<Peer> = self
+            .peers
+            .values()
+            .filter(|(_, seen)| now.saturating_sub(*seen) < PRESENCE_TIMEOUT)
+            .map(|(presence, _)| Peer {
+                client: presence.client,
+                name: presence.name.clone(),
+                active: presence.active.and_then(|cell| self.replica.cell_at(cell)),
+                ranges: presence
+                    .ranges
+                    .iter()
+                    .filter_map(|[a, b]| Some(CellRange::new(self.replica.cell_at(*a)?, self.replica.cell_at(*b)?)))
+                    .collect(),
+            })
+            .collect();
+        peers.sort_by_key(|peer| peer.client);
+        peers
+    }
+
+    /// What other clients did since the last call, to apply in order
+    pub fn receive(&mut self) -> Vec<RemoteEdit> {
+        let Some(transport) = &self.transport else { return Vec::new() };
+        let mut edits = Vec::new();
+        for text in transport.receive() {
+            match serde_json::from_str::<SyncMessage>(&text) {
+                Ok(SyncMessage::Cells { cells }) => {
+                    let entries = self.replica.remote_cells(cells);
+                    if !entries.is_empty() {
+                        edits.push(RemoteEdit::Contents(entries));
+                    }
+                }
+                Ok(SyncMessage::Lines { op }) => edits.extend(
+                    self.replica.remote_lines(op).into_iter().map(|(axis, at, count)| RemoteEdit::Shift { axis, at, count }),
+                ),
+                Ok(SyncMessage::Presence(presence)) => {
+                    if presence.client != self.replica.client {
+                        self.peers.insert(presence.client, (presence, backend::now_ms()));
+                    }
+                }
+                Ok(SyncMessage::Sheet { lines, cells }) => {
+                    // Line identities start over from the server's
+                    self.replica = Replica::new(self.replica.client);
+                    for op in lines {
+                        self.replica.remote_lines(op);
+                    }
+                    edits.push(RemoteEdit::Sheet(self.replica.remote_cells(cells)));
+                }
+                Ok(SyncMessage::Values { tick, values }) => {
+                    let values = values.into_iter().filter_map(|op| Some((self.replica.cell_at(op.cell)?, op.value))).collect();
+                    edits.push(RemoteEdit::Values { tick, values });
+                }
+                Err(e) => warn!("Sync: unreadable message: {}", e),
+            }
+        }
+        edits
+    }
+}
+
+/// A setting given at startup: `GREGSHEET_<NAME>` natively, the page's
+/// `?name=` parameter on the web (`sync` is the server to connect to, `name`
+/// what to show the others)
+pub fn startup_param(name: &str) -> Option<String> {
+    backend::startup_param(name).filter(|value| !value.is_empty())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use backend::{pump, POLL};
+
+/// One blocking thread, polling the socket between sends
+#[cfg(not(target_arch = "wasm32"))]
+mod backend {
+    use super::*;
+    use std::hash::BuildHasher;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tungstenite::stream::MaybeTlsStream;
+    use tungstenite::{Error, Message, WebSocket};
+
+    /// How long a read waits before checking for messages to send
+    pub const POLL: Duration = Duration::from_millis(50);
+
+    pub fn now_ms() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+    }
+
+    pub fn client_id() -> u64 {
+        std::collections::hash_map::RandomState::new().hash_one(now_ms())
+    }
+
+    pub fn startup_param(name: &str) -> Option<String> {
+        std::env::var(format!("GREGSHEET_{}", name.to_uppercase())).ok()
+    }
+
+    fn stream(socket: &MaybeTlsStream<TcpStream>) -> Option<&TcpStream> {
+        match socket {
+            MaybeTlsStream::Plain(stream) => Some(stream),
+            MaybeTlsStream::Rustls(stream) => Some(stream.get_ref()),
+            _ => None,
+        }
+    }
+
+    /// Trade messages with the other end until either side closes the
+    /// connection; reads must time out after `POLL`, so sends aren't held up
+    pub fn pump<S: Read + Write>(socket: &mut WebSocket<S>, channel: &Channel) {
+        while !channel.stop.load(Ordering::Relaxed) {
+            let outgoing = std::mem::take(&mut *channel.outgoing.lock().unwrap());
+            let sent = outgoing.iter().position(|text| socket.send(Message::Text(text.clone())).is_err());
+            if let Some(failed) = sent {
+                // Sent again once reconnected, ahead of newer ones
+                let mut queue = channel.outgoing.lock().unwrap();
+                let newer = std::mem::replace(&mut *queue, outgoing[failed..].to_vec());
+                queue.extend(newer);
+                break;
+            }
+            match socket.read() {
+                Ok(Message::Text(text)) => channel.incoming.lock().unwrap().push(text),
+                Ok(_) => {}
+                Err(Error::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(e) => {
+                    warn!("Sync: {}", e);
+                    break;
+                }
+            }
+        }
+        let _ = socket.close(None);
+    }
+
+    pub fn start(url: String, channel: Arc<Channel>) {
+        thread::spawn(move || {
+            while !channel.stop.load(Ordering::Relaxed) {
+                match tungstenite::connect(url.as_str()) {
+                    Ok((mut socket, _)) => {
+                        if let Some(stream) = stream(socket.get_ref()) {
+                            let _ = stream.set_read_timeout(Some(POLL));
+                        }
+                        pump(&mut socket, &channel);
+                    }
+                    Err(e) => warn!("Sync: can't connect to {}: {}", url, e),
+                }
+                let mut left = Duration::from_secs_f32(RECONNECT_DELAY);
+                while !left.is_zero() && !channel.stop.load(Ordering::Relaxed) {
+                    thread::sleep(POLL.min(left));
+                    left = left.saturating_sub(POLL);
+                }
+            }
+        });
+    }
+}
+
+/// The browser's `WebSocket`, with a task sending queued messages
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::*;
+    use wasm_bindgen::{prelude::*, JsCast};
+    use wasm_bindgen_futures::JsFuture;
+
+    pub fn now_ms() -> u64 {
+        js_sys::Date::now() as u64
+    }
+
+    pub fn client_id() -> u64 {
+        (js_sys::Math::random() * u64::MAX as f64) as u64
+    }
+
+    pub fn startup_param(name: &str) -> Option<String> {
+        let search = web_sys::window()?.location().search().ok()?;
+        web_sys::UrlSearchParams::new_with_str(&search).ok()?.get(name)
+    }
+
+    async fn sleep(seconds: f32) {
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            if let Some(window) = web_sys::window() {
+                let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, (seconds * 1000.0) as i32);
+            }
+        });
+        let _ = JsFuture::from(promise).await;
+    }
+
+    pub fn start(url: String, channel: Arc<Channel>) {
+        let socket = match web_sys::WebSocket::new(&url) {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("Sync: can't connect to {}: {:?}", url, e);
+                return reconnect(url, channel);
+            }
+        };
+        let incoming = channel.clone();
+        let onmessage = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |event: web_sys::MessageEvent| {
+            if let Some(text) = event.data().as_string() {
+                incoming.incoming.lock().unwrap().push(text);
+            }
+        });
+        socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+
+        let (closed, closed_url) = (channel.clone(), url);
+        let onclose = Closure::once_into_js(move || reconnect(closed_url, closed));
+        socket.set_onclose(Some(onclose.unchecked_ref()));
+
+        wasm_bindgen_futures::spawn_local(async move {
+            loop {
+                sleep(0.05).await;
+                if channel.stop.load(Ordering::Relaxed) {
+                    let _ = socket.close();
+                    break;
+                }
+                match socket.ready_state() {
+                    web_sys::WebSocket::CONNECTING => {}
+                    web_sys::WebSocket::OPEN => {
+                        let outgoing = std::mem::take(&mut *channel.outgoing.lock().unwrap());
+                        for text in outgoing {
+                            let _ = socket.send_with_str(&text);
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        });
+    }
+
+    fn reconnect(url: String, channel: Arc<Channel>) {
+        wasm_bindgen_futures::spawn_local(async move {
+            sleep(RECONNECT_DELAY).await;
+            if !channel.stop.load(Ordering::Relaxed) {
+                start(url, channel);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::CellContent;
+
+    /// Two clients wired straight to each other
+    #[derive(Clone, Default)]
+    struct Loopback {
+        inbox: Arc<Mutex<Vec<String>>>,
+        peer: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SyncTransport for Loopback {
+        fn send(&self, text: String) {
+            self.peer.lock().unwrap().push(text);
+        }
+
+        fn receive(&self) -> Vec<String> {
+            std::mem::take(&mut *self.inbox.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_last_writer_wins() {
+        let entry = |raw: &str| JournalEntry { col: 1, row: 2, content: Some(CellContent { raw: raw.into(), ..Default::default() }) };
+        let link = Loopback::default();
+        let (mut here, mut there) = (SyncClient::default(), SyncClient::default());
+        (here.replica.client, there.replica.client) = (1, 2);
+        here.attach(Box::new(link.clone()));
+        there.attach(Box::new(Loopback { inbox: link.peer, peer: link.inbox }));
+
+        // The later write wins on both sides (in the same millisecond, the
+        // higher client id)
+        here.send(&[entry("mine")]);
+        there.send(&[entry("theirs")]);
+        assert_eq!(here.receive(), vec![RemoteEdit::Contents(vec![entry("theirs")])]);
+        assert!(there.receive().is_empty());
+
+        // Line edits arrive as shifts
+        here.shift_lines(Axis::Column, 0, 2);
+        assert_eq!(there.receive(), vec![RemoteEdit::Shift { axis: Axis::Column, at: 0, count: 2 }]);
+
+        // Presence is by identity, so it moves with the lines
+        there.share_presence(Some((1, 1)), &[CellRange::new((0, 0), (1, 1))]);
+        assert!(here.receive().is_empty());
+        here.shift_lines(Axis::Row, 0, 1);
+        let peers = here.peers();
+        assert_eq!((peers.len(), peers[0].client), (1, 2));
+        assert_eq!(peers[0].active, Some((1, 2)));
+        assert_eq!(peers[0].ranges, vec![CellRange::new((0, 1), (1, 2))]);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+/// Size of one rich-cell layer, in pixels
+pub const LAYER_WIDTH: u32 = 80;
+pub const LAYER_HEIGHT: u32 = 30;
+/// RGBA bytes in one layer
+pub const LAYER_BYTES: usize = (LAYER_WIDTH * LAYER_HEIGHT * 4) as usize;
+/// Layers the texture array starts with (at least 2, so it's viewed as an array)
+pub const INITIAL_LAYERS: u32 = 16;
+/// Most layers the array grows to (the default GPU limit)
+pub const MAX_LAYERS: u32 = 256;
+
+/// Assigns rich-cell contents (by hash) to layers of a persistent texture array
+/// Contents keep their layer while in use, so only new contents are copied in;
+/// layers whose contents scrolled away are recycled, least recently used first
+pub struct LayerAllocator {
+    by_hash: HashMap<u64, u32>,
+    /// Per layer: its content's hash and the frame it was last used in
+    layers: Vec<Option<(u64, u64)>>,
+    frame: u64,
+}
+
+impl Default for LayerAllocator {
+    fn default() -> Self {
+        Self::new(INITIAL_LAYERS)
+    }
+}
+
+impl LayerAllocator {
+    pub fn new(capacity: u32) -> Self {
+        Self { by_hash: HashMap::new(), layers: vec![None; capacity as usize], frame: 0 }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.layers.len() as u32
+    }
+
+    /// Start assigning the layers for a new set of visible cells
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Add empty layers (existing layers keep their place)
+    pub fn grow(&mut self, capacity: u32) {
+        if capacity as usize > self.layers.len() {
+            self.layers.resize(capacity as usize, None);
+        }
+    }
+
+    /// Layer holding `hash`, and whether it was just assigned (so its pixels
+    /// still need copying in)
+    /// None when every layer is already in use this frame
+    pub fn assign(&mut self, hash: u64) -> Option<(u32, bool)> {
+        if let Some(&layer) = self.by_hash.get(&hash) {
+            self.layers[layer as usize] = Some((hash, self.frame));
+            return Some((layer, false));
+        }
+
+        // An empty layer, else the one idle longest
+        let layer = match self.layers.iter().position(Option::is_none) {
+            Some(empty) => empty,
+            None => {
+                let (stale, &(old_hash, last_used)) = self
+                    .layers
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, slot)| slot.as_ref().map(|slot| (i, slot)))
+                    .min_by_key(|(_, (_, last_used))| *last_used)?;
+                if last_used == self.frame {
+                    return None;
+                }
+                self.by_hash.remove(&old_hash);
+                stale
+            }
+        };
+        self.layers[layer] = Some((hash, self.frame));
+        self.by_hash.insert(hash, layer as u32);
+        Some((layer as u32, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_are_reused_and_recycled() {
+        let mut layers = LayerAllocator::new(2);
+        layers.begin_frame();
+        assert_eq!(layers.assign(10), Some((0, true)));
+        assert_eq!(layers.assign(20), Some((1, true)));
+        assert_eq!(layers.assign(10), Some((0, false)));
+        // Both layers are in use this frame
+        assert_eq!(layers.assign(30), None);
+
+        // Next frame only 20 is still visible, so 10's layer is recycled
+        layers.begin_frame();
+        assert_eq!(layers.assign(20), Some((1, false)));
+        assert_eq!(layers.assign(30), Some((0, true)));
+        assert_eq!(layers.assign(10), None);
+
+        layers.grow(3);
+        assert_eq!(layers.assign(10), Some((2, true)));
+        assert_eq!(layers.capacity(), 3);
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cell::{CellStyle, HorizontalAlign, NumberFormat};
+
+/// A reusable style cells can follow by ID
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedStyle {
+    pub name: String,
+    pub style: CellStyle,
+}
+
+/// The sheet's named styles; a style's ID is its index
+/// Cells reference styles instead of copying them, so restyling an entry
+/// updates every cell using it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme {
+    pub styles: Vec<NamedStyle>,
+}
+
+impl Default for Theme {
+    /// Default palette: Header, Input and Result
+    fn default() -> Self {
+        let named = |name: &str, style| NamedStyle { name: name.to_string(), style };
+        Self {
+            styles: vec![
+                named(
+                    "Header",
+                    CellStyle {
+                        bold: true,
+                        text_color: Some([0xff, 0xff, 0xff]),
+                        background: Some([0x45, 0x5a, 0x64]),
+                        ..Default::default()
+                    },
+                ),
+                named(
+                    "Input",
+                    CellStyle {
+                        text_color: Some([0x15, 0x65, 0xc0]),
+                        background: Some([0xff, 0xf8, 0xe1]),
+                        align: HorizontalAlign::Right,
+                        ..Default::default()
+                    },
+                ),
+                named(
+                    "Result",
+                    CellStyle {
+                        bold: true,
+                        background: Some([0xc8, 0xe6, 0xc9]),
+                        align: HorizontalAlign::Right,
+                        number_format: NumberFormat::Fixed(2),
+                        ..Default::default()
+                    },
+                ),
+            ],
+        }
+    }
+}
+
+impl Theme {
+    pub fn get(&self, id: u32) -> Option<&NamedStyle> {
+        self.styles.get(id as usize)
+    }
+
+    /// ID of the style with this name
+    pub fn find(&self, name: &str) -> Option<u32> {
+        self.styles.iter().position(|s| s.name == name).map(|i| i as u32)
+    }
+
+    /// Change a named style's formatting (every cell following it updates)
+    pub fn restyle(&mut self, id: u32, style: CellStyle) {
+        if let Some(named) = self.styles.get_mut(id as usize) {
+            // Named styles don't nest
+            named.style = CellStyle { named: None, ..style };
+        }
+    }
+
+    /// Formatting a cell with this style actually shows
+    /// A cell following a named style takes all its formatting from the theme,
+    /// except `locked`, which is protection rather than formatting
+    pub fn resolve(&self, style: CellStyle) -> CellStyle {
+        match style.named.and_then(|id| self.get(id)) {
+            Some(named) => CellStyle { borders: style.borders, locked: style.locked, named: style.named, ..named.style },
+            None => style,
+        }
+    }
+
+    /// Style after `current` in theme order, wrapping through "no style"
+    pub fn next_id(&self, current: Option<u32>) -> Option<u32> {
+        let next = current.map_or(0, |id| id + 1);
+        ((next as usize) < self.styles.len()).then_some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restyling_updates_followers() {
+        let mut theme = Theme::default();
+        let header = theme.find("Header").unwrap();
+        let cell = CellStyle { named: Some(header), locked: true, ..Default::default() };
+
+        let shown = theme.resolve(cell);
+        assert!(shown.bold && shown.locked);
+        assert_eq!(shown.background, Some([0x45, 0x5a, 0x64]));
+
+        theme.restyle(header, CellStyle { italic: true, ..Default::default() });
+        let shown = theme.resolve(cell);
+        assert!(!shown.bold && shown.italic && shown.locked);
+
+        // Unknown IDs fall back to the cell's own formatting
+        let orphan = CellStyle { named: Some(99), bold: true, ..Default::default() };
+        assert_eq!(theme.resolve(orphan), orphan);
+
+        assert_eq!(theme.next_id(None), Some(0));
+        assert_eq!(theme.next_id(Some(2)), None);
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::math::Vec2;
+
+/// Screen pixels a finger may wander before a touch counts as a drag
+pub const TAP_SLOP: f32 = 10.0;
+/// Seconds a still finger is held to edit the cell under it
+pub const LONG_PRESS: f64 = 0.5;
+
+/// What one finger is doing, from the moment it touched down
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TouchGesture {
+    pub id: u64,
+    pub start: Vec2,
+    pub started_at: f64,
+    /// Moved past `TAP_SLOP` (or became part of a pinch), so it's no tap
+    pub dragged: bool,
+    pub long_pressed: bool,
+}
+
+impl TouchGesture {
+    pub fn new(id: u64, start: Vec2, started_at: f64) -> Self {
+        Self { id, start, started_at, dragged: false, long_pressed: false }
+    }
+
+    /// Note the finger is now at `position`
+    pub fn moved_to(&mut self, position: Vec2) {
+        if position.distance(self.start) > TAP_SLOP {
+            self.dragged = true;
+        }
+    }
+
+    /// Whether this is the moment the finger has been held still long enough
+    /// for a long press (true once)
+    pub fn long_press_due(&mut self, now: f64) -> bool {
+        let due = !self.dragged && !self.long_pressed && now - self.started_at >= LONG_PRESS;
+        self.long_pressed |= due;
+        due
+    }
+
+    /// A quick touch that stayed put, once the finger lifts
+    pub fn is_tap(&self) -> bool {
+        !self.dragged && !self.long_pressed
+    }
+}
+
+/// How a two-finger gesture moved between frames, in screen pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pinch {
+    /// Midpoint between the fingers now
+    pub centroid: Vec2,
+    /// How far the midpoint moved
+    pub pan: Vec2,
+    /// Factor for the camera scale: below 1 as the fingers spread (zooming in)
+    pub zoom: f32,
+}
+
+pub fn pinch(previous: [Vec2; 2], current: [Vec2; 2]) -> Pinch {
+    let centroid = (current[0] + current[1]) / 2.0;
+    let spread = current[0].distance(current[1]);
+    let zoom = if spread > f32::EPSILON { previous[0].distance(previous[1]) / spread } else { 1.0 };
+    Pinch { centroid, pan: centroid - (previous[0] + previous[1]) / 2.0, zoom }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gestures() {
+        let mut touch = TouchGesture::new(1, Vec2::new(100.0, 100.0), 0.0);
+        touch.moved_to(Vec2::new(104.0, 97.0));
+        assert!(!touch.long_press_due(0.2));
+        assert!(touch.is_tap());
+        assert!(touch.long_press_due(0.6));
+        assert!(!touch.long_press_due(0.7));
+        assert!(!touch.is_tap());
+
+        let mut drag = TouchGesture::new(2, Vec2::ZERO, 0.0);
+        drag.moved_to(Vec2::new(0.0, 30.0));
+        assert!(!drag.long_press_due(1.0) && !drag.is_tap());
+
+        // Fingers spreading to twice the distance while sliding right
+        let spread = pinch([Vec2::new(90.0, 0.0), Vec2::new(110.0, 0.0)], [Vec2::new(100.0, 0.0), Vec2::new(140.0, 0.0)]);
+        assert_eq!(spread, Pinch { centroid: Vec2::new(120.0, 0.0), pan: Vec2::new(20.0, 0.0), zoom: 0.5 });
+    }
+}
@@ -0,0 +1,322 @@
+use bevy::prelude::*;
+use evalexpr::Value;
+
+use crate::cell::{parse_literal, CellContent, CellStyle};
+use crate::events::{CellChanged, ChangeSource};
+use crate::grid_state::{GridState, SheetPositions};
+use crate::journal::JournalEntry;
+
+/// Maximum number of undo steps kept
+pub const UNDO_LIMIT: usize = 200;
+
+/// One reversible change to a single cell's contents (raw text and style)
+/// `None` means the cell doesn't exist (cleared)
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellEdit {
+    pub col: i32,
+    pub row: i32,
+    pub before: Option<CellContent>,
+    pub after: Option<CellContent>,
+}
+
+/// The sheet's positional settings either side of a structural edit
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionsEdit {
+    pub before: SheetPositions,
+    pub after: SheetPositions,
+}
+
+/// A group of cell edits that is applied and undone as a single step
+/// (a paste of 100 cells is one group)
+#[derive(Clone, Debug, Default)]
+pub struct EditGroup {
+    pub label: String,
+    pub edits: Vec<CellEdit>,
+    /// Set for line inserts and deletes (see `grid_ops::shift_sheet`)
+    pub positions: Option<Box<PositionsEdit>>,
+}
+
+impl EditGroup {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            edits: Vec::new(),
+            positions: None,
+        }
+    }
+
+    /// Record setting a cell's raw text (keeping its style), capturing its current state for undo
+    pub fn set_raw(&mut self, grid: &GridState, col: i32, row: i32, raw: String) {
+        let style = grid.get_cell(col, row).map(|c| c.style).unwrap_or_default();
+        self.set_content(grid, col, row, Some(CellContent { raw, style }));
+    }
+
+    /// Record setting a cell's style (keeping its raw text)
+    pub fn set_style(&mut self, grid: &GridState, col: i32, row: i32, style: CellStyle) {
+        let raw = grid.get_cell(col, row).map(|c| c.raw.clone()).unwrap_or_default();
+        self.set_content(grid, col, row, Some(CellContent { raw, style }));
+    }
+
+    /// Record clearing a cell, capturing its current state for undo
+    pub fn clear(&mut self, grid: &GridState, col: i32, row: i32) {
+        self.set_content(grid, col, row, None);
+    }
+
+    /// Record replacing a cell's whole contents (None clears it)
+    pub fn set_content(&mut self, grid: &GridState, col: i32, row: i32, after: Option<CellContent>) {
+        let before = grid.get_cell(col, row).map(|c| c.content());
+        if before != after {
+            self.edits.push(CellEdit { col, row, before, after });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty() && self.positions.is_none()
+    }
+
+    /// True if applying (or reverting) this group would touch a locked cell
+    /// on a protected sheet
+    pub fn touches_locked(&self, grid: &GridState) -> bool {
+        self.edits.iter().any(|e| grid.is_locked(e.col, e.row))
+    }
+}
+
+/// Write one side of an edit into the grid
+/// Literals take effect immediately, formulas on the next tick
+pub fn apply_content(grid: &mut GridState, col: i32, row: i32, content: &Option<CellContent>) -> Option<CellChanged> {
+    let old = grid.get_cell(col, row).map(|c| c.value.clone());
+
+    let new = match content {
+        Some(content) => {
+            let cell = grid.get_cell_mut_or_create(col, row);
+            cell.set_raw(content.raw.clone());
+            cell.style = content.style;
+            if !cell.is_formula {
+                cell.value = parse_literal(&cell.raw);
+            }
+            Some(cell.value.clone())
+        }
+        None => {
+            grid.cells.remove(&(col, row));
+            None
+        }
+    };
+
+    let old = old.unwrap_or(Value::Empty);
+    let new = new.unwrap_or(Value::Empty);
+    (old != new).then_some(CellChanged {
+        col,
+        row,
+        old,
+        new,
+        source: ChangeSource::Edit,
+    })
+}
+
+/// History of committed edit groups for Ctrl+Z / Ctrl+Shift+Z
+#[derive(Resource, Default)]
+pub struct UndoStack {
+    undo: Vec<EditGroup>,
+    redo: Vec<EditGroup>,
+    /// Contents written by commits, undos and redos, waiting for the journal
+    applied: Vec<JournalEntry>,
+}
+
+impl UndoStack {
+    /// Apply a group to the grid and push it as one undo step
+    /// Clears the redo stack; empty groups and groups touching locked cells
+    /// (while the sheet is protected) are ignored as a whole
+    pub fn commit(&mut self, grid: &mut GridState, group: EditGroup) -> Vec<CellChanged> {
+        if group.is_empty() || group.touches_locked(grid) {
+            return Vec::new();
+        }
+        let changes = group
+            .edits
+            .iter()
+            .filter_map(|e| apply_content(grid, e.col, e.row, &e.after))
+            .collect();
+        if let Some(positions) = &group.positions {
+            grid.set_positions(positions.after.clone());
+        }
+        self.record(group);
+        changes
+    }
+
+    /// Take the cell contents written since the last call, in order
+    pub fn take_applied(&mut self) -> Vec<JournalEntry> {
+        std::mem::take(&mut self.applied)
+    }
+
+    pub fn has_applied(&self) -> bool {
+        !self.applied.is_empty()
+    }
+
+    fn note_applied<'a>(&mut self, edits: impl Iterator<Item = (&'a CellEdit, &'a Option<CellContent>)>) {
+        self.applied.extend(edits.map(|(e, content)| JournalEntry { col: e.col, row: e.row, content: content.clone() }));
+    }
+
+    /// Push a group whose edits are already in the grid (e.g. a committed
+    /// `GridState` transaction) as one undo step, clearing the redo stack
+    pub fn record(&mut self, group: EditGroup) {
+        if group.is_empty() {
+            return;
+        }
+        self.note_applied(group.edits.iter().map(|e| (e, &e.after)));
+        self.undo.push(group);
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Revert the most recent group
+    pub fn undo(&mut self, grid: &mut GridState) -> Vec<CellChanged> {
+        if self.undo.last().is_some_and(|g| g.touches_locked(grid)) {
+            return Vec::new();
+        }
+        let Some(group) = self.undo.pop() else { return Vec::new() };
+        // Reverse order so overlapping edits within a group unwind correctly
+        let changes = group
+            .edits
+            .iter()
+            .rev()
+            .filter_map(|e| apply_content(grid, e.col, e.row, &e.before))
+            .collect();
+        if let Some(positions) = &group.positions {
+            grid.set_positions(positions.before.clone());
+        }
+        self.note_applied(group.edits.iter().rev().map(|e| (e, &e.before)));
+        self.redo.push(group);
+        changes
+    }
+
+    /// Re-apply the most recently undone group
+    pub fn redo(&mut self, grid: &mut GridState) -> Vec<CellChanged> {
+        if self.redo.last().is_some_and(|g| g.touches_locked(grid)) {
+            return Vec::new();
+        }
+        let Some(group) = self.redo.pop() else { return Vec::new() };
+        let changes = group
+            .edits
+            .iter()
+            .filter_map(|e| apply_content(grid, e.col, e.row, &e.after))
+            .collect();
+        if let Some(positions) = &group.positions {
+            grid.set_positions(positions.after.clone());
+        }
+        self.note_applied(group.edits.iter().map(|e| (e, &e.after)));
+        self.undo.push(group);
+        changes
+    }
+
+    /// Forget every undo and redo step, e.g. once lines shift under them
+    /// (contents already applied still go to the journal)
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_undoes_as_one_step() {
+        let mut grid = GridState::new();
+        let mut stack = UndoStack::default();
+        grid.get_cell_mut_or_create(0, 0).set_raw("1".to_string());
+
+        let mut group = EditGroup::new("Paste");
+        for row in 0..100 {
+            group.set_raw(&grid, 0, row, format!("{}", row * 2));
+        }
+        stack.commit(&mut grid, group);
+        assert_eq!(grid.get_cell(0, 50).unwrap().value, Value::Int(100));
+
+        stack.undo(&mut grid);
+        assert_eq!(grid.get_cell(0, 0).unwrap().raw, "1");
+        assert!(grid.get_cell(0, 50).is_none());
+        assert!(!stack.can_undo());
+
+        stack.redo(&mut grid);
+        assert_eq!(grid.get_cell(0, 99).unwrap().raw, "198");
+    }
+
+    #[test]
+    fn test_new_commit_clears_redo() {
+        let mut grid = GridState::new();
+        let mut stack = UndoStack::default();
+
+        let mut group = EditGroup::new("Edit");
+        group.set_raw(&grid, 1, 1, "5".to_string());
+        stack.commit(&mut grid, group);
+        stack.undo(&mut grid);
+        assert!(stack.can_redo());
+
+        let mut group = EditGroup::new("Clear");
+        group.clear(&grid, 2, 2);
+        assert!(group.is_empty(), "clearing an empty cell is a no-op");
+
+        let mut group = EditGroup::new("Edit");
+        group.set_raw(&grid, 2, 2, "= 1".to_string());
+        stack.commit(&mut grid, group);
+        assert!(!stack.can_redo());
+    }
+
+    #[test]
+    fn test_style_edits_keep_raw_text() {
+        let mut grid = GridState::new();
+        let mut stack = UndoStack::default();
+        grid.get_cell_mut_or_create(0, 0).set_raw("= 1 + 1".to_string());
+
+        let mut group = EditGroup::new("Format");
+        group.set_style(&grid, 0, 0, CellStyle { bold: true, ..Default::default() });
+        stack.commit(&mut grid, group);
+        assert!(grid.get_cell(0, 0).unwrap().style.bold);
+
+        let mut group = EditGroup::new("Edit");
+        group.set_raw(&grid, 0, 0, "3".to_string());
+        stack.commit(&mut grid, group);
+        assert!(grid.get_cell(0, 0).unwrap().style.bold, "editing text keeps the style");
+
+        stack.undo(&mut grid);
+        stack.undo(&mut grid);
+        let cell = grid.get_cell(0, 0).unwrap();
+        assert_eq!(cell.raw, "= 1 + 1");
+        assert!(!cell.style.bold);
+    }
+
+    #[test]
+    fn test_protection_blocks_locked_cells() {
+        let mut grid = GridState::new();
+        let mut stack = UndoStack::default();
+
+        let mut group = EditGroup::new("Format");
+        group.set_style(&grid, 0, 0, CellStyle { locked: true, ..Default::default() });
+        stack.commit(&mut grid, group);
+        grid.protected = true;
+
+        // A group touching a locked cell is refused as a whole
+        let mut group = EditGroup::new("Paste");
+        group.set_raw(&grid, 0, 0, "1".to_string());
+        group.set_raw(&grid, 0, 1, "2".to_string());
+        assert!(stack.commit(&mut grid, group).is_empty());
+        assert!(grid.get_cell(0, 1).is_none());
+
+        // Undoing the lock itself needs the sheet unprotected
+        stack.undo(&mut grid);
+        assert!(grid.get_cell(0, 0).unwrap().style.locked);
+        grid.protected = false;
+        stack.undo(&mut grid);
+        assert!(grid.get_cell(0, 0).is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use evalexpr::{ContextWithMutableVariables, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::cell::parse_literal;
+use crate::formula::{build_context, evaluate_formula};
+use crate::grid_state::{CellRange, GridState};
+
+/// What an entry must satisfy
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ValidationRule {
+    /// Entry must be one of these (exact text)
+    List(Vec<String>),
+    /// Entry must be a number within the bounds (inclusive)
+    Range { min: Option<f64>, max: Option<f64> },
+    /// Expression that must evaluate to true, with the entry bound to `value`
+    /// e.g. `value > 0 && value < C0`
+    Formula(String),
+}
+
+/// What happens to an entry that breaks the rule
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvalidAction {
+    /// The edit is refused
+    #[default]
+    Reject,
+    /// The edit goes through but the cell is marked
+    Flag,
+}
+
+/// A rule applied to a region of cells
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Validation {
+    pub range: CellRange,
+    pub rule: ValidationRule,
+    pub on_invalid: InvalidAction,
+}
+
+impl Validation {
+    /// Check a raw entry; formulas aren't checked since their value isn't known yet
+    pub fn check(&self, grid: &GridState, raw: &str) -> Result<(), String> {
+        if raw.trim_start().starts_with('=') || raw.is_empty() {
+            return Ok(());
+        }
+
+        match &self.rule {
+            ValidationRule::List(allowed) => {
+                if allowed.iter().any(|a| a == raw) {
+                    Ok(())
+                } else {
+                    Err(format!("must be one of: {}", allowed.join(", ")))
+                }
+            }
+            ValidationRule::Range { min, max } => {
+                let n = match parse_literal(raw) {
+                    Value::Int(i) => i as f64,
+                    Value::Float(f) => f,
+                    _ => return Err("must be a number".to_string()),
+                };
+                if min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max) {
+                    let bound = |b: Option<f64>| b.map(|b| b.to_string()).unwrap_or_else(|| "..".to_string());
+                    Err(format!("must be between {} and {}", bound(*min), bound(*max)))
+                } else {
+                    Ok(())
+                }
+            }
+            ValidationRule::Formula(expr) => {
+                let mut context = build_context(grid);
+                let _ = context.set_value("value".to_string(), parse_literal(raw));
+                match grid.headers.resolve(expr).map(|expr| evaluate_formula(&expr, &context)) {
+                    Ok(Ok(Value::Boolean(true))) => Ok(()),
+                    _ => Err(format!("must satisfy {}", expr)),
+                }
+            }
+        }
+    }
+
+    /// Allowed values when this rule offers a dropdown picker
+    pub fn choices(&self) -> Option<&[String]> {
+        match &self.rule {
+            ValidationRule::List(allowed) => Some(allowed.as_slice()),
+            _ => None,
+        }
+    }
+}
+
+/// The rule covering a cell; later rules take precedence over earlier ones
+pub fn validation_at(grid: &GridState, col: i32, row: i32) -> Option<&Validation> {
+    grid.validations.iter().rev().find(|v| v.range.contains(col, row))
+}
+
+/// True if a cell currently holds an entry its (flagging) rule doesn't allow
+pub fn is_flagged(grid: &GridState, col: i32, row: i32) -> bool {
+    let (Some(validation), Some(cell)) = (validation_at(grid, col, row), grid.get_cell(col, row)) else {
+        return false;
+    };
+    validation.on_invalid == InvalidAction::Flag && validation.check(grid, &cell.raw).is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let mut grid = GridState::new();
+        grid.get_cell_mut_or_create(2, 0).set_raw("10".to_string());
+        grid.get_cell_mut_or_create(2, 0).value = Value::Int(10);
+
+        let rule = |rule| Validation { range: CellRange::cell(0, 0), rule, on_invalid: InvalidAction::Reject };
+
+        let list = rule(ValidationRule::List(vec!["slow".into(), "fast".into()]));
+        assert!(list.check(&grid, "fast").is_ok());
+        assert!(list.check(&grid, "medium").is_err());
+        assert!(list.check(&grid, "= B0").is_ok(), "formulas aren't checked");
+
+        let range = rule(ValidationRule::Range { min: Some(0.0), max: Some(100.0) });
+        assert!(range.check(&grid, "42").is_ok());
+        assert!(range.check(&grid, "101").is_err());
+        assert!(range.check(&grid, "abc").is_err());
+
+        let custom = rule(ValidationRule::Formula("value > 0 && value < C0".into()));
+        assert!(custom.check(&grid, "5").is_ok());
+        assert!(custom.check(&grid, "15").is_err());
+    }
+
+    #[test]
+    fn test_flagged_cells() {
+        let mut grid = GridState::new();
+        grid.validations.push(Validation {
+            range: CellRange::new((0, 0), (0, 9)),
+            rule: ValidationRule::Range { min: None, max: Some(5.0) },
+            on_invalid: InvalidAction::Flag,
+        });
+        grid.get_cell_mut_or_create(0, 1).set_raw("3".to_string());
+        grid.get_cell_mut_or_create(0, 2).set_raw("7".to_string());
+
+        assert!(!is_flagged(&grid, 0, 1));
+        assert!(is_flagged(&grid, 0, 2));
+        assert!(validation_at(&grid, 1, 2).is_none());
+    }
+}
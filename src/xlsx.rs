@@ -0,0 +1,204 @@
+use calamine::{open_workbook_from_rs, Data, Reader, Xlsx};
+use std::io::Cursor;
+
+use crate::cell::{parse_literal, ErrorCode, NumberFormat};
+use crate::formula::{name_to_coord, CellRef};
+use crate::grid_state::GridState;
+
+/// Excel functions with an equivalent here, by Excel name
+const FUNCTIONS: [(&str, &str); 13] = [
+    ("MIN", "min"),
+    ("MAX", "max"),
+    ("IF", "if"),
+    ("ABS", "math::abs"),
+    ("SQRT", "math::sqrt"),
+    ("POWER", "math::pow"),
+    ("LN", "math::ln"),
+    ("LOG10", "math::log10"),
+    ("EXP", "math::exp"),
+    ("LEN", "len"),
+    ("UPPER", "str::to_uppercase"),
+    ("LOWER", "str::to_lowercase"),
+    ("TRIM", "str::trim"),
+];
+
+/// Read the first worksheet of an .xlsx file into a new sheet
+/// Cells keep Excel's cached values until the first tick. Formulas using
+/// anything without an equivalent here (ranges, other sheets, unknown
+/// functions) keep their original text and are marked as errors
+pub fn import_xlsx(bytes: &[u8]) -> Result<GridState, String> {
+    let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let sheet = workbook.sheet_names().first().cloned().ok_or("the workbook has no sheets")?;
+    let values = workbook.worksheet_range(&sheet).map_err(|e| e.to_string())?;
+    let formulas = workbook.worksheet_formula(&sheet).map_err(|e| e.to_string())?;
+
+    let mut grid = GridState::new();
+
+    // Positions are 0-based like ours: Excel's A1 lands on A0
+    let (value_row, value_col) = values.start().unwrap_or_default();
+    for (row, col, data) in values.used_cells() {
+        let (col, row) = ((value_col + col as u32) as i32, (value_row + row as u32) as i32);
+        let (raw, format) = literal(data);
+        let cell = grid.get_cell_mut_or_create(col, row);
+        cell.set_raw(raw);
+        cell.value = parse_literal(&cell.raw);
+        if let Some(format) = format {
+            cell.style.number_format = format;
+        }
+        if let Data::Error(_) = data {
+            cell.error = true;
+            cell.error_code = ErrorCode::parse(&cell.raw).unwrap_or_default();
+        }
+    }
+
+    let (formula_row, formula_col) = formulas.start().unwrap_or_default();
+    for (row, col, formula) in formulas.used_cells() {
+        let (col, row) = ((formula_col + col as u32) as i32, (formula_row + row as u32) as i32);
+        // The cell already holds Excel's cached result, shown until the first tick
+        let cell = grid.get_cell_mut_or_create(col, row);
+        match excel_formula(formula) {
+            Some(expr) => cell.set_raw(format!("= {}", expr)),
+            None => {
+                cell.set_raw(format!("={}", formula));
+                cell.error = true;
+                cell.error_code = ErrorCode::Name;
+            }
+        }
+    }
+
+    Ok(grid)
+}
+
+/// Raw text for a stored value, plus the number format it implies
+fn literal(data: &Data) -> (String, Option<NumberFormat>) {
+    match data {
+        Data::Int(i) => (i.to_string(), None),
+        Data::Float(f) => (f.to_string(), None),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => (s.clone(), None),
+        Data::Bool(b) => (b.to_string(), None),
+        // Durations become seconds; other dates keep their Excel serial number
+        Data::DateTime(dt) if dt.is_duration() => ((dt.as_f64() * 86_400.0).round().to_string(), Some(NumberFormat::Duration)),
+        Data::DateTime(dt) => (dt.as_f64().to_string(), None),
+        Data::Error(e) => (e.to_string(), None),
+        Data::Empty => (String::new(), None),
+    }
+}
+
+/// Translate an Excel formula (without the `=`) to our formula syntax
+/// A1-style references shift to 0-based rows; returns None if the formula uses
+/// anything without an equivalent here
+fn excel_formula(formula: &str) -> Option<String> {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut out = String::with_capacity(formula.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // "say ""hi""" -> "say \"hi\""
+        if c == '"' {
+            out.push('"');
+            i += 1;
+            loop {
+                match (chars.get(i), chars.get(i + 1)) {
+                    (Some('"'), Some('"')) => {
+                        out.push_str("\\\"");
+                        i += 2;
+                    }
+                    (Some('"'), _) => break,
+                    (Some('\\'), _) => {
+                        out.push_str("\\\\");
+                        i += 1;
+                    }
+                    (Some(&c), _) => {
+                        out.push(c);
+                        i += 1;
+                    }
+                    (None, _) => return None,
+                }
+            }
+            out.push('"');
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_alphanumeric() || c == '$' || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '$' | '_' | '.')) {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            match chars.get(i) {
+                Some('(') => {
+                    let name = token.to_ascii_uppercase();
+                    let (_, ours) = FUNCTIONS.iter().find(|(excel, _)| *excel == name)?;
+                    out.push_str(ours);
+                }
+                // Ranges and other sheets
+                Some(':' | '!') => return None,
+                _ if c.is_ascii_digit() || c == '.' => out.push_str(&token),
+                _ if token.eq_ignore_ascii_case("TRUE") => out.push_str("true"),
+                _ if token.eq_ignore_ascii_case("FALSE") => out.push_str("false"),
+                _ => out.push_str(&excel_reference(&token)?.to_text()),
+            }
+            continue;
+        }
+
+        match c {
+            '<' if chars.get(i + 1) == Some(&'>') => {
+                out.push_str("!=");
+                i += 1;
+            }
+            '<' | '>' if chars.get(i + 1) == Some(&'=') => {
+                out.push(c);
+                out.push('=');
+                i += 1;
+            }
+            '=' => out.push_str("=="),
+            '&' => out.push('+'),
+            // Percentages, array constants, quoted sheet names
+            '%' | '{' | '\'' | ':' | '!' => return None,
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+
+    Some(out)
+}
+
+/// An A1-style reference (`B3`, `$B$3`), moved to our 0-based rows
+fn excel_reference(token: &str) -> Option<CellRef> {
+    let col_absolute = token.starts_with('$');
+    let rest = token.strip_prefix('$').unwrap_or(token);
+    let letters_len = rest.chars().take_while(|c| c.is_ascii_uppercase()).count();
+    let (letters, digits) = rest.split_at(letters_len);
+    let row_absolute = digits.starts_with('$');
+    let digits = digits.strip_prefix('$').unwrap_or(digits);
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let (col, row) = name_to_coord(&format!("{}{}", letters, digits))?;
+    Some(CellRef { col, row: row.checked_sub(1).filter(|r| *r >= 0)?, col_absolute, row_absolute })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formula_translation() {
+        assert_eq!(excel_formula("A1*2+$B$3").as_deref(), Some("A0*2+$B$2"));
+        assert_eq!(excel_formula("IF(A1<>\"x\",MAX(B2,1),0)").as_deref(), Some("if(A0!=\"x\",max(B1,1),0)"));
+        assert_eq!(excel_formula("A1&\" says \"\"hi\"\"\"").as_deref(), Some("A0+\" says \\\"hi\\\"\""));
+        assert_eq!(excel_formula("A1=TRUE").as_deref(), Some("A0==true"));
+        assert_eq!(excel_formula("C10>=1.5E3").as_deref(), Some("C9>=1.5E3"));
+
+        // No equivalent: ranges, other sheets, unknown functions and names
+        assert_eq!(excel_formula("SUM(A1:A3)"), None);
+        assert_eq!(excel_formula("Sheet2!A1"), None);
+        assert_eq!(excel_formula("VLOOKUP(A1,B1,2)"), None);
+        assert_eq!(excel_formula("TaxRate*2"), None);
+        assert_eq!(excel_formula("A0"), None, "Excel rows start at 1");
+    }
+}